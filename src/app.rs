@@ -1,31 +1,45 @@
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeMap, HashMap},
     convert::Infallible,
+    str::FromStr,
+    time::Duration,
 };
 
 use axum::{
     body::Body,
-    extract::{multipart::MultipartError, DefaultBodyLimit, Multipart, Path, Request, State},
+    extract::{
+        multipart::MultipartError, DefaultBodyLimit, Multipart, Path, RawPathParams, RawQuery,
+        Request, State,
+    },
     http::header,
     response::{Html, IntoResponse, Redirect, Response},
     routing::{get, post},
     Json, Router,
 };
 use axum_extra::TypedHeader;
-use bytes::Bytes;
 use handlebars::Handlebars;
-use headers::{Host, UserAgent};
-use http::{header::CACHE_CONTROL, HeaderValue, StatusCode};
+use headers::{Header, Host, UserAgent};
+use http::{
+    header::{CACHE_CONTROL, PROXY_AUTHORIZATION},
+    HeaderMap, HeaderName, HeaderValue, StatusCode,
+};
+use oci_spec::image::ImageIndex;
 use serde::{ser::SerializeMap, Serialize, Serializer};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcDateTime};
 use tower::Service;
 use tracing::{debug, info_span, Instrument};
 
 use crate::{
+    archive,
     error::PyOciError,
-    middleware::EncodeNamespace,
-    package::{Package, WithFileName},
+    limit::KeyedLimit,
+    middleware::{redact_path, EncodeNamespace},
+    otlp::{OtlpReport, OtlpStatus},
+    package::{Deprecation, Package, WithFileName},
     service::AuthHeader,
-    Env, PyOci,
+    spool::{Spool, SpooledContent},
+    transport::{count_upstream_bytes, ClientOptions, HttpClient},
+    Env, PyOci, LABEL_CLASSIFIER,
 };
 
 #[derive(Debug)]
@@ -35,10 +49,18 @@ struct AppError(anyhow::Error);
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
-        let any_err = match self.0.downcast::<PyOciError>() {
-            Ok(err) => return err.into_response(),
-            Err(err) => err,
-        };
+        if let Some(err) = self.0.downcast_ref::<PyOciError>() {
+            // Keep the status of the PyOciError but prefix the message with any context added
+            // to it, so the response tells which step failed.
+            let context = self.0.chain().take_while(|cause| !cause.is::<PyOciError>());
+            let message = context
+                .map(ToString::to_string)
+                .chain([err.message.clone()])
+                .collect::<Vec<_>>()
+                .join(": ");
+            return (err.status, message).into_response();
+        }
+        let any_err = self.0;
         let any_err = match any_err.downcast::<MultipartError>() {
             Ok(err) => return err.into_response(),
             Err(err) => err,
@@ -58,14 +80,83 @@ where
     }
 }
 
+/// Response of the package listing when a package has no files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyList {
+    /// Empty HTML index with status 200
+    #[default]
+    Empty,
+    /// Status 404, for clients that expect it for unknown packages
+    NotFound,
+}
+
+impl FromStr for EmptyList {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "empty" => Ok(EmptyList::Empty),
+            "404" => Ok(EmptyList::NotFound),
+            _ => anyhow::bail!("Unknown empty list behavior '{value}', expected '404' or 'empty'"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 struct PyOciState<'a> {
     /// Subpath `PyOCI` is hosted on
     subpath: Option<String>,
     /// Maximum versions `PyOCI` will fetch when listing a package
     max_versions: usize,
+    /// Response of the package listing when a package has no files
+    empty_list: EmptyList,
     /// User Basic password as Bearer token if the username matches this value
     bearer_username: Option<String>,
+    /// Use the `Proxy-Authorization` header when the `Authorization` header is missing
+    proxy_authorization: bool,
+    /// Trust the `X-Forwarded-*` headers set by a reverse proxy
+    trust_proxy: bool,
+    /// Render absolute package links, using the `Host` header when there is no forwarded host
+    absolute_links: bool,
+    /// Delete unreferenced blobs when deleting a package version
+    collect_garbage: bool,
+    /// Tags to probe when the registry returns an empty tag list
+    tag_fallback: Vec<String>,
+    /// Prefixes of the annotations exposed when listing a package
+    annotation_prefixes: Vec<String>,
+    /// Refuse an `ImageIndex` without the `PyOCI` artifact type
+    strict_artifact_type: bool,
+    /// Add the size of each file to the links when listing a package
+    show_sizes: bool,
+    /// Maximum size of a published wheel, 0 disables the limit
+    body_limit_wheel: usize,
+    /// Maximum size of a published source distribution, 0 disables the limit
+    body_limit_sdist: usize,
+    /// Bytes of an upload kept in memory before it is spooled to a temporary file
+    upload_buffer: usize,
+    /// Maximum ratio between the declared decompressed size and the size of an upload
+    max_compression_ratio: u64,
+    /// Limits the simultaneous publishes per namespace
+    publish_limit: KeyedLimit,
+    /// Verify the repository can be published to before publishing
+    verify_namespace: bool,
+    /// Read `Requires-Dist` from the METADATA of a wheel instead of the upload form
+    requires_dist_from_metadata: bool,
+    /// Converts the classifiers of an upload into labels
+    classifier_labels: ClassifierLabels,
+    /// Project URL labels, and the annotation they are published as
+    project_url_annotations: Vec<(String, String)>,
+    /// Media type of the layer holding a published package file
+    layer_media_type: String,
+    /// Media type of the config of a published `ImageManifest`
+    config_media_type: String,
+    /// Content of the config of a published `ImageManifest`
+    config_content: String,
+    /// HTTP client shared by all requests to the upstream OCI registries
+    http_client: HttpClient,
+    /// Status of the OTLP exporter
+    otlp_status: OtlpStatus,
     /// HTML Template registry
     templates: Handlebars<'a>,
 }
@@ -78,6 +169,7 @@ pub fn pyoci_service(
 }
 
 /// Request Router
+#[allow(clippy::too_many_lines)]
 fn router(env: &Env) -> Router {
     let pyoci_routes = Router::new()
         .fallback(
@@ -89,11 +181,35 @@ fn router(env: &Env) -> Router {
             get(|| async { Redirect::to(env!("CARGO_PKG_HOMEPAGE")) })
                 .layer(axum::middleware::from_fn(cache_control_middleware)),
         )
-        .route("/{registry}/{namespace}/{package}/", get(list_package))
+        .route(
+            "/{registry}/{namespace}/{package}/",
+            get(list_package).delete(delete_prereleases),
+        )
+        // Static segments take precedence over `{filename}`, `index.html` is not a valid file name
+        .route(
+            "/{registry}/{namespace}/{package}/index.html",
+            get(list_package),
+        )
         .route(
             "/{registry}/{namespace}/{package}/json",
             get(list_package_json),
         )
+        .route(
+            "/{registry}/{namespace}/{package}/latest",
+            get(latest_package),
+        )
+        .route(
+            "/{registry}/{namespace}/{package}/{version}/tag/{alias}",
+            post(tag_package_version),
+        )
+        .route(
+            "/{registry}/{namespace}/{package}/{version}/index.json",
+            get(package_version_index),
+        )
+        .route(
+            "/{registry}/{namespace}/{package}/{filename}/exists",
+            get(package_file_exists),
+        )
         .route(
             "/{registry}/{namespace}/{package}/{filename}",
             get(download_package).delete(delete_package_version),
@@ -101,7 +217,8 @@ fn router(env: &Env) -> Router {
         .route(
             "/{registry}/{namespace}/",
             post(publish_package).layer(DefaultBodyLimit::max(env.body_limit)),
-        );
+        )
+        .route_layer(axum::middleware::from_fn(validate_path_middleware));
     let router = match env.path {
         Some(ref subpath) => Router::new().nest(subpath, pyoci_routes),
         _ => pyoci_routes,
@@ -119,14 +236,72 @@ fn router(env: &Env) -> Router {
         .expect("Invalid template");
 
     router
+        .layer(axum::middleware::from_fn(upstream_bytes_middleware))
+        .layer(axum::middleware::from_fn_with_state(
+            env.request_deadline,
+            deadline_middleware,
+        ))
         .layer(axum::middleware::from_fn(accesslog_middleware))
         .layer(axum::middleware::from_fn(trace_middleware))
+        .layer(axum::middleware::from_fn(request_id_middleware))
         .route("/health", get(|| async { StatusCode::OK }))
+        .route("/health/otlp", get(otlp_health))
         .with_state(PyOciState {
             subpath: env.path.clone(),
             max_versions: env.max_versions,
+            empty_list: env.empty_list,
             templates: template_reg,
             bearer_username: env.bearer_username.clone(),
+            proxy_authorization: env.proxy_authorization,
+            trust_proxy: env.trust_proxy,
+            absolute_links: env.absolute_links,
+            collect_garbage: env.collect_garbage,
+            tag_fallback: env.tag_fallback.clone(),
+            annotation_prefixes: env.annotation_prefixes.clone(),
+            strict_artifact_type: env.strict_artifact_type,
+            show_sizes: env.show_sizes,
+            body_limit_wheel: env.body_limit_wheel,
+            body_limit_sdist: env.body_limit_sdist,
+            upload_buffer: env.upload_buffer,
+            max_compression_ratio: env.max_compression_ratio,
+            publish_limit: KeyedLimit::new(env.publish_concurrency),
+            verify_namespace: env.verify_namespace,
+            requires_dist_from_metadata: env.requires_dist_from_metadata,
+            classifier_labels: ClassifierLabels {
+                label_prefix: env.label_classifier.clone(),
+                mapping: env.classifier_labels.clone(),
+            },
+            project_url_annotations: env.project_url_annotations.clone(),
+            layer_media_type: env.layer_media_type.clone(),
+            config_media_type: env.config_media_type.clone(),
+            config_content: env.config_content.clone(),
+            otlp_status: env.otlp_status.clone(),
+            http_client: ClientOptions {
+                http2: env.http2,
+                circuit_breaker_threshold: env.circuit_breaker_threshold,
+                circuit_breaker_cooldown: Duration::from_secs(env.circuit_breaker_cooldown),
+                rate_limit: env.rate_limit,
+                ca_bundle: env.ca_bundle.clone(),
+                tls_insecure: env.tls_insecure,
+                json_limit: env.max_upstream_body,
+                max_manifests: env.max_manifests,
+                upload_chunk_size: env.upload_chunk_size,
+                bearer_token_file: env.bearer_token_file.clone(),
+                bearer_token_hosts: env.bearer_token_hosts.clone(),
+                upstream_concurrency: env.upstream_concurrency,
+                blob_cache_bytes: env.blob_cache_bytes,
+                proxy: env.proxy.clone(),
+                no_proxy: env.no_proxy.clone(),
+                manifest_accept: env.manifest_accept.clone(),
+                manifest_content_type: env.manifest_content_type.clone(),
+                index_content_type: env.index_content_type.clone(),
+                max_redirects: env.max_redirects,
+                pool_max_idle_per_host: env.pool_max_idle_per_host,
+                pool_idle_timeout: env.pool_idle_timeout.map(Duration::from_secs),
+                tag_pagination: env.tag_pagination,
+            }
+            .build()
+            .expect("Failed to build HTTP client"),
         })
 }
 
@@ -147,6 +322,120 @@ async fn cache_control_middleware(
     response
 }
 
+/// Reject path parameters that could make the upstream request escape the registry or repository
+///
+/// The registry is URL-decoded a second time when parsing it, so the parameters are checked
+/// both as received and decoded.
+async fn validate_path_middleware(
+    params: RawPathParams,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    for (key, value) in &params {
+        if let Err(err) = validate_path_param(key, value) {
+            return AppError(err.into()).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Check a single path parameter, see [`validate_path_middleware`]
+fn validate_path_param(key: &str, value: &str) -> Result<(), PyOciError> {
+    let decoded = urlencoding::decode(value).unwrap_or(value.into());
+    if value.contains("..") || decoded.contains("..") {
+        return Err(PyOciError::from((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid {key} '{value}'"),
+        )));
+    }
+    if key == "registry" {
+        if let Some((scheme, _)) = decoded.split_once("://") {
+            if scheme != "http" && scheme != "https" {
+                return Err(PyOciError::from((
+                    StatusCode::BAD_REQUEST,
+                    format!("Unsupported registry scheme '{scheme}', expected 'http' or 'https'"),
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Limit the total time spent handling a request
+///
+/// Responds with 504 when the request is not handled within `deadline`,
+/// this includes all requests to the upstream registry.
+async fn deadline_middleware(
+    State(deadline): State<Option<Duration>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let Some(deadline) = deadline else {
+        return next.run(request).await;
+    };
+    if let Ok(response) = tokio::time::timeout(deadline, next.run(request)).await {
+        response
+    } else {
+        tracing::warn!(
+            "Request exceeded the deadline of {}ms",
+            deadline.as_millis()
+        );
+        (StatusCode::GATEWAY_TIMEOUT, "Request deadline exceeded").into_response()
+    }
+}
+
+/// Header reporting the bytes fetched from the upstream registry for a request
+const X_PYOCI_UPSTREAM_BYTES: HeaderName = HeaderName::from_static("x-pyoci-upstream-bytes");
+
+/// Report the bytes fetched from the upstream registry in the `X-PyOCI-Upstream-Bytes` header
+///
+/// This is the sum of the manifest and blob sizes, blobs served from the blob cache are not included.
+async fn upstream_bytes_middleware(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let (mut response, bytes) = count_upstream_bytes(next.run(request)).await;
+    response
+        .headers_mut()
+        .insert(X_PYOCI_UPSTREAM_BYTES, HeaderValue::from(bytes));
+    response
+}
+
+/// Header used to correlate a request across logs and traces
+const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Return the request ID set by `request_id_middleware`
+fn request_id(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string)
+}
+
+/// Ensure every request has an `X-Request-Id`
+///
+/// An inbound `X-Request-Id` is kept, otherwise a random ID is generated.
+/// The ID is set on the request for the trace and access log middleware
+/// and returned in the response headers.
+async fn request_id_middleware(
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let request_id = match request.headers().get(X_REQUEST_ID) {
+        Some(value) if !value.is_empty() && value.len() <= 128 => value.clone(),
+        _ => {
+            let value = HeaderValue::from_str(&format!("{:032x}", rand::random::<u128>()))
+                .expect("valid request ID");
+            request.headers_mut().insert(X_REQUEST_ID, value.clone());
+            value
+        }
+    };
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(X_REQUEST_ID, request_id);
+    response
+}
+
 /// Log incoming requests
 async fn accesslog_middleware(
     method: axum::http::Method,
@@ -156,9 +445,11 @@ async fn accesslog_middleware(
     request: axum::extract::Request,
     next: axum::middleware::Next,
 ) -> axum::response::Response {
+    let request_id = request_id(&request);
     let response = next.run(request).await;
 
     let status: u16 = response.status().into();
+    let path = redact_path(uri.path());
     let host = host.map(|h| h.to_string());
     let user_agent = user_agent.map(|ua| ua.to_string());
 
@@ -167,24 +458,35 @@ async fn accesslog_middleware(
         "type" = "request",
         status,
         method = method.to_string(),
-        path = uri.path(),
+        path,
         user_agent,
+        request_id,
     );
     response
 }
 
 /// Wrap all incoming requests in a fetch trace
+///
+/// An inbound W3C `traceparent` header is passed on to the span, the trace then continues
+/// the trace of the caller.
 async fn trace_middleware(
     method: axum::http::Method,
     uri: axum::http::Uri,
     request: axum::extract::Request,
     next: axum::middleware::Next,
 ) -> axum::response::Response {
+    let request_id = request_id(&request);
+    let traceparent = request
+        .headers()
+        .get("traceparent")
+        .and_then(|value| value.to_str().ok());
     let span = info_span!(
         "fetch",
-        otel.path = uri.path(),
+        otel.path = redact_path(uri.path()),
         otel.method = method.as_str(),
-        otel.span_kind = "server"
+        otel.request_id = request_id,
+        otel.span_kind = "server",
+        traceparent
     );
     next.run(request).instrument(span).await
 }
@@ -192,7 +494,8 @@ async fn trace_middleware(
 #[derive(serde::Serialize)]
 struct ListPkgTemplateData<'a> {
     files: Vec<Package<'a, WithFileName>>,
-    subpath: Option<String>,
+    /// Prefix for all package links
+    link_base: String,
 }
 
 /// List package request handler
@@ -203,20 +506,92 @@ async fn list_package(
     State(PyOciState {
         subpath,
         max_versions,
+        empty_list,
         bearer_username,
+        proxy_authorization,
+        trust_proxy,
+        absolute_links,
         templates,
+        tag_fallback,
+        annotation_prefixes,
+        strict_artifact_type,
+        show_sizes,
+        http_client,
+        ..
     }): State<PyOciState<'_>>,
     auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
     Path((registry, namespace, package_name)): Path<(String, String, String)>,
-) -> Result<Html<String>, AppError> {
+) -> Result<(HeaderMap, Html<String>), AppError> {
     let package = Package::new(&registry, &namespace, &package_name);
 
-    let mut client = PyOci::new(package.registry()?, get_auth(auth, bearer_username)?);
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    )
+    .with_tag_fallback(tag_fallback)
+    .with_annotation_prefixes(annotation_prefixes)
+    .with_strict_artifact_type(strict_artifact_type)
+    .with_show_sizes(show_sizes);
     let files = client.list_package_files(&package, max_versions).await?;
+    if files.is_empty() && empty_list == EmptyList::NotFound {
+        return Err(PyOciError::from((
+            StatusCode::NOT_FOUND,
+            format!("Package '{}' not found", package.name()),
+        ))
+        .into());
+    }
+
+    // The files of the newest version are listed first
+    let deprecation = files
+        .first()
+        .map(|file| deprecation_headers(file.deprecation()))
+        .unwrap_or_default();
+    let data = ListPkgTemplateData {
+        files,
+        link_base: link_base(&headers, subpath.as_deref(), trust_proxy, absolute_links),
+    };
 
-    let data = ListPkgTemplateData { files, subpath };
+    Ok((deprecation, Html(templates.render("html_list_pkg", &data)?)))
+}
 
-    Ok(Html(templates.render("html_list_pkg", &data)?))
+/// Prefix for the links in the package listing
+///
+/// Without a trusted proxy, links are relative to the host and only include the subpath.
+/// When the proxy is trusted, `X-Forwarded-Prefix` is prepended to the subpath and if
+/// `X-Forwarded-Host` is set, links are made absolute using `X-Forwarded-Proto` as the scheme.
+///
+/// With `absolute_links`, links are always absolute, falling back to the `Host` header
+/// and the `http` scheme when there are no (trusted) forwarded headers.
+fn link_base(
+    headers: &HeaderMap,
+    subpath: Option<&str>,
+    trust_proxy: bool,
+    absolute_links: bool,
+) -> String {
+    let subpath = subpath.unwrap_or_default();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            // Proxies can append to these headers, the first value is set by the outermost proxy
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let forwarded = |name: &str| header(name).filter(|_| trust_proxy);
+    let prefix = forwarded("X-Forwarded-Prefix")
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    let host = forwarded("X-Forwarded-Host")
+        .or_else(|| header(header::HOST.as_str()).filter(|_| absolute_links));
+    match (forwarded("X-Forwarded-Proto"), host) {
+        (Some(proto), Some(host)) => format!("{proto}://{host}{prefix}{subpath}"),
+        (None, Some(host)) if absolute_links => format!("http://{host}{prefix}{subpath}"),
+        (None, Some(host)) => format!("//{host}{prefix}{subpath}"),
+        (_, None) => format!("{prefix}{subpath}"),
+    }
 }
 
 /// JSON response for listing a package
@@ -224,14 +599,94 @@ async fn list_package(
 struct ListJson {
     info: Info,
     #[serde(serialize_with = "ser_releases")]
-    releases: BTreeSet<String>,
+    releases: Vec<String>,
+    /// Files of the latest release
+    urls: Vec<FileJson>,
+}
+
+/// Window of a JSON listing, from the `limit` and `page` or `offset` query parameters
+#[derive(Debug, Default, PartialEq)]
+struct Pagination {
+    /// Number of results to skip
+    offset: usize,
+    /// Maximum number of results, `None` returns all results after `offset`
+    limit: Option<usize>,
+    /// Page requested, starting at 1, `None` when paging by `offset`
+    page: Option<usize>,
+}
+
+impl Pagination {
+    /// `page` is only applied together with `limit`
+    fn from_query(query: Option<&str>) -> Result<Self, PyOciError> {
+        let mut page: Option<usize> = None;
+        let mut offset = None;
+        let mut limit = None;
+        for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            // `offset` can be 0, the others must be a positive integer
+            let minimum = usize::from(key != "offset");
+            let parse = || {
+                value
+                    .parse()
+                    .ok()
+                    .filter(|value| *value >= minimum)
+                    .ok_or_else(|| {
+                        PyOciError::from((
+                            StatusCode::BAD_REQUEST,
+                            format!("Invalid '{key}' query parameter"),
+                        ))
+                    })
+            };
+            match key.as_ref() {
+                "page" => page = Some(parse()?),
+                "offset" => offset = Some(parse()?),
+                "limit" => limit = Some(parse()?),
+                _ => {}
+            }
+        }
+        let offset = match (page, offset, limit) {
+            (Some(_), Some(_), _) => {
+                return Err(PyOciError::from((
+                    StatusCode::BAD_REQUEST,
+                    "Use either the 'page' or the 'offset' query parameter",
+                )))
+            }
+            (Some(page), None, Some(limit)) => (page - 1).saturating_mul(limit),
+            (_, offset, _) => offset.unwrap_or_default(),
+        };
+        Ok(Self {
+            offset,
+            limit,
+            page: page.filter(|_| limit.is_some()),
+        })
+    }
+
+    /// Query of the next window, `None` if there are no results after this window
+    fn next(&self, total: usize) -> Option<String> {
+        let limit = self.limit?;
+        if total <= self.offset.saturating_add(limit) {
+            return None;
+        }
+        Some(match self.page {
+            Some(page) => format!("page={}&limit={limit}", page + 1),
+            None => format!("offset={}&limit={limit}", self.offset + limit),
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct FileJson {
+    filename: String,
+    /// Hex encoded digests of the file, keyed by hash algorithm
+    digests: BTreeMap<&'static str, String>,
+    /// Annotations of the file, including the labels set when publishing
+    annotations: BTreeMap<String, String>,
 }
 
 /// Serializer for the releases field
 ///
 /// The releases serialize to {"<version>":[]} with a key for every version.
 /// The list is kept empty so we don't need to query for each version manifest
-fn ser_releases<S>(releases: &BTreeSet<String>, serializer: S) -> Result<S::Ok, S::Error>
+fn ser_releases<S>(releases: &[String], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -246,6 +701,8 @@ where
 struct Info {
     name: String,
     project_urls: HashMap<String, String>,
+    /// `Requires-Dist` of the newest version, `null` when it has no dependencies
+    requires_dist: Option<Vec<String>>,
 }
 
 /// List package JSON request handler
@@ -255,62 +712,273 @@ struct Info {
 #[tracing::instrument(skip_all)]
 async fn list_package_json(
     State(PyOciState {
-        bearer_username, ..
+        subpath,
+        bearer_username,
+        proxy_authorization,
+        trust_proxy,
+        absolute_links,
+        tag_fallback,
+        annotation_prefixes,
+        strict_artifact_type,
+        http_client,
+        ..
     }): State<PyOciState<'_>>,
     auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
     Path((registry, namespace, package_name)): Path<(String, String, String)>,
-) -> Result<Json<ListJson>, AppError> {
+    RawQuery(query): RawQuery,
+) -> Result<(HeaderMap, Json<ListJson>), AppError> {
+    let pagination = Pagination::from_query(query.as_deref())?;
     let package = Package::new(&registry, &namespace, &package_name);
 
-    let mut client = PyOci::new(package.registry()?, get_auth(auth, bearer_username)?);
-    let versions = client.list_package_versions(&package).await?;
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    )
+    .with_tag_fallback(tag_fallback)
+    .with_annotation_prefixes(annotation_prefixes)
+    .with_strict_artifact_type(strict_artifact_type);
+    let versions = client
+        .list_package_versions(&package, pagination.offset, pagination.limit)
+        .await?;
 
     let mut project_urls = HashMap::new();
-    if let Some(last_version) = versions.last() {
-        if let Some(package) = client
-            .package_info_for_ref(&package, last_version)
-            .await?
-            .first()
-            .map(Package::project_urls)
-            .unwrap()
-        {
-            project_urls = package;
+    let mut requires_dist = None;
+    let mut urls = Vec::new();
+    if let Some(latest) = &versions.latest {
+        let files = client.package_info_for_ref(&package, latest).await?;
+        if let Some(urls) = files.first().and_then(Package::project_urls) {
+            project_urls = urls;
         }
+        requires_dist = files.first().and_then(Package::requires_dist);
+        urls = files
+            .iter()
+            .map(|file| FileJson {
+                filename: file.filename(),
+                digests: file.digests(),
+                annotations: file.annotations().clone(),
+            })
+            .collect();
+    }
+
+    let mut response_headers = HeaderMap::new();
+    if let Some(query) = pagination.next(versions.total) {
+        let next = format!(
+            "<{}{}/json?{query}>; rel=\"next\"",
+            link_base(&headers, subpath.as_deref(), trust_proxy, absolute_links),
+            package.package_uri(),
+        );
+        response_headers.insert(header::LINK, HeaderValue::from_str(&next)?);
     }
     let response = ListJson {
         info: Info {
             name: package.name().to_string(),
             project_urls,
+            requires_dist,
         },
-        releases: versions,
+        releases: versions.versions,
+        urls,
+    };
+
+    Ok((response_headers, Json(response)))
+}
+
+/// Redirect to the file of the newest version matching the `arch` query parameter
+///
+/// `arch` matches the end of the file architecture, `.whl` matches any wheel.
+#[tracing::instrument(skip_all)]
+async fn latest_package(
+    State(PyOciState {
+        subpath,
+        max_versions,
+        bearer_username,
+        proxy_authorization,
+        trust_proxy,
+        absolute_links,
+        tag_fallback,
+        strict_artifact_type,
+        http_client,
+        ..
+    }): State<PyOciState<'_>>,
+    auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
+    Path((registry, namespace, package_name)): Path<(String, String, String)>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, AppError> {
+    let Some(arch) = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .find_map(|(key, value)| (key == "arch").then_some(value.into_owned()))
+        .filter(|arch| !arch.is_empty())
+    else {
+        return Err(
+            PyOciError::from((StatusCode::BAD_REQUEST, "Missing 'arch' query parameter")).into(),
+        );
+    };
+    let package = Package::new(&registry, &namespace, &package_name);
+
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    )
+    .with_tag_fallback(tag_fallback)
+    .with_strict_artifact_type(strict_artifact_type);
+    let Some(file) = client
+        .latest_package_file(&package, &arch, max_versions)
+        .await?
+    else {
+        return Err(PyOciError::from((
+            StatusCode::NOT_FOUND,
+            format!("No '{arch}' file found for '{package_name}'"),
+        ))
+        .into());
     };
 
-    Ok(Json(response))
+    let location = format!(
+        "{}{}",
+        link_base(&headers, subpath.as_deref(), trust_proxy, absolute_links),
+        file.py_uri()
+    );
+    Ok((StatusCode::FOUND, [(header::LOCATION, location)]).into_response())
 }
 
 /// Download package request handler
 #[tracing::instrument(skip_all)]
 async fn download_package(
     State(PyOciState {
-        bearer_username, ..
+        bearer_username,
+        proxy_authorization,
+        strict_artifact_type,
+        http_client,
+        ..
     }): State<PyOciState<'_>>,
     Path((registry, namespace, package_name, filename)): Path<(String, String, String, String)>,
     auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
     let package = Package::from_filename(&registry, &namespace, &package_name, &filename)?;
 
-    let mut client = PyOci::new(package.registry()?, get_auth(auth, bearer_username)?);
-    let data = client.download_package_file(&package).await?.bytes_stream();
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    )
+    .with_strict_artifact_type(strict_artifact_type);
+    let (response, deprecation) = client.download_package_file(&package).await?;
 
     Ok((
+        deprecation_headers(&deprecation),
         [(
             header::CONTENT_DISPOSITION,
             format!("attachment; filename=\"{}\"", package.filename()),
         )],
-        Body::from_stream(data),
+        Body::from_stream(response.bytes_stream()),
     ))
 }
 
+/// Tag package version request handler
+///
+/// Points the `alias` tag at the `ImageIndex` of an existing version, for example `latest`.
+#[tracing::instrument(skip_all)]
+async fn tag_package_version(
+    State(PyOciState {
+        bearer_username,
+        proxy_authorization,
+        strict_artifact_type,
+        http_client,
+        ..
+    }): State<PyOciState<'_>>,
+    Path((registry, namespace, name, version, alias)): Path<(
+        String,
+        String,
+        String,
+        String,
+        String,
+    )>,
+    auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
+) -> Result<String, AppError> {
+    let package = Package::new(&registry, &namespace, &name);
+
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    )
+    .with_strict_artifact_type(strict_artifact_type);
+    client
+        .tag_package_version(&package, &version, &alias)
+        .await?;
+    Ok("Tagged".into())
+}
+
+/// Version index request handler
+///
+/// Returns the `ImageIndex` of a version as pulled from the registry,
+/// to inspect the annotations and manifest descriptors.
+#[tracing::instrument(skip_all)]
+async fn package_version_index(
+    State(PyOciState {
+        bearer_username,
+        proxy_authorization,
+        http_client,
+        ..
+    }): State<PyOciState<'_>>,
+    Path((registry, namespace, name, version)): Path<(String, String, String, String)>,
+    auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
+) -> Result<Json<ImageIndex>, AppError> {
+    let package = Package::new(&registry, &namespace, &name);
+
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    );
+    Ok(Json(client.version_index(&package, &version).await?))
+}
+
+/// Response of the file exists endpoint
+#[derive(Serialize)]
+struct Exists {
+    exists: bool,
+}
+
+/// Check if a package file exists, without downloading it
+///
+/// Useful in CI to decide whether to publish a file.
+#[tracing::instrument(skip_all)]
+async fn package_file_exists(
+    State(PyOciState {
+        bearer_username,
+        proxy_authorization,
+        strict_artifact_type,
+        http_client,
+        ..
+    }): State<PyOciState<'_>>,
+    Path((registry, namespace, name, filename)): Path<(String, String, String, String)>,
+    auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
+) -> Result<Json<Exists>, AppError> {
+    let package = Package::from_filename(&registry, &namespace, &name, &filename)?;
+
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    )
+    .with_strict_artifact_type(strict_artifact_type);
+    let exists = client.package_file_exists(&package).await?;
+    Ok(Json(Exists { exists }))
+}
+
+/// Report whether the OTLP exporter is running and the outcome of its last flush
+async fn otlp_health(
+    State(PyOciState { otlp_status, .. }): State<PyOciState<'_>>,
+) -> Json<OtlpReport> {
+    Json(otlp_status.report())
+}
+
 /// Delete package version request handler
 ///
 /// This endpoint does not exist as an official spec in the python ecosystem
@@ -318,62 +986,296 @@ async fn download_package(
 #[tracing::instrument(skip_all)]
 async fn delete_package_version(
     State(PyOciState {
-        bearer_username, ..
+        bearer_username,
+        proxy_authorization,
+        strict_artifact_type,
+        collect_garbage,
+        http_client,
+        ..
     }): State<PyOciState<'_>>,
     Path((registry, namespace, name, version)): Path<(String, String, String, String)>,
     auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
 ) -> Result<String, AppError> {
     let package = Package::new(&registry, &namespace, &name).with_oci_file(&version, "");
 
-    let mut client = PyOci::new(package.registry()?, get_auth(auth, bearer_username)?);
-    client.delete_package_version(&package).await?;
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    )
+    .with_strict_artifact_type(strict_artifact_type);
+    client
+        .delete_package_version(&package, collect_garbage)
+        .await?;
     Ok("Deleted".into())
 }
 
+/// Delete the pre-releases of a package
+///
+/// Deleting all versions at once is not supported, this requires both the `pre_only=1` and
+/// `confirm=1` query parameters.
+#[tracing::instrument(skip_all)]
+async fn delete_prereleases(
+    State(PyOciState {
+        bearer_username,
+        proxy_authorization,
+        tag_fallback,
+        strict_artifact_type,
+        collect_garbage,
+        http_client,
+        ..
+    }): State<PyOciState<'_>>,
+    Path((registry, namespace, name)): Path<(String, String, String)>,
+    RawQuery(query): RawQuery,
+    auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
+) -> Result<String, AppError> {
+    let mut pre_only = false;
+    let mut confirm = false;
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        match key.as_ref() {
+            "pre_only" => pre_only = value == "1",
+            "confirm" => confirm = value == "1",
+            _ => {}
+        }
+    }
+    if !pre_only {
+        return Err(PyOciError::from((
+            StatusCode::BAD_REQUEST,
+            "Only deleting pre-releases is supported, set the 'pre_only=1' query parameter",
+        ))
+        .into());
+    }
+    if !confirm {
+        return Err(PyOciError::from((
+            StatusCode::BAD_REQUEST,
+            format!("Set the 'confirm=1' query parameter to delete the pre-releases of '{name}'"),
+        ))
+        .into());
+    }
+    let package = Package::new(&registry, &namespace, &name);
+
+    let mut client = PyOci::new(
+        package.registry()?,
+        get_auth(auth, &headers, proxy_authorization, bearer_username)?,
+        http_client,
+    )
+    .with_tag_fallback(tag_fallback)
+    .with_strict_artifact_type(strict_artifact_type);
+    let deleted = client.delete_prereleases(&package, collect_garbage).await?;
+    Ok(format!("Deleted {} pre-releases", deleted.len()))
+}
+
 /// Publish package request handler
 ///
 /// ref: <https://docs.pypi.org/api/upload/>
 #[tracing::instrument(skip_all)]
 async fn publish_package(
     State(PyOciState {
-        bearer_username, ..
+        bearer_username,
+        proxy_authorization,
+        body_limit_wheel,
+        body_limit_sdist,
+        upload_buffer,
+        max_compression_ratio,
+        publish_limit,
+        verify_namespace,
+        requires_dist_from_metadata,
+        classifier_labels,
+        project_url_annotations,
+        layer_media_type,
+        config_media_type,
+        config_content,
+        http_client,
+        ..
     }): State<PyOciState<'_>>,
     Path((registry, namespace)): Path<(String, String)>,
     auth: Option<TypedHeader<AuthHeader>>,
+    headers: HeaderMap,
     multipart: Multipart,
 ) -> Result<String, AppError> {
-    let form_data = UploadForm::from_multipart(multipart).await?;
+    // Rejected before reading the form, so the upload is not buffered for nothing
+    let Some(_permit) = publish_limit.try_acquire(&format!("{registry}/{namespace}")) else {
+        return Err(PyOciError::from((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Too many simultaneous publishes to '{namespace}', retry later"),
+        ))
+        .into());
+    };
+    let mut form_data =
+        UploadForm::from_multipart(multipart, upload_buffer, &classifier_labels).await?;
+    // Annotations from headers take precedence over the classifiers of the package
+    form_data.labels.extend(header_annotations(&headers)?);
+
+    // Check all files before publishing any of them
+    let mut files_requires_dist = Vec::new();
+    for file in &form_data.files {
+        let package = Package::from_filename(
+            &registry,
+            &namespace,
+            &form_data.package_name,
+            &file.filename,
+        )?;
+        package.verify_content(file.content.head())?;
+        let (kind, limit) = if package.oci_architecture() == ".tar.gz" {
+            ("source distributions", body_limit_sdist)
+        } else {
+            ("wheels", body_limit_wheel)
+        };
+        if limit > 0 && file.content.len() > limit as u64 {
+            return Err(PyOciError::from((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "Content of '{}' exceeds the limit of {limit} bytes for {kind}",
+                    package.filename()
+                ),
+            ))
+            .into());
+        }
+        if max_compression_ratio > 0 {
+            archive::verify_compression_ratio(&package, &file.content, max_compression_ratio)
+                .await?;
+        }
+        // The form fields are provided by the client, the wheel holds the actual dependencies.
+        // Source distributions don't have a standard place for them, so they get none.
+        files_requires_dist.push(if !requires_dist_from_metadata {
+            form_data.requires_dist.clone()
+        } else if package.oci_architecture() == ".tar.gz" {
+            vec![]
+        } else {
+            archive::wheel_requires_dist(&package, &file.content).await?
+        });
+    }
+    let auth = get_auth(auth, &headers, proxy_authorization, bearer_username)?;
+    // `If-None-Match: *` only creates new files, an existing file fails the precondition
+    let create_only = headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value == "*");
+
+    // Each file is verified against its own sha256_digest while publishing
+    for (file, requires_dist) in form_data.files.into_iter().zip(files_requires_dist) {
+        let package = Package::from_filename(
+            &registry,
+            &namespace,
+            &form_data.package_name,
+            &file.filename,
+        )?;
+        let mut client = PyOci::new(package.registry()?, auth.clone(), http_client.clone())
+            .with_verify_repository(verify_namespace)
+            .with_project_url_annotations(project_url_annotations.clone())
+            .with_config_media_type(config_media_type.clone())
+            .with_config_content(config_content.clone())
+            .with_create_only(create_only);
+        client
+            .publish_package_file(
+                &package,
+                file.content,
+                &layer_media_type,
+                form_data.labels.clone(),
+                file.sha256,
+                form_data.project_urls.clone(),
+                requires_dist,
+                form_data.upload_time,
+            )
+            .await?;
+    }
+    Ok("Published".into())
+}
 
-    let package = Package::from_filename(
-        &registry,
-        &namespace,
-        &form_data.package_name,
-        &form_data.filename,
-    )?;
-    let mut client = PyOci::new(package.registry()?, get_auth(auth, bearer_username)?);
+/// Collect the annotations set with `X-PyOCI-Annotation-<key>: <value>` headers
+///
+/// Header names are case-insensitive, so the keys are always lowercase.
+/// Annotations used internally by `PyOCI` (`com.pyoci.*`) can not be set,
+/// except for the deprecation annotations, see [`Deprecation`].
+fn header_annotations(headers: &HeaderMap) -> Result<HashMap<String, String>, PyOciError> {
+    let mut annotations = HashMap::new();
+    for (name, value) in headers {
+        let Some(key) = name.as_str().strip_prefix("x-pyoci-annotation-") else {
+            continue;
+        };
+        let deprecation = matches!(key, "com.pyoci.deprecated" | "com.pyoci.sunset");
+        if key.starts_with("com.pyoci.") && !deprecation {
+            return Err(PyOciError::from((
+                StatusCode::BAD_REQUEST,
+                format!("Annotation '{key}' is reserved for PyOCI"),
+            )));
+        }
+        let Ok(value) = value.to_str() else {
+            return Err(PyOciError::from((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid value for annotation '{key}'"),
+            )));
+        };
+        let timestamp = OffsetDateTime::parse(value, &Rfc3339).is_ok();
+        if deprecation && !timestamp && (key == "com.pyoci.sunset" || value != "true") {
+            return Err(PyOciError::from((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid value for annotation '{key}', expected an RFC 3339 timestamp"),
+            )));
+        }
+        debug!("Found annotation header '{key}={value}'");
+        annotations.insert(key.to_string(), value.to_string());
+    }
+    Ok(annotations)
+}
 
-    client
-        .publish_package_file(
-            &package,
-            form_data.content,
-            form_data.labels,
-            form_data.sha256,
-            form_data.project_urls,
-        )
-        .await?;
-    Ok("Published".into())
+/// `Deprecation` and `Sunset` response headers of a deprecated package file
+///
+/// A deprecation timestamp is sent as `@<unix timestamp>` (RFC 9745),
+/// the sunset as HTTP-date (RFC 8594).
+fn deprecation_headers(deprecation: &Deprecation) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let timestamp = |value: &str| OffsetDateTime::parse(value, &Rfc3339).ok();
+    if let Some(deprecated) = &deprecation.deprecated {
+        let value = match timestamp(deprecated) {
+            Some(timestamp) => format!("@{}", timestamp.unix_timestamp()),
+            None => "true".to_string(),
+        };
+        headers.insert("Deprecation", HeaderValue::from_str(&value).unwrap());
+    }
+    let http_date = time::format_description::parse_borrowed::<2>(
+        "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT",
+    )
+    .expect("valid HTTP-date format");
+    if let Some(sunset) = deprecation
+        .sunset
+        .as_deref()
+        .and_then(timestamp)
+        .and_then(|sunset| {
+            sunset
+                .to_offset(time::UtcOffset::UTC)
+                .format(&http_date)
+                .ok()
+        })
+    {
+        headers.insert("Sunset", HeaderValue::from_str(&sunset).unwrap());
+    }
+    headers
 }
 
 /// Parse the Authentication header, if provided.
 ///
+/// If pyoci was started with `PYOCI_PROXY_AUTHORIZATION` the `Proxy-Authorization` header
+/// is used when there is no Authorization header.
+///
 /// If pyoci was started with `PYOCI_BEARER_USERNAME` it will be compared
 /// with the provided username, if there is a match the password is used as the
 /// Bearer token directly.
 fn get_auth(
     auth: Option<TypedHeader<AuthHeader>>,
+    headers: &HeaderMap,
+    proxy_authorization: bool,
     bearer_username: Option<String>,
 ) -> Result<Option<AuthHeader>, PyOciError> {
-    if let Some(TypedHeader(mut auth)) = auth {
+    let auth = match auth {
+        Some(TypedHeader(auth)) => Some(auth),
+        None if proxy_authorization => {
+            AuthHeader::decode(&mut headers.get_all(PROXY_AUTHORIZATION).iter()).ok()
+        }
+        None => None,
+    };
+    if let Some(mut auth) = auth {
         // An Authorization header is provided
         if let Some(bearer_username) = bearer_username {
             // PYOCI_BEARER_USERNAME is set
@@ -396,9 +1298,9 @@ impl MaybeEmpty for String {
     }
 }
 
-impl MaybeEmpty for Bytes {
+impl MaybeEmpty for SpooledContent {
     fn empty(&self) -> bool {
-        self.is_empty()
+        self.len() == 0
     }
 }
 
@@ -408,26 +1310,107 @@ impl MaybeEmpty for Bytes {
 #[derive(Debug, Eq, PartialEq)]
 struct UploadForm {
     package_name: String,
-    filename: String,
-    content: Vec<u8>,
+    /// Package files, one per "content" form-field
+    files: Vec<UploadFile>,
     labels: HashMap<String, String>,
-    sha256: Option<String>,
     project_urls: HashMap<String, String>,
+    /// `Requires-Dist` entries of the package, one per "`requires_dist`" form-field
+    requires_dist: Vec<String>,
+    /// Original upload time of the package, used when migrating packages
+    upload_time: Option<UtcDateTime>,
+}
+
+/// Converts the classifiers of an upload into labels
+#[derive(Debug, Clone)]
+struct ClassifierLabels {
+    /// Classifiers `<label_prefix> :: <Key> :: <Value>` are added as label `<Key>=<Value>`
+    label_prefix: String,
+    /// Classifiers `<classifier> :: ... :: <Value>` are added as label `<key>=<Value>`,
+    /// as `(classifier, key)` pairs
+    mapping: Vec<(String, String)>,
+}
+
+impl Default for ClassifierLabels {
+    fn default() -> Self {
+        Self {
+            label_prefix: LABEL_CLASSIFIER.to_string(),
+            mapping: Vec::new(),
+        }
+    }
+}
+
+impl ClassifierLabels {
+    /// Collect the labels from the classifiers
+    ///
+    /// Labels of the label prefix take precedence over mapped classifiers, multiple mapped
+    /// classifiers for the same key are joined with ", ".
+    /// Any other classifier will be discarded.
+    fn labels(&self, classifiers: &[String]) -> HashMap<String, String> {
+        let label_prefix = format!("{} :: ", self.label_prefix);
+        let mut labels = HashMap::new();
+        let mut mapped: HashMap<&str, Vec<&str>> = HashMap::new();
+        for classifier in classifiers {
+            if let Some(label) = classifier.strip_prefix(&label_prefix) {
+                if let [key, value] = label.splitn(2, " :: ").collect::<Vec<_>>()[..] {
+                    labels.insert(key.to_string(), value.to_string());
+                    debug!("Found label '{key}={value}'");
+                } else {
+                    debug!("Invalid PyOci label '{label}'");
+                }
+            } else if let Some((_, key)) = self.mapping.iter().find(|(prefix, _)| {
+                classifier
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.starts_with(" :: "))
+            }) {
+                let value = classifier.rsplit(" :: ").next().unwrap_or_default();
+                debug!("Mapped classifier '{classifier}' to label '{key}={value}'");
+                mapped.entry(key).or_default().push(value);
+            } else {
+                debug!("Discarding field 'classifiers': {classifier}");
+            }
+        }
+        for (key, values) in mapped {
+            labels
+                .entry(key.to_string())
+                .or_insert_with(|| values.join(", "));
+        }
+        labels
+    }
+}
+
+/// Package file of the upload form
+#[derive(Debug, Eq, PartialEq)]
+struct UploadFile {
+    filename: String,
+    content: SpooledContent,
+    /// Declared digest of the content, the "`sha256_digest`" form-field at the same position
+    sha256: Option<String>,
 }
 
 impl UploadForm {
     /// Convert a Multipart into an `UploadForm`
     ///
+    /// Multiple files can be uploaded with multiple "content" form-fields, the n-th
+    /// "`sha256_digest`" form-field belongs to the n-th file.
+    ///
+    /// The content is kept in memory up to `upload_buffer` bytes, larger content is spooled to a
+    /// temporary file.
+    ///
     /// Returns `MultiPartError` if the form can't be parsed
-    async fn from_multipart(mut multipart: Multipart) -> anyhow::Result<Self> {
+    async fn from_multipart(
+        mut multipart: Multipart,
+        upload_buffer: usize,
+        classifier_labels: &ClassifierLabels,
+    ) -> anyhow::Result<Self> {
         let mut action = None;
         let mut protocol_version = None;
-        let mut content = None;
+        let mut contents = Vec::new();
         let mut package_name = None;
-        let mut filename = None;
-        let mut sha256 = None;
-        let mut labels = HashMap::new();
+        let mut sha256 = Vec::new();
+        let mut upload_time = None;
+        let mut classifiers = Vec::new();
         let mut project_urls = HashMap::new();
+        let mut requires_dist = Vec::new();
 
         // Extract the fields from the form
         while let Some(field) = multipart.next_field().await? {
@@ -439,57 +1422,74 @@ impl UploadForm {
                 ":action" => action = Some(field.text().await?),
                 "protocol_version" => protocol_version = Some(field.text().await?),
                 "content" => {
-                    filename = field.file_name().map(ToString::to_string);
-                    content = Some(field.bytes().await?);
+                    let filename = field.file_name().map(ToString::to_string);
+                    let mut spool = Spool::new(upload_buffer);
+                    let mut field = field;
+                    while let Some(chunk) = field.chunk().await? {
+                        spool.write(&chunk).await?;
+                    }
+                    contents.push((filename, spool.finish().await?));
                 }
                 "name" => package_name = Some(field.text().await?),
-                "classifiers" => {
-                    let classifier = field.text().await?;
-                    Self::parse_classifier(&classifier, &mut labels);
-                }
+                "classifiers" => classifiers.push(field.text().await?),
                 "project_urls" => {
                     let project_url = field.text().await?;
                     Self::parse_project_url(&project_url, &mut project_urls);
                 }
-                "sha256_digest" => sha256 = Some(field.text().await?),
+                "requires_dist" => requires_dist.push(field.text().await?),
+                "sha256_digest" => sha256.push(field.text().await?),
+                "upload_time" => {
+                    upload_time = Some(Self::parse_upload_time(&field.text().await?)?);
+                }
                 name => debug!("Discarding field '{name}': {}", field.text().await?),
             }
         }
 
         Self::validate_action(action.as_deref())?;
         Self::validate_protocol(protocol_version.as_deref())?;
-        let content = Self::not_empty(content, "content")?;
-        let filename = Self::not_empty(filename, "filename")?;
+        if contents.is_empty() {
+            return Err(
+                PyOciError::from((StatusCode::BAD_REQUEST, "Form missing 'content'")).into(),
+            );
+        }
+        if sha256.len() > contents.len() {
+            return Err(PyOciError::from((
+                StatusCode::BAD_REQUEST,
+                "Form has more 'sha256_digest' than 'content' form-fields",
+            ))
+            .into());
+        }
+        let mut sha256 = sha256.into_iter();
+        let mut files = Vec::with_capacity(contents.len());
+        for (filename, content) in contents {
+            files.push(UploadFile {
+                content: Self::not_empty(Some(content), "content")?,
+                filename: Self::not_empty(filename, "filename")?,
+                sha256: sha256.next(),
+            });
+        }
         let package_name = Self::not_empty(package_name, "name")?;
 
         Ok(Self {
             package_name,
-            filename,
-            content: content.into(),
-            labels,
-            sha256,
+            files,
+            labels: classifier_labels.labels(&classifiers),
             project_urls,
+            requires_dist,
+            upload_time,
         })
     }
 
-    #[allow(clippy::doc_markdown)]
-    /// Parse a classifier and insert it into the labels map
-    ///
-    /// Classifier format:
-    /// `"PyOCI :: Label :: <Key> :: <Value>"`
-    ///
-    /// Any other format will be discarded
-    fn parse_classifier(classifier: &str, labels: &mut HashMap<String, String>) {
-        if let Some(label) = classifier.strip_prefix("PyOCI :: Label :: ") {
-            if let [key, value] = label.splitn(2, " :: ").collect::<Vec<_>>()[..] {
-                labels.insert(key.to_string(), value.to_string());
-                debug!("Found label '{key}={value}'");
-            } else {
-                debug!("Invalid PyOci label '{label}'");
-            }
-        } else {
-            debug!("Discarding field 'classifiers': {classifier}");
-        }
+    /// Parse the RFC3339 "`upload_time`" form-field
+    fn parse_upload_time(value: &str) -> Result<UtcDateTime, PyOciError> {
+        OffsetDateTime::parse(value, &Rfc3339)
+            .map(OffsetDateTime::to_utc)
+            .map_err(|_| {
+                PyOciError::from((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid 'upload_time' form-field '{value}', expected RFC3339"),
+                ))
+            })
     }
 
     /// Parse a project URL and insert it into the project URLs map
@@ -580,8 +1580,51 @@ mod tests {
         },
     };
     use pretty_assertions::assert_eq;
+    use test_case::test_case;
     use tower::ServiceExt;
 
+    /// sha256 of the file in the `ImageManifest` returned by [`mock_file_manifests`]
+    const FILE_SHA256: &str = "b7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0";
+
+    /// Mock the `ImageManifest` of the files of package `name`, expecting `hits` requests
+    ///
+    /// Pulled to find the sha256 of files without the `com.pyoci.sha256_digest` annotation.
+    async fn mock_file_manifests(
+        server: &mut mockito::ServerGuard,
+        name: &str,
+        hits: usize,
+    ) -> mockito::Mock {
+        server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(format!("^/v2/{name}/manifests/sha256:")),
+            )
+            .with_status(200)
+            .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+            .with_body(formatdoc!(
+                r#"{{
+                  "schemaVersion": 2,
+                  "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                  "artifactType": "application/pyoci.package.v1",
+                  "config": {{
+                    "mediaType": "application/vnd.oci.empty.v1+json",
+                    "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+                    "size": 2
+                  }},
+                  "layers": [
+                    {{
+                      "mediaType": "application/pyoci.package.v1",
+                      "digest": "sha256:{FILE_SHA256}",
+                      "size": 123456
+                    }}
+                  ]
+                }}"#
+            ))
+            .expect(hits)
+            .create_async()
+            .await
+    }
+
     #[test]
     fn test_get_auth() {
         // Basic
@@ -589,6 +1632,8 @@ mod tests {
             Some(TypedHeader(AuthHeader::Basic(Authorization::basic(
                 "user", "pass",
             )))),
+            &HeaderMap::new(),
+            false,
             None,
         )
         .unwrap();
@@ -601,6 +1646,8 @@ mod tests {
             Some(TypedHeader(AuthHeader::Basic(Authorization::basic(
                 "__user__", "pass",
             )))),
+            &HeaderMap::new(),
+            false,
             Some("__user__".to_string()),
         )
         .unwrap();
@@ -614,6 +1661,8 @@ mod tests {
             Some(TypedHeader(AuthHeader::Bearer(
                 Authorization::bearer("foobar").unwrap(),
             ))),
+            &HeaderMap::new(),
+            false,
             None,
         )
         .unwrap();
@@ -625,10 +1674,150 @@ mod tests {
 
     #[test]
     fn test_get_auth_none() {
-        let auth = get_auth(None, None).unwrap();
+        let auth = get_auth(None, &HeaderMap::new(), true, None).unwrap();
+        assert_eq!(auth, None);
+    }
+
+    #[test]
+    fn test_get_auth_proxy_authorization() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            PROXY_AUTHORIZATION,
+            HeaderValue::from_static("Basic dXNlcjpwYXNz"),
+        );
+
+        // Used when the Authorization header is missing
+        let auth = get_auth(None, &headers, true, None).unwrap();
+        assert_eq!(
+            auth,
+            Some(AuthHeader::Basic(Authorization::basic("user", "pass")))
+        );
+        // Converted into Bearer like the Authorization header
+        let auth = get_auth(None, &headers, true, Some("user".to_string())).unwrap();
+        assert_eq!(
+            auth,
+            Some(AuthHeader::Bearer(Authorization::bearer("pass").unwrap()))
+        );
+        // Authorization header takes precedence
+        let auth = get_auth(
+            Some(TypedHeader(AuthHeader::Bearer(
+                Authorization::bearer("foobar").unwrap(),
+            ))),
+            &headers,
+            true,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            auth,
+            Some(AuthHeader::Bearer(Authorization::bearer("foobar").unwrap()))
+        );
+        // Ignored unless enabled
+        let auth = get_auth(None, &headers, false, None).unwrap();
         assert_eq!(auth, None);
     }
 
+    #[test_case(false, false, &[], None, ""; "untrusted, no headers")]
+    #[test_case(false, false, &[], Some("/foo"), "/foo"; "untrusted, subpath")]
+    #[test_case(false, false, &[("X-Forwarded-Proto", "https"), ("X-Forwarded-Host", "pyoci.example"), ("X-Forwarded-Prefix", "/proxy")], Some("/foo"), "/foo"; "untrusted, headers ignored")]
+    #[test_case(true, false, &[], Some("/foo"), "/foo"; "trusted, no headers")]
+    #[test_case(true, false, &[("X-Forwarded-Proto", "https"), ("X-Forwarded-Host", "pyoci.example")], None, "https://pyoci.example"; "trusted, proto and host")]
+    #[test_case(true, false, &[("X-Forwarded-Proto", "https"), ("X-Forwarded-Host", "pyoci.example"), ("X-Forwarded-Prefix", "/proxy/")], Some("/foo"), "https://pyoci.example/proxy/foo"; "trusted, all headers")]
+    #[test_case(true, false, &[("X-Forwarded-Host", "pyoci.example")], None, "//pyoci.example"; "trusted, host only")]
+    #[test_case(true, false, &[("X-Forwarded-Prefix", "/proxy")], None, "/proxy"; "trusted, prefix only")]
+    #[test_case(true, false, &[("X-Forwarded-Proto", "https, http"), ("X-Forwarded-Host", "pyoci.example, internal")], None, "https://pyoci.example"; "trusted, multiple proxies")]
+    #[test_case(false, true, &[("Host", "pyoci.example")], Some("/foo"), "http://pyoci.example/foo"; "absolute, host")]
+    #[test_case(false, true, &[("Host", "pyoci.example"), ("X-Forwarded-Proto", "https"), ("X-Forwarded-Host", "proxy.example")], None, "http://pyoci.example"; "absolute, untrusted headers ignored")]
+    #[test_case(true, true, &[("Host", "pyoci.example"), ("X-Forwarded-Proto", "https")], None, "https://pyoci.example"; "absolute, trusted proto")]
+    #[test_case(true, true, &[("Host", "internal"), ("X-Forwarded-Host", "pyoci.example")], None, "http://pyoci.example"; "absolute, trusted host")]
+    #[test_case(false, true, &[], Some("/foo"), "/foo"; "absolute, no host")]
+    fn link_base(
+        trust_proxy: bool,
+        absolute_links: bool,
+        headers: &[(&str, &str)],
+        subpath: Option<&str>,
+        expected: &str,
+    ) {
+        let mut header_map = HeaderMap::new();
+        for (key, value) in headers {
+            header_map.insert(
+                http::HeaderName::from_bytes(key.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        assert_eq!(
+            super::link_base(&header_map, subpath, trust_proxy, absolute_links),
+            expected
+        );
+    }
+
+    #[test_case(true, "http://pyoci.example/"; "absolute")]
+    #[test_case(false, "/"; "relative")]
+    #[tokio::test]
+    async fn list_package_absolute_links(absolute_links: bool, link_base: &str) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let index = serde_json::json!({
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.index.v1+json",
+          "artifactType": ARTIFACT_TYPE,
+          "manifests": [{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "digest": digest("manifest"),
+            "size": 6,
+            "platform": {"architecture": ".tar.gz", "os": "any"},
+            "annotations": {"com.pyoci.sha256_digest": "1234"}
+          }]
+        });
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/test_package", "tags": ["1.0.0"]}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.0.0")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(index.to_string())
+                .create_async()
+                .await,
+        ];
+
+        let env = Env {
+            absolute_links,
+            ..Env::default()
+        };
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .header("Host", "pyoci.example")
+            .body(Body::empty())
+            .unwrap();
+        let response = pyoci_service(&env).oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        let href = format!(
+            "href=\"{link_base}{encoded_url}/mockserver/test-package/test_package-1.0.0.tar.gz#sha256=1234\""
+        );
+        assert!(body.contains(&href), "{href} not in {body}");
+    }
+
     #[tokio::test]
     async fn upload_form_missing_action() {
         let form = "--foobar\r\n\
@@ -644,7 +1833,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect_err("Expected Error")
             .downcast::<PyOciError>()
@@ -668,7 +1857,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect_err("Expected Error")
             .downcast::<PyOciError>()
@@ -692,7 +1881,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect_err("Expected Error")
             .downcast::<PyOciError>()
@@ -720,7 +1909,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect_err("Expected Error")
             .downcast::<PyOciError>()
@@ -748,7 +1937,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect_err("Expected Error")
             .downcast::<PyOciError>()
@@ -780,7 +1969,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect_err("Expected Error")
             .downcast::<PyOciError>()
@@ -812,7 +2001,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect_err("Expected Error")
             .downcast::<PyOciError>()
@@ -844,7 +2033,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect_err("Expected Error")
             .downcast::<PyOciError>()
@@ -881,16 +2070,18 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect("Valid Form");
-        assert_eq!(result.filename, "foobar-1.0.0.tar.gz");
         assert_eq!(
-            result.content,
-            String::from("someawesomepackagedata").into_bytes()
+            result.files,
+            vec![UploadFile {
+                filename: "foobar-1.0.0.tar.gz".to_string(),
+                content: String::from("someawesomepackagedata").into_bytes().into(),
+                sha256: None,
+            }]
         );
         assert_eq!(result.labels, HashMap::new());
-        assert_eq!(result.sha256, None);
     }
 
     #[tokio::test]
@@ -933,7 +2124,7 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect("Valid Form");
         assert_eq!(
@@ -948,6 +2139,74 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Check if labels are only taken from classifiers with the configured prefix
+    fn classifier_labels_custom_prefix() {
+        let classifier_labels = ClassifierLabels {
+            label_prefix: "Acme :: Label".to_string(),
+            ..ClassifierLabels::default()
+        };
+        let labels = classifier_labels.labels(&[
+            "Acme :: Label :: team :: platform".to_string(),
+            "PyOCI :: Label :: other-label :: foobar".to_string(),
+        ]);
+        assert_eq!(
+            labels,
+            HashMap::from([("team".to_string(), "platform".to_string())])
+        );
+    }
+
+    #[test]
+    /// Check if License classifiers are mapped to the licenses annotation
+    fn classifier_labels_license() {
+        let classifier_labels = ClassifierLabels {
+            mapping: vec![(
+                "License".to_string(),
+                "org.opencontainers.image.licenses".to_string(),
+            )],
+            ..ClassifierLabels::default()
+        };
+        let labels = classifier_labels.labels(&[
+            "License :: OSI Approved :: MIT License".to_string(),
+            "License :: OSI Approved :: Apache Software License".to_string(),
+            "Licensed :: Not a license".to_string(),
+            "PyOCI :: Label :: other-label :: foobar".to_string(),
+        ]);
+        assert_eq!(
+            labels,
+            HashMap::from([
+                (
+                    "org.opencontainers.image.licenses".to_string(),
+                    "MIT License, Apache Software License".to_string()
+                ),
+                ("other-label".to_string(), "foobar".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    /// Check if an explicit label takes precedence over a mapped classifier
+    fn classifier_labels_precedence() {
+        let classifier_labels = ClassifierLabels {
+            mapping: vec![(
+                "License".to_string(),
+                "org.opencontainers.image.licenses".to_string(),
+            )],
+            ..ClassifierLabels::default()
+        };
+        let labels = classifier_labels.labels(&[
+            "PyOCI :: Label :: org.opencontainers.image.licenses :: MIT".to_string(),
+            "License :: OSI Approved :: MIT License".to_string(),
+        ]);
+        assert_eq!(
+            labels,
+            HashMap::from([(
+                "org.opencontainers.image.licenses".to_string(),
+                "MIT".to_string()
+            )])
+        );
+    }
+
     #[tokio::test]
     /// Check if project URLs are properly parsed
     async fn upload_form_project_urls() {
@@ -984,89 +2243,138 @@ mod tests {
             .unwrap();
         let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        let result = UploadForm::from_multipart(multipart)
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
             .await
             .expect("Valid Form");
         assert_eq!(
             result,
             UploadForm {
                 package_name: "foobar".to_string(),
-                filename: "foobar-1.0.0.tar.gz".to_string(),
-                content: String::from("someawesomepackagedata").into_bytes(),
+                files: vec![UploadFile {
+                    filename: "foobar-1.0.0.tar.gz".to_string(),
+                    content: String::from("someawesomepackagedata").into_bytes().into(),
+                    sha256: None,
+                }],
                 labels: HashMap::new(),
-                sha256: None,
                 project_urls: HashMap::from([
                     (
                         "Repository".to_string(),
                         "https://github/allexveldman/pyoci".to_string()
                     ),
                     ("Homepage".to_string(), "https://pyoci.com".to_string())
-                ])
+                ]),
+                requires_dist: vec![],
+                upload_time: None,
             }
         );
     }
 
     #[tokio::test]
-    async fn cache_control_unmatched() {
-        let router = router(&Env::default());
-
-        let req = Request::builder()
-            .method("GET")
-            .uri("/foo")
-            .body(Body::empty())
-            .unwrap();
-        let response = router.oneshot(req).await.unwrap();
-
-        assert_eq!(response.status(), StatusCode::NOT_FOUND);
-        assert_eq!(
-            response.headers().get("Cache-Control"),
-            Some(&HeaderValue::from_str("max-age=604800, public").unwrap())
-        );
-    }
-
-    #[tokio::test]
-    async fn cache_control_root() {
-        let router = router(&Env::default());
-
-        let req = Request::builder()
-            .method("GET")
-            .uri("/")
-            .body(Body::empty())
+    /// Check if the n-th sha256_digest belongs to the n-th file
+    async fn upload_form_multiple_files() {
+        let form = "--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"sha256_digest\"\r\n\
+            \r\n\
+            1234\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            someawesomepackagedata\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"sha256_digest\"\r\n\
+            \r\n\
+            5678\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0-py3-none-any.whl\"\r\n\
+            \r\n\
+            otherawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req: Request<Body> = Request::builder()
+            .method("POST")
+            .uri("/pypi/pytest/")
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(form.to_string().into())
             .unwrap();
-        let response = router.oneshot(req).await.unwrap();
+        let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
+            .await
+            .expect("Valid Form");
         assert_eq!(
-            response.headers().get("Cache-Control"),
-            Some(&HeaderValue::from_str("max-age=604800, public").unwrap())
+            result.files,
+            vec![
+                UploadFile {
+                    filename: "foobar-1.0.0.tar.gz".to_string(),
+                    content: String::from("someawesomepackagedata").into_bytes().into(),
+                    sha256: Some("1234".to_string()),
+                },
+                UploadFile {
+                    filename: "foobar-1.0.0-py3-none-any.whl".to_string(),
+                    content: String::from("otherawesomepackagedata").into_bytes().into(),
+                    sha256: Some("5678".to_string()),
+                },
+            ]
         );
     }
 
     #[tokio::test]
-    async fn publish_package_body_limit() {
-        let env = Env {
-            body_limit: 10,
-            ..Env::default()
-        };
-        let service = pyoci_service(&env);
-
-        let form = "Exceeds max body limit";
-        let req = Request::builder()
+    async fn upload_form_too_many_digests() {
+        let form = "--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"sha256_digest\"\r\n\
+            \r\n\
+            1234\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"sha256_digest\"\r\n\
+            \r\n\
+            5678\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            someawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req: Request<Body> = Request::builder()
             .method("POST")
             .uri("/pypi/pytest/")
             .header("Content-Type", "multipart/form-data; boundary=foobar")
-            .body(form.into())
+            .body(form.to_string().into())
             .unwrap();
-        let response = service.oneshot(req).await.unwrap();
+        let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
+            .await
+            .expect_err("Expected Error")
+            .downcast::<PyOciError>()
+            .expect("Expected PyOciError");
+        assert_eq!(result.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            result.message,
+            "Form has more 'sha256_digest' than 'content' form-fields"
+        );
     }
 
     #[tokio::test]
-    async fn publish_package_content_filename_invalid() {
-        let env = Env::default();
-        let service = pyoci_service(&env);
-
+    /// Check if each Requires-Dist form-field is collected
+    async fn upload_form_requires_dist() {
         let form = "--foobar\r\n\
             Content-Disposition: form-data; name=\":action\"\r\n\
             \r\n\
@@ -1080,40 +2388,1235 @@ mod tests {
             \r\n\
             foobar\r\n\
             --foobar\r\n\
-            Content-Disposition: form-data; name=\"content\"; filename=\".env\"\r\n\
+            Content-Disposition: form-data; name=\"requires_dist\"\r\n\
+            \r\n\
+            requests>=2.0\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"requires_dist\"\r\n\
+            \r\n\
+            pytest; extra == \"test\"\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
             \r\n\
             someawesomepackagedata\r\n\
             --foobar--\r\n";
-        let req = Request::builder()
+        let req: Request<Body> = Request::builder()
             .method("POST")
             .uri("/pypi/pytest/")
             .header("Content-Type", "multipart/form-data; boundary=foobar")
-            .body(form.into())
+            .body(form.to_string().into())
             .unwrap();
-        let response = service.oneshot(req).await.unwrap();
+        let multipart = Multipart::from_request(req, &()).await.unwrap();
 
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-        let body = String::from_utf8(
-            to_bytes(response.into_body(), usize::MAX)
-                .await
-                .unwrap()
-                .into(),
-        )
-        .unwrap();
-        assert_eq!(&body, "Unkown filetype '.env'");
+        let result = UploadForm::from_multipart(multipart, 0, &ClassifierLabels::default())
+            .await
+            .expect("Valid Form");
+        assert_eq!(
+            result.requires_dist,
+            vec![
+                "requests>=2.0".to_string(),
+                "pytest; extra == \"test\"".to_string()
+            ]
+        );
     }
 
-    #[tokio::test]
-    async fn publish_package() {
-        let mut server = mockito::Server::new_async().await;
-        let url = server.url();
-        let encoded_url = urlencoding::encode(&url).into_owned();
+    #[test_case("2020-01-02T03:04:05+01:00"; "with offset")]
+    #[test_case("2020-01-02T02:04:05Z"; "UTC")]
+    fn upload_form_upload_time(value: &str) {
+        let result = UploadForm::parse_upload_time(value).unwrap();
+        assert_eq!(result.unix_timestamp(), 1_577_930_645);
+    }
 
-        // Set timestamp to fixed time
-        crate::time::set_timestamp(1_732_134_216);
+    #[test_case("2020-01-02"; "date only")]
+    #[test_case("yesterday"; "invalid")]
+    fn upload_form_upload_time_invalid(value: &str) {
+        let err = UploadForm::parse_upload_time(value).unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            err.message,
+            format!("Invalid 'upload_time' form-field '{value}', expected RFC3339")
+        );
+    }
 
-        let mocks = vec![
-            // Mock the server, in order of expected requests
+    #[tokio::test]
+    async fn request_id_inbound() {
+        let router = router(&Env::default());
+
+        let req = Request::builder()
+            .method("GET")
+            .uri("/foo")
+            .header("X-Request-Id", "my-request-id")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(req).await.unwrap();
+
+        assert_eq!(
+            response.headers().get("X-Request-Id"),
+            Some(&HeaderValue::from_str("my-request-id").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn request_id_generated() {
+        let router = router(&Env::default());
+
+        let req = Request::builder()
+            .method("GET")
+            .uri("/foo")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(req).await.unwrap();
+
+        let request_id = response
+            .headers()
+            .get("X-Request-Id")
+            .expect("X-Request-Id header")
+            .to_str()
+            .unwrap();
+        assert_eq!(request_id.len(), 32);
+        assert!(request_id.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[tokio::test]
+    async fn cache_control_unmatched() {
+        let router = router(&Env::default());
+
+        let req = Request::builder()
+            .method("GET")
+            .uri("/foo")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers().get("Cache-Control"),
+            Some(&HeaderValue::from_str("max-age=604800, public").unwrap())
+        );
+    }
+
+    #[test_case("PATCH", "/pypi/pytest/pytest/pytest-1.0.0.tar.gz", "GET,HEAD,DELETE"; "file")]
+    #[test_case("POST", "/pypi/pytest/pytest/", "GET,HEAD,DELETE"; "list")]
+    #[test_case("GET", "/pypi/pytest/", "POST"; "publish")]
+    #[tokio::test]
+    async fn method_not_allowed(method: &str, uri: &str, allow: &str) {
+        let router = router(&Env::default());
+
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            response.headers().get("Allow"),
+            Some(&HeaderValue::from_str(allow).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn cache_control_root() {
+        let router = router(&Env::default());
+
+        let req = Request::builder()
+            .method("GET")
+            .uri("/")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            response.headers().get("Cache-Control"),
+            Some(&HeaderValue::from_str("max-age=604800, public").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_package_body_limit() {
+        let env = Env {
+            body_limit: 10,
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+
+        let form = "Exceeds max body limit";
+        let req = Request::builder()
+            .method("POST")
+            .uri("/pypi/pytest/")
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(form.into())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn publish_package_content_filename_invalid() {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+
+        let form = "--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\".env\"\r\n\
+            \r\n\
+            someawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri("/pypi/pytest/")
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(form.into())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+        assert_eq!(&body, "Unkown filetype '.env'");
+    }
+
+    #[test_case("foobar-1.0.0-py3-none-any.whl", b"PK\x03\x04someawesomepackagedata", StatusCode::PAYLOAD_TOO_LARGE, "Content of 'foobar-1.0.0-py3-none-any.whl' exceeds the limit of 10 bytes for wheels"; "wheel too large")]
+    #[test_case("foobar-1.0.0.tar.gz", b"\x1f\x8bsomeawesomepackagedata", StatusCode::OK, "Published"; "sdist within limit")]
+    #[tokio::test]
+    async fn publish_package_body_limit_per_type(
+        filename: &str,
+        content: &[u8],
+        expected_status: StatusCode,
+        expected_body: &str,
+    ) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let encoded_url = urlencoding::encode(&url).into_owned();
+        tokio::spawn(async move { axum::serve(listener, crate::registry::router()).await });
+
+        let env = Env {
+            body_limit_wheel: 10,
+            body_limit_sdist: 100,
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+
+        let mut form = format!(
+            "--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"{filename}\"\r\n\
+            \r\n"
+        )
+        .into_bytes();
+        form.extend_from_slice(content);
+        form.extend_from_slice(b"\r\n--foobar--\r\n");
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/{encoded_url}/mockserver/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(form))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(status, expected_status);
+        assert_eq!(body, expected_body);
+    }
+
+    /// Decompression bombs are rejected before anything is sent to the registry
+    #[tokio::test]
+    async fn publish_package_compression_ratio() {
+        let env = Env {
+            max_compression_ratio: 100,
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+
+        let mut form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n"
+            .to_vec();
+        // Gzip stream declaring 4GB of decompressed content in its trailer
+        form.extend_from_slice(b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03compressed");
+        form.extend_from_slice(b"\x00\x00\x00\x00\xff\xff\xff\xff");
+        form.extend_from_slice(b"\r\n--foobar--\r\n");
+        let req = Request::builder()
+            .method("POST")
+            .uri("/pypi/pytest/")
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(form))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+        assert_eq!(
+            &body,
+            "Content of 'foobar-1.0.0.tar.gz' decompresses to 4294967295 bytes, exceeding the maximum compression ratio of 100"
+        );
+    }
+
+    /// A wheel without METADATA is rejected when the dependencies are read from it
+    #[tokio::test]
+    async fn publish_package_requires_dist_from_metadata() {
+        let env = Env {
+            requires_dist_from_metadata: true,
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"requires_dist\"\r\n\
+            \r\n\
+            requests>=2.0\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0-py3-none-any.whl\"\r\n\
+            \r\n\
+            PK\x03\x04not a zip archive\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri("/pypi/pytest/")
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(&form[..]))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+        assert_eq!(
+            &body,
+            "Content of 'foobar-1.0.0-py3-none-any.whl' is not a valid zip archive"
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_package_content_mismatch() {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+
+        let form = "--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0-py3-none-any.whl\"\r\n\
+            \r\n\
+            someawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri("/pypi/pytest/")
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(form.into())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+        assert_eq!(
+            &body,
+            "Content of 'foobar-1.0.0-py3-none-any.whl' is not a zip archive"
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_package() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        // Set timestamp to fixed time
+        crate::time::set_timestamp(1_732_134_216);
+
+        let mocks = vec![
+            // Mock the server, in order of expected requests
+            // IndexManifest does not yet exist
+            server
+                .mock("GET", "/v2/mockserver/foobar/manifests/1.0.0")
+                .with_status(404)
+                .create_async()
+                .await,
+            // HEAD request to check if blob exists for:
+            // - layer
+            // - config
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/foobar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(404)
+                .create_async()
+                .await,
+            // POST request with blob for layer
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .with_status(202) // ACCEPTED
+                .with_header(
+                    "Location",
+                    &format!("{url}/v2/mockserver/foobar/blobs/uploads/1?_state=uploading"),
+                )
+                .create_async()
+                .await,
+            server
+                .mock("PUT", "/v2/mockserver/foobar/blobs/uploads/1?_state=uploading&digest=sha256%3A7244ca446253b2193b5eb95510df109d42b5d40da8e64c6609e66facc397a8dc")
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // POST request with blob for config
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .with_status(202) // ACCEPTED
+                .with_header(
+                    "Location",
+                    &format!("{url}/v2/mockserver/foobar/blobs/uploads/2?_state=uploading"),
+                )
+                .create_async()
+                .await,
+            server
+                .mock("PUT", "/v2/mockserver/foobar/blobs/uploads/2?_state=uploading&digest=sha256%3A44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a")
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // PUT request to create Manifest
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/sha256:d7842782406bbdcb856242ba6f358031939c56e5f2284a9fb4580f0eb0899bd6")
+                .match_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // PUT request to create Index, including the package digests
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/1.0.0")
+                .match_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+                .match_body(mockito::Matcher::PartialJsonString(
+                    r#"{"manifests":[{"annotations":{
+                        "com.pyoci.sha256_digest":"7244ca446253b2193b5eb95510df109d42b5d40da8e64c6609e66facc397a8dc",
+                        "com.pyoci.sha512_digest":"6acaa382cf5499a0f9efe4f3e91d642b369cc0012c3508f693607b4211b5c562c4d3ee33e8fdeb81496d49819b2b329990650a68c702aa2b9e9189e1acf53791"
+                    }}]}"#
+                        .to_string(),
+                ))
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            server
+                .mock("GET", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            \x1f\x8bsomeawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/{encoded_url}/mockserver/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(&form[..]))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(&body, "Published");
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    /// The layer uses the configured media type, the artifact type is unchanged
+    async fn publish_package_layer_media_type() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/foobar/manifests/1.0.0")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/foobar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(200)
+                .create_async()
+                .await,
+            // PUT request to create Manifest, with the configured layer media type
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(
+                        r"/v2/mockserver/foobar/manifests/sha256:.+".to_string(),
+                    ),
+                )
+                .match_body(mockito::Matcher::PartialJsonString(
+                    r#"{
+                        "artifactType":"application/pyoci.package.v1",
+                        "layers":[{"mediaType":"application/x-python-sdist"}]
+                    }"#
+                    .to_string(),
+                ))
+                .with_status(201)
+                .create_async()
+                .await,
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/1.0.0")
+                .match_body(mockito::Matcher::PartialJsonString(
+                    r#"{"artifactType":"application/pyoci.package.v1"}"#.to_string(),
+                ))
+                .with_status(201)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env {
+            layer_media_type: "application/x-python-sdist".to_string(),
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            \x1f\x8bsomeawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/{encoded_url}/mockserver/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(&form[..]))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    /// A failing upstream request names the step that failed
+    async fn publish_package_push_manifest_failed() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/foobar/manifests/1.0.0")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/foobar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(200)
+                .create_async()
+                .await,
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/sha256:d7842782406bbdcb856242ba6f358031939c56e5f2284a9fb4580f0eb0899bd6")
+                .with_status(500)
+                .with_body("Internal registry error")
+                .create_async()
+                .await,
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/1.0.0")
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        // Set timestamp to fixed time, the manifest digest depends on it
+        crate::time::set_timestamp(1_732_134_216);
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            \x1f\x8bsomeawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/{encoded_url}/mockserver/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(&form[..]))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            &body,
+            "Failed to push manifest 'mockserver/foobar:sha256:d7842782406bbdcb856242ba6f358031939c56e5f2284a9fb4580f0eb0899bd6': Internal registry error"
+        );
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test_case(None, StatusCode::CONFLICT; "without precondition")]
+    #[test_case(Some("*"), StatusCode::PRECONDITION_FAILED; "create only")]
+    #[tokio::test]
+    /// An existing file is rejected with 412 when only creating is requested
+    async fn publish_package_if_none_match(if_none_match: Option<&str>, expected: StatusCode) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let index = r#"{
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.index.v1+json",
+          "artifactType": "application/pyoci.package.v1",
+          "manifests": [
+            {
+              "mediaType": "application/vnd.oci.image.manifest.v1+json",
+              "digest": "sha256:6b95ce6324c6745397ccdb66864a73598b4df8989b1c0c8f0f386d85e2640d47",
+              "size": 406,
+              "platform": {
+                "architecture": ".tar.gz",
+                "os": "any"
+              }
+            }
+          ]
+        }"#;
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/foobar/manifests/1.0.0")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(index)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/foobar/blobs/.+".to_string()),
+                )
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            \x1f\x8bsomeawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let mut req = Request::builder()
+            .method("POST")
+            .uri(format!("/{encoded_url}/mockserver/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar");
+        if let Some(if_none_match) = if_none_match {
+            req = req.header("If-None-Match", if_none_match);
+        }
+        let req = req.body(Body::from(&form[..])).unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            &body,
+            "Platform '.tar.gz' already exists for version '1.0.0'"
+        );
+        assert_eq!(status, expected);
+    }
+
+    #[tokio::test]
+    /// Labels, dependencies and the upload time are added to the manifest descriptor in the index
+    async fn publish_package_labels() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let mocks = vec![
+            // IndexManifest does not yet exist
+            server
+                .mock("GET", "/v2/mockserver/foobar/manifests/1.0.0")
+                .with_status(404)
+                .create_async()
+                .await,
+            // Blobs do not yet exist
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/foobar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(404)
+                .create_async()
+                .await,
+            // Upload layer and config blobs
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .expect(2)
+                .with_status(202) // ACCEPTED
+                .with_header(
+                    "Location",
+                    &format!("{url}/v2/mockserver/foobar/blobs/uploads/1"),
+                )
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(
+                        r"/v2/mockserver/foobar/blobs/uploads/1\?digest=.+".to_string(),
+                    ),
+                )
+                .expect(2)
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // PUT request to create Manifest
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(
+                        r"/v2/mockserver/foobar/manifests/sha256:.+".to_string(),
+                    ),
+                )
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // PUT request to create Index, including the label
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/1.0.0")
+                .match_body(mockito::Matcher::PartialJsonString(
+                    r#"{"manifests":[{"annotations":{
+                        "org.opencontainers.image.source":"https://github.com/allexveldman/pyoci",
+                        "org.opencontainers.image.created":"2020-01-02T02:04:05Z",
+                        "com.pyoci.requires_dist":"[\"requests>=2.0\"]"
+                    }}]}"#
+                        .to_string(),
+                ))
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"classifiers\"\r\n\
+            \r\n\
+            PyOCI :: Label :: org.opencontainers.image.source :: https://github.com/allexveldman/pyoci\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"requires_dist\"\r\n\
+            \r\n\
+            requests>=2.0\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"upload_time\"\r\n\
+            \r\n\
+            2020-01-02T03:04:05+01:00\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            \x1f\x8bsomeawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/{encoded_url}/mockserver/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(&form[..]))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    /// Annotations set through headers are added to the manifest and override the classifiers
+    async fn publish_package_header_annotations() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let mocks = vec![
+            // IndexManifest does not yet exist
+            server
+                .mock("GET", "/v2/mockserver/foobar/manifests/1.0.0")
+                .with_status(404)
+                .create_async()
+                .await,
+            // Blobs do not yet exist
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/foobar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(404)
+                .create_async()
+                .await,
+            // Upload layer and config blobs
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .expect(2)
+                .with_status(202) // ACCEPTED
+                .with_header(
+                    "Location",
+                    &format!("{url}/v2/mockserver/foobar/blobs/uploads/1"),
+                )
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(
+                        r"/v2/mockserver/foobar/blobs/uploads/1\?digest=.+".to_string(),
+                    ),
+                )
+                .expect(2)
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // PUT request to create Manifest, including the annotations
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(
+                        r"/v2/mockserver/foobar/manifests/sha256:.+".to_string(),
+                    ),
+                )
+                .match_body(mockito::Matcher::PartialJsonString(
+                    r#"{"annotations":{
+                        "org.opencontainers.image.source":"https://example.com/override",
+                        "org.example.pipeline":"1234"
+                    }}"#
+                    .to_string(),
+                ))
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // PUT request to create Index, including the annotations
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/1.0.0")
+                .match_body(mockito::Matcher::PartialJsonString(
+                    r#"{"manifests":[{"annotations":{
+                        "org.opencontainers.image.source":"https://example.com/override",
+                        "org.example.pipeline":"1234"
+                    }}]}"#
+                        .to_string(),
+                ))
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"classifiers\"\r\n\
+            \r\n\
+            PyOCI :: Label :: org.opencontainers.image.source :: https://github.com/allexveldman/pyoci\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            \x1f\x8bsomeawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/{encoded_url}/mockserver/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .header(
+                "X-PyOCI-Annotation-org.opencontainers.image.source",
+                "https://example.com/override",
+            )
+            .header("X-PyOCI-Annotation-org.example.pipeline", "1234")
+            .body(Body::from(&form[..]))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    /// Internal annotations can not be set through headers
+    async fn publish_package_header_annotations_reserved() {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            \x1f\x8bsomeawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri("/foo.example/mockserver/")
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .header("X-PyOCI-Annotation-com.pyoci.sha256_digest", "1234")
+            .body(Body::from(&form[..]))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body,
+            "Annotation 'com.pyoci.sha256_digest' is reserved for PyOCI"
+        );
+    }
+
+    #[test_case("com.pyoci.deprecated", "true", true; "deprecated")]
+    #[test_case("com.pyoci.deprecated", "2026-01-01T00:00:00Z", true; "deprecated timestamp")]
+    #[test_case("com.pyoci.deprecated", "yes", false; "deprecated invalid")]
+    #[test_case("com.pyoci.sunset", "2026-06-30T00:00:00Z", true; "sunset")]
+    #[test_case("com.pyoci.sunset", "true", false; "sunset invalid")]
+    fn header_annotations_deprecation(key: &str, value: &str, valid: bool) {
+        let headers = HeaderMap::from_iter([(
+            HeaderName::from_str(&format!("x-pyoci-annotation-{key}")).unwrap(),
+            HeaderValue::from_str(value).unwrap(),
+        )]);
+
+        let result = header_annotations(&headers);
+        if valid {
+            assert_eq!(
+                result.unwrap(),
+                HashMap::from([(key.to_string(), value.to_string())])
+            );
+        } else {
+            assert_eq!(
+                result.unwrap_err().message,
+                format!("Invalid value for annotation '{key}', expected an RFC 3339 timestamp")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn deprecated_package_headers() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let annotations = serde_json::json!({
+            "com.pyoci.sha256_digest": "1234",
+            "com.pyoci.deprecated": "2026-01-01T00:00:00Z",
+            "com.pyoci.sunset": "2026-06-30T00:00:00Z"
+        });
+        let index = serde_json::json!({
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.index.v1+json",
+          "artifactType": ARTIFACT_TYPE,
+          "manifests": [{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "digest": digest("manifest"),
+            "size": 6,
+            "platform": {"architecture": ".tar.gz", "os": "any"},
+            "annotations": annotations
+          }]
+        });
+        let manifest = serde_json::json!({
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.manifest.v1+json",
+          "artifactType": ARTIFACT_TYPE,
+          "config": {
+            "mediaType": "application/vnd.oci.empty.v1+json",
+            "digest": digest("{}"),
+            "size": 2
+          },
+          "layers": [{"mediaType": ARTIFACT_TYPE, "digest": digest("foo"), "size": 3}],
+          "annotations": annotations
+        });
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/test_package", "tags": ["1.0.0"]}"#)
+                .create_async()
+                .await,
+            // Pulled when listing and downloading
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.0.0")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(index.to_string())
+                .expect(2)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    format!(
+                        "/v2/mockserver/test_package/manifests/{}",
+                        digest("manifest")
+                    )
+                    .as_str(),
+                )
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(manifest.to_string())
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    format!("/v2/mockserver/test_package/blobs/{}", digest("foo")).as_str(),
+                )
+                .with_status(200)
+                .with_body("foo")
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        for uri in [
+            format!("/{encoded_url}/mockserver/test-package/"),
+            format!("/{encoded_url}/mockserver/test-package/test_package-1.0.0.tar.gz"),
+        ] {
+            let req = Request::builder()
+                .method("GET")
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = pyoci_service(&env).oneshot(req).await.unwrap();
+
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get("Deprecation"),
+                Some(&HeaderValue::from_static("@1767225600"))
+            );
+            assert_eq!(
+                response.headers().get("Sunset"),
+                Some(&HeaderValue::from_static("Tue, 30 Jun 2026 00:00:00 GMT"))
+            );
+        }
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn publish_package_subpath() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        // Set timestamp to fixed time
+        crate::time::set_timestamp(1_732_134_216);
+
+        let mocks = vec![
+            // Mock the server, in order of expected requests
             // IndexManifest does not yet exist
             server
                 .mock("GET", "/v2/mockserver/foobar/manifests/1.0.0")
@@ -1143,7 +3646,7 @@ mod tests {
                 .create_async()
                 .await,
             server
-                .mock("PUT", "/v2/mockserver/foobar/blobs/uploads/1?_state=uploading&digest=sha256%3Ab7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0")
+                .mock("PUT", "/v2/mockserver/foobar/blobs/uploads/1?_state=uploading&digest=sha256%3A7244ca446253b2193b5eb95510df109d42b5d40da8e64c6609e66facc397a8dc")
                 .with_status(201) // CREATED
                 .create_async()
                 .await,
@@ -1155,59 +3658,338 @@ mod tests {
                     "Location",
                     &format!("{url}/v2/mockserver/foobar/blobs/uploads/2?_state=uploading"),
                 )
-                .create_async()
-                .await,
+                .create_async()
+                .await,
+            server
+                .mock("PUT", "/v2/mockserver/foobar/blobs/uploads/2?_state=uploading&digest=sha256%3A44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a")
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // PUT request to create Manifest
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/sha256:d7842782406bbdcb856242ba6f358031939c56e5f2284a9fb4580f0eb0899bd6")
+                .match_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            // PUT request to create Index
+            server
+                .mock("PUT", "/v2/mockserver/foobar/manifests/1.0.0")
+                .match_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+            server
+                .mock("GET", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env {
+            path: Some("/foo".to_string()),
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+
+        let form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            foobar\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
+            \r\n\
+            \x1f\x8bsomeawesomepackagedata\r\n\
+            --foobar--\r\n";
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/foo/{encoded_url}/mockserver/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(&form[..]))
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(&body, "Published");
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[test_case(""; "trailing slash")]
+    #[test_case("index.html"; "index html")]
+    #[tokio::test]
+    async fn list_package(index: &str) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let tags_list = TagListBuilder::default()
+            .name("test-package")
+            .tags(vec![
+                "0.1.0".to_string(),
+                // max_versions is set to 2, so this version will be excluded
+                "0.0.1".to_string(),
+                "1.2.3".to_string(),
+            ])
+            .build()
+            .unwrap();
+
+        let index_010 = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![DescriptorBuilder::default()
+                .media_type("application/vnd.oci.image.manifest.v1+json")
+                .digest(digest("FooBar"))
+                .size(6_u64)
+                .platform(
+                    PlatformBuilder::default()
+                        .architecture(Arch::Other(".tar.gz".to_string()))
+                        .os(Os::Other("any".to_string()))
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let index_123 = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![DescriptorBuilder::default()
+                .media_type("application/vnd.oci.image.manifest.v1+json")
+                .digest(digest("FooBar"))
+                .size(6_u64)
+                .platform(
+                    PlatformBuilder::default()
+                        .architecture(Arch::Other(".tar.gz".to_string()))
+                        .os(Os::Other("any".to_string()))
+                        .build()
+                        .unwrap(),
+                )
+                .annotations(HashMap::from([(
+                    "com.pyoci.sha256_digest".to_string(),
+                    "1234".to_string(),
+                )]))
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let mocks = vec![
+            // List tags
+            server
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
+                .create_async()
+                .await,
+            // Pull 0.1.0 manifest
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/0.1.0")
+                .match_header(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index_010).unwrap())
+                .create_async()
+                .await,
+            // Pull 1.2.3 manifest
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.2.3")
+                .match_header(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index_123).unwrap())
+                .create_async()
+                .await,
+            // Files without sha256 annotation
+            mock_file_manifests(&mut server, "mockserver/test_package", 1).await,
+            server
+                .mock("GET", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env {
+            max_versions: 2,
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/{encoded_url}/mockserver/test-package/{index}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            formatdoc!(
+                r#"
+                <!DOCTYPE html>
+                <html lang="en">
+                <head>
+                    <meta charset="UTF-8">
+                    <title>PyOCI</title>
+                </head>
+                <body>
+                    <a href="/{encoded_url}/mockserver/test-package/test_package-1.2.3.tar.gz#sha256=1234">test_package-1.2.3.tar.gz</a>
+                    <a href="/{encoded_url}/mockserver/test-package/test_package-0.1.0.tar.gz#sha256={FILE_SHA256}">test_package-0.1.0.tar.gz</a>
+                </body>
+                </html>
+                "#
+            )
+        );
+    }
+
+    /// An empty tag list lists the `tag_fallback` tags that exist
+    #[tokio::test]
+    async fn list_package_tag_fallback() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let index = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![DescriptorBuilder::default()
+                .media_type("application/vnd.oci.image.manifest.v1+json")
+                .digest(digest("FooBar"))
+                .size(6_u64)
+                .platform(
+                    PlatformBuilder::default()
+                        .architecture(Arch::Other(".tar.gz".to_string()))
+                        .os(Os::Other("any".to_string()))
+                        .build()
+                        .unwrap(),
+                )
+                .annotations(HashMap::from([(
+                    "com.pyoci.sha256_digest".to_string(),
+                    "1234".to_string(),
+                )]))
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let mocks = vec![
             server
-                .mock("PUT", "/v2/mockserver/foobar/blobs/uploads/2?_state=uploading&digest=sha256%3A44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a")
-                .with_status(201) // CREATED
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/test_package", "tags": []}"#)
                 .create_async()
                 .await,
-            // PUT request to create Manifest
+            // Probed by the tag fallback, then pulled for the files
             server
-                .mock("PUT", "/v2/mockserver/foobar/manifests/sha256:e281659053054737342fd0c74a7605c4678c227db1e073260b44f845dfdf535a")
-                .match_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
-                .with_status(201) // CREATED
+                .mock("GET", "/v2/mockserver/test_package/manifests/latest")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index).unwrap())
+                .expect(2)
                 .create_async()
                 .await,
-            // PUT request to create Index
             server
-                .mock("PUT", "/v2/mockserver/foobar/manifests/1.0.0")
-                .match_header("Content-Type", "application/vnd.oci.image.index.v1+json")
-                .with_status(201) // CREATED
+                .mock("GET", "/v2/mockserver/test_package/manifests/stable")
+                .with_status(404)
                 .create_async()
                 .await,
+        ];
+
+        let env = Env {
+            tag_fallback: vec!["latest".to_string(), "stable".to_string()],
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.matches("<a href").count(), 1);
+        assert!(body
+            .contains(r#"test_package-latest.tar.gz#sha256=1234">test_package-latest.tar.gz</a>"#));
+    }
+
+    #[test_case(EmptyList::Empty, StatusCode::OK; "empty")]
+    #[test_case(EmptyList::NotFound, StatusCode::NOT_FOUND; "not found")]
+    #[tokio::test]
+    async fn list_package_empty(empty_list: EmptyList, expected: StatusCode) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let mocks = vec![
             server
-                .mock("GET", mockito::Matcher::Any)
-                .expect(0)
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/test_package", "tags": []}"#)
                 .create_async()
                 .await,
         ];
 
-        let env = Env::default();
+        let env = Env {
+            empty_list,
+            ..Env::default()
+        };
         let service = pyoci_service(&env);
-
-        let form = "--foobar\r\n\
-            Content-Disposition: form-data; name=\":action\"\r\n\
-            \r\n\
-            file_upload\r\n\
-            --foobar\r\n\
-            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
-            \r\n\
-            1\r\n\
-            --foobar\r\n\
-            Content-Disposition: form-data; name=\"name\"\r\n\
-            \r\n\
-            foobar\r\n\
-            --foobar\r\n\
-            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
-            \r\n\
-            someawesomepackagedata\r\n\
-            --foobar--\r\n";
         let req = Request::builder()
-            .method("POST")
-            .uri(format!("/{encoded_url}/mockserver/"))
-            .header("Content-Type", "multipart/form-data; boundary=foobar")
-            .body(form.into())
+            .method("GET")
+            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
 
@@ -1223,83 +4005,215 @@ mod tests {
         for mock in mocks {
             mock.assert_async().await;
         }
-        assert_eq!(&body, "Published");
-        assert_eq!(status, StatusCode::OK);
+        assert_eq!(status, expected);
+        if empty_list == EmptyList::NotFound {
+            assert_eq!(body, "Package 'test-package' not found");
+        } else {
+            assert!(!body.contains("<a href"));
+        }
+    }
+
+    #[test_case("404", EmptyList::NotFound; "not found")]
+    #[test_case("empty", EmptyList::Empty; "empty")]
+    fn empty_list_from_str(value: &str, expected: EmptyList) {
+        assert_eq!(value.parse::<EmptyList>().unwrap(), expected);
+        assert!("200".parse::<EmptyList>().is_err());
     }
 
     #[tokio::test]
-    async fn publish_package_subpath() {
+    async fn list_package_show_sizes() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
 
-        // Set timestamp to fixed time
-        crate::time::set_timestamp(1_732_134_216);
+        let tags_list = TagListBuilder::default()
+            .name("test-package")
+            .tags(vec!["1.2.3".to_string()])
+            .build()
+            .unwrap();
+
+        let index = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![DescriptorBuilder::default()
+                .media_type("application/vnd.oci.image.manifest.v1+json")
+                .digest(digest("FooBar"))
+                .size(6_u64)
+                .platform(
+                    PlatformBuilder::default()
+                        .architecture(Arch::Other(".tar.gz".to_string()))
+                        .os(Os::Other("any".to_string()))
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let manifest = r#"{
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.manifest.v1+json",
+          "artifactType": "application/pyoci.package.v1",
+          "config": {
+            "mediaType": "application/vnd.oci.empty.v1+json",
+            "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+            "size": 2
+          },
+          "layers": [
+            {
+              "mediaType": "application/pyoci.package.v1",
+              "digest": "sha256:b7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0",
+              "size": 123456
+            }
+          ]
+        }"#;
 
         let mocks = vec![
-            // Mock the server, in order of expected requests
-            // IndexManifest does not yet exist
             server
-                .mock("GET", "/v2/mockserver/foobar/manifests/1.0.0")
-                .with_status(404)
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
                 .create_async()
                 .await,
-            // HEAD request to check if blob exists for:
-            // - layer
-            // - config
             server
-                .mock(
-                    "HEAD",
-                    mockito::Matcher::Regex(r"/v2/mockserver/foobar/blobs/.+".to_string()),
-                )
-                .expect(2)
-                .with_status(404)
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.2.3")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index).unwrap())
                 .create_async()
                 .await,
-            // POST request with blob for layer
+            // The ImageManifest holds the size of the file
             server
-                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
-                .with_status(202) // ACCEPTED
-                .with_header(
-                    "Location",
-                    &format!("{url}/v2/mockserver/foobar/blobs/uploads/1?_state=uploading"),
+                .mock(
+                    "GET",
+                    format!("/v2/mockserver/test_package/manifests/{}", digest("FooBar")).as_str(),
                 )
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(manifest)
                 .create_async()
                 .await,
-            server
-                .mock("PUT", "/v2/mockserver/foobar/blobs/uploads/1?_state=uploading&digest=sha256%3Ab7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0")
-                .with_status(201) // CREATED
-                .create_async()
-                .await,
-            // POST request with blob for config
-            server
-                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
-                .with_status(202) // ACCEPTED
-                .with_header(
-                    "Location",
-                    &format!("{url}/v2/mockserver/foobar/blobs/uploads/2?_state=uploading"),
+        ];
+
+        let env = Env {
+            show_sizes: true,
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            body.contains(&format!(
+                r#"<a href="/{encoded_url}/mockserver/test-package/test_package-1.2.3.tar.gz#sha256=b7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0" data-size="123456">test_package-1.2.3.tar.gz</a>"#
+            )),
+            "{body}"
+        );
+    }
+
+    #[tokio::test]
+    async fn list_package_subpath() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let tags_list = TagListBuilder::default()
+            .name("test-package")
+            .tags(vec!["0.1.0".to_string(), "1.2.3".to_string()])
+            .build()
+            .unwrap();
+
+        let index_010 = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![DescriptorBuilder::default()
+                .media_type("application/vnd.oci.image.manifest.v1+json")
+                .digest(digest("FooBar"))
+                .size(6_u64)
+                .platform(
+                    PlatformBuilder::default()
+                        .architecture(Arch::Other(".tar.gz".to_string()))
+                        .os(Os::Other("any".to_string()))
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let index_123 = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![DescriptorBuilder::default()
+                .media_type("application/vnd.oci.image.manifest.v1+json")
+                .digest(digest("FooBar"))
+                .size(6_u64)
+                .platform(
+                    PlatformBuilder::default()
+                        .architecture(Arch::Other(".tar.gz".to_string()))
+                        .os(Os::Other("any".to_string()))
+                        .build()
+                        .unwrap(),
                 )
-                .create_async()
-                .await,
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let mocks = vec![
+            // List tags
             server
-                .mock("PUT", "/v2/mockserver/foobar/blobs/uploads/2?_state=uploading&digest=sha256%3A44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a")
-                .with_status(201) // CREATED
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
                 .create_async()
                 .await,
-            // PUT request to create Manifest
+            // Pull 0.1.0 manifest
             server
-                .mock("PUT", "/v2/mockserver/foobar/manifests/sha256:e281659053054737342fd0c74a7605c4678c227db1e073260b44f845dfdf535a")
-                .match_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
-                .with_status(201) // CREATED
+                .mock("GET", "/v2/mockserver/test_package/manifests/0.1.0")
+                .match_header(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index_010).unwrap())
                 .create_async()
                 .await,
-            // PUT request to create Index
+            // Pull 1.2.3 manifest
             server
-                .mock("PUT", "/v2/mockserver/foobar/manifests/1.0.0")
-                .match_header("Content-Type", "application/vnd.oci.image.index.v1+json")
-                .with_status(201) // CREATED
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.2.3")
+                .match_header(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index_123).unwrap())
                 .create_async()
                 .await,
+            // Files without sha256 annotation
+            mock_file_manifests(&mut server, "mockserver/test_package", 2).await,
             server
                 .mock("GET", mockito::Matcher::Any)
                 .expect(0)
@@ -1312,29 +4226,10 @@ mod tests {
             ..Env::default()
         };
         let service = pyoci_service(&env);
-
-        let form = "--foobar\r\n\
-            Content-Disposition: form-data; name=\":action\"\r\n\
-            \r\n\
-            file_upload\r\n\
-            --foobar\r\n\
-            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
-            \r\n\
-            1\r\n\
-            --foobar\r\n\
-            Content-Disposition: form-data; name=\"name\"\r\n\
-            \r\n\
-            foobar\r\n\
-            --foobar\r\n\
-            Content-Disposition: form-data; name=\"content\"; filename=\"foobar-1.0.0.tar.gz\"\r\n\
-            \r\n\
-            someawesomepackagedata\r\n\
-            --foobar--\r\n";
         let req = Request::builder()
-            .method("POST")
-            .uri(format!("/foo/{encoded_url}/mockserver/"))
-            .header("Content-Type", "multipart/form-data; boundary=foobar")
-            .body(form.into())
+            .method("GET")
+            .uri(format!("/foo/{encoded_url}/mockserver/test-package/"))
+            .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
 
@@ -1350,12 +4245,29 @@ mod tests {
         for mock in mocks {
             mock.assert_async().await;
         }
-        assert_eq!(&body, "Published");
         assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            formatdoc!(
+                r#"
+                <!DOCTYPE html>
+                <html lang="en">
+                <head>
+                    <meta charset="UTF-8">
+                    <title>PyOCI</title>
+                </head>
+                <body>
+                    <a href="/foo/{encoded_url}/mockserver/test-package/test_package-1.2.3.tar.gz#sha256={FILE_SHA256}">test_package-1.2.3.tar.gz</a>
+                    <a href="/foo/{encoded_url}/mockserver/test-package/test_package-0.1.0.tar.gz#sha256={FILE_SHA256}">test_package-0.1.0.tar.gz</a>
+                </body>
+                </html>
+                "#
+            )
+        );
     }
 
     #[tokio::test]
-    async fn list_package() {
+    async fn list_package_multipart_namespace() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
@@ -1418,14 +4330,14 @@ mod tests {
         let mocks = vec![
             // List tags
             server
-                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .mock("GET", "/v2/mockserver/subnamespace/test_package/tags/list")
                 .with_status(200)
                 .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
                 .create_async()
                 .await,
             // Pull 0.1.0 manifest
             server
-                .mock("GET", "/v2/mockserver/test_package/manifests/0.1.0")
+                .mock("GET", "/v2/mockserver/subnamespace/test_package/manifests/0.1.0")
                 .match_header(
                     "accept",
                     "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
@@ -1436,7 +4348,7 @@ mod tests {
                 .await,
             // Pull 1.2.3 manifest
             server
-                .mock("GET", "/v2/mockserver/test_package/manifests/1.2.3")
+                .mock("GET", "/v2/mockserver/subnamespace/test_package/manifests/1.2.3")
                 .match_header(
                     "accept",
                     "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
@@ -1445,6 +4357,8 @@ mod tests {
                 .with_body(serde_json::to_string::<ImageIndex>(&index_123).unwrap())
                 .create_async()
                 .await,
+            // Files without sha256 annotation
+            mock_file_manifests(&mut server, "mockserver/subnamespace/test_package", 1).await,
             server
                 .mock("GET", mockito::Matcher::Any)
                 .expect(0)
@@ -1459,7 +4373,9 @@ mod tests {
         let service = pyoci_service(&env);
         let req = Request::builder()
             .method("GET")
-            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .uri(format!(
+                "/{encoded_url}/mockserver/subnamespace/test-package/"
+            ))
             .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
@@ -1488,8 +4404,8 @@ mod tests {
                     <title>PyOCI</title>
                 </head>
                 <body>
-                    <a href="/{encoded_url}/mockserver/test-package/test_package-1.2.3.tar.gz#sha256=1234">test_package-1.2.3.tar.gz</a>
-                    <a href="/{encoded_url}/mockserver/test-package/test_package-0.1.0.tar.gz">test_package-0.1.0.tar.gz</a>
+                    <a href="/{encoded_url}/mockserver/subnamespace/test-package/test_package-1.2.3.tar.gz#sha256=1234">test_package-1.2.3.tar.gz</a>
+                    <a href="/{encoded_url}/mockserver/subnamespace/test-package/test_package-0.1.0.tar.gz#sha256={FILE_SHA256}">test_package-0.1.0.tar.gz</a>
                 </body>
                 </html>
                 "#
@@ -1498,14 +4414,19 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn list_package_subpath() {
+    async fn list_package_multipart_namespace_with_subpath() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
 
         let tags_list = TagListBuilder::default()
             .name("test-package")
-            .tags(vec!["0.1.0".to_string(), "1.2.3".to_string()])
+            .tags(vec![
+                "0.1.0".to_string(),
+                // max_versions is set to 2, so this version will be excluded
+                "0.0.1".to_string(),
+                "1.2.3".to_string(),
+            ])
             .build()
             .unwrap();
 
@@ -1529,7 +4450,176 @@ mod tests {
             .build()
             .unwrap();
 
-        let index_123 = ImageIndexBuilder::default()
+        let index_123 = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![DescriptorBuilder::default()
+                .media_type("application/vnd.oci.image.manifest.v1+json")
+                .digest(digest("FooBar"))
+                .size(6_u64)
+                .platform(
+                    PlatformBuilder::default()
+                        .architecture(Arch::Other(".tar.gz".to_string()))
+                        .os(Os::Other("any".to_string()))
+                        .build()
+                        .unwrap(),
+                )
+                .annotations(HashMap::from([(
+                    "com.pyoci.sha256_digest".to_string(),
+                    "1234".to_string(),
+                )]))
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let mocks = vec![
+            // List tags
+            server
+                .mock("GET", "/v2/mockserver/subnamespace/test_package/tags/list")
+                .with_status(200)
+                .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
+                .create_async()
+                .await,
+            // Pull 0.1.0 manifest
+            server
+                .mock("GET", "/v2/mockserver/subnamespace/test_package/manifests/0.1.0")
+                .match_header(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index_010).unwrap())
+                .create_async()
+                .await,
+            // Pull 1.2.3 manifest
+            server
+                .mock("GET", "/v2/mockserver/subnamespace/test_package/manifests/1.2.3")
+                .match_header(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index_123).unwrap())
+                .create_async()
+                .await,
+            // Files without sha256 annotation
+            mock_file_manifests(&mut server, "mockserver/subnamespace/test_package", 1).await,
+            server
+                .mock("GET", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env {
+            max_versions: 2,
+            path: Some("/foo".to_string()),
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!(
+                "/foo/{encoded_url}/mockserver/subnamespace/test-package/"
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            formatdoc!(
+                r#"
+                <!DOCTYPE html>
+                <html lang="en">
+                <head>
+                    <meta charset="UTF-8">
+                    <title>PyOCI</title>
+                </head>
+                <body>
+                    <a href="/foo/{encoded_url}/mockserver/subnamespace/test-package/test_package-1.2.3.tar.gz#sha256=1234">test_package-1.2.3.tar.gz</a>
+                    <a href="/foo/{encoded_url}/mockserver/subnamespace/test-package/test_package-0.1.0.tar.gz#sha256={FILE_SHA256}">test_package-0.1.0.tar.gz</a>
+                </body>
+                </html>
+                "#
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn list_package_missing_index() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let mocks = vec![
+            // List tags
+            server
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(404)
+                .with_body("Server missing message")
+                .create_async()
+                .await,
+            server
+                .mock("GET", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, "Server missing message");
+    }
+
+    #[tokio::test]
+    async fn list_package_missing_manifest() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let tags_list = TagListBuilder::default()
+            .name("test-package")
+            .tags(vec!["0.1.0".to_string(), "1.2.3".to_string()])
+            .build()
+            .unwrap();
+
+        let index_010 = ImageIndexBuilder::default()
             .schema_version(2_u32)
             .media_type("application/vnd.oci.image.index.v1+json")
             .artifact_type(ARTIFACT_TYPE)
@@ -1549,6 +4639,8 @@ mod tests {
             .build()
             .unwrap();
 
+        // Files without sha256 annotation
+        let _file_manifests = mock_file_manifests(&mut server, "mockserver/test_package", 1).await;
         let mocks = vec![
             // List tags
             server
@@ -1574,9 +4666,7 @@ mod tests {
                 .match_header(
                     "accept",
                     "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
-                .with_status(200)
-                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
-                .with_body(serde_json::to_string::<ImageIndex>(&index_123).unwrap())
+                .with_status(404)
                 .create_async()
                 .await,
             server
@@ -1586,14 +4676,11 @@ mod tests {
                 .await,
         ];
 
-        let env = Env {
-            path: Some("/foo".to_string()),
-            ..Env::default()
-        };
+        let env = Env::default();
         let service = pyoci_service(&env);
         let req = Request::builder()
             .method("GET")
-            .uri(format!("/foo/{encoded_url}/mockserver/test-package/"))
+            .uri(format!("/{encoded_url}/mockserver/test-package/"))
             .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
@@ -1610,45 +4697,68 @@ mod tests {
         for mock in mocks {
             mock.assert_async().await;
         }
-        assert_eq!(status, StatusCode::OK);
-        assert_eq!(
-            body,
-            formatdoc!(
-                r#"
-                <!DOCTYPE html>
-                <html lang="en">
-                <head>
-                    <meta charset="UTF-8">
-                    <title>PyOCI</title>
-                </head>
-                <body>
-                    <a href="/foo/{encoded_url}/mockserver/test-package/test_package-1.2.3.tar.gz">test_package-1.2.3.tar.gz</a>
-                    <a href="/foo/{encoded_url}/mockserver/test-package/test_package-0.1.0.tar.gz">test_package-0.1.0.tar.gz</a>
-                </body>
-                </html>
-                "#
-            )
-        );
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, "ImageManifest '1.2.3' does not exist");
     }
 
     #[tokio::test]
-    async fn list_package_multipart_namespace() {
+    async fn list_package_deadline() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        // Registry responds slower than the request deadline
+        server
+            .mock("GET", "/v2/mockserver/test_package/tags/list")
+            .with_status(200)
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_millis(500));
+                w.write_all(br#"{"name":"test-package","tags":[]}"#)
+            })
+            .create_async()
+            .await;
+
+        let env = Env {
+            request_deadline: Some(Duration::from_millis(100)),
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body, "Request deadline exceeded");
+    }
+
+    #[test_case(None, "null"; "no dependencies")]
+    #[test_case(Some("[]"), "null"; "empty dependencies")]
+    #[test_case(Some(r#"["requests>=2.0"]"#), r#"["requests>=2.0"]"#; "dependencies")]
+    #[tokio::test]
+    async fn list_package_json(requires_dist: Option<&str>, expected: &str) {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
 
         let tags_list = TagListBuilder::default()
             .name("test-package")
-            .tags(vec![
-                "0.1.0".to_string(),
-                // max_versions is set to 2, so this version will be excluded
-                "0.0.1".to_string(),
-                "1.2.3".to_string(),
-            ])
+            .tags(vec!["0.1.0".to_string(), "1.2.3".to_string()])
             .build()
             .unwrap();
 
-        let index_010 = ImageIndexBuilder::default()
+        let index = ImageIndexBuilder::default()
             .schema_version(2_u32)
             .media_type("application/vnd.oci.image.index.v1+json")
             .artifact_type(ARTIFACT_TYPE)
@@ -1663,30 +4773,29 @@ mod tests {
                         .build()
                         .unwrap(),
                 )
-                .build()
-                .unwrap()])
-            .build()
-            .unwrap();
-
-        let index_123 = ImageIndexBuilder::default()
-            .schema_version(2_u32)
-            .media_type("application/vnd.oci.image.index.v1+json")
-            .artifact_type(ARTIFACT_TYPE)
-            .manifests(vec![DescriptorBuilder::default()
-                .media_type("application/vnd.oci.image.manifest.v1+json")
-                .digest(digest("FooBar"))
-                .size(6_u64)
-                .platform(
-                    PlatformBuilder::default()
-                        .architecture(Arch::Other(".tar.gz".to_string()))
-                        .os(Os::Other("any".to_string()))
-                        .build()
-                        .unwrap(),
+                .annotations(
+                    [
+                        (
+                            "com.pyoci.project_urls".to_string(),
+                            r#"{"Repository": "https://github.com/allexveldman/pyoci"}"#
+                                .to_string(),
+                        ),
+                        ("com.pyoci.sha256_digest".to_string(), "1234".to_string()),
+                        ("com.pyoci.sha512_digest".to_string(), "5678".to_string()),
+                        (
+                            "org.opencontainers.image.source".to_string(),
+                            "https://github.com/allexveldman/pyoci".to_string(),
+                        ),
+                    ]
+                    .into_iter()
+                    .chain(requires_dist.map(|requires_dist| {
+                        (
+                            "com.pyoci.requires_dist".to_string(),
+                            requires_dist.to_string(),
+                        )
+                    }))
+                    .collect::<HashMap<_, _>>(),
                 )
-                .annotations(HashMap::from([(
-                    "com.pyoci.sha256_digest".to_string(),
-                    "1234".to_string(),
-                )]))
                 .build()
                 .unwrap()])
             .build()
@@ -1695,31 +4804,20 @@ mod tests {
         let mocks = vec![
             // List tags
             server
-                .mock("GET", "/v2/mockserver/subnamespace/test_package/tags/list")
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
                 .with_status(200)
                 .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
                 .create_async()
                 .await,
-            // Pull 0.1.0 manifest
-            server
-                .mock("GET", "/v2/mockserver/subnamespace/test_package/manifests/0.1.0")
-                .match_header(
-                    "accept",
-                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
-                .with_status(200)
-                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
-                .with_body(serde_json::to_string::<ImageIndex>(&index_010).unwrap())
-                .create_async()
-                .await,
-            // Pull 1.2.3 manifest
+            // Pull 1.2.3 manifest for project_urls
             server
-                .mock("GET", "/v2/mockserver/subnamespace/test_package/manifests/1.2.3")
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.2.3")
                 .match_header(
                     "accept",
                     "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
                 .with_status(200)
                 .with_header("content-type", "application/vnd.oci.image.index.v1+json")
-                .with_body(serde_json::to_string::<ImageIndex>(&index_123).unwrap())
+                .with_body(serde_json::to_string::<ImageIndex>(&index).unwrap())
                 .create_async()
                 .await,
             server
@@ -1729,16 +4827,11 @@ mod tests {
                 .await,
         ];
 
-        let env = Env {
-            max_versions: 2,
-            ..Env::default()
-        };
+        let env = Env::default();
         let service = pyoci_service(&env);
         let req = Request::builder()
             .method("GET")
-            .uri(format!(
-                "/{encoded_url}/mockserver/subnamespace/test-package/"
-            ))
+            .uri(format!("/{encoded_url}/mockserver/test-package/json"))
             .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
@@ -1758,26 +4851,188 @@ mod tests {
         assert_eq!(status, StatusCode::OK);
         assert_eq!(
             body,
-            formatdoc!(
-                r#"
-                <!DOCTYPE html>
-                <html lang="en">
-                <head>
-                    <meta charset="UTF-8">
-                    <title>PyOCI</title>
-                </head>
-                <body>
-                    <a href="/{encoded_url}/mockserver/subnamespace/test-package/test_package-1.2.3.tar.gz#sha256=1234">test_package-1.2.3.tar.gz</a>
-                    <a href="/{encoded_url}/mockserver/subnamespace/test-package/test_package-0.1.0.tar.gz">test_package-0.1.0.tar.gz</a>
-                </body>
-                </html>
-                "#
+            format!(
+                r#"{{"info":{{"name":"test-package","project_urls":{{"Repository":"https://github.com/allexveldman/pyoci"}},"requires_dist":{expected}}},"releases":{{"0.1.0":[],"1.2.3":[]}},"urls":[{{"filename":"test_package-1.2.3.tar.gz","digests":{{"sha256":"1234","sha512":"5678"}},"annotations":{{"org.opencontainers.image.source":"https://github.com/allexveldman/pyoci"}}}}]}}"#
+            )
+        );
+    }
+
+    /// The latest version without files returns the package without project URLs
+    #[tokio::test]
+    async fn list_package_json_no_files() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "test-package", "tags": ["1.0.0"]}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.0.0")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string(&version_index(&[])).unwrap())
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/{encoded_url}/mockserver/test-package/json"))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            r#"{"info":{"name":"test-package","project_urls":{},"requires_dist":null},"releases":{"1.0.0":[]},"urls":[]}"#
+        );
+    }
+
+    /// `ImageIndex` of a single version with a file for each of `archs`
+    fn version_index(archs: &[&str]) -> ImageIndex {
+        ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(
+                archs
+                    .iter()
+                    .map(|arch| {
+                        DescriptorBuilder::default()
+                            .media_type("application/vnd.oci.image.manifest.v1+json")
+                            .digest(digest(arch))
+                            .size(6_u64)
+                            .platform(
+                                PlatformBuilder::default()
+                                    .architecture(Arch::Other((*arch).to_string()))
+                                    .os(Os::Other("any".to_string()))
+                                    .build()
+                                    .unwrap(),
+                            )
+                            .build()
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>(),
             )
+            .build()
+            .unwrap()
+    }
+
+    #[test_case("page=1&limit=2", r#"{"0.1.0":[],"1.0.0":[]}"#, Some("page=2&limit=2"); "first page")]
+    #[test_case("page=2&limit=2", r#"{"1.2.3":[]}"#, None; "last page")]
+    #[test_case("page=3&limit=2", "{}", None; "past last page")]
+    #[test_case("offset=0&limit=2", r#"{"0.1.0":[],"1.0.0":[]}"#, Some("offset=2&limit=2"); "first offset")]
+    #[test_case("offset=1&limit=1", r#"{"1.0.0":[]}"#, Some("offset=2&limit=1"); "offset window")]
+    #[test_case("offset=1", r#"{"1.0.0":[],"1.2.3":[]}"#, None; "offset without limit")]
+    #[test_case("offset=5&limit=2", "{}", None; "past last offset")]
+    #[tokio::test]
+    async fn list_package_json_pagination(query: &str, releases: &str, next: Option<&str>) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let tags_list = TagListBuilder::default()
+            .name("test-package")
+            .tags(vec![
+                "0.1.0".to_string(),
+                "1.0.0".to_string(),
+                "1.2.3".to_string(),
+            ])
+            .build()
+            .unwrap();
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
+                .create_async()
+                .await,
+            // Files of the latest version are included on every page
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.2.3")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string(&version_index(&[".tar.gz"])).unwrap())
+                .create_async()
+                .await,
+            mock_file_manifests(&mut server, "mockserver/test_package", 1).await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!(
+                "/{encoded_url}/mockserver/test-package/json?{query}"
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let link = response
+            .headers()
+            .get(header::LINK)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body: serde_json::Value =
+            serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+                .unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["releases"],
+            serde_json::from_str::<serde_json::Value>(releases).unwrap()
         );
+        assert_eq!(body["urls"][0]["filename"], "test_package-1.2.3.tar.gz");
+        assert_eq!(body["urls"][0]["digests"]["sha256"], FILE_SHA256);
+        assert_eq!(
+            link,
+            next.map(|query| format!(
+                "</{encoded_url}/mockserver/test-package/json?{query}>; rel=\"next\""
+            ))
+        );
+    }
+
+    #[test_case("page=0&limit=2", "Invalid 'page' query parameter"; "page zero")]
+    #[test_case("limit=all", "Invalid 'limit' query parameter"; "limit not a number")]
+    #[test_case("offset=-1&limit=2", "Invalid 'offset' query parameter"; "negative offset")]
+    #[test_case("page=2&offset=2&limit=2", "Use either the 'page' or the 'offset' query parameter"; "page and offset")]
+    #[tokio::test]
+    async fn list_package_json_pagination_invalid(query: &str, expected: &str) {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/foo.example/mockserver/test-package/json?{query}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, expected);
     }
 
     #[tokio::test]
-    async fn list_package_multipart_namespace_with_subpath() {
+    async fn latest_package() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
@@ -1785,86 +5040,37 @@ mod tests {
         let tags_list = TagListBuilder::default()
             .name("test-package")
             .tags(vec![
-                "0.1.0".to_string(),
-                // max_versions is set to 2, so this version will be excluded
-                "0.0.1".to_string(),
-                "1.2.3".to_string(),
+                "0.9.0".to_string(),
+                "1.10.0".to_string(),
+                "1.9.0".to_string(),
+                "latest".to_string(),
             ])
             .build()
             .unwrap();
 
-        let index_010 = ImageIndexBuilder::default()
-            .schema_version(2_u32)
-            .media_type("application/vnd.oci.image.index.v1+json")
-            .artifact_type(ARTIFACT_TYPE)
-            .manifests(vec![DescriptorBuilder::default()
-                .media_type("application/vnd.oci.image.manifest.v1+json")
-                .digest(digest("FooBar"))
-                .size(6_u64)
-                .platform(
-                    PlatformBuilder::default()
-                        .architecture(Arch::Other(".tar.gz".to_string()))
-                        .os(Os::Other("any".to_string()))
-                        .build()
-                        .unwrap(),
-                )
-                .build()
-                .unwrap()])
-            .build()
-            .unwrap();
-
-        let index_123 = ImageIndexBuilder::default()
-            .schema_version(2_u32)
-            .media_type("application/vnd.oci.image.index.v1+json")
-            .artifact_type(ARTIFACT_TYPE)
-            .manifests(vec![DescriptorBuilder::default()
-                .media_type("application/vnd.oci.image.manifest.v1+json")
-                .digest(digest("FooBar"))
-                .size(6_u64)
-                .platform(
-                    PlatformBuilder::default()
-                        .architecture(Arch::Other(".tar.gz".to_string()))
-                        .os(Os::Other("any".to_string()))
-                        .build()
-                        .unwrap(),
-                )
-                .annotations(HashMap::from([(
-                    "com.pyoci.sha256_digest".to_string(),
-                    "1234".to_string(),
-                )]))
-                .build()
-                .unwrap()])
-            .build()
-            .unwrap();
-
         let mocks = vec![
-            // List tags
             server
-                .mock("GET", "/v2/mockserver/subnamespace/test_package/tags/list")
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
                 .with_status(200)
                 .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
                 .create_async()
                 .await,
-            // Pull 0.1.0 manifest
+            // Newest version, no wheel
             server
-                .mock("GET", "/v2/mockserver/subnamespace/test_package/manifests/0.1.0")
-                .match_header(
-                    "accept",
-                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.10.0")
                 .with_status(200)
                 .with_header("content-type", "application/vnd.oci.image.index.v1+json")
-                .with_body(serde_json::to_string::<ImageIndex>(&index_010).unwrap())
+                .with_body(serde_json::to_string(&version_index(&[".tar.gz"])).unwrap())
                 .create_async()
                 .await,
-            // Pull 1.2.3 manifest
             server
-                .mock("GET", "/v2/mockserver/subnamespace/test_package/manifests/1.2.3")
-                .match_header(
-                    "accept",
-                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.9.0")
                 .with_status(200)
                 .with_header("content-type", "application/vnd.oci.image.index.v1+json")
-                .with_body(serde_json::to_string::<ImageIndex>(&index_123).unwrap())
+                .with_body(
+                    serde_json::to_string(&version_index(&[".tar.gz", "py3-none-any.whl"]))
+                        .unwrap(),
+                )
                 .create_async()
                 .await,
             server
@@ -1874,71 +5080,51 @@ mod tests {
                 .await,
         ];
 
-        let env = Env {
-            max_versions: 2,
-            path: Some("/foo".to_string()),
-            ..Env::default()
-        };
+        let env = Env::default();
         let service = pyoci_service(&env);
         let req = Request::builder()
             .method("GET")
             .uri(format!(
-                "/foo/{encoded_url}/mockserver/subnamespace/test-package/"
+                "/{encoded_url}/mockserver/test-package/latest?arch=.whl"
             ))
             .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
 
-        let status = response.status();
-        let body = String::from_utf8(
-            to_bytes(response.into_body(), usize::MAX)
-                .await
-                .unwrap()
-                .into(),
-        )
-        .unwrap();
-
         for mock in mocks {
             mock.assert_async().await;
         }
-        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response.status(), StatusCode::FOUND);
         assert_eq!(
-            body,
-            formatdoc!(
-                r#"
-                <!DOCTYPE html>
-                <html lang="en">
-                <head>
-                    <meta charset="UTF-8">
-                    <title>PyOCI</title>
-                </head>
-                <body>
-                    <a href="/foo/{encoded_url}/mockserver/subnamespace/test-package/test_package-1.2.3.tar.gz#sha256=1234">test_package-1.2.3.tar.gz</a>
-                    <a href="/foo/{encoded_url}/mockserver/subnamespace/test-package/test_package-0.1.0.tar.gz">test_package-0.1.0.tar.gz</a>
-                </body>
-                </html>
-                "#
-            )
+            response.headers().get("Location").unwrap(),
+            &format!("/{encoded_url}/mockserver/test-package/test_package-1.9.0-py3-none-any.whl")
         );
     }
 
     #[tokio::test]
-    async fn list_package_missing_index() {
+    async fn latest_package_no_match() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
 
+        let tags_list = TagListBuilder::default()
+            .name("test-package")
+            .tags(vec!["0.1.0".to_string()])
+            .build()
+            .unwrap();
+
         let mocks = vec![
-            // List tags
             server
                 .mock("GET", "/v2/mockserver/test_package/tags/list")
-                .with_status(404)
-                .with_body("Server missing message")
+                .with_status(200)
+                .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
                 .create_async()
                 .await,
             server
-                .mock("GET", mockito::Matcher::Any)
-                .expect(0)
+                .mock("GET", "/v2/mockserver/test_package/manifests/0.1.0")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string(&version_index(&[".tar.gz"])).unwrap())
                 .create_async()
                 .await,
         ];
@@ -1947,85 +5133,82 @@ mod tests {
         let service = pyoci_service(&env);
         let req = Request::builder()
             .method("GET")
-            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .uri(format!(
+                "/{encoded_url}/mockserver/test-package/latest?arch=.whl"
+            ))
             .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
 
-        let status = response.status();
-        let body = String::from_utf8(
-            to_bytes(response.into_body(), usize::MAX)
-                .await
-                .unwrap()
-                .into(),
-        )
-        .unwrap();
-
         for mock in mocks {
             mock.assert_async().await;
         }
-        assert_eq!(status, StatusCode::NOT_FOUND);
-        assert_eq!(body, "Server missing message");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "No '.whl' file found for 'test-package'");
     }
 
     #[tokio::test]
-    async fn list_package_missing_manifest() {
+    async fn latest_package_missing_arch() {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri("/ghcr.io/mockserver/test-package/latest")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "Missing 'arch' query parameter");
+    }
+
+    #[test_case("1.0.1", "Invalid alias '1.0.1'"; "version")]
+    #[test_case("1.0.1-local", "Invalid alias '1.0.1-local'"; "local version")]
+    #[test_case(".latest", "Invalid alias '.latest'"; "invalid tag")]
+    #[tokio::test]
+    async fn tag_package_version_invalid_alias(alias: &str, expected: &str) {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
 
-        let tags_list = TagListBuilder::default()
-            .name("test-package")
-            .tags(vec!["0.1.0".to_string(), "1.2.3".to_string()])
-            .build()
-            .unwrap();
+        let mock = server
+            .mock("GET", mockito::Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
 
-        let index_010 = ImageIndexBuilder::default()
-            .schema_version(2_u32)
-            .media_type("application/vnd.oci.image.index.v1+json")
-            .artifact_type(ARTIFACT_TYPE)
-            .manifests(vec![DescriptorBuilder::default()
-                .media_type("application/vnd.oci.image.manifest.v1+json")
-                .digest(digest("FooBar"))
-                .size(6_u64)
-                .platform(
-                    PlatformBuilder::default()
-                        .architecture(Arch::Other(".tar.gz".to_string()))
-                        .os(Os::Other("any".to_string()))
-                        .build()
-                        .unwrap(),
-                )
-                .build()
-                .unwrap()])
-            .build()
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!(
+                "/{encoded_url}/mockserver/test-package/1.0.0/tag/{alias}"
+            ))
+            .body(Body::empty())
             .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, expected);
+    }
 
+    #[tokio::test]
+    async fn package_version_index() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let index = version_index(&[".tar.gz", "py3-none-any.whl"]);
         let mocks = vec![
-            // List tags
-            server
-                .mock("GET", "/v2/mockserver/test_package/tags/list")
-                .with_status(200)
-                .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
-                .create_async()
-                .await,
-            // Pull 0.1.0 manifest
             server
-                .mock("GET", "/v2/mockserver/test_package/manifests/0.1.0")
-                .match_header(
-                    "accept",
-                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.0.0-local")
                 .with_status(200)
                 .with_header("content-type", "application/vnd.oci.image.index.v1+json")
-                .with_body(serde_json::to_string::<ImageIndex>(&index_010).unwrap())
-                .create_async()
-                .await,
-            // Pull 1.2.3 manifest
-            server
-                .mock("GET", "/v2/mockserver/test_package/manifests/1.2.3")
-                .match_header(
-                    "accept",
-                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
-                .with_status(404)
+                .with_body(serde_json::to_string(&index).unwrap())
                 .create_async()
                 .await,
             server
@@ -2039,82 +5222,69 @@ mod tests {
         let service = pyoci_service(&env);
         let req = Request::builder()
             .method("GET")
-            .uri(format!("/{encoded_url}/mockserver/test-package/"))
+            .uri(format!(
+                "/{encoded_url}/mockserver/test-package/1.0.0+local/index.json"
+            ))
             .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
 
-        let status = response.status();
-        let body = String::from_utf8(
-            to_bytes(response.into_body(), usize::MAX)
-                .await
-                .unwrap()
-                .into(),
-        )
-        .unwrap();
-
         for mock in mocks {
             mock.assert_async().await;
         }
-        assert_eq!(status, StatusCode::NOT_FOUND);
-        assert_eq!(body, "ImageManifest '1.2.3' does not exist");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<ImageIndex>(&body).unwrap(), index);
     }
 
     #[tokio::test]
-    async fn list_package_json() {
-        let mut server = mockito::Server::new_async().await;
-        let url = server.url();
-        let encoded_url = urlencoding::encode(&url).into_owned();
-
-        let tags_list = TagListBuilder::default()
-            .name("test-package")
-            .tags(vec!["0.1.0".to_string(), "1.2.3".to_string()])
-            .build()
-            .unwrap();
-
-        let index = ImageIndexBuilder::default()
-            .schema_version(2_u32)
-            .media_type("application/vnd.oci.image.index.v1+json")
-            .artifact_type(ARTIFACT_TYPE)
-            .manifests(vec![DescriptorBuilder::default()
-                .media_type("application/vnd.oci.image.manifest.v1+json")
-                .digest(digest("FooBar"))
-                .size(6_u64)
-                .platform(
-                    PlatformBuilder::default()
-                        .architecture(Arch::Other(".tar.gz".to_string()))
-                        .os(Os::Other("any".to_string()))
-                        .build()
-                        .unwrap(),
-                )
-                .annotations(HashMap::from([(
-                    "com.pyoci.project_urls".to_string(),
-                    r#"{"Repository": "https://github.com/allexveldman/pyoci"}"#.to_string(),
-                )]))
-                .build()
-                .unwrap()])
-            .build()
+    async fn package_version_index_missing() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let mock = server
+            .mock("GET", "/v2/mockserver/test_package/manifests/1.0.0")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!(
+                "/{encoded_url}/mockserver/test-package/1.0.0/index.json"
+            ))
+            .body(Body::empty())
             .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "Version '1.0.0' does not exist");
+    }
+
+    #[test_case("test_package-1.0.0.tar.gz", 200, true; "existing file")]
+    #[test_case("test_package-1.0.0-py3-none-any.whl", 200, false; "missing architecture")]
+    #[test_case("test_package-1.0.0.tar.gz", 404, false; "missing version")]
+    #[tokio::test]
+    async fn package_file_exists(filename: &str, index_status: usize, expected: bool) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
 
+        let index = version_index(&[".tar.gz"]);
         let mocks = vec![
-            // List tags
-            server
-                .mock("GET", "/v2/mockserver/test_package/tags/list")
-                .with_status(200)
-                .with_body(serde_json::to_string::<TagList>(&tags_list).unwrap())
-                .create_async()
-                .await,
-            // Pull 1.2.3 manifest for project_urls
             server
-                .mock("GET", "/v2/mockserver/test_package/manifests/1.2.3")
-                .match_header(
-                    "accept",
-                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
-                .with_status(200)
+                .mock("GET", "/v2/mockserver/test_package/manifests/1.0.0")
+                .with_status(index_status)
                 .with_header("content-type", "application/vnd.oci.image.index.v1+json")
-                .with_body(serde_json::to_string::<ImageIndex>(&index).unwrap())
+                .with_body(serde_json::to_string(&index).unwrap())
                 .create_async()
                 .await,
+            // The file itself is never downloaded
             server
                 .mock("GET", mockito::Matcher::Any)
                 .expect(0)
@@ -2126,28 +5296,74 @@ mod tests {
         let service = pyoci_service(&env);
         let req = Request::builder()
             .method("GET")
-            .uri(format!("/{encoded_url}/mockserver/test-package/json"))
+            .uri(format!(
+                "/{encoded_url}/mockserver/test-package/{filename}/exists"
+            ))
             .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
 
-        let status = response.status();
-        let body = String::from_utf8(
-            to_bytes(response.into_body(), usize::MAX)
-                .await
-                .unwrap()
-                .into(),
-        )
-        .unwrap();
-
         for mock in mocks {
             mock.assert_async().await;
         }
-        assert_eq!(status, StatusCode::OK);
-        assert_eq!(
-            body,
-            r#"{"info":{"name":"test-package","project_urls":{"Repository":"https://github.com/allexveldman/pyoci"}},"releases":{"0.1.0":[],"1.2.3":[]}}"#
-        );
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({"exists": expected}));
+    }
+
+    #[test_case("GET", "a/b/c/test-package/", "/v2/a/b/c/test_package/tags/list"; "list")]
+    #[test_case("GET", "a/b/c/test-package/json", "/v2/a/b/c/test_package/tags/list"; "list json")]
+    #[test_case("GET", "a/b/c/test-package/test_package-1.0.0.tar.gz", "/v2/a/b/c/test_package/manifests/1.0.0"; "download")]
+    #[test_case("DELETE", "a/b/c/test-package/1.0.0", "/v2/a/b/c/test_package/manifests/1.0.0"; "delete")]
+    #[test_case("POST", "a/b/c/", "/v2/a/b/c/test_package/manifests/1.0.0"; "publish")]
+    #[test_case("POST", "a/b/c/test-package/1.0.0/tag/latest", "/v2/a/b/c/test_package/manifests/1.0.0"; "tag")]
+    #[test_case("GET", "a/b/c/test-package/latest?arch=.whl", "/v2/a/b/c/test_package/tags/list"; "latest")]
+    #[test_case("GET", "a/b/c/test-package/1.0.0/index.json", "/v2/a/b/c/test_package/manifests/1.0.0"; "index")]
+    #[test_case("GET", "a/b/c/test-package/test_package-1.0.0.tar.gz/exists", "/v2/a/b/c/test_package/manifests/1.0.0"; "exists")]
+    #[tokio::test]
+    /// All operations support namespaces with multiple segments
+    async fn multi_segment_namespace(method: &str, path: &str, upstream: &str) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        // Only check the first request reaches the right repository
+        let mock = server
+            .mock("GET", upstream)
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let form = "--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            test-package\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"test_package-1.0.0-py3-none-any.whl\"\r\n\
+            \r\n\
+            PK\x03\x04\r\n\
+            --foobar--\r\n";
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("/{encoded_url}/{path}"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(form.into())
+            .unwrap();
+        service.oneshot(req).await.unwrap();
+
+        mock.assert_async().await;
     }
 
     #[tokio::test]
@@ -2263,12 +5479,96 @@ mod tests {
         let response = service.oneshot(req).await.unwrap();
 
         let status = response.status();
+        let upstream_bytes = response.headers().get("X-PyOCI-Upstream-Bytes").cloned();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, blob);
+        // Index, manifest and blob were fetched from the registry
+        let fetched = serde_json::to_string(&index).unwrap().len()
+            + serde_json::to_string(&manifest).unwrap().len()
+            + blob.len();
+        assert_eq!(upstream_bytes, Some(HeaderValue::from(fetched)));
+    }
+
+    #[tokio::test]
+    async fn download_package_digest() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let manifest = ImageManifestBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.manifest.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .config(
+                DescriptorBuilder::default()
+                    .media_type("application/vnd.oci.empty.v1+json")
+                    .digest(digest("config-digest")) // sha:7b6a7aed8c63f4480a863fa046048c4bfb77d4514212ad646a5fcadcf8f5da47
+                    .size(0_u64)
+                    .build()
+                    .unwrap(),
+            )
+            .layers(vec![DescriptorBuilder::default()
+                .media_type(ARTIFACT_TYPE)
+                .digest(digest("layer-digest")) // sha:8a576772defc4006637b27e7b0bef2c8bb6f3f7465d27426f1684da58ea9f969
+                .size(42_u64)
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let blob = Bytes::from(vec![1, 2, 3]);
+
+        let mocks = vec![
+            // Pull the pinned manifest, the index is never pulled
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/sha256:bc669544845542470042912a0f61b90499ffc2320b45ea66b0be50439c5aab19")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(serde_json::to_string::<ImageManifest>(&manifest).unwrap())
+                .create_async()
+                .await,
+            // Pull 0.1.0.tar.gz blob
+            server
+                .mock("GET", "/v2/mockserver/test_package/blobs/sha256:8a576772defc4006637b27e7b0bef2c8bb6f3f7465d27426f1684da58ea9f969")
+                .with_status(200)
+                .with_body(blob.clone())
+                .create_async()
+                .await,
+            server
+                .mock("GET", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!(
+                "http://localhost.unittest/{encoded_url}/mockserver/test_package/test_package-0.1.0.tar.gz@sha256:bc669544845542470042912a0f61b90499ffc2320b45ea66b0be50439c5aab19"
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let headers = response.headers().clone();
         let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
 
         for mock in mocks {
             mock.assert_async().await;
         }
         assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            headers.get("Content-Disposition").unwrap(),
+            "attachment; filename=\"test_package-0.1.0.tar.gz\""
+        );
         assert_eq!(body, blob);
     }
 
@@ -2397,6 +5697,52 @@ mod tests {
         assert_eq!(body, blob);
     }
 
+    #[test_case("foo.example%252F..%252Finternal", "Invalid registry 'foo.example%2F..%2Finternal'"; "encoded traversal in registry")]
+    #[test_case("file%3A%2F%2F%2Fetc%2Fpasswd", "Unsupported registry scheme 'file', expected 'http' or 'https'"; "file registry")]
+    #[test_case("file%253A%252F%252F%252Fetc", "Unsupported registry scheme 'file', expected 'http' or 'https'"; "double encoded file registry")]
+    #[tokio::test]
+    async fn invalid_registry(registry: &str, expected: &str) {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/{registry}/mockserver/test-package/"))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, expected);
+    }
+
+    #[test_case("/foo.example/%2E%2E/test-package/"; "namespace")]
+    #[test_case("/foo.example/mockserver/..%2Fother/json"; "package")]
+    #[test_case("/foo.example/mockserver/test-package/..%2F..%2Ftest_package-1.0.tar.gz"; "filename")]
+    #[tokio::test]
+    async fn invalid_path_traversal(uri: &str) {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.starts_with("Invalid "), "{body}");
+    }
+
     #[tokio::test]
     async fn download_package_invalid_file() {
         let env = Env::default();
@@ -2441,17 +5787,111 @@ mod tests {
         )
         .unwrap();
 
-        assert_eq!(status, StatusCode::BAD_REQUEST);
-        assert_eq!(body, "Invalid binary distribution filename 'foo.whl'");
-    }
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "Invalid binary distribution filename 'foo.whl'");
+    }
+
+    #[tokio::test]
+    async fn download_package_invalid_tar() {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri("http://localhost.unittest/wp/mockserver/test_package/foo.tar.gz")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "Invalid source distribution filename 'foo.tar.gz'");
+    }
+
+    #[tokio::test]
+    async fn download_package_missing_manifest() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let index = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![
+                DescriptorBuilder::default()
+                    .media_type("application/vnd.oci.image.manifest.v1+json")
+                    .digest(digest("FooBar"))
+                    .size(6_u64)
+                    .platform(
+                        PlatformBuilder::default()
+                            .architecture(Arch::Other(".whl".to_string()))
+                            .os(Os::Other("any".to_string()))
+                            .build()
+                            .unwrap(),
+                    )
+                    .build()
+                    .unwrap(),
+                DescriptorBuilder::default()
+                    .media_type("application/vnd.oci.image.manifest.v1+json")
+                    .digest(digest("manifest-digest")) // sha256:bc669544845542470042912a0f61b90499ffc2320b45ea66b0be50439c5aab19
+                    .size(6_u64)
+                    .platform(
+                        PlatformBuilder::default()
+                            .architecture(Arch::Other(".tar.gz".to_string()))
+                            .os(Os::Other("any".to_string()))
+                            .build()
+                            .unwrap(),
+                    )
+                    .build()
+                    .unwrap(),
+            ])
+            .build()
+            .unwrap();
+
+        let mocks = vec![
+            // Pull 0.1.0 index
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/0.1.0")
+                .match_header(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index).unwrap())
+                .create_async()
+                .await,
+            // Pull 0.1.0.tar.gz manifest
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/sha256:bc669544845542470042912a0f61b90499ffc2320b45ea66b0be50439c5aab19")
+                .match_header(
+                    "accept",
+                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
+                .with_status(404)
+                .create_async()
+                .await,
+
+            server
+                .mock("GET", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
 
-    #[tokio::test]
-    async fn download_package_invalid_tar() {
         let env = Env::default();
         let service = pyoci_service(&env);
         let req = Request::builder()
             .method("GET")
-            .uri("http://localhost.unittest/wp/mockserver/test_package/foo.tar.gz")
+            .uri(format!(
+                "http://localhost.unittest/{encoded_url}/mockserver/test_package/test_package-0.1.0.tar.gz"
+            ))
             .body(Body::empty())
             .unwrap();
         let response = service.oneshot(req).await.unwrap();
@@ -2465,12 +5905,15 @@ mod tests {
         )
         .unwrap();
 
-        assert_eq!(status, StatusCode::BAD_REQUEST);
-        assert_eq!(body, "Invalid source distribution filename 'foo.tar.gz'");
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, "ImageManifest does not exist");
     }
 
     #[tokio::test]
-    async fn download_package_missing_manifest() {
+    async fn download_package_missing_architecture() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
@@ -2495,11 +5938,11 @@ mod tests {
                     .unwrap(),
                 DescriptorBuilder::default()
                     .media_type("application/vnd.oci.image.manifest.v1+json")
-                    .digest(digest("manifest-digest")) // sha256:bc669544845542470042912a0f61b90499ffc2320b45ea66b0be50439c5aab19
+                    .digest(digest("BarBaz"))
                     .size(6_u64)
                     .platform(
                         PlatformBuilder::default()
-                            .architecture(Arch::Other(".tar.gz".to_string()))
+                            .architecture(Arch::Other("py3-none-any.whl".to_string()))
                             .os(Os::Other("any".to_string()))
                             .build()
                             .unwrap(),
@@ -2522,15 +5965,6 @@ mod tests {
                 .with_body(serde_json::to_string::<ImageIndex>(&index).unwrap())
                 .create_async()
                 .await,
-            // Pull 0.1.0.tar.gz manifest
-            server
-                .mock("GET", "/v2/mockserver/test_package/manifests/sha256:bc669544845542470042912a0f61b90499ffc2320b45ea66b0be50439c5aab19")
-                .match_header(
-                    "accept",
-                    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json")
-                .with_status(404)
-                .create_async()
-                .await,
 
             server
                 .mock("GET", mockito::Matcher::Any)
@@ -2563,11 +5997,119 @@ mod tests {
             mock.assert_async().await;
         }
         assert_eq!(status, StatusCode::NOT_FOUND);
-        assert_eq!(body, "ImageManifest does not exist");
+        assert_eq!(body, "Requested architecture '.tar.gz' not available, available architectures: ['.whl', 'py3-none-any.whl']");
     }
 
     #[tokio::test]
-    async fn download_package_missing_architecture() {
+    /// A file not published by PyOCI is matched on the OS the wheel is built for
+    async fn download_package_platform_os() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let arch = "cp312-cp312-macosx_11_0_arm64.whl";
+        let descriptor = |os: Os, manifest_digest: &str| {
+            DescriptorBuilder::default()
+                .media_type("application/vnd.oci.image.manifest.v1+json")
+                .digest(digest(manifest_digest))
+                .size(6_u64)
+                .platform(
+                    PlatformBuilder::default()
+                        .architecture(Arch::Other(arch.to_string()))
+                        .os(os)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()
+        };
+        let index = ImageIndexBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.index.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .manifests(vec![
+                descriptor(Os::Linux, "linux-manifest-digest"),
+                // sha256:bc669544845542470042912a0f61b90499ffc2320b45ea66b0be50439c5aab19
+                descriptor(Os::Darwin, "manifest-digest"),
+            ])
+            .build()
+            .unwrap();
+
+        let manifest = ImageManifestBuilder::default()
+            .schema_version(2_u32)
+            .media_type("application/vnd.oci.image.manifest.v1+json")
+            .artifact_type(ARTIFACT_TYPE)
+            .config(
+                DescriptorBuilder::default()
+                    .media_type("application/vnd.oci.empty.v1+json")
+                    .digest(digest("config-digest"))
+                    .size(0_u64)
+                    .build()
+                    .unwrap(),
+            )
+            .layers(vec![DescriptorBuilder::default()
+                .media_type(ARTIFACT_TYPE)
+                .digest(digest("layer-digest")) // sha:8a576772defc4006637b27e7b0bef2c8bb6f3f7465d27426f1684da58ea9f969
+                .size(42_u64)
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+
+        let blob = Bytes::from(vec![1, 2, 3]);
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/0.1.0")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(serde_json::to_string::<ImageIndex>(&index).unwrap())
+                .create_async()
+                .await,
+            // Only the darwin manifest is pulled
+            server
+                .mock("GET", "/v2/mockserver/test_package/manifests/sha256:bc669544845542470042912a0f61b90499ffc2320b45ea66b0be50439c5aab19")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(serde_json::to_string::<ImageManifest>(&manifest).unwrap())
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/test_package/blobs/sha256:8a576772defc4006637b27e7b0bef2c8bb6f3f7465d27426f1684da58ea9f969")
+                .with_status(200)
+                .with_body(blob.clone())
+                .create_async()
+                .await,
+            server
+                .mock("GET", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!(
+                "http://localhost.unittest/{encoded_url}/mockserver/test_package/test_package-0.1.0-{arch}"
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, blob);
+    }
+
+    #[tokio::test]
+    async fn download_package_platform_os_mismatch() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
         let encoded_url = urlencoding::encode(&url).into_owned();
@@ -2582,8 +6124,9 @@ mod tests {
                 .size(6_u64)
                 .platform(
                     PlatformBuilder::default()
-                        .architecture(Arch::Other(".whl".to_string()))
-                        .os(Os::Other("any".to_string()))
+                        .architecture(Arch::Other(".tar.gz".to_string()))
+                        // Same architecture, but not published by PyOCI
+                        .os(Os::Linux)
                         .build()
                         .unwrap(),
                 )
@@ -2636,7 +6179,10 @@ mod tests {
             mock.assert_async().await;
         }
         assert_eq!(status, StatusCode::NOT_FOUND);
-        assert_eq!(body, "Requested architecture '.tar.gz' not available");
+        assert_eq!(
+            body,
+            "Requested architecture '.tar.gz' not available, available architectures: ['.tar.gz']"
+        );
     }
 
     #[tokio::test]
@@ -2869,6 +6415,156 @@ mod tests {
         assert_eq!(body, "Deleted");
     }
 
+    #[tokio::test]
+    async fn delete_prereleases() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let encoded_url = urlencoding::encode(&url).into_owned();
+
+        let index = |manifest: &str| {
+            serde_json::json!({
+              "schemaVersion": 2,
+              "mediaType": "application/vnd.oci.image.index.v1+json",
+              "artifactType": ARTIFACT_TYPE,
+              "manifests": [{
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": digest(manifest),
+                "size": 6,
+                "platform": {"architecture": ".tar.gz", "os": "any"}
+              }]
+            })
+            .to_string()
+        };
+        let manifest = |layer: &str| {
+            serde_json::json!({
+              "schemaVersion": 2,
+              "mediaType": "application/vnd.oci.image.manifest.v1+json",
+              "artifactType": ARTIFACT_TYPE,
+              "config": {
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "digest": digest("config-digest"),
+                "size": 2
+              },
+              "layers": [{"mediaType": ARTIFACT_TYPE, "digest": digest(layer), "size": 42}]
+            })
+            .to_string()
+        };
+
+        let mut mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/test_package/tags/list")
+                .with_status(200)
+                .with_body(
+                    serde_json::json!({
+                      "name": "mockserver/test_package",
+                      "tags": ["1.0.0", "1.0.0.post1", "1.1.0a1", "2.0.0.dev1"]
+                    })
+                    .to_string(),
+                )
+                .create_async()
+                .await,
+        ];
+        // Stable versions are not touched
+        for method in ["GET", "DELETE"] {
+            mocks.push(
+                server
+                    .mock(
+                        method,
+                        mockito::Matcher::Regex(
+                            r"/v2/mockserver/test_package/manifests/1\.0\.0".to_string(),
+                        ),
+                    )
+                    .expect(0)
+                    .create_async()
+                    .await,
+            );
+        }
+        for version in ["1.1.0a1", "2.0.0.dev1"] {
+            let mani = format!("{version}-manifest");
+            let layer = format!("{version}-layer");
+            let manifest_path = format!("/v2/mockserver/test_package/manifests/{}", digest(&mani));
+            mocks.extend([
+                server
+                    .mock(
+                        "GET",
+                        format!("/v2/mockserver/test_package/manifests/{version}").as_str(),
+                    )
+                    .with_status(200)
+                    .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                    .with_body(index(&mani))
+                    .create_async()
+                    .await,
+                server
+                    .mock("GET", manifest_path.as_str())
+                    .with_status(200)
+                    .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                    .with_body(manifest(&layer))
+                    .create_async()
+                    .await,
+                server
+                    .mock(
+                        "DELETE",
+                        format!("/v2/mockserver/test_package/blobs/{}", digest(&layer)).as_str(),
+                    )
+                    .with_status(202)
+                    .create_async()
+                    .await,
+                server
+                    .mock("DELETE", manifest_path.as_str())
+                    .with_status(202)
+                    .create_async()
+                    .await,
+                server
+                    .mock(
+                        "DELETE",
+                        format!("/v2/mockserver/test_package/manifests/{version}").as_str(),
+                    )
+                    .with_status(202)
+                    .create_async()
+                    .await,
+            ]);
+        }
+
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("DELETE")
+            .uri(format!(
+                "/{encoded_url}/mockserver/test-package/?pre_only=1&confirm=1"
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Deleted 2 pre-releases");
+    }
+
+    #[test_case("confirm=1", "Only deleting pre-releases is supported, set the 'pre_only=1' query parameter"; "all versions")]
+    #[test_case("pre_only=1", "Set the 'confirm=1' query parameter to delete the pre-releases of 'test-package'"; "not confirmed")]
+    #[tokio::test]
+    async fn delete_prereleases_invalid_query(query: &str, expected: &str) {
+        let router = router(&Env::default());
+
+        let req = Request::builder()
+            .method("DELETE")
+            .uri(format!("/pypi/mockserver/test-package/?{query}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, expected);
+    }
+
     #[tokio::test]
     async fn delete_package_subpath() {
         let mut server = mockito::Server::new_async().await;
@@ -3008,6 +6704,80 @@ mod tests {
         assert_eq!(status, StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn health_otlp_inactive() {
+        let env = Env::default();
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri("/health/otlp")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body = String::from_utf8(
+            to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"active":false,"last_flush":null}"#);
+    }
+
+    #[test_case(200, true; "success")]
+    #[test_case(500, false; "failure")]
+    #[tokio::test]
+    async fn health_otlp_flush(collector_status: usize, success: bool) {
+        let mut server = mockito::Server::new_async().await;
+        for path in ["/v1/logs", "/v1/traces", "/v1/metrics"] {
+            server
+                .mock("POST", path)
+                .with_status(collector_status)
+                .create_async()
+                .await;
+        }
+
+        let env = Env::default();
+        let cancel_token = tokio_util::sync::CancellationToken::new();
+        let (subscriber, handle) = crate::otlp::otlp(
+            tracing_subscriber::registry(),
+            Some(server.url()),
+            Some("unittest".to_string()),
+            crate::otlp::Protocol::Http,
+            1.0,
+            HashMap::new(),
+            Duration::from_secs(1),
+            cancel_token.clone(),
+            env.otlp_status.clone(),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("unittest").entered();
+            tracing::info!("unittest log");
+        });
+        cancel_token.cancel();
+        handle.unwrap().await.unwrap();
+
+        let service = pyoci_service(&env);
+        let req = Request::builder()
+            .method("GET")
+            .uri("/health/otlp")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(req).await.unwrap();
+
+        let status = response.status();
+        let body: serde_json::Value =
+            serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+                .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["last_flush"]["success"], success);
+        assert_eq!(body["last_flush"]["error"].is_null(), success);
+        assert!(body["last_flush"]["timestamp"].is_string());
+    }
+
     #[test]
     fn router_empty_subpath() {
         let _ = router(&Env {
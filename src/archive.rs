@@ -0,0 +1,549 @@
+//! Inspect uploaded archives
+//!
+//! Used to reject decompression bombs without decompressing the upload.
+//! Only the sizes recorded in the archive are read, the compressed data itself is not inspected,
+//! except for the metadata of a wheel, see [`wheel_requires_dist`].
+use std::io::Read;
+
+use anyhow::Result;
+use flate2::read::DeflateDecoder;
+use http::StatusCode;
+
+use crate::{
+    error::PyOciError,
+    package::{Package, WithFileName},
+    spool::SpooledContent,
+};
+
+/// Size of the zip End Of Central Directory record, without the comment
+const EOCD_SIZE: u64 = 22;
+/// Maximum size of the zip archive comment
+const MAX_COMMENT_SIZE: u64 = 0xFFFF;
+/// Size of a zip local file header, without the name and extra field
+const LOCAL_HEADER_SIZE: u64 = 30;
+/// Maximum decompressed size of the `METADATA` of a wheel
+const MAX_METADATA_SIZE: u64 = 1024 * 1024;
+
+/// Entry of the zip central directory
+#[derive(Debug)]
+struct ZipEntry {
+    name: String,
+    /// Compression method, 0 is stored and 8 is deflate
+    method: u64,
+    compressed: u64,
+    uncompressed: u64,
+    /// Offset of the local file header
+    offset: u64,
+}
+
+/// Check the declared decompressed size of the content does not exceed `max_ratio` times the
+/// size of the content
+///
+/// Returns a `BAD_REQUEST` [`PyOciError`] if the ratio is exceeded or the archive can't be parsed.
+pub async fn verify_compression_ratio(
+    package: &Package<'_, WithFileName>,
+    content: &SpooledContent,
+    max_ratio: u64,
+) -> Result<()> {
+    let size = if package.oci_architecture() == ".tar.gz" {
+        gzip_size(content).await?
+    } else {
+        zip_size(content).await?
+    };
+    let Some(size) = size else {
+        let kind = if package.oci_architecture() == ".tar.gz" {
+            "gzip"
+        } else {
+            "zip"
+        };
+        return Err(PyOciError::from((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Content of '{}' is not a valid {kind} archive",
+                package.filename()
+            ),
+        ))
+        .into());
+    };
+    if size > content.len().saturating_mul(max_ratio) {
+        return Err(PyOciError::from((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Content of '{}' decompresses to {size} bytes, exceeding the maximum compression ratio of {max_ratio}",
+                package.filename()
+            ),
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Declared decompressed size of a gzip stream
+///
+/// Read from the ISIZE field of the gzip trailer, which holds the size modulo 2^32.
+/// Returns None if the content is too small to be a gzip stream.
+async fn gzip_size(content: &SpooledContent) -> Result<Option<u64>> {
+    // 10 bytes header and 8 bytes trailer
+    if content.len() < 18 {
+        return Ok(None);
+    }
+    let trailer = content.read_range(content.len() - 4, 4).await?;
+    Ok(le(&trailer, 0, 4))
+}
+
+/// Declared decompressed size of a zip archive
+///
+/// The sum of the uncompressed sizes of all entries in the central directory.
+/// Returns None if the central directory can't be found or parsed.
+async fn zip_size(content: &SpooledContent) -> Result<Option<u64>> {
+    let Some(entries) = zip_entries(content).await? else {
+        return Ok(None);
+    };
+    Ok(Some(entries.iter().fold(0, |total: u64, entry| {
+        total.saturating_add(entry.uncompressed)
+    })))
+}
+
+/// `Requires-Dist` entries of the `*.dist-info/METADATA` of a wheel
+///
+/// Returns a `BAD_REQUEST` [`PyOciError`] if the wheel has no readable `METADATA`.
+pub async fn wheel_requires_dist(
+    package: &Package<'_, WithFileName>,
+    content: &SpooledContent,
+) -> Result<Vec<String>> {
+    let invalid = |reason: &str| {
+        PyOciError::from((
+            StatusCode::BAD_REQUEST,
+            format!("Content of '{}' {reason}", package.filename()),
+        ))
+    };
+    let entries = zip_entries(content)
+        .await?
+        .ok_or_else(|| invalid("is not a valid zip archive"))?;
+    let entry = entries
+        .iter()
+        .find(|entry| {
+            entry
+                .name
+                .split_once('/')
+                .is_some_and(|(dir, file)| dir.ends_with(".dist-info") && file == "METADATA")
+        })
+        .ok_or_else(|| invalid("does not contain a .dist-info/METADATA file"))?;
+    let metadata = read_entry(content, entry)
+        .await?
+        .ok_or_else(|| invalid("has an unreadable .dist-info/METADATA file"))?;
+    Ok(requires_dist(&String::from_utf8_lossy(&metadata)))
+}
+
+/// Values of the `Requires-Dist` headers of core metadata
+///
+/// The headers end at the first empty line, the description follows.
+fn requires_dist(metadata: &str) -> Vec<String> {
+    metadata
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Requires-Dist"))
+        .map(|(_, value)| value.trim().to_string())
+        .collect()
+}
+
+/// Decompressed content of a stored or deflated zip entry
+///
+/// Returns None if the entry can't be read or exceeds [`MAX_METADATA_SIZE`].
+async fn read_entry(content: &SpooledContent, entry: &ZipEntry) -> Result<Option<Vec<u8>>> {
+    if entry.uncompressed > MAX_METADATA_SIZE || entry.compressed > MAX_METADATA_SIZE {
+        return Ok(None);
+    }
+    let header = content.read_range(entry.offset, LOCAL_HEADER_SIZE).await?;
+    if !header.starts_with(b"PK\x03\x04") {
+        return Ok(None);
+    }
+    let (Some(name_len), Some(extra_len)) = (le(&header, 26, 2), le(&header, 28, 2)) else {
+        return Ok(None);
+    };
+    let start = entry.offset + LOCAL_HEADER_SIZE + name_len + extra_len;
+    let data = content.read_range(start, entry.compressed).await?;
+    if data.len() as u64 != entry.compressed {
+        return Ok(None);
+    }
+    let mut decompressed = Vec::new();
+    match entry.method {
+        0 => decompressed = data,
+        8 => {
+            if DeflateDecoder::new(data.as_slice())
+                .take(MAX_METADATA_SIZE + 1)
+                .read_to_end(&mut decompressed)
+                .is_err()
+            {
+                return Ok(None);
+            }
+        }
+        _ => return Ok(None),
+    }
+    if decompressed.len() as u64 != entry.uncompressed {
+        return Ok(None);
+    }
+    Ok(Some(decompressed))
+}
+
+/// Entries of the central directory of a zip archive
+///
+/// Returns None if the central directory can't be found or parsed.
+async fn zip_entries(content: &SpooledContent) -> Result<Option<Vec<ZipEntry>>> {
+    let Some((offset, size, entries)) = central_directory(content).await? else {
+        return Ok(None);
+    };
+    if offset
+        .checked_add(size)
+        .is_none_or(|end| end > content.len())
+    {
+        return Ok(None);
+    }
+    let directory = content.read_range(offset, size).await?;
+
+    let mut result = Vec::new();
+    let mut pos = 0;
+    for _ in 0..entries {
+        let Some(header) = directory.get(pos..pos + 46) else {
+            return Ok(None);
+        };
+        if !header.starts_with(b"PK\x01\x02") {
+            return Ok(None);
+        }
+        let (
+            Some(method),
+            Some(compressed),
+            Some(uncompressed),
+            Some(name_len),
+            Some(extra_len),
+            Some(comment_len),
+            Some(local_offset),
+        ) = (
+            le(header, 10, 2),
+            le(header, 20, 4),
+            le(header, 24, 4),
+            le_usize(header, 28),
+            le_usize(header, 30),
+            le_usize(header, 32),
+            le(header, 42, 4),
+        )
+        else {
+            return Ok(None);
+        };
+        let name_start = pos + 46;
+        let extra_start = name_start + name_len;
+        let (Some(name), Some(extra)) = (
+            directory.get(name_start..extra_start),
+            directory.get(extra_start..extra_start + extra_len),
+        ) else {
+            return Ok(None);
+        };
+        // Values that don't fit are stored in the Zip64 extended information extra field
+        let Some([uncompressed, compressed, local_offset]) =
+            zip64_extra(extra, [uncompressed, compressed, local_offset])
+        else {
+            return Ok(None);
+        };
+        result.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method,
+            compressed,
+            uncompressed,
+            offset: local_offset,
+        });
+        pos = extra_start + extra_len + comment_len;
+    }
+    Ok(Some(result))
+}
+
+/// Locate the central directory of a zip archive
+///
+/// Returns the (offset, size, number of entries) of the central directory.
+async fn central_directory(content: &SpooledContent) -> Result<Option<(u64, u64, u64)>> {
+    if content.len() < EOCD_SIZE {
+        return Ok(None);
+    }
+    // The End Of Central Directory record is at the end, followed by an optional comment
+    let tail_start = content.len().saturating_sub(EOCD_SIZE + MAX_COMMENT_SIZE);
+    let tail = content
+        .read_range(tail_start, content.len() - tail_start)
+        .await?;
+    let Some(eocd_pos) = (0..=tail.len() - 22)
+        .rev()
+        .find(|&pos| tail[pos..].starts_with(b"PK\x05\x06"))
+    else {
+        return Ok(None);
+    };
+    let eocd = &tail[eocd_pos..];
+    let (Some(entries), Some(size), Some(offset)) =
+        (le(eocd, 10, 2), le(eocd, 12, 4), le(eocd, 16, 4))
+    else {
+        return Ok(None);
+    };
+    if entries != 0xFFFF && size != 0xFFFF_FFFF && offset != 0xFFFF_FFFF {
+        return Ok(Some((offset, size, entries)));
+    }
+
+    // Zip64 archive, the Zip64 End Of Central Directory locator precedes the EOCD record
+    let Some(locator) = eocd_pos
+        .checked_sub(20)
+        .map(|pos| &tail[pos..eocd_pos])
+        .filter(|locator| locator.starts_with(b"PK\x06\x07"))
+    else {
+        return Ok(None);
+    };
+    let Some(eocd64_offset) = le(locator, 8, 8).filter(|offset| *offset < content.len()) else {
+        return Ok(None);
+    };
+    let eocd64 = content.read_range(eocd64_offset, 56).await?;
+    if !eocd64.starts_with(b"PK\x06\x06") {
+        return Ok(None);
+    }
+    let (Some(entries), Some(size), Some(offset)) =
+        (le(&eocd64, 32, 8), le(&eocd64, 40, 8), le(&eocd64, 48, 8))
+    else {
+        return Ok(None);
+    };
+    Ok(Some((offset, size, entries)))
+}
+
+/// Replace the `values` set to `0xFFFFFFFF` by the Zip64 extended information extra field
+///
+/// The field holds the uncompressed size, compressed size and offset, in that order,
+/// only including the values that did not fit.
+fn zip64_extra(extra: &[u8], mut values: [u64; 3]) -> Option<[u64; 3]> {
+    if !values.contains(&0xFFFF_FFFF) {
+        return Some(values);
+    }
+    let mut pos = 0;
+    while let (Some(id), Some(len)) = (le(extra, pos, 2), le_usize(extra, pos + 2)) {
+        if id == 0x0001 {
+            let mut field = pos + 4;
+            for value in values.iter_mut().filter(|value| **value == 0xFFFF_FFFF) {
+                *value = le(extra, field, 8)?;
+                field += 8;
+            }
+            return Some(values);
+        }
+        pos += 4 + len;
+    }
+    None
+}
+
+/// Read a little-endian unsigned integer of `size` bytes at `offset`
+fn le(data: &[u8], offset: usize, size: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + size)?;
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| (value << 8) | u64::from(*byte)),
+    )
+}
+
+/// Read a little-endian 2 byte length at `offset`
+fn le_usize(data: &[u8], offset: usize) -> Option<usize> {
+    le(data, offset, 2).and_then(|value| usize::try_from(value).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    /// Gzip stream declaring `size` decompressed bytes
+    fn gzip(size: u32) -> Vec<u8> {
+        let mut data = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03".to_vec();
+        data.extend_from_slice(b"compressed");
+        // CRC32
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&size.to_le_bytes());
+        data
+    }
+
+    /// Zip archive with an entry for each of the declared uncompressed `sizes`
+    ///
+    /// Sizes that don't fit 32 bits are written as Zip64 extra fields.
+    fn zip(sizes: &[u64]) -> Vec<u8> {
+        let mut data = b"PK\x03\x04local entries".to_vec();
+        let offset = data.len();
+        for (i, size) in sizes.iter().enumerate() {
+            let name = format!("file{i}");
+            let (size32, extra) = match u32::try_from(*size) {
+                Ok(size) if size != u32::MAX => (size, Vec::new()),
+                _ => {
+                    let mut extra = b"\x01\x00\x08\x00".to_vec();
+                    extra.extend_from_slice(&size.to_le_bytes());
+                    (u32::MAX, extra)
+                }
+            };
+            data.extend_from_slice(b"PK\x01\x02");
+            data.extend_from_slice(&[0; 16]);
+            // Compressed size
+            data.extend_from_slice(&10u32.to_le_bytes());
+            data.extend_from_slice(&size32.to_le_bytes());
+            data.extend_from_slice(&u16::try_from(name.len()).unwrap().to_le_bytes());
+            data.extend_from_slice(&u16::try_from(extra.len()).unwrap().to_le_bytes());
+            data.extend_from_slice(&[0; 14]);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&extra);
+        }
+        let size = data.len() - offset;
+        data.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00");
+        let entries = u16::try_from(sizes.len()).unwrap().to_le_bytes();
+        data.extend_from_slice(&entries);
+        data.extend_from_slice(&entries);
+        data.extend_from_slice(&u32::try_from(size).unwrap().to_le_bytes());
+        data.extend_from_slice(&u32::try_from(offset).unwrap().to_le_bytes());
+        data.extend_from_slice(b"\x07\x00comment");
+        data
+    }
+
+    #[tokio::test]
+    async fn gzip_declared_size() {
+        let content = SpooledContent::from(gzip(1234));
+        assert_eq!(gzip_size(&content).await.unwrap(), Some(1234));
+    }
+
+    #[test_case(&[], 0; "empty")]
+    #[test_case(&[10, 20, 30], 60; "multiple")]
+    #[test_case(&[10, 5_000_000_000], 5_000_000_010; "zip64 entry")]
+    #[tokio::test]
+    async fn zip_declared_size(sizes: &[u64], expected: u64) {
+        let content = SpooledContent::from(zip(sizes));
+        assert_eq!(zip_size(&content).await.unwrap(), Some(expected));
+    }
+
+    #[test_case(b"PK\x03\x04"; "too small")]
+    #[test_case(b"PK\x03\x04 no central directory record"; "no eocd")]
+    #[test_case(b"PK\x03\x04PK\x05\x06\x00\x00\x00\x00\x01\x00\x01\x00\x2e\x00\x00\x00\x00\x00\x00\x00\x00\x00"; "directory out of bounds")]
+    #[tokio::test]
+    async fn zip_invalid(data: &[u8]) {
+        let content = SpooledContent::from(data.to_vec());
+        assert_eq!(zip_size(&content).await.unwrap(), None);
+    }
+
+    #[test_case("foo-1.tar.gz", gzip(1000), 100; "sdist")]
+    #[test_case("foo-1-py3-none-any.whl", zip(&[1000, 2000]), 100; "wheel")]
+    #[tokio::test]
+    async fn compression_ratio(filename: &str, data: Vec<u8>, max_ratio: u64) {
+        let package = Package::from_filename("ghcr.io", "allexveldman", "foo", filename).unwrap();
+        let content = SpooledContent::from(data);
+        verify_compression_ratio(&package, &content, max_ratio)
+            .await
+            .unwrap();
+    }
+
+    #[test_case("foo-1.tar.gz", gzip(u32::MAX), "Content of 'foo-1.tar.gz' decompresses to 4294967295 bytes, exceeding the maximum compression ratio of 100"; "sdist bomb")]
+    #[test_case("foo-1-py3-none-any.whl", zip(&[1_000_000, 5_000_000_000]), "Content of 'foo-1-py3-none-any.whl' decompresses to 5001000000 bytes, exceeding the maximum compression ratio of 100"; "wheel bomb")]
+    #[test_case("foo-1.tar.gz", b"\x1f\x8b".to_vec(), "Content of 'foo-1.tar.gz' is not a valid gzip archive"; "sdist truncated")]
+    #[test_case("foo-1-py3-none-any.whl", b"PK\x03\x04".to_vec(), "Content of 'foo-1-py3-none-any.whl' is not a valid zip archive"; "wheel truncated")]
+    #[tokio::test]
+    async fn compression_ratio_exceeded(filename: &str, data: Vec<u8>, message: &str) {
+        let package = Package::from_filename("ghcr.io", "allexveldman", "foo", filename).unwrap();
+        let content = SpooledContent::from(data);
+        let err = verify_compression_ratio(&package, &content, 100)
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<PyOciError>().unwrap();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(err.message, message);
+    }
+
+    /// Zip archive of the `files`, the content is deflated when `deflate` is set
+    fn wheel(files: &[(&str, &[u8])], deflate: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+        for (name, content) in files {
+            let (method, compressed) = if deflate {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                std::io::Write::write_all(&mut encoder, content).unwrap();
+                (8u16, encoder.finish().unwrap())
+            } else {
+                (0u16, content.to_vec())
+            };
+            let offset = u32::try_from(data.len()).unwrap();
+            let name_len = u16::try_from(name.len()).unwrap().to_le_bytes();
+            let compressed_len = u32::try_from(compressed.len()).unwrap().to_le_bytes();
+            let content_len = u32::try_from(content.len()).unwrap().to_le_bytes();
+
+            data.extend_from_slice(b"PK\x03\x04\x14\x00\x00\x00");
+            data.extend_from_slice(&method.to_le_bytes());
+            // Time, date and CRC32
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&compressed_len);
+            data.extend_from_slice(&content_len);
+            data.extend_from_slice(&name_len);
+            data.extend_from_slice(&[0; 2]);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&compressed);
+
+            directory.extend_from_slice(b"PK\x01\x02\x14\x00\x14\x00\x00\x00");
+            directory.extend_from_slice(&method.to_le_bytes());
+            directory.extend_from_slice(&[0; 8]);
+            directory.extend_from_slice(&compressed_len);
+            directory.extend_from_slice(&content_len);
+            directory.extend_from_slice(&name_len);
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+        let offset = u32::try_from(data.len()).unwrap();
+        let size = u32::try_from(directory.len()).unwrap();
+        data.extend_from_slice(&directory);
+        data.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00");
+        let entries = u16::try_from(files.len()).unwrap().to_le_bytes();
+        data.extend_from_slice(&entries);
+        data.extend_from_slice(&entries);
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data
+    }
+
+    const METADATA: &[u8] = b"Metadata-Version: 2.1\r\n\
+        Name: foo\r\n\
+        Version: 1\r\n\
+        Requires-Dist: requests>=2.0\r\n\
+        Requires-Dist: pytest; extra == \"test\"\r\n\
+        \r\n\
+        Requires-Dist: in the description\r\n";
+
+    #[test_case(false; "stored")]
+    #[test_case(true; "deflated")]
+    #[tokio::test]
+    async fn wheel_metadata_requires_dist(deflate: bool) {
+        let package =
+            Package::from_filename("ghcr.io", "allexveldman", "foo", "foo-1-py3-none-any.whl")
+                .unwrap();
+        let content = SpooledContent::from(wheel(
+            &[
+                ("foo/__init__.py", b"".as_slice()),
+                ("foo/data.dist-info/METADATA", b"Requires-Dist: nested"),
+                ("foo-1.dist-info/METADATA", METADATA),
+            ],
+            deflate,
+        ));
+        assert_eq!(
+            wheel_requires_dist(&package, &content).await.unwrap(),
+            vec!["requests>=2.0", "pytest; extra == \"test\""]
+        );
+    }
+
+    #[test_case(wheel(&[("foo/__init__.py", b"")], true), "Content of 'foo-1-py3-none-any.whl' does not contain a .dist-info/METADATA file"; "missing")]
+    #[test_case(b"PK\x03\x04".to_vec(), "Content of 'foo-1-py3-none-any.whl' is not a valid zip archive"; "truncated")]
+    #[tokio::test]
+    async fn wheel_metadata_invalid(data: Vec<u8>, message: &str) {
+        let package =
+            Package::from_filename("ghcr.io", "allexveldman", "foo", "foo-1-py3-none-any.whl")
+                .unwrap();
+        let content = SpooledContent::from(data);
+        let err = wheel_requires_dist(&package, &content).await.unwrap_err();
+        let err = err.downcast_ref::<PyOciError>().unwrap();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(err.message, message);
+    }
+}
@@ -0,0 +1,117 @@
+//! In-process cache for blob content
+//!
+//! Blobs are addressed by their registry, repository and digest, so cached content never goes
+//! stale and is only served for the repository it was pulled from.
+//! The least recently used blobs are evicted once the cache exceeds its capacity.
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use bytes::Bytes;
+
+/// Blobs larger than this fraction of the capacity are not cached,
+/// so a single large file can not evict the whole cache
+const MAX_ENTRY_FRACTION: u64 = 8;
+
+/// Least recently used cache of blob content, bounded by the total size in bytes
+#[derive(Debug)]
+pub struct BlobCache {
+    /// Maximum total size of the cached content in bytes
+    capacity: u64,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// (last use, content) by key
+    entries: HashMap<String, (u64, Bytes)>,
+    /// Key by last use, oldest first
+    order: BTreeMap<u64, String>,
+    /// Counter to order the uses
+    tick: u64,
+    /// Total size of the cached content in bytes
+    size: u64,
+}
+
+impl BlobCache {
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Returns true if a blob of `size` bytes is small enough to be cached
+    pub fn accepts(&self, size: u64) -> bool {
+        size <= self.capacity / MAX_ENTRY_FRACTION
+    }
+
+    /// Get the content of a blob, marking it as recently used
+    pub fn get(&self, digest: &str) -> Option<Bytes> {
+        let mut inner = self.inner.lock().expect("Failed to lock blob cache");
+        let inner = &mut *inner;
+        inner.tick += 1;
+        let (last_use, data) = inner.entries.get_mut(digest)?;
+        inner.order.remove(last_use);
+        inner.order.insert(inner.tick, digest.to_string());
+        *last_use = inner.tick;
+        Some(data.clone())
+    }
+
+    /// Add the content of a blob, evicting the least recently used blobs when over capacity
+    pub fn insert(&self, digest: String, data: Bytes) {
+        let size = data.len() as u64;
+        if !self.accepts(size) {
+            return;
+        }
+        let mut inner = self.inner.lock().expect("Failed to lock blob cache");
+        inner.tick += 1;
+        let tick = inner.tick;
+        if let Some((last_use, old)) = inner.entries.insert(digest.clone(), (tick, data)) {
+            inner.order.remove(&last_use);
+            inner.size -= old.len() as u64;
+        }
+        inner.order.insert(tick, digest);
+        inner.size += size;
+        while inner.size > self.capacity {
+            let Some((_, digest)) = inner.order.pop_first() else {
+                break;
+            };
+            if let Some((_, data)) = inner.entries.remove(&digest) {
+                inner.size -= data.len() as u64;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn blob_cache_evicts_least_recently_used() {
+        let cache = BlobCache::new(80);
+        cache.insert("a".to_string(), Bytes::from(vec![b'a'; 10]));
+        cache.insert("b".to_string(), Bytes::from(vec![b'b'; 10]));
+        // Use "a" so "b" becomes the least recently used
+        assert!(cache.get("a").is_some());
+        for digest in ["c", "d", "e", "f", "g", "h", "i"] {
+            cache.insert(digest.to_string(), Bytes::from(vec![b'x'; 10]));
+        }
+
+        assert_eq!(cache.get("a"), Some(Bytes::from(vec![b'a'; 10])));
+        assert_eq!(cache.get("b"), None);
+    }
+
+    #[test]
+    fn blob_cache_skips_large_blobs() {
+        let cache = BlobCache::new(80);
+        assert!(cache.accepts(10));
+        assert!(!cache.accepts(11));
+        cache.insert("a".to_string(), Bytes::from(vec![b'a'; 11]));
+
+        assert_eq!(cache.get("a"), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Concurrency limit per key
+//!
+//! Used to limit the number of simultaneous publishes to a single namespace,
+//! without holding back the other namespaces.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Keyed semaphore that rejects instead of waiting when a key has no permits left
+#[derive(Debug, Clone, Default)]
+pub struct KeyedLimit {
+    /// Permits per key, 0 disables the limit
+    limit: usize,
+    /// Number of permits in use by key, keys without permits in use are removed
+    in_use: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl KeyedLimit {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            in_use: Arc::default(),
+        }
+    }
+
+    /// Take a permit for `key`, returns `None` when all permits of `key` are in use
+    ///
+    /// The permit is given back when the returned [`Permit`] is dropped.
+    pub fn try_acquire(&self, key: &str) -> Option<Permit> {
+        if self.limit == 0 {
+            return Some(Permit {
+                limit: self.clone(),
+                key: None,
+            });
+        }
+        let mut in_use = self.in_use.lock().expect("Failed to lock permits");
+        let count = in_use.entry(key.to_string()).or_default();
+        if *count >= self.limit {
+            return None;
+        }
+        *count += 1;
+        Some(Permit {
+            limit: self.clone(),
+            key: Some(key.to_string()),
+        })
+    }
+}
+
+/// Permit of a [`KeyedLimit`], given back on drop
+#[derive(Debug)]
+pub struct Permit {
+    limit: KeyedLimit,
+    /// `None` when the limit is disabled
+    key: Option<String>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let mut in_use = self.limit.in_use.lock().expect("Failed to lock permits");
+        if let Some(count) = in_use.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                in_use.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyed_limit_per_key() {
+        let limit = KeyedLimit::new(2);
+        let first = limit.try_acquire("ghcr.io/team-a");
+        let second = limit.try_acquire("ghcr.io/team-a");
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(limit.try_acquire("ghcr.io/team-a").is_none());
+        // Other keys are not affected
+        assert!(limit.try_acquire("ghcr.io/team-b").is_some());
+
+        drop(first);
+        assert!(limit.try_acquire("ghcr.io/team-a").is_some());
+    }
+
+    #[test]
+    fn keyed_limit_releases_keys() {
+        let limit = KeyedLimit::new(1);
+        let permit = limit.try_acquire("ghcr.io/team-a");
+        assert!(permit.is_some());
+        drop(permit);
+
+        assert!(limit.in_use.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keyed_limit_disabled() {
+        let limit = KeyedLimit::new(0);
+        let permits: Vec<_> = (0..10)
+            .map(|_| limit.try_acquire("ghcr.io/team-a"))
+            .collect();
+
+        assert!(permits.iter().all(Option::is_some));
+        assert!(limit.in_use.lock().unwrap().is_empty());
+    }
+}
@@ -21,6 +21,19 @@ mod service;
 mod time;
 // Error type
 mod error;
+// Spooled buffer for uploads
+mod spool;
+// Archive inspection
+mod archive;
+// PEP 440 version ordering
+mod version;
+// In-process cache for blob content
+mod cache;
+// Concurrency limit per key
+mod limit;
+// In-memory OCI registry
+#[cfg(any(test, feature = "test-registry"))]
+mod registry;
 
 use axum::ServiceExt;
 use pyoci::PyOci;
@@ -28,7 +41,9 @@ use tokio::task::JoinHandle;
 
 use std::collections::HashMap;
 use std::env;
+use std::future::{Future, IntoFuture};
 use std::net::Ipv6Addr;
+use std::path::PathBuf;
 use std::sync::LazyLock;
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
@@ -42,10 +57,20 @@ use crate::otlp::otlp;
 // crate constants
 const PYOCI_VERSION: &str = env!("CARGO_PKG_VERSION");
 const USER_AGENT: &str = concat!("pyoci ", env!("CARGO_PKG_VERSION"));
+/// Artifact type of the `ImageIndex` and `ImageManifest` of a package
 const ARTIFACT_TYPE: &str = "application/pyoci.package.v1";
+/// Default media type of the layer holding the package file
+const LAYER_MEDIA_TYPE: &str = "application/pyoci.package.v1";
+/// Default media type of the `{}` config of a published `ImageManifest`
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+/// Default content of the config of a published `ImageManifest`
+const CONFIG_CONTENT: &str = "{}";
+/// Default prefix of the classifiers holding a label
+const LABEL_CLASSIFIER: &str = "PyOCI :: Label";
 
 /// Runtime environment variables
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 struct Env {
     /// Post `PyOCI` is listening on
     port: u16,
@@ -57,16 +82,120 @@ struct Env {
     otlp_endpoint: Option<String>,
     /// OTLP authentication header value
     otlp_auth: Option<String>,
+    /// Transport used to send data to the OTLP collector
+    otlp_protocol: otlp::Protocol,
+    /// Fraction of the traces exported to the OTLP collector
+    otlp_trace_sample_ratio: f64,
+    /// Status of the OTLP exporter, reported on `/health/otlp`
+    otlp_status: otlp::OtlpStatus,
+    /// Additional OTLP resource attributes, as `(key, value)` pairs
+    otlp_resource_attributes: Vec<(String, String)>,
     #[allow(clippy::struct_field_names)]
     deployment_env: Option<String>,
     container_name: Option<String>,
     pod_name: Option<String>,
     replica_name: Option<String>,
     body_limit: usize,
+    /// Maximum size of a published wheel, 0 only applies `body_limit`
+    body_limit_wheel: usize,
+    /// Maximum size of a published source distribution, 0 only applies `body_limit`
+    body_limit_sdist: usize,
     /// Maximum number of version `PyOCI` will fetch when listing a package
     max_versions: usize,
+    /// Response of the package listing when a package has no files
+    empty_list: app::EmptyList,
     /// User Basic auth password as Bearer token if this username is used
     bearer_username: Option<String>,
+    /// Use the `Proxy-Authorization` header when the `Authorization` header is missing
+    proxy_authorization: bool,
+    /// Trust the `X-Forwarded-*` headers set by a reverse proxy
+    trust_proxy: bool,
+    /// Render absolute package links, using the `Host` header when there is no forwarded host
+    absolute_links: bool,
+    /// Delete unreferenced blobs when deleting a package version
+    collect_garbage: bool,
+    /// Use HTTP/2 prior knowledge for requests to the upstream OCI registry
+    http2: bool,
+    /// Consecutive upstream failures before the circuit breaker opens, 0 disables it
+    circuit_breaker_threshold: u32,
+    /// Seconds the circuit breaker stays open
+    circuit_breaker_cooldown: u64,
+    /// Maximum requests per second to a single registry, 0 disables the rate limit
+    rate_limit: f64,
+    /// PEM file with additional root certificates for the upstream registries
+    ca_bundle: Option<PathBuf>,
+    /// Skip TLS certificate verification of the upstream registries
+    tls_insecure: bool,
+    /// Maximum size of manifest and tag list responses from the upstream registries
+    max_upstream_body: usize,
+    /// Maximum number of manifests in an `ImageIndex` from the upstream registries
+    max_manifests: usize,
+    /// Maximum time to handle a single request
+    request_deadline: Option<Duration>,
+    /// Time in-flight requests get to complete when shutting down
+    drain_timeout: Duration,
+    /// Tags to probe when the registry returns an empty tag list
+    tag_fallback: Vec<String>,
+    /// Prefixes of the annotations exposed when listing a package
+    annotation_prefixes: Vec<String>,
+    /// Refuse an `ImageIndex` without the `PyOCI` artifact type
+    strict_artifact_type: bool,
+    /// Add the size of each file to the links when listing a package
+    show_sizes: bool,
+    /// Bytes of an upload kept in memory before it is spooled to a temporary file
+    upload_buffer: usize,
+    /// Size in bytes of the chunks blobs are uploaded in, 0 uploads blobs in a single request
+    upload_chunk_size: u64,
+    /// Maximum ratio between the declared decompressed size and the size of an upload,
+    /// 0 disables the check
+    max_compression_ratio: u64,
+    /// File to read the registry Bearer token from, for requests without credentials
+    bearer_token_file: Option<PathBuf>,
+    /// Registry hosts the token of `bearer_token_file` is sent to
+    bearer_token_hosts: Vec<String>,
+    /// Maximum number of concurrent manifest fetches across all requests
+    upstream_concurrency: usize,
+    /// Maximum number of simultaneous publishes to a single namespace, 0 disables the limit
+    publish_concurrency: usize,
+    /// Verify the repository can be published to before publishing
+    verify_namespace: bool,
+    /// Read `Requires-Dist` from the METADATA of a wheel instead of the upload form
+    requires_dist_from_metadata: bool,
+    /// Media type of the layer holding a published package file
+    layer_media_type: String,
+    /// Media type of the config of a published `ImageManifest`
+    config_media_type: String,
+    /// Content of the config of a published `ImageManifest`
+    config_content: String,
+    /// Prefix of the classifiers holding a label
+    label_classifier: String,
+    /// Classifiers added as label, as `(classifier, key)` pairs
+    classifier_labels: Vec<(String, String)>,
+    /// Project URL labels published as annotations, and the annotation key
+    project_url_annotations: Vec<(String, String)>,
+    /// Size in bytes of the in-process blob cache, 0 disables the cache
+    blob_cache_bytes: u64,
+    /// Proxy for requests to the registry
+    proxy: Option<String>,
+    /// Hosts that bypass the proxy
+    no_proxy: Vec<String>,
+    /// Media types for the `Accept` header of manifest requests, in order of preference
+    manifest_accept: Vec<String>,
+    /// `Content-Type` of pushed image manifests
+    manifest_content_type: Option<String>,
+    /// `Content-Type` of pushed image indexes
+    index_content_type: Option<String>,
+    /// Maximum number of redirects followed for a request to the registry
+    max_redirects: usize,
+    /// Maximum idle connections kept open per registry host
+    pool_max_idle_per_host: Option<usize>,
+    /// Seconds an idle connection to the registry is kept open
+    pool_idle_timeout: Option<u64>,
+    /// How the pages of the tag list are requested
+    tag_pagination: transport::TagPagination,
+    /// Serve an in-memory OCI registry on `localhost:5000`
+    #[cfg(feature = "test-registry")]
+    inmem_registry: bool,
 }
 
 impl Env {
@@ -78,15 +207,68 @@ impl Env {
             path: None,
             otlp_endpoint: None,
             otlp_auth: None,
+            otlp_protocol: otlp::Protocol::Http,
+            otlp_trace_sample_ratio: 1.0,
+            otlp_status: otlp::OtlpStatus::default(),
+            otlp_resource_attributes: vec![],
             deployment_env: None,
             container_name: None,
             pod_name: None,
             replica_name: None,
             body_limit: 50_000_000,
+            body_limit_wheel: 0,
+            body_limit_sdist: 0,
             max_versions: 100,
+            empty_list: app::EmptyList::Empty,
             bearer_username: None,
+            proxy_authorization: false,
+            trust_proxy: false,
+            absolute_links: false,
+            collect_garbage: false,
+            http2: false,
+            circuit_breaker_threshold: 0,
+            circuit_breaker_cooldown: 30,
+            rate_limit: 0.0,
+            ca_bundle: None,
+            tls_insecure: false,
+            max_upstream_body: 10_000_000,
+            max_manifests: 1000,
+            request_deadline: None,
+            drain_timeout: Duration::from_secs(30),
+            tag_fallback: Vec::new(),
+            annotation_prefixes: vec!["org.opencontainers.".to_string()],
+            strict_artifact_type: true,
+            show_sizes: false,
+            upload_buffer: 10_000_000,
+            upload_chunk_size: 0,
+            max_compression_ratio: 0,
+            bearer_token_file: None,
+            bearer_token_hosts: vec![],
+            upstream_concurrency: 0,
+            publish_concurrency: 0,
+            verify_namespace: false,
+            requires_dist_from_metadata: false,
+            layer_media_type: LAYER_MEDIA_TYPE.to_string(),
+            config_media_type: CONFIG_MEDIA_TYPE.to_string(),
+            config_content: CONFIG_CONTENT.to_string(),
+            label_classifier: LABEL_CLASSIFIER.to_string(),
+            classifier_labels: vec![],
+            project_url_annotations: vec![],
+            blob_cache_bytes: 0,
+            proxy: None,
+            no_proxy: vec![],
+            manifest_accept: vec![],
+            manifest_content_type: None,
+            index_content_type: None,
+            max_redirects: 10,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            tag_pagination: transport::TagPagination::Link,
+            #[cfg(feature = "test-registry")]
+            inmem_registry: false,
         }
     }
+    #[allow(clippy::too_many_lines)]
     fn new() -> Self {
         Self {
             port: env::var("PORT")
@@ -98,13 +280,177 @@ impl Env {
             body_limit: env::var("PYOCI_MAX_BODY").map_or(50_000_000, |f| {
                 f.parse().expect("PYOCI_MAX_BODY is not a valid integer")
             }),
+            body_limit_wheel: env::var("PYOCI_MAX_BODY_WHEEL").map_or(0, |f| {
+                f.parse()
+                    .expect("PYOCI_MAX_BODY_WHEEL is not a valid integer")
+            }),
+            body_limit_sdist: env::var("PYOCI_MAX_BODY_SDIST").map_or(0, |f| {
+                f.parse()
+                    .expect("PYOCI_MAX_BODY_SDIST is not a valid integer")
+            }),
             max_versions: env::var("PYOCI_MAX_VERSIONS").map_or(100, |f| {
                 f.parse()
                     .expect("PYOCI_MAX_VERSIONS is not a valid integer")
             }),
+            empty_list: env::var("PYOCI_EMPTY_LIST").map_or(app::EmptyList::Empty, |f| {
+                f.parse().expect("PYOCI_EMPTY_LIST is not '404' or 'empty'")
+            }),
             bearer_username: env::var("PYOCI_BEARER_USERNAME").ok(),
+            proxy_authorization: env_flag("PYOCI_PROXY_AUTHORIZATION"),
+            trust_proxy: env_flag("PYOCI_TRUST_PROXY"),
+            absolute_links: env_flag("PYOCI_ABSOLUTE_LINKS"),
+            collect_garbage: env_flag("PYOCI_GC"),
+            http2: env_flag("PYOCI_HTTP2"),
+            circuit_breaker_threshold: env::var("PYOCI_CIRCUIT_BREAKER_THRESHOLD").map_or(0, |f| {
+                f.parse()
+                    .expect("PYOCI_CIRCUIT_BREAKER_THRESHOLD is not a valid integer")
+            }),
+            circuit_breaker_cooldown: env::var("PYOCI_CIRCUIT_BREAKER_COOLDOWN").map_or(30, |f| {
+                f.parse()
+                    .expect("PYOCI_CIRCUIT_BREAKER_COOLDOWN is not a valid integer")
+            }),
+            rate_limit: env::var("PYOCI_RATE_LIMIT").map_or(0.0, |f| {
+                f.parse().expect("PYOCI_RATE_LIMIT is not a valid number")
+            }),
+            ca_bundle: env::var_os("PYOCI_CA_BUNDLE").map(PathBuf::from),
+            tls_insecure: env_flag("PYOCI_TLS_INSECURE"),
+            max_upstream_body: env::var("PYOCI_MAX_UPSTREAM_BODY").map_or(10_000_000, |f| {
+                f.parse()
+                    .expect("PYOCI_MAX_UPSTREAM_BODY is not a valid integer")
+            }),
+            max_manifests: env::var("PYOCI_MAX_MANIFESTS").map_or(1000, |f| {
+                f.parse()
+                    .expect("PYOCI_MAX_MANIFESTS is not a valid integer")
+            }),
+            request_deadline: env::var("PYOCI_REQUEST_DEADLINE").ok().map(|f| {
+                Duration::from_secs(
+                    f.parse()
+                        .expect("PYOCI_REQUEST_DEADLINE is not a valid integer"),
+                )
+            }),
+            drain_timeout: Duration::from_secs(env::var("PYOCI_DRAIN_TIMEOUT").map_or(30, |f| {
+                f.parse()
+                    .expect("PYOCI_DRAIN_TIMEOUT is not a valid integer")
+            })),
+            tag_fallback: env_list("PYOCI_TAG_FALLBACK").unwrap_or_default(),
+            annotation_prefixes: env_list("PYOCI_ANNOTATION_PREFIXES")
+                .unwrap_or_else(|| vec!["org.opencontainers.".to_string()]),
+            strict_artifact_type: env::var("PYOCI_STRICT_ARTIFACT_TYPE").map_or(true, |f| {
+                !matches!(f.to_lowercase().as_str(), "0" | "false")
+            }),
+            show_sizes: env_flag("PYOCI_SHOW_SIZES"),
+            verify_namespace: env_flag("PYOCI_VERIFY_NAMESPACE"),
+            requires_dist_from_metadata: env_flag("PYOCI_REQUIRES_DIST_FROM_METADATA"),
+            upload_buffer: env::var("PYOCI_UPLOAD_BUFFER").map_or(10_000_000, |f| {
+                f.parse()
+                    .expect("PYOCI_UPLOAD_BUFFER is not a valid integer")
+            }),
+            upload_chunk_size: env::var("PYOCI_UPLOAD_CHUNK_SIZE").map_or(0, |f| {
+                f.parse()
+                    .expect("PYOCI_UPLOAD_CHUNK_SIZE is not a valid integer")
+            }),
+            max_compression_ratio: env::var("PYOCI_MAX_COMPRESSION_RATIO").map_or(0, |f| {
+                f.parse()
+                    .expect("PYOCI_MAX_COMPRESSION_RATIO is not a valid integer")
+            }),
+            bearer_token_file: env::var_os("PYOCI_BEARER_TOKEN_FILE").map(PathBuf::from),
+            bearer_token_hosts: {
+                let hosts = env_list("PYOCI_BEARER_TOKEN_HOSTS").unwrap_or_default();
+                // Without hosts the token would be sent to any registry in the request path
+                assert!(
+                    !hosts.is_empty() || env::var_os("PYOCI_BEARER_TOKEN_FILE").is_none(),
+                    "PYOCI_BEARER_TOKEN_FILE requires PYOCI_BEARER_TOKEN_HOSTS"
+                );
+                hosts
+            },
+            upstream_concurrency: env::var("PYOCI_GLOBAL_UPSTREAM_CONCURRENCY").map_or(0, |f| {
+                f.parse()
+                    .expect("PYOCI_GLOBAL_UPSTREAM_CONCURRENCY is not a valid integer")
+            }),
+            publish_concurrency: env::var("PYOCI_NAMESPACE_PUBLISH_CONCURRENCY").map_or(0, |f| {
+                f.parse()
+                    .expect("PYOCI_NAMESPACE_PUBLISH_CONCURRENCY is not a valid integer")
+            }),
+            layer_media_type: env::var("PYOCI_LAYER_MEDIA_TYPE")
+                .unwrap_or_else(|_| LAYER_MEDIA_TYPE.to_string()),
+            config_media_type: env::var("PYOCI_CONFIG_MEDIA_TYPE")
+                .unwrap_or_else(|_| CONFIG_MEDIA_TYPE.to_string()),
+            config_content: env::var("PYOCI_CONFIG_CONTENT").map_or_else(
+                |_| CONFIG_CONTENT.to_string(),
+                |f| {
+                    let content = serde_json::from_str::<serde_json::Value>(&f)
+                        .expect("PYOCI_CONFIG_CONTENT is not valid JSON");
+                    // The empty media type requires the `{}` content
+                    assert!(
+                        content == serde_json::json!({})
+                            || env::var("PYOCI_CONFIG_MEDIA_TYPE")
+                                .is_ok_and(|media_type| media_type != CONFIG_MEDIA_TYPE),
+                        "PYOCI_CONFIG_CONTENT requires PYOCI_CONFIG_MEDIA_TYPE, \
+                        {CONFIG_MEDIA_TYPE} only allows `{{}}`"
+                    );
+                    f
+                },
+            ),
+            label_classifier: env::var("PYOCI_LABEL_CLASSIFIER")
+                .unwrap_or_else(|_| LABEL_CLASSIFIER.to_string()),
+            classifier_labels: env_pairs("PYOCI_CLASSIFIER_LABELS"),
+            project_url_annotations: env_pairs("PYOCI_PROJECT_URL_ANNOTATIONS"),
+            blob_cache_bytes: env::var("PYOCI_BLOB_CACHE_BYTES").map_or(0, |f| {
+                f.parse()
+                    .expect("PYOCI_BLOB_CACHE_BYTES is not a valid integer")
+            }),
+            proxy: env::var("PYOCI_PROXY").ok(),
+            no_proxy: env_list("PYOCI_NO_PROXY").unwrap_or_default(),
+            manifest_accept: env_list("PYOCI_MANIFEST_ACCEPT").unwrap_or_default(),
+            manifest_content_type: env::var("PYOCI_MANIFEST_CONTENT_TYPE").ok(),
+            index_content_type: env::var("PYOCI_INDEX_CONTENT_TYPE").ok(),
+            max_redirects: env::var("PYOCI_MAX_REDIRECTS").map_or(10, |f| {
+                f.parse()
+                    .expect("PYOCI_MAX_REDIRECTS is not a valid integer")
+            }),
+            pool_max_idle_per_host: env::var("PYOCI_POOL_MAX_IDLE_PER_HOST").ok().map(|f| {
+                f.parse()
+                    .expect("PYOCI_POOL_MAX_IDLE_PER_HOST is not a valid integer")
+            }),
+            pool_idle_timeout: env::var("PYOCI_POOL_IDLE_TIMEOUT").ok().map(|f| {
+                f.parse()
+                    .expect("PYOCI_POOL_IDLE_TIMEOUT is not a valid integer")
+            }),
+            tag_pagination: env::var("PYOCI_TAG_PAGINATION").map_or(
+                transport::TagPagination::Link,
+                |f| {
+                    f.parse()
+                        .expect("PYOCI_TAG_PAGINATION is not 'link' or 'last'")
+                },
+            ),
+            #[cfg(feature = "test-registry")]
+            inmem_registry: env_flag("PYOCI_INMEM_REGISTRY"),
             otlp_endpoint: env::var("OTLP_ENDPOINT").ok(),
             otlp_auth: env::var("OTLP_AUTH").ok(),
+            otlp_protocol: env::var("OTLP_PROTOCOL").map_or(otlp::Protocol::Http, |f| {
+                f.parse().expect("OTLP_PROTOCOL is not 'http' or 'grpc'")
+            }),
+            otlp_trace_sample_ratio: env::var("OTLP_TRACE_SAMPLE_RATIO").map_or(1.0, |f| {
+                let ratio: f64 = f
+                    .parse()
+                    .expect("OTLP_TRACE_SAMPLE_RATIO is not a valid number");
+                assert!(
+                    (0.0..=1.0).contains(&ratio),
+                    "OTLP_TRACE_SAMPLE_RATIO must be between 0.0 and 1.0"
+                );
+                ratio
+            }),
+            otlp_status: otlp::OtlpStatus::default(),
+            // Values are percent-encoded, following the OpenTelemetry SDK configuration
+            otlp_resource_attributes: env_pairs("OTLP_RESOURCE_ATTRIBUTES")
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = urlencoding::decode(&value)
+                        .expect("OTLP_RESOURCE_ATTRIBUTES value is not valid UTF-8")
+                        .into_owned();
+                    (key, value)
+                })
+                .collect(),
             deployment_env: env::var("DEPLOYMENT_ENVIRONMENT").ok(),
             // https://learn.microsoft.com/en-us/azure/container-apps/environment-variables
             container_name: env::var("CONTAINER_APP_NAME").ok(),
@@ -113,33 +459,80 @@ impl Env {
         }
     }
 
-    fn trace_attributes(&self) -> HashMap<&'static str, Option<String>> {
-        HashMap::from([
+    /// Resource attributes of the exported logs, traces and metrics
+    ///
+    /// `OTLP_RESOURCE_ATTRIBUTES` are added to the fixed attributes,
+    /// the fixed attributes take precedence when they are set.
+    fn trace_attributes(&self) -> HashMap<String, Option<String>> {
+        let mut attributes: HashMap<String, Option<String>> = self
+            .otlp_resource_attributes
+            .iter()
+            .map(|(key, value)| (key.clone(), Some(value.clone())))
+            .collect();
+        for (key, value) in [
             ("service.name", Some("pyoci".to_string())),
             ("service.version", Some(PYOCI_VERSION.to_string())),
             ("deployment.environment", self.deployment_env.clone()),
             ("k8s.container.name", self.container_name.clone()),
             ("k8s.pod.name", self.pod_name.clone()),
             ("k8s.replicaset.name", self.replica_name.clone()),
-        ])
+        ] {
+            if value.is_some() || !attributes.contains_key(key) {
+                attributes.insert(key.to_string(), value);
+            }
+        }
+        attributes
     }
 }
 
+// Return true if the environment variable is set to "1" or "true"
+fn env_flag(key: &str) -> bool {
+    env::var(key).is_ok_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true"))
+}
+
+// Return the comma separated values of the environment variable, None if it is not set
+fn env_list(key: &str) -> Option<Vec<String>> {
+    let value = env::var(key).ok()?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+// Return the comma separated `<left>=<right>` pairs of the environment variable
+fn env_pairs(key: &str) -> Vec<(String, String)> {
+    env_list(key)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|item| {
+            let (left, right) = item
+                .split_once('=')
+                .unwrap_or_else(|| panic!("{key} item '{item}' is not a '<left>=<right>' pair"));
+            (left.trim().to_string(), right.trim().to_string())
+        })
+        .collect()
+}
+
 // Return the optional subpath, taking into account "empty" subpaths as None
-// Also strips a trailing "/" if present.
+// Ensures the subpath starts with a "/" and strips any trailing "/".
 fn clean_subpath(subpath: Option<String>) -> Option<String> {
     let subpath = subpath?;
-    // Strip trailing "/" if it is in the subpath
-    let subpath = subpath
-        .strip_suffix('/')
-        .map(ToString::to_string)
-        .unwrap_or(subpath);
+    let subpath = subpath.trim_end_matches('/');
     // Router.nest() panics when there is no subpath, prevent the panic when
     // `path` is empty or root instead of None
-    if ["", "/"].contains(&subpath.as_str()) {
+    if subpath.is_empty() {
         return None;
     }
-    Some(subpath)
+    // Router.nest() also panics when the path does not start with a "/"
+    if subpath.starts_with('/') {
+        Some(subpath.to_string())
+    } else {
+        Some(format!("/{subpath}"))
+    }
 }
 
 static ENV: LazyLock<Env> = LazyLock::new(Env::new);
@@ -154,15 +547,38 @@ async fn main() {
         tracing::info!("Sending logs/traces to OTLP collector");
     }
 
+    #[cfg(feature = "test-registry")]
+    if environ.inmem_registry {
+        let listener = tokio::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 5000))
+            .await
+            .expect("Could not bind the in-memory registry to socket");
+        tracing::warn!(
+            "Serving in-memory OCI registry on http://{}",
+            listener.local_addr().unwrap()
+        );
+        tokio::spawn(async move {
+            axum::serve(listener, registry::router())
+                .await
+                .expect("Failed to start the in-memory registry");
+        });
+    }
+
     // Setup the webserver
     let listener = tokio::net::TcpListener::bind((Ipv6Addr::UNSPECIFIED, environ.port))
         .await
         .expect("Could not bind to socket");
     tracing::info!("Listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, pyoci_service(environ).into_make_service())
-        .with_graceful_shutdown(shutdown_signal(cancel_token, otlp_handle))
-        .await
-        .expect("Failed to start the server");
+    let shutdown_token = CancellationToken::new();
+    let server = axum::serve(listener, pyoci_service(environ).into_make_service())
+        .with_graceful_shutdown(shutdown_signal(shutdown_token.clone()));
+    drain(
+        server.into_future(),
+        shutdown_token,
+        environ.drain_timeout,
+        cancel_token,
+        otlp_handle,
+    )
+    .await;
 }
 
 /// Setup tracing with a console log and OTLP trace/log.
@@ -190,9 +606,12 @@ fn setup_tracing(
             el_reg,
             environ.otlp_endpoint.clone(),
             environ.otlp_auth.clone(),
+            environ.otlp_protocol,
+            environ.otlp_trace_sample_ratio,
             environ.trace_attributes(),
             Duration::from_secs(30),
             cancel_token,
+            environ.otlp_status.clone(),
         );
         (el_reg, handle)
     };
@@ -201,7 +620,9 @@ fn setup_tracing(
 }
 
 /// Handler for gracefully shutting down on Ctrl+c and SIGTERM
-async fn shutdown_signal(cancel_token: CancellationToken, handle: Option<JoinHandle<()>>) {
+///
+/// Cancels `cancel_token` when a signal is received.
+async fn shutdown_signal(cancel_token: CancellationToken) {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
             .await
@@ -222,7 +643,34 @@ async fn shutdown_signal(cancel_token: CancellationToken, handle: Option<JoinHan
     }
     tracing::info!("Gracefully shutting down");
     cancel_token.cancel();
-    if let Some(handle) = handle {
+}
+
+/// Run `server` until it stops, then send the remaining OTLP data
+///
+/// After `shutdown_token` is cancelled, in-flight requests get `timeout` to complete,
+/// requests still running after that are dropped.
+/// The OTLP data is flushed by cancelling `cancel_token` and awaiting `otlp_handle` of
+/// [`setup_tracing`] once the requests completed, so their spans are exported.
+async fn drain(
+    server: impl Future<Output = std::io::Result<()>>,
+    shutdown_token: CancellationToken,
+    timeout: Duration,
+    cancel_token: CancellationToken,
+    otlp_handle: Option<JoinHandle<()>>,
+) {
+    let deadline = async {
+        shutdown_token.cancelled().await;
+        tokio::time::sleep(timeout).await;
+    };
+    tokio::select! {
+        result = server => result.expect("Failed to start the server"),
+        () = deadline => tracing::warn!(
+            "In-flight requests did not complete within {}s, shutting down",
+            timeout.as_secs()
+        ),
+    }
+    cancel_token.cancel();
+    if let Some(handle) = otlp_handle {
         handle.await.unwrap();
     }
 }
@@ -230,13 +678,19 @@ async fn shutdown_signal(cancel_token: CancellationToken, handle: Option<JoinHan
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::future::IntoFuture;
     use test_case::test_case;
+    use tracing::Instrument;
 
     #[test_case(Some("/foo".to_string()), Some("/foo") ; "Valid, no change")]
     #[test_case(Some("/foo/".to_string()), Some("/foo") ; "Trailing slash")]
     #[test_case(Some("/".to_string()), None ; "Root only")]
     #[test_case(Some("//".to_string()), None ; "Double slash")]
     #[test_case(Some(String::new()), None ; "Empty")]
+    #[test_case(None, None ; "Not set")]
+    #[test_case(Some("foo".to_string()), Some("/foo") ; "Missing leading slash")]
+    #[test_case(Some("/a/b".to_string()), Some("/a/b") ; "Nested")]
+    #[test_case(Some("a/b//".to_string()), Some("/a/b") ; "Nested, missing leading slash, trailing slashes")]
     fn clean_subpath(input: Option<String>, expected: Option<&str>) {
         assert_eq!(
             super::clean_subpath(input),
@@ -244,6 +698,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn trace_attributes_resource_attributes() {
+        let env = Env {
+            deployment_env: Some("prod".to_string()),
+            otlp_resource_attributes: vec![
+                ("cloud.region".to_string(), "westeurope".to_string()),
+                ("deployment.environment".to_string(), "test".to_string()),
+                ("k8s.pod.name".to_string(), "pyoci-0".to_string()),
+            ],
+            ..Env::default()
+        };
+        let attributes = env.trace_attributes();
+
+        assert_eq!(attributes["cloud.region"].as_deref(), Some("westeurope"));
+        // Set attributes take precedence, unset attributes do not remove the configured ones
+        assert_eq!(
+            attributes["deployment.environment"].as_deref(),
+            Some("prod")
+        );
+        assert_eq!(attributes["k8s.pod.name"].as_deref(), Some("pyoci-0"));
+        assert_eq!(attributes["service.name"].as_deref(), Some("pyoci"));
+    }
+
     #[tokio::test]
     async fn test_setup_tracing() {
         let mut server = mockito::Server::new_async().await;
@@ -290,20 +767,96 @@ mod tests {
     #[tokio::test]
     async fn test_shutdown_signal() {
         let cancel_token = CancellationToken::new();
-        let upstream_cancel_token = cancel_token.clone();
-        let shutdown_cancel_token = cancel_token.clone();
-
-        // Create a handle to join in `shutdown_signal`
-        let handle = tokio::spawn(async move {
-            tokio::select! {
-                () = std::future::pending() => {},
-                () = upstream_cancel_token.cancelled() => {},
-            }
-        });
         // spawn `shutdown_signal`
-        let handle = tokio::spawn(shutdown_signal(shutdown_cancel_token, Some(handle)));
-        // Cancel both the upstream task and the shutdown_signal task
+        let handle = tokio::spawn(shutdown_signal(cancel_token.clone()));
         cancel_token.cancel();
         handle.await.unwrap();
     }
+
+    #[tokio::test]
+    // Test if the span of a request completing during shutdown is sent to the OTLP collector
+    async fn shutdown_flushes_in_flight_request() {
+        let mut otlp_server = mockito::Server::new_async().await;
+        let traces_mock = otlp_server
+            .mock("POST", "/v1/traces")
+            .match_body(mockito::Matcher::Regex("slow_request".to_string()))
+            .create_async()
+            .await;
+        otlp_server
+            .mock("POST", mockito::Matcher::Any)
+            .create_async()
+            .await;
+
+        let cancel_token = CancellationToken::new();
+        let env = Env {
+            otlp_endpoint: Some(otlp_server.url()),
+            otlp_auth: Some("unittest".to_string()),
+            ..Env::default()
+        };
+        let (subscriber, otlp_handle) = setup_tracing(&env, cancel_token.clone());
+        // The test runtime is single threaded, the server tasks use this subscriber as well
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let started = std::sync::Arc::new(tokio::sync::Notify::new());
+        let router = axum::Router::new().route(
+            "/slow",
+            axum::routing::get({
+                let started = started.clone();
+                || async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_millis(200))
+                        .instrument(tracing::info_span!("slow_request"))
+                        .await;
+                    "done"
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/slow", listener.local_addr().unwrap());
+        let shutdown_token = CancellationToken::new();
+        let server = tokio::spawn({
+            let shutdown_token = shutdown_token.clone();
+            async move {
+                let server = axum::serve(listener, router)
+                    .with_graceful_shutdown(shutdown_signal(shutdown_token.clone()));
+                drain(
+                    server.into_future(),
+                    shutdown_token,
+                    Duration::from_secs(5),
+                    cancel_token,
+                    otlp_handle,
+                )
+                .await;
+            }
+        });
+
+        let request = tokio::spawn(reqwest::get(url));
+        // Shut down while the request is in-flight
+        started.notified().await;
+        shutdown_token.cancel();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        server.await.unwrap();
+        traces_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    // Test if the server is no longer awaited once the drain timeout expires
+    async fn drain_timeout() {
+        let shutdown_token = CancellationToken::new();
+        shutdown_token.cancel();
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            drain(
+                std::future::pending(),
+                shutdown_token,
+                Duration::from_millis(10),
+                CancellationToken::new(),
+                None,
+            ),
+        )
+        .await
+        .expect("drain did not stop at the timeout");
+    }
 }
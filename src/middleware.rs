@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use http::{Method, Request, Uri};
 use tower::Service;
 
@@ -44,13 +46,26 @@ where
 //
 // By URL-encoding the namespace we allow Axum Router to route like regular
 fn urlencode_namespace<B>(mut req: Request<B>, subpath: Option<&str>) -> Request<B> {
-    let Some(uri) = urlencode_namespace_(req.method() == Method::POST, req.uri().path(), subpath)
+    let Some(path) = urlencode_namespace_(req.method() == Method::POST, req.uri().path(), subpath)
     else {
         return req;
     };
+    // Keep the scheme, authority and query of the original URI
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path,
+    };
+    let mut parts = req.uri().clone().into_parts();
+    let Ok(path_and_query) = path_and_query.parse() else {
+        return req;
+    };
+    parts.path_and_query = Some(path_and_query);
+    let Ok(uri) = Uri::from_parts(parts) else {
+        return req;
+    };
     *req.uri_mut() = uri;
 
-    tracing::debug!("Rewriten: {}", req.uri());
+    tracing::debug!("Rewriten: {}", redact_path(req.uri().path()));
     req
 }
 
@@ -59,11 +74,14 @@ fn urlencode_namespace<B>(mut req: Request<B>, subpath: Option<&str>) -> Request
 //  /{registry}/{namespace with extra paths}/{package}/
 //  /{registry}/{namespace with extra paths}/{package}/json
 //  /{registry}/{namespace with extra paths}/{package}/{filename}
+//  /{registry}/{namespace with extra paths}/{package}/{version}/index.json
+//  /{registry}/{namespace with extra paths}/{package}/{filename}/exists
 // DELETE:
 //  /{registry}/{namespace with extra paths}/{package}/{filename}
 // POST:
 //  /{registry}/{namespace with extra paths}/
-fn urlencode_namespace_(is_post_request: bool, uri: &str, subpath: Option<&str>) -> Option<Uri> {
+//  /{registry}/{namespace with extra paths}/{package}/{version}/tag/{alias}
+fn urlencode_namespace_(is_post_request: bool, uri: &str, subpath: Option<&str>) -> Option<String> {
     let subpath_len = if let Some(value) = subpath {
         value.len()
     } else {
@@ -77,8 +95,16 @@ fn urlencode_namespace_(is_post_request: bool, uri: &str, subpath: Option<&str>)
         return None;
     }
 
-    // Find the last 2 (GET/DELETE) or 1 (POST) "/", anything before that is the namespace
-    let expected_sep_count = if is_post_request { 1 } else { 2 };
+    // Find the last 2 (GET/DELETE), 3 (GET index/exists), 1 (POST) or 4 (POST tag) "/",
+    // anything before that is the namespace
+    let is_tag_request = !uri.ends_with('/') && uri.rsplit('/').nth(1) == Some("tag");
+    let is_index_request = uri.ends_with("/index.json") || uri.ends_with("/exists");
+    let expected_sep_count = match (is_post_request, is_tag_request, is_index_request) {
+        (true, true, _) => 4,
+        (true, false, _) => 1,
+        (false, _, true) => 3,
+        (false, _, false) => 2,
+    };
     let namespace_end = findn_slash(expected_sep_count, uri.char_indices().rev());
 
     // return if we did not reach the expected number of "/"
@@ -88,16 +114,48 @@ fn urlencode_namespace_(is_post_request: bool, uri: &str, subpath: Option<&str>)
     let prefix = &uri[..registry_end];
     let namespace = &uri[registry_end..namespace_end].replace('/', "%2F");
     let postfix = &uri[namespace_end..];
-    tracing::debug!("Prefix: {}", prefix);
+    tracing::debug!("Prefix: {}", redact_path(prefix));
     tracing::debug!("Namespace: {}", namespace);
     tracing::debug!("Postfix: {}", postfix);
 
-    let Ok(uri) = [prefix, namespace, postfix].concat().parse() else {
-        // Since we don't alter the original URI in unpredictable ways,
-        // this return should be unreachable.
-        return None;
+    Some([prefix, namespace, postfix].concat())
+}
+
+/// Strip credentials from a request path so it can be logged
+///
+/// The registry can include userinfo, for example `https%3A%2F%2Fuser%3Apass%40example.com`.
+/// See [`redact_registry`] for the segments that are redacted.
+pub fn redact_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| redact_registry(segment).unwrap_or_else(|| segment.to_string()))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Strip the userinfo of a registry path segment, keeping the scheme if there is one
+///
+/// Only a segment that is a registry host with userinfo is redacted, the userinfo has to be
+/// `user:pass` when there is no scheme. Other segments containing an "@", like a filename,
+/// are returned as `None`.
+fn redact_registry(segment: &str) -> Option<String> {
+    let decoded = urlencoding::decode(segment).unwrap_or(Cow::Borrowed(segment));
+    let (scheme, authority) = match decoded.split_once("://") {
+        Some((scheme, authority)) => (Some(scheme), authority),
+        None => (None, decoded.as_ref()),
     };
-    Some(uri)
+    let (userinfo, host) = authority.rsplit_once('@')?;
+    if scheme.is_none() && !userinfo.contains(':') {
+        return None;
+    }
+    // `host` has to be a host with an optional port, and nothing else
+    let url = url::Url::parse(&format!("https://{host}")).ok()?;
+    if url.path() != "/" || url.query().is_some() || url.fragment().is_some() {
+        return None;
+    }
+    Some(match scheme {
+        Some(scheme) => urlencoding::encode(&format!("{scheme}://{host}")).into_owned(),
+        None => urlencoding::encode(host).into_owned(),
+    })
 }
 
 // Return the byte location in `it` of the nth '/'
@@ -135,6 +193,18 @@ mod tests {
     #[test_case("GET",None, "/reg/nmsps/sub-nmsps/package/foo.whl", "/reg/nmsps%2Fsub-nmsps/package/foo.whl"; "download package, sub-namespace")]
     #[test_case("DELETE",None, "/reg/nmsps/sub-nmsps/package/foo.whl", "/reg/nmsps%2Fsub-nmsps/package/foo.whl"; "delete package, sub-namespace")]
     #[test_case("POST",None, "/reg/nmsps/sub-nmsps/", "/reg/nmsps%2Fsub-nmsps/"; "post package, sub-namespace")]
+    #[test_case("GET",None, "/reg/a/b/c/package/", "/reg/a%2Fb%2Fc/package/"; "list package, 3-level namespace")]
+    #[test_case("GET",None, "/reg/a/b/c/package/json", "/reg/a%2Fb%2Fc/package/json"; "list package json, 3-level namespace")]
+    #[test_case("GET",None, "/reg/a/b/c/package/foo.whl", "/reg/a%2Fb%2Fc/package/foo.whl"; "download package, 3-level namespace")]
+    #[test_case("DELETE",None, "/reg/a/b/c/package/1.0.0", "/reg/a%2Fb%2Fc/package/1.0.0"; "delete package, 3-level namespace")]
+    #[test_case("POST",None, "/reg/a/b/c/", "/reg/a%2Fb%2Fc/"; "post package, 3-level namespace")]
+    #[test_case("GET",None, "/reg/nmsps/package/1.0.0/index.json", "/reg/nmsps/package/1.0.0/index.json"; "version index, no change")]
+    #[test_case("GET",None, "/reg/a/b/c/package/1.0.0/index.json", "/reg/a%2Fb%2Fc/package/1.0.0/index.json"; "version index, 3-level namespace")]
+    #[test_case("GET",None, "/reg/nmsps/package/foo.whl/exists", "/reg/nmsps/package/foo.whl/exists"; "file exists, no change")]
+    #[test_case("GET",None, "/reg/a/b/c/package/foo.whl/exists", "/reg/a%2Fb%2Fc/package/foo.whl/exists"; "file exists, 3-level namespace")]
+    #[test_case("POST",None, "/reg/nmsps/package/1.0.0/tag/latest", "/reg/nmsps/package/1.0.0/tag/latest"; "tag package, no change")]
+    #[test_case("POST",None, "/reg/a/b/c/package/1.0.0/tag/latest", "/reg/a%2Fb%2Fc/package/1.0.0/tag/latest"; "tag package, 3-level namespace")]
+    #[test_case("POST",None, "/reg/a/tag/b/", "/reg/a%2Ftag%2Fb/"; "post package, tag in namespace")]
     #[test_case("GET",None, "/foobarbaz", "/foobarbaz"; "no second slash")]
     #[test_case("GET",None, "/foobarbaz/", "/foobarbaz/"; "no third slash in GET")]
     #[test_case("POST",None, "/foobarbaz/", "/foobarbaz/"; "no third slash in POST")]
@@ -157,4 +227,30 @@ mod tests {
             expected
         );
     }
+
+    #[test]
+    fn urlencode_namespace_keep_uri_parts() {
+        let req = Request::builder()
+            .method("GET")
+            .uri("http://localhost/reg/nmsps/sub-nmsps/package/?foo=bar")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            super::urlencode_namespace(req, None).uri(),
+            "http://localhost/reg/nmsps%2Fsub-nmsps/package/?foo=bar"
+        );
+    }
+
+    #[test_case("/reg/nmsps/package/", "/reg/nmsps/package/"; "no credentials")]
+    #[test_case("/user%3Apass%40reg/nmsps/package/", "/reg/nmsps/package/"; "encoded credentials")]
+    #[test_case("/user:pass@reg/nmsps/package/", "/reg/nmsps/package/"; "plain credentials")]
+    #[test_case("/https%3A%2F%2Fuser%3Apass%40reg/nmsps/", "/https%3A%2F%2Freg/nmsps/"; "credentials with scheme")]
+    #[test_case("/foo/http%3A%2F%2Fuser%40reg%3A5000/nmsps%2Fsub/package/json", "/foo/http%3A%2F%2Freg%3A5000/nmsps%2Fsub/package/json"; "username with subpath")]
+    #[test_case("/reg/nmsps/package/file@sha256:abc", "/reg/nmsps/package/file@sha256:abc"; "digest in filename")]
+    #[test_case("/reg/nmsps/package/file%40sha256%3Aabc", "/reg/nmsps/package/file%40sha256%3Aabc"; "encoded digest in filename")]
+    #[test_case("/reg/nmsps/package/name@reg.example", "/reg/nmsps/package/name@reg.example"; "without password or scheme")]
+    #[test_case("/reg/nmsps/package/a:b@c.whl#x", "/reg/nmsps/package/a:b@c.whl#x"; "not a host")]
+    fn redact_path(path: &str, expected: &str) {
+        assert_eq!(super::redact_path(path), expected);
+    }
 }
@@ -1,29 +1,49 @@
 use std::{
     collections::{BTreeSet, HashMap},
+    io::Read,
     str::FromStr,
+    sync::Arc,
 };
 
 use anyhow::{bail, Context, Result};
 use base16ct::lower::encode_string as hex_encode;
-use http::{HeaderValue, StatusCode};
+use flate2::read::GzDecoder;
+use http::{header::CONTENT_ENCODING, HeaderValue, StatusCode};
 use oci_spec::{
     distribution::TagList,
     image::{
-        Arch, Descriptor, DescriptorBuilder, Digest as OciDigest, ImageIndex, ImageManifest, Os,
-        Platform, PlatformBuilder, Sha256Digest,
+        Arch, Descriptor, DescriptorBuilder, Digest as OciDigest, ImageIndex, ImageManifest,
+        MediaType, Os, Platform, PlatformBuilder, Sha256Digest,
     },
 };
 use reqwest::Response;
 use sha2::{Digest, Sha256};
+use tokio::sync::Semaphore;
 use url::Url;
 
 use crate::{
+    cache::BlobCache,
     error::PyOciError,
     package::{Package, WithFileName},
     service::AuthHeader,
-    transport::HttpTransport,
+    spool::SpooledContent,
+    transport::{
+        HttpClient, HttpTransport, TagPagination, INDEX_CONTENT_TYPE, MANIFEST_CONTENT_TYPE,
+    },
+    ARTIFACT_TYPE,
 };
 
+/// Maximum number of nested `ImageIndex` levels to follow when looking for the `PyOCI` index
+const MAX_INDEX_DEPTH: usize = 2;
+
+/// Number of consecutive failed chunks after which a chunked blob upload is aborted
+const UPLOAD_RESUME_ATTEMPTS: usize = 3;
+
+/// `Accept-Encoding` of manifest and tag list requests, decoded by [`read_body`]
+///
+/// Blob requests do not send it, so the content is received as-is for the digest verification.
+const JSON_ACCEPT_ENCODING: &str = "gzip, br";
+
 /// Build an URL from a format string while sanitizing the parameters
 ///
 /// Note that if the resulting path is an absolute URL, the registry URL is ignored.
@@ -54,20 +74,30 @@ fn sanitize(value: &str) -> Result<&str> {
 
 /// Container for a Blob/Layer data, combined with a Descriptor
 pub struct Blob {
-    data: Vec<u8>,
+    content: SpooledContent,
     descriptor: Descriptor,
 }
 
 impl Blob {
     pub fn new(data: Vec<u8>, artifact_type: &str) -> Self {
-        let digest = digest(&data);
+        Self::from_content(data.into(), artifact_type)
+    }
+
+    /// Create a Blob from content of which the digest is already calculated
+    pub fn from_content(content: SpooledContent, artifact_type: &str) -> Self {
+        let digest: OciDigest = Sha256Digest::from_str(content.sha256())
+            .expect("Invalid Digest")
+            .into();
         let descriptor = DescriptorBuilder::default()
             .media_type(artifact_type)
             .digest(digest)
-            .size(data.len() as u64)
+            .size(content.len())
             .build()
             .expect("valid Descriptor");
-        Blob { data, descriptor }
+        Blob {
+            content,
+            descriptor,
+        }
     }
 
     pub fn descriptor(&self) -> &Descriptor {
@@ -91,6 +121,55 @@ pub enum Manifest {
     Manifest(Box<ImageManifest>),
 }
 
+/// Condition for the registry to accept a pushed manifest
+#[derive(Debug, Clone, Copy)]
+pub enum Precondition<'a> {
+    /// Always accept the manifest
+    None,
+    /// Only replace the manifest with this digest, `If-Match`
+    Match(&'a OciDigest),
+    /// Only create the manifest if it does not exist, `If-None-Match: *`
+    NoneMatch,
+}
+
+/// Platform as set by `PyOCI` for a package file
+///
+/// The architecture holds the distribution specific part of the filename,
+/// the OS is always "any".
+pub fn platform(package: &Package<WithFileName>) -> Platform {
+    PlatformBuilder::default()
+        .architecture(Arch::Other(package.oci_architecture().to_string()))
+        .os(Os::Other("any".to_string()))
+        .build()
+        .expect("valid Platform")
+}
+
+/// OS implied by the platform tag of a wheel
+///
+/// Returns `None` for pure wheels and source distributions, which run on any OS.
+/// A wheel with multiple platform tags, `macosx_10_9_x86_64.macosx_11_0_arm64`,
+/// implies the OS of the first recognized tag.
+pub fn implied_os(package: &Package<WithFileName>) -> Option<Os> {
+    let (_, platform_tags) = package
+        .oci_architecture()
+        .strip_suffix(".whl")?
+        .rsplit_once('-')?;
+    platform_tags.split('.').find_map(|tag| {
+        if tag.starts_with("macosx") {
+            Some(Os::Darwin)
+        } else if ["manylinux", "musllinux", "linux"]
+            .iter()
+            .any(|prefix| tag.starts_with(prefix))
+        {
+            Some(Os::Linux)
+        } else if tag.starts_with("win") {
+            Some(Os::Windows)
+        } else {
+            None
+        }
+    })
+}
+
 /// Container for a `ImageManifest` combined with a Platform
 #[derive(Debug)]
 pub struct PlatformManifest {
@@ -100,18 +179,21 @@ pub struct PlatformManifest {
 
 impl PlatformManifest {
     pub fn new(manifest: ImageManifest, package: &Package<WithFileName>) -> Self {
-        let platform = PlatformBuilder::default()
-            .architecture(Arch::Other(package.oci_architecture().to_string()))
-            .os(Os::Other("any".to_string()))
-            .build()
-            .expect("valid Platform");
-        PlatformManifest { manifest, platform }
+        PlatformManifest {
+            manifest,
+            platform: platform(package),
+        }
     }
 
     pub fn descriptor(&self, annotations: HashMap<String, String>) -> Descriptor {
         let (digest, data) = self.digest();
         DescriptorBuilder::default()
-            .media_type("application/vnd.oci.image.manifest.v1+json")
+            .media_type(
+                self.manifest
+                    .media_type()
+                    .clone()
+                    .unwrap_or(MediaType::ImageManifest),
+            )
             .digest(digest)
             .size(data.len() as u64)
             .platform(self.platform.clone())
@@ -120,7 +202,7 @@ impl PlatformManifest {
             .expect("Valid PlatformManifest Descriptor")
     }
 
-    fn digest(&self) -> (OciDigest, String) {
+    pub fn digest(&self) -> (OciDigest, String) {
         let data = serde_json::to_string(&self.manifest).expect("valid json");
         (digest(&data), data)
     }
@@ -131,14 +213,46 @@ impl PlatformManifest {
 pub struct Oci {
     registry: Url,
     transport: HttpTransport,
+    /// Maximum size of JSON responses, 0 means no limit
+    json_limit: usize,
+    /// Maximum number of manifests in an `ImageIndex`, 0 means no limit
+    max_manifests: usize,
+    /// Size in bytes of the chunks blobs are uploaded in, 0 means a single request
+    upload_chunk_size: u64,
+    /// Limits concurrent manifest fetches across all requests, `None` means no limit
+    manifest_permits: Option<Arc<Semaphore>>,
+    /// Cache for downloaded blobs, `None` means no caching
+    blob_cache: Option<Arc<BlobCache>>,
+    /// `Accept` header of manifest requests
+    manifest_accept: String,
+    /// `Content-Type` header of pushed `ImageManifest`s
+    manifest_content_type: String,
+    /// `Content-Type` header of pushed `ImageIndex`es
+    index_content_type: String,
+    /// How the pages of the tag list are requested
+    tag_pagination: TagPagination,
 }
 
 /// Low-level functionality for interacting with the OCI registry
 impl Oci {
-    pub fn new(registry: Url, auth: Option<AuthHeader>) -> Oci {
+    pub fn new(registry: Url, auth: Option<AuthHeader>, client: HttpClient) -> Oci {
+        // The token file does not replace credentials of the caller
+        let token_file = match auth {
+            None => client.bearer_token_file(&registry),
+            Some(_) => None,
+        };
         Oci {
             registry,
-            transport: HttpTransport::new(auth),
+            json_limit: client.json_limit(),
+            max_manifests: client.max_manifests(),
+            upload_chunk_size: client.upload_chunk_size(),
+            manifest_permits: client.manifest_permits(),
+            blob_cache: client.blob_cache(),
+            manifest_accept: client.manifest_accept().to_string(),
+            manifest_content_type: client.manifest_content_type().to_string(),
+            index_content_type: client.index_content_type().to_string(),
+            tag_pagination: client.tag_pagination(),
+            transport: HttpTransport::new(auth, client).with_token_file(token_file),
         }
     }
     /// Push a blob to the registry using POST then PUT method
@@ -177,30 +291,35 @@ impl Oci {
             .post(url)
             .header("Content-Type", "application/octet-stream");
         let response = self.transport.send(request).await?;
-        let location = match response.status() {
+        let url: Url = match response.status() {
             StatusCode::CREATED => return Ok(()),
-            StatusCode::ACCEPTED => response
-                .headers()
-                .get("Location")
-                .context("Registry response did not contain a Location header")?
-                .to_str()
-                .context("Failed to parse Location header as ASCII")?,
+            StatusCode::ACCEPTED => upload_url(&self.registry, name, response.headers())?,
             status => {
                 return Err(PyOciError::from((status, response.text().await?)).into());
             }
         };
-        let mut url: Url = build_url!(&self.registry, "{}", location);
-        // `append_pair` percent-encodes the values as application/x-www-form-urlencoded.
-        // ghcr.io seems to be fine with a percent-encoded digest but this could be an issue with
-        // other registries.
-        url.query_pairs_mut().append_pair("digest", &digest);
 
-        let request = self
-            .transport
-            .put(url)
-            .header("Content-Type", "application/octet-stream")
-            .header("Content-Length", blob.data.len().to_string())
-            .body(blob.data);
+        let request = if self.upload_chunk_size > 0 && blob.content.len() > self.upload_chunk_size {
+            let (mut url, sent_digest) = self.push_chunks(url, &blob.content).await?;
+            if sent_digest != digest {
+                bail!(
+                    "Uploaded content of '{name}' has digest '{sent_digest}', expected '{digest}'"
+                );
+            }
+            url.query_pairs_mut().append_pair("digest", &sent_digest);
+            self.transport.put(url).header("Content-Length", "0")
+        } else {
+            let mut url = url;
+            // `append_pair` percent-encodes the values as application/x-www-form-urlencoded.
+            // ghcr.io seems to be fine with a percent-encoded digest but this could be an issue
+            // with other registries.
+            url.query_pairs_mut().append_pair("digest", &digest);
+            self.transport
+                .put(url)
+                .header("Content-Type", "application/octet-stream")
+                .header("Content-Length", blob.content.len().to_string())
+                .body(blob.content.into_body().await?)
+        };
         let response = self.transport.send(request).await?;
         match response.status() {
             StatusCode::CREATED => {}
@@ -220,6 +339,112 @@ impl Oci {
         Ok(())
     }
 
+    /// Upload content in chunks using PATCH requests
+    ///
+    /// When a chunk fails, the upload status is requested from the registry and the upload
+    /// resumes from the last offset the registry committed.
+    ///
+    /// The sha256 digest is calculated over the chunks as they are sent, bytes sent again after
+    /// resuming are only hashed once.
+    ///
+    /// Returns the URL to close the upload session with and the digest of the sent content.
+    ///
+    /// <https://github.com/opencontainers/distribution-spec/blob/main/spec.md#pushing-a-blob-in-chunks>
+    async fn push_chunks(
+        &mut self,
+        mut url: Url,
+        content: &SpooledContent,
+    ) -> Result<(Url, String)> {
+        let mut offset = 0;
+        let mut failures = 0;
+        // Whether the registry acknowledged receiving the first byte
+        let mut started = false;
+        let mut sha256 = Sha256::new();
+        // Number of leading bytes included in `sha256`
+        let mut hashed = 0;
+        while offset < content.len() {
+            let chunk = content.read_range(offset, self.upload_chunk_size).await?;
+            let end = offset + chunk.len() as u64 - 1;
+            if offset <= hashed && hashed <= end {
+                sha256.update(&chunk[usize::try_from(hashed - offset)?..]);
+                hashed = end + 1;
+            }
+            let request = self
+                .transport
+                .patch(url.clone())
+                .header("Content-Type", "application/octet-stream")
+                .header("Content-Range", format!("{offset}-{end}"))
+                .header("Content-Length", chunk.len().to_string())
+                .body(chunk);
+            let error = match self.transport.send(request).await {
+                Ok(response) if response.status() == StatusCode::ACCEPTED => {
+                    (url, offset) = self.upload_session(&response, true)?;
+                    started = true;
+                    failures = 0;
+                    continue;
+                }
+                Ok(response)
+                    if response.status().is_server_error()
+                        || response.status() == StatusCode::RANGE_NOT_SATISFIABLE =>
+                {
+                    let status = response.status();
+                    anyhow::Error::from(PyOciError::from((status, response.text().await?)))
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    return Err(PyOciError::from((status, response.text().await?)).into());
+                }
+                Err(err) => err,
+            };
+            failures += 1;
+            if failures >= UPLOAD_RESUME_ATTEMPTS {
+                return Err(error.context(format!("Failed to upload chunk {offset}-{end}")));
+            }
+            tracing::warn!("Failed to upload chunk {offset}-{end}, resuming upload: {error:#}");
+
+            let response = self.transport.send(self.transport.get(url.clone())).await?;
+            match response.status() {
+                StatusCode::NO_CONTENT => {
+                    (url, offset) = self.upload_session(&response, started)?;
+                }
+                status => {
+                    return Err(PyOciError::from((status, response.text().await?)).into());
+                }
+            }
+        }
+        Ok((url, format!("sha256:{}", hex_encode(&sha256.finalize()))))
+    }
+
+    /// Location and next offset of an upload session
+    ///
+    /// The `Range` header holds the inclusive range of bytes the registry received.
+    /// Some registries report `0-0` for an empty session, which is only taken as the first byte
+    /// when the registry acknowledged receiving it (`started`).
+    fn upload_session(&self, response: &Response, started: bool) -> Result<(Url, u64)> {
+        let location = response
+            .headers()
+            .get("Location")
+            .context("Registry response did not contain a Location header")?
+            .to_str()
+            .context("Failed to parse Location header as ASCII")?;
+        let url = build_url!(&self.registry, "{}", location);
+        let offset = match response.headers().get("Range") {
+            Some(range) => {
+                let (_, end) = range
+                    .to_str()
+                    .context("Failed to parse Range header as ASCII")?
+                    .split_once('-')
+                    .context("Invalid Range header")?;
+                match end.parse::<u64>().context("Invalid Range header")? {
+                    0 if !started => 0,
+                    end => end + 1,
+                }
+            }
+            None => 0,
+        };
+        Ok((url, offset))
+    }
+
     /// Pull a blob from the registry
     ///
     /// This returns the raw response so the caller can handle the blob as needed
@@ -232,13 +457,45 @@ impl Oci {
         descriptor: Descriptor,
     ) -> Result<Response> {
         let digest = descriptor.digest().to_string();
+        // Cached per repository, a digest only grants access to a blob the registry served
+        // for this repository before
+        let cache_key = format!(
+            "{}/{name}@{digest}",
+            self.registry.origin().ascii_serialization()
+        );
+        if let Some(data) = self
+            .blob_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&cache_key))
+        {
+            tracing::debug!("Blob served from cache: {name}:{digest}");
+            return Ok(http::Response::new(data).into());
+        }
         let url = build_url!(&self.registry, "/v2/{}/blobs/{}", &name, &digest);
         let request = self.transport.get(url);
         let response = self.transport.send(request).await?;
 
         match response.status() {
-            StatusCode::OK => Ok(response),
-            status => Err(PyOciError::from((status, response.text().await?)).into()),
+            StatusCode::OK => {}
+            status => return Err(PyOciError::from((status, response.text().await?)).into()),
+        }
+        match &self.blob_cache {
+            // Only sha256 content can be verified before it is cached
+            Some(cache) if cache.accepts(descriptor.size()) && digest.starts_with("sha256:") => {
+                let data = response.bytes().await?;
+                if data.len() as u64 != descriptor.size()
+                    || self::digest(&data).to_string() != digest
+                {
+                    return Err(PyOciError::from((
+                        StatusCode::BAD_GATEWAY,
+                        format!("Registry returned content not matching blob '{digest}'"),
+                    ))
+                    .into());
+                }
+                cache.insert(cache_key, data.clone());
+                Ok(http::Response::new(data).into())
+            }
+            _ => Ok(response),
         }
     }
 
@@ -263,62 +520,117 @@ impl Oci {
     #[tracing::instrument(skip_all, fields(otel.name = name))]
     pub async fn list_tags(&mut self, name: &str) -> anyhow::Result<BTreeSet<String>> {
         let url = build_url!(&self.registry, "/v2/{}/tags/list", name);
-        let request = self.transport.get(url);
+        let (mut link_header, page) = self.tag_page(url).await?;
+        let page_size = page.len();
+        let mut last = page.last().cloned();
+        let mut tags: BTreeSet<String> = page.into_iter().collect();
+        if link_header.is_none() && self.tag_pagination == TagPagination::Last {
+            // Continue after the last tag until a page is shorter than the first one
+            while let Some(ref last_tag) = last {
+                let mut url = build_url!(&self.registry, "/v2/{}/tags/list", name);
+                url.query_pairs_mut().append_pair("last", last_tag);
+                let (_, page) = self.tag_page(url).await?;
+                let full_page = page.len() >= page_size;
+                last = page.last().cloned();
+                // A registry ignoring `last` returns the first page again
+                let new_tags = page.into_iter().filter(|tag| tags.insert(tag.clone()));
+                if new_tags.count() == 0 || !full_page {
+                    break;
+                }
+            }
+        }
+        while let Some(ref link) = link_header {
+            // Follow the link headers as long as a Link header is returned
+            let mut url = self.registry.clone();
+            url.set_path("");
+            let url = url.join(&link.0)?;
+            let page;
+            (link_header, page) = self.tag_page(url).await?;
+            tags.extend(page);
+        }
+
+        Ok(tags)
+    }
+
+    /// Fetch a single page of the tag list, with the `Link` to the next page if any
+    async fn tag_page(&mut self, url: Url) -> anyhow::Result<(Option<Link>, Vec<String>)> {
+        let request = self
+            .transport
+            .get(url)
+            .header("Accept-Encoding", JSON_ACCEPT_ENCODING);
         let response = self.transport.send(request).await?;
         match response.status() {
             StatusCode::OK => {}
             status => return Err(PyOciError::from((status, response.text().await?)).into()),
         }
-        let mut link_header = match response.headers().get("link") {
+        let link_header = match response.headers().get("link") {
             Some(link) => Some(Link::try_from(link)?),
             None => None,
         };
-        let mut tags: BTreeSet<String> = response
-            .json::<TagList>()
-            .await?
-            .tags()
-            .iter()
-            .map(ToOwned::to_owned)
-            .collect();
-        while let Some(ref link) = link_header {
-            // Follow the link headers as long as a Link header is returned
-            let mut url = self.registry.clone();
-            url.set_path("");
-            let url = url.join(&link.0)?;
-            let request = self.transport.get(url);
-            let response = self.transport.send(request).await?;
-            match response.status() {
-                StatusCode::OK => {}
-                status => return Err(PyOciError::from((status, response.text().await?)).into()),
+        let body = read_body(response, self.json_limit).await?;
+        let tags = serde_json::from_slice::<TagList>(&body)?.tags().clone();
+        Ok((link_header, tags))
+    }
+
+    /// Verify the repository `name` can be published to
+    ///
+    /// The distribution spec has no call to create a repository, registries create it on the
+    /// first push. A missing repository, or a registry not supporting the tag list, is
+    /// therefore accepted, only a `401 Unauthorized` or `403 Forbidden` is returned as an error.
+    #[tracing::instrument(skip_all, fields(otel.name = name))]
+    pub async fn verify_repository(&mut self, name: &str) -> Result<()> {
+        let url = build_url!(&self.registry, "/v2/{}/tags/list", name);
+        let request = self.transport.get(url);
+        let response = self.transport.send(request).await?;
+        match response.status() {
+            status @ (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => Err(PyOciError::from((
+                status,
+                format!(
+                    "Not allowed to publish to '{name}': {}",
+                    response.text().await?
+                ),
+            ))
+            .into()),
+            status => {
+                tracing::debug!("Repository '{name}' check returned {status}, continuing");
+                Ok(())
             }
-            link_header = match response.headers().get("link") {
-                Some(link) => Some(Link::try_from(link)?),
-                None => None,
-            };
-            let tag_list = response.json::<TagList>().await?;
-            tags.extend(tag_list.tags().iter().map(ToOwned::to_owned));
         }
-
-        Ok(tags)
     }
 
     /// Push a manifest to the registry
     ///
     /// `ImageIndex` will be pushed with a version tag if version is set
     /// `ImageManifest` will always be pushed with a digest reference
+    ///
+    /// With a `precondition`, the registry is asked to only accept the manifest when the current
+    /// manifest has the given digest or does not exist.
+    /// A `412 Precondition Failed` is returned as a [`PyOciError`].
+    ///
+    /// A `415 Unsupported Media Type` is returned as a `502 Bad Gateway` naming the
+    /// `Content-Type` that was sent, see `PYOCI_MANIFEST_CONTENT_TYPE` and `PYOCI_INDEX_CONTENT_TYPE`.
     #[tracing::instrument(skip_all, fields(otel.name = name, otel.version = version))]
     pub async fn push_manifest(
         &mut self,
         name: &str,
         manifest: Manifest,
         version: Option<&str>,
+        precondition: Precondition<'_>,
     ) -> Result<()> {
-        let (url, data, content_type) = match manifest {
-            Manifest::Index(index) => {
+        let (url, data, content_type, setting) = match manifest {
+            Manifest::Index(mut index) => {
                 let version = version.context("`version` required for pushing an ImageIndex")?;
                 let url = build_url!(&self.registry, "v2/{}/manifests/{}", name, version);
+                // The `mediaType` has to match the `Content-Type`, an existing index can have
+                // been pushed with another one
+                index.set_media_type(Some(MediaType::from(self.index_content_type.as_str())));
                 let data = serde_json::to_string(&index)?;
-                (url, data, "application/vnd.oci.image.index.v1+json")
+                (
+                    url,
+                    data,
+                    self.index_content_type.clone(),
+                    "PYOCI_INDEX_CONTENT_TYPE",
+                )
             }
             Manifest::Manifest(manifest) => {
                 let data = serde_json::to_string(&manifest)?;
@@ -329,34 +641,130 @@ impl Oci {
                     name,
                     data_digest.as_ref()
                 );
-                (url, data, "application/vnd.oci.image.manifest.v1+json")
+                (
+                    url,
+                    data,
+                    self.manifest_content_type.clone(),
+                    "PYOCI_MANIFEST_CONTENT_TYPE",
+                )
             }
         };
 
-        let request = self
+        let mut request = self
             .transport
             .put(url)
-            .header("Content-Type", content_type)
+            .header("Content-Type", &content_type)
             .body(data);
+        match precondition {
+            Precondition::None => {}
+            Precondition::Match(digest) => {
+                request = request.header("If-Match", format!("\"{digest}\""));
+            }
+            Precondition::NoneMatch => request = request.header("If-None-Match", "*"),
+        }
         let response = self.transport.send(request).await?;
         match response.status() {
             StatusCode::CREATED => {}
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+                return Err(PyOciError::from((
+                    StatusCode::BAD_GATEWAY,
+                    format!(
+                        "Registry does not accept manifests with Content-Type '{content_type}', \
+                        set {setting} to the media type it expects: {}",
+                        response.text().await?
+                    ),
+                ))
+                .into())
+            }
             status => return Err(PyOciError::from((status, response.text().await?)).into()),
         }
         Ok(())
     }
 
+    /// `Content-Type` of pushed `ImageManifest`s, also set as their `mediaType`
+    pub fn manifest_content_type(&self) -> &str {
+        &self.manifest_content_type
+    }
+
     /// Pull a manifest from the registry
     ///
     /// If the manifest does not exist, Ok<None> is returned
     /// If any other error happens, an Err is returned
+    ///
+    /// When an `ImageIndex` wraps the `PyOCI` index in another index, the `PyOCI` index is returned.
+    /// Only use this for reading, updating or deleting the returned index would not change
+    /// what `reference` points to, use `pull_manifest_with_digest` instead.
     #[tracing::instrument(skip_all, fields(otel.name = name, otel.reference = reference))]
     pub async fn pull_manifest(&mut self, name: &str, reference: &str) -> Result<Option<Manifest>> {
+        match self.fetch_manifest(name, reference).await? {
+            Some((Manifest::Index(index), _)) => Ok(Some(Manifest::Index(Box::new(
+                self.resolve_index(name, *index).await?,
+            )))),
+            manifest => Ok(manifest.map(|(manifest, _)| manifest)),
+        }
+    }
+
+    /// Pull the manifest `reference` points to as-is, including its digest
+    ///
+    /// Nested indexes are not followed, so the manifest can be updated or deleted.
+    /// The digest can be passed to `push_manifest` to only update `reference` when it
+    /// did not change in the meantime.
+    #[tracing::instrument(skip_all, fields(otel.name = name, otel.reference = reference))]
+    pub async fn pull_manifest_with_digest(
+        &mut self,
+        name: &str,
+        reference: &str,
+    ) -> Result<Option<(Manifest, OciDigest)>> {
+        self.fetch_manifest(name, reference).await
+    }
+
+    /// Find the `PyOCI` index in an index-of-indexes
+    ///
+    /// Nested indexes are followed up to `MAX_INDEX_DEPTH` levels deep.
+    /// If `index` is a `PyOCI` index, or no nested `PyOCI` index is found, `index` is returned.
+    async fn resolve_index(&mut self, name: &str, index: ImageIndex) -> Result<ImageIndex> {
+        let mut candidates = vec![(index.clone(), 0)];
+        while let Some((candidate, depth)) = candidates.pop() {
+            if matches!(candidate.artifact_type(), Some(MediaType::Other(value)) if value == ARTIFACT_TYPE)
+            {
+                return Ok(candidate);
+            }
+            if depth == MAX_INDEX_DEPTH {
+                continue;
+            }
+            // Reversed so the first nested index is checked first
+            for descriptor in candidate.manifests().iter().rev() {
+                if *descriptor.media_type() != MediaType::ImageIndex {
+                    continue;
+                }
+                let digest = descriptor.digest().to_string();
+                if let Some((Manifest::Index(nested), _)) =
+                    self.fetch_manifest(name, &digest).await?
+                {
+                    candidates.push((*nested, depth + 1));
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// Pull a single manifest and its digest from the registry, without following nested indexes
+    async fn fetch_manifest(
+        &mut self,
+        name: &str,
+        reference: &str,
+    ) -> Result<Option<(Manifest, OciDigest)>> {
+        // Held until the body is read
+        let _permit = match &self.manifest_permits {
+            Some(permits) => Some(permits.clone().acquire_owned().await?),
+            None => None,
+        };
         let url = build_url!(&self.registry, "/v2/{}/manifests/{}", name, reference);
-        let request = self.transport.get(url).header(
-            "Accept",
-            "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json",
-        );
+        let request = self
+            .transport
+            .get(url)
+            .header("Accept", &self.manifest_accept)
+            .header("Accept-Encoding", JSON_ACCEPT_ENCODING);
         let response = self.transport.send(request).await?;
         match response.status() {
             StatusCode::NOT_FOUND => return Ok(None),
@@ -364,28 +772,76 @@ impl Oci {
             status => return Err(PyOciError::from((status, response.text().await?)).into()),
         }
 
-        match response.headers().get("Content-Type") {
-            Some(value) if value == "application/vnd.oci.image.index.v1+json" => {
-                Ok(Some(Manifest::Index(Box::new(
-                    response
-                        .json::<ImageIndex>()
-                        .await
-                        .expect("valid Index json"),
-                ))))
+        let content_type = response.headers().get("Content-Type").cloned();
+        match content_type {
+            Some(value) if value == INDEX_CONTENT_TYPE || value == self.index_content_type => {
+                let body = read_body(response, self.json_limit).await?;
+                let index = parse_manifest::<ImageIndex>(&body)?;
+                check_references(&self.registry, index.manifests())?;
+                if self.max_manifests != 0 && index.manifests().len() > self.max_manifests {
+                    return Err(PyOciError::from((
+                        StatusCode::BAD_GATEWAY,
+                        format!(
+                            "ImageIndex '{reference}' contains more than {} manifests",
+                            self.max_manifests
+                        ),
+                    ))
+                    .into());
+                }
+                Ok(Some((Manifest::Index(Box::new(index)), digest(&body))))
             }
-            Some(value) if value == "application/vnd.oci.image.manifest.v1+json" => {
-                Ok(Some(Manifest::Manifest(Box::new(
-                    response
-                        .json::<ImageManifest>()
-                        .await
-                        .expect("valid Manifest json"),
-                ))))
+            Some(value)
+                if value == MANIFEST_CONTENT_TYPE || value == self.manifest_content_type =>
+            {
+                let body = read_body(response, self.json_limit).await?;
+                let manifest = parse_manifest::<ImageManifest>(&body)?;
+                check_references(&self.registry, manifest.layers())?;
+                check_references(&self.registry, [manifest.config()])?;
+                Ok(Some((
+                    Manifest::Manifest(Box::new(manifest)),
+                    digest(&body),
+                )))
             }
             Some(content_type) => bail!("Unknown Content-Type: {}", content_type.to_str().unwrap()),
             None => bail!("Missing Content-Type header"),
         }
     }
 
+    /// Add `tag` to the manifest `reference` points to
+    ///
+    /// The manifest is pushed as-is, re-serializing it could change the digest.
+    #[tracing::instrument(skip_all, fields(otel.name = name, otel.reference = reference))]
+    pub async fn tag_manifest(&mut self, name: &str, reference: &str, tag: &str) -> Result<()> {
+        let url = build_url!(&self.registry, "/v2/{}/manifests/{}", name, reference);
+        let request = self
+            .transport
+            .get(url)
+            .header("Accept", &self.manifest_accept)
+            .header("Accept-Encoding", JSON_ACCEPT_ENCODING);
+        let response = self.transport.send(request).await?;
+        if response.status() != StatusCode::OK {
+            return Err(PyOciError::from((response.status(), response.text().await?)).into());
+        }
+        let content_type = response
+            .headers()
+            .get("Content-Type")
+            .cloned()
+            .context("Missing Content-Type header")?;
+        let body = read_body(response, self.json_limit).await?;
+
+        let url = build_url!(&self.registry, "/v2/{}/manifests/{}", name, tag);
+        let request = self
+            .transport
+            .put(url)
+            .header("Content-Type", content_type)
+            .body(body);
+        let response = self.transport.send(request).await?;
+        match response.status() {
+            StatusCode::CREATED => Ok(()),
+            status => Err(PyOciError::from((status, response.text().await?)).into()),
+        }
+    }
+
     /// Delete a tag or manifest
     ///
     /// reference: tag or digest of the manifest to delete
@@ -402,6 +858,134 @@ impl Oci {
     }
 }
 
+/// URL of the upload session started by a blob POST
+///
+/// Uses the `Location` header, falling back to the `Docker-Upload-UUID` header
+/// for registries that only return the session ID.
+fn upload_url(registry: &Url, name: &str, headers: &http::HeaderMap) -> Result<Url> {
+    if let Some(location) = headers.get("Location") {
+        let location = location
+            .to_str()
+            .context("Failed to parse Location header as ASCII")?;
+        return Ok(build_url!(registry, "{}", location));
+    }
+    let uuid = headers
+        .get("Docker-Upload-UUID")
+        .context("Registry response did not contain a Location or Docker-Upload-UUID header")?
+        .to_str()
+        .context("Failed to parse Docker-Upload-UUID header as ASCII")?;
+    Ok(build_url!(registry, "/v2/{}/blobs/uploads/{}", name, uuid))
+}
+
+/// Parse a manifest received from the registry
+///
+/// Returns a 502 when the registry returned invalid JSON for the Content-Type it reported.
+fn parse_manifest<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, PyOciError> {
+    serde_json::from_slice(body).map_err(|err| {
+        tracing::warn!("Invalid manifest JSON: {err}");
+        PyOciError::from((
+            StatusCode::BAD_GATEWAY,
+            "Registry returned invalid manifest JSON",
+        ))
+    })
+}
+
+/// Reject descriptors referencing content on another registry
+///
+/// Referenced content is always pulled by digest from `registry`,
+/// a descriptor with `urls` pointing elsewhere is an invalid or malicious manifest.
+fn check_references<'a>(
+    registry: &Url,
+    descriptors: impl IntoIterator<Item = &'a Descriptor>,
+) -> Result<(), PyOciError> {
+    for descriptor in descriptors {
+        for url in descriptor.urls().iter().flatten() {
+            if Url::parse(url).is_ok_and(|url| url.origin() == registry.origin()) {
+                continue;
+            }
+            return Err(PyOciError::from((
+                StatusCode::BAD_GATEWAY,
+                format!(
+                    "Descriptor '{}' references content outside of the registry: {url}",
+                    descriptor.digest()
+                ),
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Read the response body, failing when it exceeds `limit` bytes
+///
+/// A `limit` of 0 reads the full body.
+/// A `gzip` or `br` encoded body is decoded, the limit applies to the encoded and the decoded body.
+async fn read_body(mut response: Response, limit: usize) -> Result<Vec<u8>> {
+    let encoding = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).to_ascii_lowercase());
+    let body = if limit == 0 {
+        response.bytes().await?.to_vec()
+    } else {
+        if response
+            .content_length()
+            .is_some_and(|length| length > limit as u64)
+        {
+            return Err(too_large(limit).into());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(too_large(limit).into());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        body
+    };
+    decode_body(encoding.as_deref(), body, limit)
+}
+
+/// Decode a body with the `Content-Encoding` of the response
+fn decode_body(encoding: Option<&str>, body: Vec<u8>, limit: usize) -> Result<Vec<u8>> {
+    let reader: Box<dyn Read + '_> = match encoding {
+        None | Some("identity") => return Ok(body),
+        Some("gzip") => Box::new(GzDecoder::new(&body[..])),
+        Some("br") => Box::new(brotli_decompressor::Decompressor::new(&body[..], 4096)),
+        Some(encoding) => {
+            return Err(PyOciError::from((
+                StatusCode::BAD_GATEWAY,
+                format!("OCI registry response has unsupported Content-Encoding '{encoding}'"),
+            ))
+            .into())
+        }
+    };
+    // Read one byte beyond the limit to detect a body exceeding it
+    let max = if limit == 0 {
+        u64::MAX
+    } else {
+        limit as u64 + 1
+    };
+    let mut decoded = Vec::new();
+    reader.take(max).read_to_end(&mut decoded).map_err(|err| {
+        PyOciError::from((
+            StatusCode::BAD_GATEWAY,
+            format!("Failed to decode OCI registry response: {err}"),
+        ))
+    })?;
+    if limit > 0 && decoded.len() > limit {
+        return Err(too_large(limit).into());
+    }
+    Ok(decoded)
+}
+
+/// Error for a response exceeding `limit` bytes
+fn too_large(limit: usize) -> PyOciError {
+    PyOciError::from((
+        StatusCode::BAD_GATEWAY,
+        format!("OCI registry response exceeds {limit} bytes"),
+    ))
+}
+
 struct Link(String);
 
 impl TryFrom<&HeaderValue> for Link {
@@ -453,6 +1037,14 @@ mod tests {
     use pretty_assertions::assert_eq;
 
     use super::*;
+    use crate::{
+        spool::Spool,
+        transport::{ClientOptions, MANIFEST_ACCEPT},
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use serde_json::json;
+    use std::io::Write;
+    use test_case::test_case;
 
     #[test]
     fn test_build_url() -> Result<()> {
@@ -532,7 +1124,11 @@ mod tests {
                 .await,
         );
 
-        let mut client = Oci::new(Url::parse(&url).expect("valid url"), None);
+        let mut client = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
         let blob = Blob::new("hello".into(), "application/octet-stream");
         let _ = client.push_blob("mockserver/foobar", blob).await;
 
@@ -540,17 +1136,13 @@ mod tests {
             mock.assert_async().await;
         }
     }
-    /// Test if an absolute Location header is properly handled
+    /// Test if the upload URL is built from the session ID when the Location header is missing
     #[tokio::test]
-    async fn test_push_blob_location_absolute() {
+    async fn push_blob_upload_uuid() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
 
-        let mut mocks = vec![];
-        // Mock the server, in order of expected requests
-
-        // HEAD request to check if blob exists
-        mocks.push(
+        let mocks = vec![
             server
                 .mock(
                     "HEAD",
@@ -559,76 +1151,1478 @@ mod tests {
                 .with_status(404)
                 .create_async()
                 .await,
-        );
-        // POST request initiating blob upload
-        mocks.push(
             server
                 .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
                 .with_status(202) // ACCEPTED
-                .with_header(
-                    "Location",
-                    &format!("{url}/v2/mockserver/foobar/blobs/uploads/1?_state=uploading"),
-                )
+                .with_header("Docker-Upload-UUID", "1234")
                 .create_async()
                 .await,
-        );
-        // PUT request to upload blob
-        mocks.push(
             server
                 .mock(
                     "PUT",
-                    "/v2/mockserver/foobar/blobs/uploads/1?_state=uploading&digest=sha256%3A2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+                    "/v2/mockserver/foobar/blobs/uploads/1234?digest=sha256%3A2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
                 )
                 .with_status(201) // CREATED
+                .with_header(
+                    "Location",
+                    "/v2/mockserver/foobar/blobs/sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+                )
                 .create_async()
                 .await,
-        );
+        ];
 
-        let mut client = Oci::new(Url::parse(&url).expect("valid url"), None);
+        let mut client = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
         let blob = Blob::new("hello".into(), "application/octet-stream");
-        let _ = client.push_blob("mockserver/foobar", blob).await;
+        client.push_blob("mockserver/foobar", blob).await.unwrap();
 
         for mock in mocks {
             mock.assert_async().await;
         }
     }
 
+    /// Test a chunked upload resuming after a failed chunk
     #[tokio::test]
-    async fn list_tags() {
+    async fn push_blob_chunked_resume() {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
+        let session = "/v2/mockserver/foobar/blobs/uploads/1";
 
-        let tags = r#"{
-          "name": "mockserver/bar",
-          "tags": [
-            "1",
-            "2",
-            "3"
-          ]
-        }"#;
-        server
-            .mock("GET", "/v2/mockserver/bar/tags/list")
-            .with_status(200)
-            .with_body(tags)
-            .create_async()
-            .await;
-
-        let mut pyoci = Oci::new(Url::parse(&url).expect("valid url"), None);
-
-        let result = pyoci
-            .list_tags("mockserver/bar")
-            .await
-            .expect("Valid response");
-
-        assert_eq!(
-            result,
-            BTreeSet::from(["1".to_string(), "2".to_string(), "3".to_string()])
+        let mut mocks = vec![];
+        mocks.push(
+            server
+                .mock(
+                    "HEAD",
+                    "/v2/mockserver/foobar/blobs/sha256:98d234db7e91f5ba026a25d0d6f17bc5ee0a347ea2216b0c9de06d43536d49f4",
+                )
+                .with_status(404)
+                .create_async()
+                .await,
         );
-    }
-
-    #[tokio::test]
-    async fn list_tags_link_header() {
-        let mut server = mockito::Server::new_async().await;
+        mocks.push(
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .with_status(202)
+                .with_header("Location", session)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "0-3")
+                .match_body("hell")
+                .with_status(202)
+                .with_header("Location", session)
+                .with_header("Range", "0-3")
+                .create_async()
+                .await,
+        );
+        // The connection drops while sending the second chunk, only part of it is committed
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "4-7")
+                .with_status(502)
+                .expect(1)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("GET", session)
+                .with_status(204)
+                .with_header("Location", session)
+                .with_header("Range", "0-5")
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "6-9")
+                .match_body("orld")
+                .with_status(202)
+                .with_header("Location", session)
+                .with_header("Range", "0-9")
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "10-10")
+                .match_body("!")
+                .with_status(202)
+                .with_header("Location", session)
+                .with_header("Range", "0-10")
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock(
+                    "PUT",
+                    "/v2/mockserver/foobar/blobs/uploads/1?digest=sha256%3A98d234db7e91f5ba026a25d0d6f17bc5ee0a347ea2216b0c9de06d43536d49f4",
+                )
+                .match_body("")
+                .with_status(201)
+                .with_header("Location", "/v2/mockserver/foobar/blobs/sha256:98d234db7e91f5ba026a25d0d6f17bc5ee0a347ea2216b0c9de06d43536d49f4")
+                .create_async()
+                .await,
+        );
+
+        let client = ClientOptions {
+            upload_chunk_size: 4,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut client = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        let blob = Blob::new("helloworld!".into(), "application/octet-stream");
+        client.push_blob("mockserver/foobar", blob).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    /// Test a chunked upload restarting when the first chunk failed and the registry reports
+    /// an empty session as `Range: 0-0`
+    #[tokio::test]
+    async fn push_blob_chunked_resume_empty_range() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let session = "/v2/mockserver/foobar/blobs/uploads/1";
+
+        let mut mocks = vec![];
+        mocks.push(
+            server
+                .mock(
+                    "HEAD",
+                    "/v2/mockserver/foobar/blobs/sha256:98d234db7e91f5ba026a25d0d6f17bc5ee0a347ea2216b0c9de06d43536d49f4",
+                )
+                .with_status(404)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .with_status(202)
+                .with_header("Location", session)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "0-3")
+                .with_status(502)
+                .expect(1)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("GET", session)
+                .with_status(204)
+                .with_header("Location", session)
+                .with_header("Range", "0-0")
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "0-3")
+                .match_body("hell")
+                .with_status(202)
+                .with_header("Location", session)
+                .with_header("Range", "0-3")
+                .expect(1)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "4-7")
+                .match_body("owor")
+                .with_status(202)
+                .with_header("Location", session)
+                .with_header("Range", "0-7")
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "8-10")
+                .match_body("ld!")
+                .with_status(202)
+                .with_header("Location", session)
+                .with_header("Range", "0-10")
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock(
+                    "PUT",
+                    "/v2/mockserver/foobar/blobs/uploads/1?digest=sha256%3A98d234db7e91f5ba026a25d0d6f17bc5ee0a347ea2216b0c9de06d43536d49f4",
+                )
+                .match_body("")
+                .with_status(201)
+                .with_header("Location", "/v2/mockserver/foobar/blobs/sha256:98d234db7e91f5ba026a25d0d6f17bc5ee0a347ea2216b0c9de06d43536d49f4")
+                .create_async()
+                .await,
+        );
+
+        let client = ClientOptions {
+            upload_chunk_size: 4,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut client = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        let blob = Blob::new("helloworld!".into(), "application/octet-stream");
+        client.push_blob("mockserver/foobar", blob).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    /// Test the digest of a chunked upload is calculated over the sent chunks
+    #[tokio::test]
+    async fn push_blob_chunked_digest() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let session = "/v2/mockserver/foobar/blobs/uploads/1";
+        let content = b"The quick brown fox jumps over the lazy dog";
+        // sha256 of `content`
+        let digest = "sha256:d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592";
+
+        let mut mocks = vec![
+            server
+                .mock(
+                    "HEAD",
+                    format!("/v2/mockserver/foobar/blobs/{digest}").as_str(),
+                )
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .with_status(202)
+                .with_header("Location", session)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    format!("{session}?digest={}", urlencoding::encode(digest)).as_str(),
+                )
+                .match_body("")
+                .with_status(201)
+                .with_header("Location", &format!("/v2/mockserver/foobar/blobs/{digest}"))
+                .create_async()
+                .await,
+        ];
+        for (start, chunk) in content.chunks(16).enumerate() {
+            let start = start * 16;
+            let end = start + chunk.len() - 1;
+            mocks.push(
+                server
+                    .mock("PATCH", session)
+                    .match_header("Content-Range", format!("{start}-{end}").as_str())
+                    .match_body(chunk.to_vec())
+                    .with_status(202)
+                    .with_header("Location", session)
+                    .with_header("Range", &format!("0-{end}"))
+                    .create_async()
+                    .await,
+            );
+        }
+
+        let client = ClientOptions {
+            upload_chunk_size: 16,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut client = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        // Spilled to a file, the content is only read one chunk at a time
+        let mut spool = Spool::new(8);
+        spool.write(content).await.unwrap();
+        let content = spool.finish().await.unwrap();
+        assert!(content.is_spilled());
+        let blob = Blob::from_content(content, "application/octet-stream");
+        client.push_blob("mockserver/foobar", blob).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    /// Test a chunked upload is not closed when the sent content does not match the digest
+    #[tokio::test]
+    async fn push_blob_chunked_digest_mismatch() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let session = "/v2/mockserver/foobar/blobs/uploads/1";
+
+        let mocks = vec![
+            server
+                .mock("HEAD", mockito::Matcher::Any)
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .with_status(202)
+                .with_header("Location", session)
+                .create_async()
+                .await,
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "0-3")
+                .with_status(202)
+                .with_header("Location", session)
+                .with_header("Range", "0-3")
+                .create_async()
+                .await,
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "4-4")
+                .with_status(202)
+                .with_header("Location", session)
+                .with_header("Range", "0-4")
+                .create_async()
+                .await,
+            server
+                .mock("PUT", mockito::Matcher::Any)
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let client = ClientOptions {
+            upload_chunk_size: 4,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut client = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        let mut blob = Blob::new("hello".into(), "application/octet-stream");
+        blob.descriptor = Blob::new("world".into(), "application/octet-stream").descriptor;
+        let err = client
+            .push_blob("mockserver/foobar", blob)
+            .await
+            .unwrap_err();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert!(err.to_string().starts_with(
+            "Uploaded content of 'mockserver/foobar' has digest 'sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824'"
+        ));
+    }
+
+    /// Test a chunked upload is aborted when chunks keep failing
+    #[tokio::test]
+    async fn push_blob_chunked_failed() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let session = "/v2/mockserver/foobar/blobs/uploads/1";
+
+        let mut mocks = vec![];
+        mocks.push(
+            server
+                .mock(
+                    "HEAD",
+                    "/v2/mockserver/foobar/blobs/sha256:98d234db7e91f5ba026a25d0d6f17bc5ee0a347ea2216b0c9de06d43536d49f4",
+                )
+                .with_status(404)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .with_status(202)
+                .with_header("Location", session)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("PATCH", session)
+                .match_header("Content-Range", "0-3")
+                .with_status(503)
+                .with_body("Unavailable")
+                .expect(UPLOAD_RESUME_ATTEMPTS)
+                .create_async()
+                .await,
+        );
+        mocks.push(
+            server
+                .mock("GET", session)
+                .with_status(204)
+                .with_header("Location", session)
+                .expect(UPLOAD_RESUME_ATTEMPTS - 1)
+                .create_async()
+                .await,
+        );
+
+        let client = ClientOptions {
+            upload_chunk_size: 4,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut client = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        let blob = Blob::new("helloworld!".into(), "application/octet-stream");
+        let err = client
+            .push_blob("mockserver/foobar", blob)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Failed to upload chunk 0-3: 503 Service Unavailable: Unavailable"
+        );
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    /// Test if an absolute Location header is properly handled
+    #[tokio::test]
+    async fn test_push_blob_location_absolute() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mut mocks = vec![];
+        // Mock the server, in order of expected requests
+
+        // HEAD request to check if blob exists
+        mocks.push(
+            server
+                .mock(
+                    "HEAD",
+                    "/v2/mockserver/foobar/blobs/sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+                )
+                .with_status(404)
+                .create_async()
+                .await,
+        );
+        // POST request initiating blob upload
+        mocks.push(
+            server
+                .mock("POST", "/v2/mockserver/foobar/blobs/uploads/")
+                .with_status(202) // ACCEPTED
+                .with_header(
+                    "Location",
+                    &format!("{url}/v2/mockserver/foobar/blobs/uploads/1?_state=uploading"),
+                )
+                .create_async()
+                .await,
+        );
+        // PUT request to upload blob
+        mocks.push(
+            server
+                .mock(
+                    "PUT",
+                    "/v2/mockserver/foobar/blobs/uploads/1?_state=uploading&digest=sha256%3A2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+                )
+                .with_status(201) // CREATED
+                .create_async()
+                .await,
+        );
+
+        let mut client = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let blob = Blob::new("hello".into(), "application/octet-stream");
+        let _ = client.push_blob("mockserver/foobar", blob).await;
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn list_tags() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let tags = r#"{
+          "name": "mockserver/bar",
+          "tags": [
+            "1",
+            "2",
+            "3"
+          ]
+        }"#;
+        server
+            .mock("GET", "/v2/mockserver/bar/tags/list")
+            .with_status(200)
+            .with_body(tags)
+            .create_async()
+            .await;
+
+        let mut pyoci = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+
+        let result = pyoci
+            .list_tags("mockserver/bar")
+            .await
+            .expect("Valid response");
+
+        assert_eq!(
+            result,
+            BTreeSet::from(["1".to_string(), "2".to_string(), "3".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    /// Without a `Link` header, the next pages are requested with `?last=`
+    async fn list_tags_pagination_last() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/bar", "tags": ["1", "2"]}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list?last=2")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/bar", "tags": ["3", "4"]}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list?last=4")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/bar", "tags": ["5"]}"#)
+                .create_async()
+                .await,
+        ];
+
+        let client = ClientOptions {
+            tag_pagination: TagPagination::Last,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut oci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+
+        let result = oci
+            .list_tags("mockserver/bar")
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            result,
+            BTreeSet::from(["1", "2", "3", "4", "5"].map(ToString::to_string))
+        );
+    }
+
+    #[tokio::test]
+    /// A registry ignoring `?last=` returns the first page again, which ends the pagination
+    async fn list_tags_pagination_last_ignored() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(r"^/v2/mockserver/bar/tags/list".to_string()),
+            )
+            .with_status(200)
+            .with_body(r#"{"name": "mockserver/bar", "tags": ["1", "2"]}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            tag_pagination: TagPagination::Last,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut oci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+
+        let result = oci
+            .list_tags("mockserver/bar")
+            .await
+            .expect("Valid response");
+
+        mock.assert_async().await;
+        assert_eq!(result, BTreeSet::from(["1", "2"].map(ToString::to_string)));
+    }
+
+    #[test_case(true, None, Some("Bearer filetoken"); "configured host")]
+    #[test_case(false, None, None; "other host")]
+    #[test_case(true, Some("callertoken"), Some("Bearer callertoken"); "caller credentials")]
+    #[tokio::test]
+    /// The token file is only used for the configured hosts, without credentials of the caller
+    async fn bearer_token_file(
+        configured: bool,
+        caller: Option<&str>,
+        expected: Option<&'static str>,
+    ) {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::parse(&server.url()).expect("valid url");
+        let mock = server
+            .mock("GET", "/v2/mockserver/bar/tags/list")
+            .match_header(
+                "Authorization",
+                expected.map_or(mockito::Matcher::Missing, Into::into),
+            )
+            .with_status(200)
+            .with_body(r#"{"name": "mockserver/bar", "tags": ["1"]}"#)
+            .create_async()
+            .await;
+
+        let path =
+            std::env::temp_dir().join(format!("pyoci-token-{:032x}", rand::random::<u128>()));
+        std::fs::write(&path, "filetoken\n").unwrap();
+        let host = if configured {
+            format!("{}:{}", url.host_str().unwrap(), url.port().unwrap())
+        } else {
+            "registry.example.com".to_string()
+        };
+        let client = ClientOptions {
+            bearer_token_file: Some(path.clone()),
+            bearer_token_hosts: vec![host],
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let auth = caller.map(|token| {
+            AuthHeader::Bearer(headers::Authorization::bearer(token).expect("valid token"))
+        });
+        let mut oci = Oci::new(url, auth, client);
+
+        let result = oci.list_tags("mockserver/bar").await;
+        std::fs::remove_file(&path).unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(result.unwrap(), BTreeSet::from(["1".to_string()]));
+    }
+
+    /// Tags of the gzip encoded tag list
+    fn many_tags() -> BTreeSet<String> {
+        (0..100).map(|minor| format!("1.{minor}.0")).collect()
+    }
+
+    /// gzip encoded tag list, as served by some registries
+    fn gzip_tags() -> Vec<u8> {
+        let tags = json!({"name": "mockserver/bar", "tags": many_tags()});
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(tags.to_string().as_bytes())
+            .expect("valid gzip");
+        encoder.finish().expect("valid gzip")
+    }
+
+    // Test if a gzip encoded tag list is requested and decoded
+    #[tokio::test]
+    async fn list_tags_gzip() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("GET", "/v2/mockserver/bar/tags/list")
+            .match_header("accept-encoding", "gzip, br")
+            .with_status(200)
+            .with_header("content-encoding", "gzip")
+            .with_body(gzip_tags())
+            .create_async()
+            .await;
+
+        let mut pyoci = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let result = pyoci
+            .list_tags("mockserver/bar")
+            .await
+            .expect("Valid response");
+
+        assert_eq!(result, many_tags());
+        mock.assert_async().await;
+    }
+
+    // Test if the size limit applies to the decoded tag list
+    #[tokio::test]
+    async fn list_tags_gzip_too_large() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let body = gzip_tags();
+        server
+            .mock("GET", "/v2/mockserver/bar/tags/list")
+            .with_status(200)
+            .with_header("content-encoding", "gzip")
+            .with_body(&body)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            // Fits the encoded body, but not the decoded body
+            json_limit: body.len(),
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut pyoci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        let err = pyoci
+            .list_tags("mockserver/bar")
+            .await
+            .expect_err("Expected an Err")
+            .downcast::<PyOciError>()
+            .expect("Expected a PyOciError");
+
+        assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            err.message,
+            format!("OCI registry response exceeds {} bytes", body.len())
+        );
+    }
+
+    // Test if a blob is only pulled from the registry once when the cache is enabled
+    #[tokio::test]
+    async fn pull_blob_cached() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock(
+                "GET",
+                "/v2/mockserver/bar/blobs/sha256:936a185caaa266bb9cbe981e9e05cb78cd732b0b3280eb944412bb6f8f8f07af",
+            )
+            .with_status(200)
+            .with_body("helloworld")
+            // Blobs are received as-is for the digest verification
+            .match_header("accept-encoding", mockito::Matcher::Missing)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            blob_cache_bytes: 1000,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let descriptor = DescriptorBuilder::default()
+            .media_type("application/pyoci.package.v1")
+            .digest(digest("helloworld"))
+            .size(10_u64)
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            // Each request uses a new client, the cache is shared through the HttpClient
+            let mut oci = Oci::new(Url::parse(&url).expect("valid url"), None, client.clone());
+            let response = oci
+                .pull_blob("mockserver/bar".to_string(), descriptor.clone())
+                .await
+                .expect("Valid response");
+            assert_eq!(response.text().await.unwrap(), "helloworld");
+        }
+        mock.assert_async().await;
+    }
+
+    // Test if the cache is not shared between repositories
+    #[tokio::test]
+    async fn pull_blob_cached_per_repository() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let descriptor = DescriptorBuilder::default()
+            .media_type("application/pyoci.package.v1")
+            .digest(digest("helloworld"))
+            .size(10_u64)
+            .build()
+            .unwrap();
+        let mut mocks = vec![];
+        for name in ["mockserver/bar", "mockserver/foo"] {
+            mocks.push(
+                server
+                    .mock(
+                        "GET",
+                        format!("/v2/{name}/blobs/{}", descriptor.digest()).as_str(),
+                    )
+                    .with_status(200)
+                    .with_body("helloworld")
+                    .expect(1)
+                    .create_async()
+                    .await,
+            );
+        }
+
+        let client = ClientOptions {
+            blob_cache_bytes: 1000,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        for name in ["mockserver/bar", "mockserver/foo"] {
+            let mut oci = Oci::new(Url::parse(&url).expect("valid url"), None, client.clone());
+            oci.pull_blob(name.to_string(), descriptor.clone())
+                .await
+                .expect("Valid response");
+        }
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    // Test if content not matching the digest is rejected and not cached
+    #[test_case("helloworld!"; "size")]
+    #[test_case("hellowOrld"; "digest")]
+    #[tokio::test]
+    async fn pull_blob_cached_mismatch(body: &str) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let descriptor = DescriptorBuilder::default()
+            .media_type("application/pyoci.package.v1")
+            .digest(digest("helloworld"))
+            .size(10_u64)
+            .build()
+            .unwrap();
+        let mock = server
+            .mock(
+                "GET",
+                format!("/v2/mockserver/bar/blobs/{}", descriptor.digest()).as_str(),
+            )
+            .with_status(200)
+            .with_body(body)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            blob_cache_bytes: 1000,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        for _ in 0..2 {
+            let mut oci = Oci::new(Url::parse(&url).expect("valid url"), None, client.clone());
+            let err = oci
+                .pull_blob("mockserver/bar".to_string(), descriptor.clone())
+                .await
+                .expect_err("Expected an Err")
+                .downcast::<PyOciError>()
+                .expect("Expected a PyOciError");
+            assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+        }
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn list_tags_too_large() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let tags = r#"{"name": "mockserver/bar", "tags": ["1", "2", "3"]}"#;
+        server
+            .mock("GET", "/v2/mockserver/bar/tags/list")
+            .with_status(200)
+            .with_body(tags)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            json_limit: 32,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut pyoci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+
+        let err = pyoci
+            .list_tags("mockserver/bar")
+            .await
+            .expect_err("Expected an error")
+            .downcast::<PyOciError>()
+            .unwrap();
+        assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(err.message, "OCI registry response exceeds 32 bytes");
+    }
+
+    #[tokio::test]
+    async fn list_tags_too_large_chunked() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        // No Content-Length, the limit is enforced while reading
+        server
+            .mock("GET", "/v2/mockserver/bar/tags/list")
+            .with_status(200)
+            .with_chunked_body(|w| {
+                w.write_all(br#"{"name": "mockserver/bar", "#)?;
+                w.write_all(br#""tags": ["1", "2", "3"]}"#)
+            })
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            json_limit: 32,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut pyoci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+
+        let err = pyoci
+            .list_tags("mockserver/bar")
+            .await
+            .expect_err("Expected an error")
+            .downcast::<PyOciError>()
+            .unwrap();
+        assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn pull_manifest_too_many_manifests() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let descriptor = r#"{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+            "size": 6
+        }"#;
+        let index = format!(
+            r#"{{
+              "schemaVersion": 2,
+              "mediaType": "application/vnd.oci.image.index.v1+json",
+              "manifests": [{}]
+            }}"#,
+            [descriptor; 3].join(",")
+        );
+        server
+            .mock("GET", "/v2/mockserver/bar/manifests/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+            .with_body(index)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            max_manifests: 2,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut pyoci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+
+        let err = pyoci
+            .pull_manifest("mockserver/bar", "1")
+            .await
+            .expect_err("Expected an error")
+            .downcast::<PyOciError>()
+            .unwrap();
+        assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(err.message, "ImageIndex '1' contains more than 2 manifests");
+    }
+
+    #[test_case("foo-1-cp312-cp312-macosx_11_0_arm64.whl", Some(&Os::Darwin); "macos")]
+    #[test_case("foo-1-cp312-cp312-manylinux_2_17_x86_64.manylinux2014_x86_64.whl", Some(&Os::Linux); "manylinux")]
+    #[test_case("foo-1-cp312-cp312-musllinux_1_2_aarch64.whl", Some(&Os::Linux); "musllinux")]
+    #[test_case("foo-1-cp312-cp312-win_amd64.whl", Some(&Os::Windows); "windows")]
+    #[test_case("foo-1-py3-none-any.whl", None; "pure wheel")]
+    #[test_case("foo-1.tar.gz", None; "sdist")]
+    fn implied_os_from_filename(filename: &str, expected: Option<&Os>) {
+        let package = Package::from_filename("ghcr.io", "mockserver", "foo", filename).unwrap();
+        assert_eq!(implied_os(&package).as_ref(), expected);
+    }
+
+    #[test_case("application/vnd.oci.image.index.v1+json"; "index")]
+    #[test_case("application/vnd.oci.image.manifest.v1+json"; "manifest")]
+    #[tokio::test]
+    async fn pull_manifest_invalid_json(content_type: &str) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/mockserver/bar/manifests/1")
+            .with_status(200)
+            .with_header("content-type", content_type)
+            .with_body(r#"{"schemaVersion": 2, "manifests": ["#)
+            .create_async()
+            .await;
+
+        let mut pyoci = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+
+        let err = pyoci
+            .pull_manifest("mockserver/bar", "1")
+            .await
+            .expect_err("Expected an error")
+            .downcast::<PyOciError>()
+            .unwrap();
+        assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(err.message, "Registry returned invalid manifest JSON");
+    }
+
+    /// `ImageIndex` referencing the nested index at `digest`
+    fn wrapping_index(digest: &str) -> String {
+        format!(
+            r#"{{
+              "schemaVersion": 2,
+              "mediaType": "application/vnd.oci.image.index.v1+json",
+              "manifests": [
+                {{
+                  "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                  "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+                  "size": 6
+                }},
+                {{
+                  "mediaType": "application/vnd.oci.image.index.v1+json",
+                  "digest": "{digest}",
+                  "size": 6
+                }}
+              ]
+            }}"#
+        )
+    }
+
+    // Test if the configured media types are sent in order when pulling a manifest
+    #[tokio::test]
+    async fn pull_manifest_accept() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("GET", "/v2/mockserver/bar/manifests/1")
+            .match_header(
+                "accept",
+                "application/vnd.oci.image.index.v1+json, application/vnd.oci.image.manifest.v1+json",
+            )
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            manifest_accept: vec![
+                "application/vnd.oci.image.index.v1+json".to_string(),
+                "application/vnd.oci.image.manifest.v1+json".to_string(),
+            ],
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut oci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        let result = oci.pull_manifest("mockserver/bar", "1").await.unwrap();
+
+        assert!(result.is_none());
+        mock.assert_async().await;
+    }
+
+    // Test if a 415 on pushing a manifest names the Content-Type that was sent
+    #[tokio::test]
+    async fn push_manifest_unsupported_media_type() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("PUT", "/v2/mockserver/bar/manifests/1")
+            .match_header(
+                "content-type",
+                "application/vnd.docker.distribution.manifest.list.v2+json",
+            )
+            .with_status(415)
+            .with_body("MANIFEST_INVALID")
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            index_content_type: Some(
+                "application/vnd.docker.distribution.manifest.list.v2+json".to_string(),
+            ),
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let index: ImageIndex =
+            serde_json::from_str(r#"{"schemaVersion": 2, "manifests": []}"#).unwrap();
+        let mut oci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        let err = oci
+            .push_manifest(
+                "mockserver/bar",
+                Manifest::Index(Box::new(index)),
+                Some("1"),
+                Precondition::None,
+            )
+            .await
+            .unwrap_err()
+            .downcast::<PyOciError>()
+            .unwrap();
+
+        assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            err.message,
+            "Registry does not accept manifests with Content-Type \
+            'application/vnd.docker.distribution.manifest.list.v2+json', \
+            set PYOCI_INDEX_CONTENT_TYPE to the media type it expects: MANIFEST_INVALID"
+        );
+        mock.assert_async().await;
+    }
+
+    // Test if an index is pushed and pulled again with the configured Content-Type
+    #[tokio::test]
+    async fn index_content_type_round_trip() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let content_type = "application/vnd.docker.distribution.manifest.list.v2+json";
+        let body = format!(r#"{{"schemaVersion":2,"mediaType":"{content_type}","manifests":[]}}"#);
+        let mocks = vec![
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .match_header("content-type", content_type)
+                .match_body(body.as_str())
+                .with_status(201)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .match_header(
+                    "accept",
+                    format!("{MANIFEST_ACCEPT}, {content_type}").as_str(),
+                )
+                .with_status(200)
+                .with_header("content-type", content_type)
+                .with_body(&body)
+                .create_async()
+                .await,
+        ];
+
+        let client = ClientOptions {
+            index_content_type: Some(content_type.to_string()),
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        // Pushed with the OCI media type before the Content-Type was configured
+        let index: ImageIndex = serde_json::from_str(
+            r#"{"schemaVersion": 2, "mediaType": "application/vnd.oci.image.index.v1+json", "manifests": []}"#,
+        )
+        .unwrap();
+        let mut oci = Oci::new(Url::parse(&url).expect("valid url"), None, client);
+        oci.push_manifest(
+            "mockserver/bar",
+            Manifest::Index(Box::new(index)),
+            Some("1"),
+            Precondition::None,
+        )
+        .await
+        .expect("Valid response");
+        let Some(Manifest::Index(index)) = oci.pull_manifest("mockserver/bar", "1").await.unwrap()
+        else {
+            panic!("Expected an ImageIndex");
+        };
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            index.media_type(),
+            &Some(MediaType::Other(content_type.to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn pull_manifest_nested_index() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let nested = "sha256:2222222222222222222222222222222222222222222222222222222222222222";
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(wrapping_index(nested))
+                .create_async()
+                .await,
+            server
+                .mock("GET", format!("/v2/mockserver/bar/manifests/{nested}").as_str())
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(
+                    r#"{
+                      "schemaVersion": 2,
+                      "mediaType": "application/vnd.oci.image.index.v1+json",
+                      "artifactType": "application/pyoci.package.v1",
+                      "manifests": [
+                        {
+                          "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                          "digest": "sha256:3333333333333333333333333333333333333333333333333333333333333333",
+                          "size": 6
+                        }
+                      ]
+                    }"#,
+                )
+                .create_async()
+                .await,
+        ];
+
+        let mut client = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let Some(Manifest::Index(index)) =
+            client.pull_manifest("mockserver/bar", "1").await.unwrap()
+        else {
+            panic!("Expected an ImageIndex");
+        };
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            index.artifact_type(),
+            &Some(MediaType::Other(ARTIFACT_TYPE.to_string()))
+        );
+        assert_eq!(
+            index.manifests()[0].digest().to_string(),
+            "sha256:3333333333333333333333333333333333333333333333333333333333333333"
+        );
+    }
+
+    // Test if the wrapping index is returned as-is when it is going to be updated
+    #[tokio::test]
+    async fn pull_manifest_with_digest_nested_index() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let nested = "sha256:2222222222222222222222222222222222222222222222222222222222222222";
+        let wrapper = wrapping_index(nested);
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(&wrapper)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    format!("/v2/mockserver/bar/manifests/{nested}").as_str(),
+                )
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let mut client = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let Some((Manifest::Index(index), index_digest)) = client
+            .pull_manifest_with_digest("mockserver/bar", "1")
+            .await
+            .unwrap()
+        else {
+            panic!("Expected an ImageIndex");
+        };
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(index.artifact_type(), &None);
+        assert_eq!(index.manifests().len(), 2);
+        assert_eq!(index_digest, digest(&wrapper));
+    }
+
+    #[test_case("https://evil.example/v2/mockserver/bar/manifests/sha256:3333"; "other registry")]
+    #[test_case("not a url"; "invalid url")]
+    #[tokio::test]
+    /// Descriptors pointing to another registry are rejected, nothing is pulled from them
+    async fn pull_manifest_foreign_reference(reference: &str) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("GET", "/v2/mockserver/bar/manifests/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+            .with_body(
+                json!({
+                  "schemaVersion": 2,
+                  "mediaType": "application/vnd.oci.image.index.v1+json",
+                  "artifactType": "application/pyoci.package.v1",
+                  "manifests": [
+                    {
+                      "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                      "digest": "sha256:3333333333333333333333333333333333333333333333333333333333333333",
+                      "size": 6,
+                      "urls": [reference]
+                    }
+                  ]
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let mut client = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let err = client
+            .pull_manifest("mockserver/bar", "1")
+            .await
+            .unwrap_err()
+            .downcast::<PyOciError>()
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            err.message,
+            format!(
+                "Descriptor 'sha256:3333333333333333333333333333333333333333333333333333333333333333' \
+                references content outside of the registry: {reference}"
+            )
+        );
+    }
+
+    #[tokio::test]
+    /// A descriptor referencing the configured registry is accepted
+    async fn pull_manifest_same_registry_reference() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("GET", "/v2/mockserver/bar/manifests/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+            .with_body(
+                json!({
+                  "schemaVersion": 2,
+                  "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                  "config": {
+                    "mediaType": "application/vnd.oci.empty.v1+json",
+                    "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+                    "size": 2
+                  },
+                  "layers": [
+                    {
+                      "mediaType": "application/pyoci.package.v1",
+                      "digest": "sha256:3333333333333333333333333333333333333333333333333333333333333333",
+                      "size": 6,
+                      "urls": [format!("{url}/v2/mockserver/bar/blobs/sha256:3333")]
+                    }
+                  ]
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let mut client = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let manifest = client.pull_manifest("mockserver/bar", "1").await.unwrap();
+
+        mock.assert_async().await;
+        assert!(matches!(manifest, Some(Manifest::Manifest(_))));
+    }
+
+    #[tokio::test]
+    /// Nested indexes deeper than `MAX_INDEX_DEPTH` are not followed
+    async fn pull_manifest_nested_index_depth() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let digests = [
+            "sha256:2222222222222222222222222222222222222222222222222222222222222222",
+            "sha256:3333333333333333333333333333333333333333333333333333333333333333",
+            "sha256:4444444444444444444444444444444444444444444444444444444444444444",
+            "sha256:5555555555555555555555555555555555555555555555555555555555555555",
+        ];
+        let mut mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(wrapping_index(digests[0]))
+                .create_async()
+                .await,
+        ];
+        for (reference, nested) in digests.iter().zip(&digests[1..]) {
+            mocks.push(
+                server
+                    .mock(
+                        "GET",
+                        format!("/v2/mockserver/bar/manifests/{reference}").as_str(),
+                    )
+                    .with_status(200)
+                    .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                    .with_body(wrapping_index(nested))
+                    // Only the indexes up to MAX_INDEX_DEPTH are pulled
+                    .expect(usize::from(*reference != digests[MAX_INDEX_DEPTH]))
+                    .create_async()
+                    .await,
+            );
+        }
+
+        let mut client = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let Some(Manifest::Index(index)) =
+            client.pull_manifest("mockserver/bar", "1").await.unwrap()
+        else {
+            panic!("Expected an ImageIndex");
+        };
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        // No PyOCI index found, the top-level index is returned
+        assert_eq!(index.manifests()[1].digest().to_string(), digests[0]);
+    }
+
+    #[tokio::test]
+    async fn list_tags_link_header() {
+        let mut server = mockito::Server::new_async().await;
         let url = server.url();
 
         server
@@ -685,7 +2679,11 @@ mod tests {
             .create_async()
             .await;
 
-        let mut pyoci = Oci::new(Url::parse(&url).expect("valid url"), None);
+        let mut pyoci = Oci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
 
         let result = pyoci
             .list_tags("mockserver/bar")
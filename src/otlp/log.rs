@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::fmt::{self, Write};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::Instant;
 
 use prost::Message;
 use tracing::Subscriber;
@@ -16,16 +16,16 @@ use opentelemetry_proto::tonic::common::v1::{AnyValue, KeyValue};
 use opentelemetry_proto::tonic::logs::v1::{LogRecord, ResourceLogs, ScopeLogs};
 use opentelemetry_proto::tonic::resource::v1::Resource;
 
+use crate::otlp::metrics::FlushMetrics;
 use crate::otlp::trace::{SpanId, TraceId};
-use crate::otlp::Toilet;
+use crate::otlp::{export_result, Protocol, Signal, Toilet};
 use crate::time::time_unix_ns;
-use crate::USER_AGENT;
 
 /// Convert a batch of log records into a `ExportLogsServiceRequest`
 /// <https://opentelemetry.io/docs/specs/otlp/#otlpgrpc>
 fn build_logs_export_body(
     logs: Vec<LogRecord>,
-    attributes: &HashMap<&str, Option<String>>,
+    attributes: &HashMap<String, Option<String>>,
 ) -> ExportLogsServiceRequest {
     let scope_logs = ScopeLogs {
         scope: None,
@@ -39,7 +39,7 @@ fn build_logs_export_body(
             continue;
         };
         attrs.push(KeyValue {
-            key: (*key).into(),
+            key: key.clone(),
             value: Some(AnyValue {
                 value: Some(any_value::Value::StringValue(value.into())),
             }),
@@ -65,17 +65,33 @@ fn build_logs_export_body(
 pub struct OtlpLogLayer {
     otlp_endpoint: String,
     otlp_auth: String,
+    protocol: Protocol,
+    /// Shared by all flushes of the layer
+    client: reqwest::Client,
     /// Buffer of `LogRecords`, each (log) event during a request will be added to this buffer
     records: Arc<RwLock<Vec<LogRecord>>>,
+    /// Duration and size of the flushes
+    flush_metrics: FlushMetrics,
 }
 
 // Public methods
 impl OtlpLogLayer {
-    pub fn new(otlp_endpoint: &str, otlp_auth: &str) -> Self {
+    pub fn new(otlp_endpoint: &str, otlp_auth: &str, protocol: Protocol) -> Self {
         Self {
             otlp_endpoint: otlp_endpoint.to_string(),
             otlp_auth: otlp_auth.to_string(),
+            protocol,
+            client: protocol.client(),
             records: Arc::new(RwLock::new(vec![])),
+            flush_metrics: FlushMetrics::default(),
+        }
+    }
+
+    /// Record the flushes in `flush_metrics`, shared with the metrics layer
+    pub(super) fn with_flush_metrics(self, flush_metrics: FlushMetrics) -> Self {
+        Self {
+            flush_metrics,
+            ..self
         }
     }
 }
@@ -83,43 +99,33 @@ impl OtlpLogLayer {
 impl Toilet for OtlpLogLayer {
     /// Push all recorded log messages to the OTLP collector
     /// This should be called at the end of every request, after the span is closed
-    async fn flush(&self, attributes: &HashMap<&str, Option<String>>) {
+    async fn flush(
+        &self,
+        attributes: &HashMap<String, Option<String>>,
+    ) -> Option<Result<(), String>> {
         let records: Vec<LogRecord> = self.records.write().unwrap().drain(..).collect();
         if records.is_empty() {
             tracing::debug!("No logs to send");
-            return;
+            return None;
         }
         tracing::info!("Sending {} log records to OTLP", records.len());
-        let client = reqwest::Client::builder()
-            .user_agent(USER_AGENT)
-            .timeout(Duration::from_secs(10))
-            .build()
-            .unwrap();
-
+        let records_count = records.len();
         let body = build_logs_export_body(records, attributes).encode_to_vec();
-        let mut url = url::Url::parse(&self.otlp_endpoint).unwrap();
-        url.path_segments_mut().unwrap().extend(&["v1", "logs"]);
         // send to OTLP Collector
-        match client
-            .post(url)
-            .header("Content-Type", "application/x-protobuf")
-            .header("Authorization", &self.otlp_auth)
-            .body(body)
-            .send()
-            .await
-        {
-            Ok(response) => {
-                if response.status().is_success() {
-                    tracing::info!("Logs sent to OTLP: {:?}", response);
-                } else {
-                    tracing::info!("Failed to send logs to OTLP: {:?}", response);
-                    tracing::info!("Response body: {:?}", response.text().await.unwrap());
-                }
-            }
-            Err(err) => {
-                tracing::info!("Error sending logs to OTLP: {:?}", err);
-            }
-        }
+        let start = Instant::now();
+        let result = self
+            .protocol
+            .export(
+                &self.client,
+                &self.otlp_endpoint,
+                &self.otlp_auth,
+                Signal::Logs,
+                body,
+            )
+            .await;
+        self.flush_metrics
+            .record(Signal::Logs, start.elapsed(), records_count);
+        Some(export_result(Signal::Logs, self.protocol, result).await)
     }
 }
 
@@ -218,7 +224,7 @@ mod tests {
             .await;
 
         // init tracing with the otlp layer
-        let otlp_layer = OtlpLogLayer::new(&url, "unittest_auth");
+        let otlp_layer = OtlpLogLayer::new(&url, "unittest_auth", Protocol::Http);
         let otlp_clone = otlp_layer.clone();
         let subscriber = tracing_subscriber::registry()
             .with(SpanIdLayer::default())
@@ -248,7 +254,10 @@ mod tests {
             }
         );
         otlp_clone
-            .flush(&HashMap::from([("unittest", Some("test1".into()))]))
+            .flush(&HashMap::from([(
+                "unittest".to_string(),
+                Some("test1".into()),
+            )]))
             .await;
 
         mock.assert_async().await;
@@ -266,7 +275,7 @@ mod tests {
             .await;
 
         // init tracing with the otlp layer
-        let otlp_layer = OtlpLogLayer::new(&url, "");
+        let otlp_layer = OtlpLogLayer::new(&url, "", Protocol::Http);
         let otlp_clone = otlp_layer.clone();
         let subscriber = tracing_subscriber::registry()
             .with(SpanIdLayer::default())
@@ -280,7 +289,9 @@ mod tests {
         });
 
         assert_eq!(otlp_clone.records.read().unwrap().len(), 0);
-        otlp_clone.flush(&HashMap::from([("unittest", None)])).await;
+        otlp_clone
+            .flush(&HashMap::from([("unittest".to_string(), None)]))
+            .await;
 
         mock.assert_async().await;
     }
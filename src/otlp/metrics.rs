@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use prost::Message;
 
@@ -8,7 +8,7 @@ use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequ
 use opentelemetry_proto::tonic::common::v1::any_value;
 use opentelemetry_proto::tonic::common::v1::{AnyValue, KeyValue};
 use opentelemetry_proto::tonic::metrics::v1::{
-    metric::Data, number_data_point::Value, AggregationTemporality, Metric, NumberDataPoint,
+    metric::Data, number_data_point::Value, AggregationTemporality, Gauge, Metric, NumberDataPoint,
     ResourceMetrics, ScopeMetrics, Sum,
 };
 use opentelemetry_proto::tonic::resource::v1::Resource;
@@ -18,15 +18,15 @@ use tracing_subscriber::layer::Context;
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::Layer;
 
-use crate::otlp::Toilet;
+use crate::otlp::{export_result, Protocol, Signal, Toilet};
 use crate::time::time_unix_ns;
-use crate::USER_AGENT;
 
 /// Set of metrics to track
 #[derive(Debug)]
 struct Metrics {
     uptime: UptimeMetric,
     requests: RequestsMetric,
+    flush: FlushMetrics,
 }
 
 impl Default for Metrics {
@@ -34,16 +34,19 @@ impl Default for Metrics {
         Self {
             uptime: UptimeMetric::new(),
             requests: RequestsMetric::new(),
+            flush: FlushMetrics::default(),
         }
     }
 }
 
 impl Metrics {
     fn as_metrics(&self, attributes: &[KeyValue]) -> Vec<Metric> {
-        vec![
+        let mut metrics = vec![
             self.uptime.as_metric(attributes),
             self.requests.as_metric(attributes),
-        ]
+        ];
+        metrics.extend(self.flush.as_metrics(attributes));
+        metrics
     }
 }
 
@@ -122,11 +125,92 @@ impl RequestsMetric {
     }
 }
 
+/// Duration and number of items of the flushes to the OTLP collector, by signal
+///
+/// Recorded by each layer when flushing, exported by the [`OtlpMetricsLayer`]
+/// to detect a slow collector.
+#[derive(Debug, Clone, Default)]
+pub(super) struct FlushMetrics(Arc<RwLock<BTreeMap<&'static str, FlushStats>>>);
+
+#[derive(Debug, Default)]
+struct FlushStats {
+    /// Duration of the last flush
+    duration: Duration,
+    /// Total number of items sent
+    items: u64,
+}
+
+impl FlushMetrics {
+    /// Record a flush of `items` that took `duration`
+    pub(super) fn record(&self, signal: Signal, duration: Duration, items: usize) {
+        let mut stats = self.0.write().unwrap();
+        let stats = stats.entry(signal.name()).or_default();
+        stats.duration = duration;
+        stats.items += items as u64;
+    }
+
+    /// No metrics are returned before the first flush
+    fn as_metrics(&self, attributes: &[KeyValue]) -> Vec<Metric> {
+        let stats = self.0.read().unwrap();
+        if stats.is_empty() {
+            return vec![];
+        }
+        let now = time_unix_ns();
+        let data_points = |value: fn(&FlushStats) -> Value| {
+            stats
+                .iter()
+                .map(|(signal, stats)| {
+                    let mut attributes = attributes.to_vec();
+                    attributes.push(KeyValue {
+                        key: "signal".to_string(),
+                        value: Some(AnyValue {
+                            value: Some(any_value::Value::StringValue((*signal).to_string())),
+                        }),
+                        ..KeyValue::default()
+                    });
+                    NumberDataPoint {
+                        attributes,
+                        start_time_unix_nano: now,
+                        time_unix_nano: now,
+                        value: Some(value(stats)),
+                        ..NumberDataPoint::default()
+                    }
+                })
+                .collect()
+        };
+        vec![
+            Metric {
+                name: "pyoci.otlp.flush.duration".to_string(),
+                description: "Duration in seconds of the last flush to the OTLP collector"
+                    .to_string(),
+                unit: "seconds".to_string(),
+                data: Some(Data::Gauge(Gauge {
+                    data_points: data_points(|stats| Value::AsDouble(stats.duration.as_secs_f64())),
+                })),
+                metadata: vec![],
+            },
+            Metric {
+                name: "pyoci.otlp.flush.items".to_string(),
+                description: "Total number of items flushed to the OTLP collector".to_string(),
+                unit: "items".to_string(),
+                data: Some(Data::Sum(Sum {
+                    data_points: data_points(|stats| {
+                        Value::AsInt(i64::try_from(stats.items).unwrap_or(i64::MAX))
+                    }),
+                    aggregation_temporality: AggregationTemporality::Cumulative.into(),
+                    is_monotonic: true,
+                })),
+                metadata: vec![],
+            },
+        ]
+    }
+}
+
 /// Convert metrics into a `ExportMetricsServiceRequest`
 /// <https://opentelemetry.io/docs/specs/otlp/#otlpgrpc>
 fn build_metrics_export_body(
     metrics: &Metrics,
-    attributes: &HashMap<&str, Option<String>>,
+    attributes: &HashMap<String, Option<String>>,
 ) -> ExportMetricsServiceRequest {
     let mut attrs = vec![];
     for (key, value) in attributes {
@@ -134,7 +218,7 @@ fn build_metrics_export_body(
             continue;
         };
         attrs.push(KeyValue {
-            key: (*key).into(),
+            key: key.clone(),
             value: Some(AnyValue {
                 value: Some(any_value::Value::StringValue(value.into())),
             }),
@@ -164,19 +248,29 @@ fn build_metrics_export_body(
 pub struct OtlpMetricsLayer {
     otlp_endpoint: String,
     otlp_auth: String,
+    protocol: Protocol,
+    /// Shared by all flushes of the layer
+    client: reqwest::Client,
     /// Buffer of Metrics
     metrics: Arc<Metrics>,
 }
 
 // Public methods
 impl OtlpMetricsLayer {
-    pub fn new(otlp_endpoint: &str, otlp_auth: &str) -> Self {
+    pub fn new(otlp_endpoint: &str, otlp_auth: &str, protocol: Protocol) -> Self {
         Self {
             otlp_endpoint: otlp_endpoint.to_string(),
             otlp_auth: otlp_auth.to_string(),
+            protocol,
+            client: protocol.client(),
             metrics: Arc::new(Metrics::default()),
         }
     }
+
+    /// Recorder of the flushes, exported with the other metrics
+    pub(super) fn flush_metrics(&self) -> FlushMetrics {
+        self.metrics.flush.clone()
+    }
 }
 
 impl<S> Layer<S> for OtlpMetricsLayer
@@ -199,36 +293,87 @@ where
 impl Toilet for OtlpMetricsLayer {
     /// Push all recorded log messages to the OTLP collector
     /// This should be called at the end of every request, after the span is closed
-    async fn flush(&self, attributes: &HashMap<&str, Option<String>>) {
-        let client = reqwest::Client::builder()
-            .user_agent(USER_AGENT)
-            .timeout(Duration::from_secs(10))
-            .build()
-            .unwrap();
-
-        let body = build_metrics_export_body(&self.metrics, attributes).encode_to_vec();
-        let mut url = url::Url::parse(&self.otlp_endpoint).unwrap();
-        url.path_segments_mut().unwrap().extend(&["v1", "metrics"]);
+    async fn flush(
+        &self,
+        attributes: &HashMap<String, Option<String>>,
+    ) -> Option<Result<(), String>> {
+        let body = build_metrics_export_body(&self.metrics, attributes);
+        let metrics_count = body.resource_metrics[0].scope_metrics[0].metrics.len();
         // send to OTLP Collector
-        match client
-            .post(url)
-            .header("Content-Type", "application/x-protobuf")
-            .header("Authorization", &self.otlp_auth)
-            .body(body)
-            .send()
-            .await
-        {
-            Ok(response) => {
-                if response.status().is_success() {
-                    tracing::info!("Metrics sent to OTLP: {:?}", response);
-                } else {
-                    tracing::info!("Failed to send metrics to OTLP: {:?}", response);
-                    tracing::info!("Response body: {:?}", response.text().await.unwrap());
-                }
-            }
-            Err(err) => {
-                tracing::info!("Error sending metrics to OTLP: {:?}", err);
-            }
-        }
+        let start = Instant::now();
+        let result = self
+            .protocol
+            .export(
+                &self.client,
+                &self.otlp_endpoint,
+                &self.otlp_auth,
+                Signal::Metrics,
+                body.encode_to_vec(),
+            )
+            .await;
+        self.metrics
+            .flush
+            .record(Signal::Metrics, start.elapsed(), metrics_count);
+        Some(export_result(Signal::Metrics, self.protocol, result).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::otlp::trace::{OtlpTraceLayer, SpanIdLayer, SpanTimeLayer};
+    use tracing::dispatcher;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn flush_metrics_empty() {
+        assert_eq!(FlushMetrics::default().as_metrics(&[]), vec![]);
+    }
+
+    #[tokio::test]
+    async fn flush_records_metrics() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let mock = server
+            .mock("POST", "/v1/traces")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let metrics_layer = OtlpMetricsLayer::new(&url, "unittest_auth", Protocol::Http);
+        let trace_layer = OtlpTraceLayer::new(&url, "unittest_auth", Protocol::Http)
+            .with_flush_metrics(metrics_layer.flush_metrics());
+        let trace_clone = trace_layer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanIdLayer::default())
+            .with(SpanTimeLayer::default())
+            .with(trace_layer);
+        let dispatch = dispatcher::Dispatch::new(subscriber);
+        dispatcher::with_default(&dispatch, || {
+            let span = tracing::info_span!("unittest").entered();
+            tracing::info_span!("subspan").entered().exit();
+            span.exit();
+        });
+        trace_clone.flush(&HashMap::new()).await;
+        mock.assert_async().await;
+
+        let metrics = metrics_layer.flush_metrics().as_metrics(&[]);
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].name, "pyoci.otlp.flush.duration");
+        let Some(Data::Gauge(gauge)) = &metrics[0].data else {
+            panic!("Expected a gauge");
+        };
+        assert_eq!(gauge.data_points.len(), 1);
+        assert_eq!(
+            gauge.data_points[0].attributes[0].value,
+            Some(AnyValue {
+                value: Some(any_value::Value::StringValue("traces".to_string())),
+            })
+        );
+        assert_eq!(metrics[1].name, "pyoci.otlp.flush.items");
+        let Some(Data::Sum(sum)) = &metrics[1].data else {
+            panic!("Expected a sum");
+        };
+        assert_eq!(sum.data_points[0].value, Some(Value::AsInt(2)));
     }
 }
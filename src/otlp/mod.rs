@@ -2,8 +2,13 @@ mod log;
 mod metrics;
 mod trace;
 
+use http_body_util::BodyExt;
 use metrics::OtlpMetricsLayer;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use time::format_description::well_known::Rfc3339;
 use tokio::task::JoinHandle;
 use tokio::time::{interval, Duration, MissedTickBehavior};
 
@@ -16,6 +21,191 @@ use tracing::Subscriber;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::registry::LookupSpan;
 
+use crate::time::now_utc;
+use crate::USER_AGENT;
+
+/// Transport used to send the data to the OTLP collector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Protobuf over HTTP, `POST <endpoint>/v1/<signal>`
+    #[default]
+    Http,
+    /// gRPC over HTTP/2, `POST <endpoint>/opentelemetry.proto.collector.<signal>.v1.<Service>/Export`
+    Grpc,
+}
+
+impl FromStr for Protocol {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "http" | "http/protobuf" => Ok(Protocol::Http),
+            "grpc" => Ok(Protocol::Grpc),
+            _ => anyhow::bail!("Unknown OTLP protocol '{value}', expected 'http' or 'grpc'"),
+        }
+    }
+}
+
+/// Kind of data sent to the OTLP collector
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Logs,
+    Traces,
+    Metrics,
+}
+
+impl Signal {
+    /// Lowercase name of the signal, as used in the HTTP path
+    fn name(self) -> &'static str {
+        match self {
+            Signal::Logs => "logs",
+            Signal::Traces => "traces",
+            Signal::Metrics => "metrics",
+        }
+    }
+}
+
+impl Protocol {
+    /// HTTP client to export with, built once per layer
+    fn client(self) -> reqwest::Client {
+        let mut client = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(10));
+        if self == Protocol::Grpc {
+            // gRPC is only served over HTTP/2, also without TLS
+            client = client.http2_prior_knowledge();
+        }
+        client.build().expect("valid OTLP client")
+    }
+
+    /// Send the encoded `Export<Signal>ServiceRequest` `body` to the OTLP collector
+    ///
+    /// <https://opentelemetry.io/docs/specs/otlp/#protocol-details>
+    async fn export(
+        self,
+        client: &reqwest::Client,
+        endpoint: &str,
+        auth: &str,
+        signal: Signal,
+        body: Vec<u8>,
+    ) -> reqwest::Result<reqwest::Response> {
+        let mut url = url::Url::parse(endpoint).unwrap();
+        let (content_type, body) = match self {
+            Protocol::Http => {
+                url.path_segments_mut()
+                    .unwrap()
+                    .extend(&["v1", signal.name()]);
+                ("application/x-protobuf", body)
+            }
+            Protocol::Grpc => {
+                let service = match signal {
+                    Signal::Logs => "opentelemetry.proto.collector.logs.v1.LogsService",
+                    Signal::Traces => "opentelemetry.proto.collector.trace.v1.TraceService",
+                    Signal::Metrics => "opentelemetry.proto.collector.metrics.v1.MetricsService",
+                };
+                url.path_segments_mut()
+                    .unwrap()
+                    .pop_if_empty()
+                    .extend(&[service, "Export"]);
+                ("application/grpc", grpc_message(&body))
+            }
+        };
+        let mut request = client
+            .post(url)
+            .header("Content-Type", content_type)
+            .header("Authorization", auth);
+        if self == Protocol::Grpc {
+            request = request.header("TE", "trailers");
+        }
+        request.body(body).send().await
+    }
+}
+
+/// Read the `grpc-status` and `grpc-message` of a gRPC response
+///
+/// gRPC errors are returned with a 200 status code and a non-zero `grpc-status`.
+/// The `grpc-status` is found in the headers when the response has no body,
+/// otherwise it is in the trailers.
+async fn grpc_status(response: reqwest::Response) -> Result<(String, String), String> {
+    let headers = response.headers().clone();
+    let trailers = http::Response::from(response)
+        .into_body()
+        .collect()
+        .await
+        .map_err(|err| format!("Failed to read the gRPC response: {err}"))?
+        .trailers()
+        .cloned()
+        .unwrap_or_default();
+    let value = |name: &str| {
+        headers
+            .get(name)
+            .or_else(|| trailers.get(name))
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+    };
+    let status = value("grpc-status").ok_or("Missing grpc-status in the gRPC response")?;
+    Ok((status, value("grpc-message").unwrap_or_default()))
+}
+
+/// Status of the OTLP exporter, updated after every flush
+///
+/// Shared with the `/health/otlp` endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct OtlpStatus(Arc<Mutex<OtlpReport>>);
+
+/// Snapshot of the [`OtlpStatus`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct OtlpReport {
+    /// OTLP is set up and the background flush task is running
+    pub active: bool,
+    /// Outcome of the last flush that sent data to the collector
+    pub last_flush: Option<FlushReport>,
+}
+
+/// Outcome of a flush
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FlushReport {
+    pub success: bool,
+    /// RFC3339 time the flush completed
+    pub timestamp: String,
+    /// Why the flush failed
+    pub error: Option<String>,
+}
+
+impl OtlpStatus {
+    pub fn report(&self) -> OtlpReport {
+        self.0.lock().expect("Failed to lock OTLP status").clone()
+    }
+
+    fn set_active(&self, active: bool) {
+        self.0.lock().expect("Failed to lock OTLP status").active = active;
+    }
+
+    /// Record the outcome of a flush
+    fn record(&self, result: Result<(), String>) {
+        let report = FlushReport {
+            success: result.is_ok(),
+            timestamp: now_utc().format(&Rfc3339).expect("valid timestamp"),
+            error: result.err(),
+        };
+        self.0
+            .lock()
+            .expect("Failed to lock OTLP status")
+            .last_flush = Some(report);
+    }
+}
+
+/// Frame a protobuf message as an uncompressed gRPC message
+///
+/// <https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#requests>
+fn grpc_message(message: &[u8]) -> Vec<u8> {
+    let length = u32::try_from(message.len()).expect("OTLP export exceeds 4GiB");
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&length.to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
 /// Wrap `subscriber` with OTLP tracing.
 /// Note that this adds 4 types to every trace's extensions:
 /// - [`TraceId`](opentelemetry::trace::TraceId) - ID shared by all nested spans
@@ -29,14 +219,24 @@ use tracing_subscriber::registry::LookupSpan;
 /// Returns the amended Subscriber and a `JoinHandle` for the background Task.
 /// After canceling the `cancel_token`, await the `JoinHandle` to ensure everything gets flushed.
 ///
+/// Only `trace_sample_ratio` of the traces are exported, logs and metrics are not sampled.
+///
+/// The data is sent to the collector using `protocol`.
+///
+/// The outcome of every flush is recorded in `status`.
+///
 /// OTLP tracing won't be set up if `otlp_endpoint` or `otlp_auth` is None.
+#[allow(clippy::too_many_arguments)]
 pub fn otlp<S>(
     subscriber: S,
     otlp_endpoint: Option<String>,
     otlp_auth: Option<String>,
-    attributes: HashMap<&'static str, Option<String>>,
+    protocol: Protocol,
+    trace_sample_ratio: f64,
+    attributes: HashMap<String, Option<String>>,
     flush_interval: Duration,
     cancel_token: CancellationToken,
+    status: OtlpStatus,
 ) -> (Box<dyn Subscriber + Send + Sync>, Option<JoinHandle<()>>)
 where
     S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
@@ -44,51 +244,112 @@ where
     let (Some(otlp_endpoint), Some(otlp_auth)) = (otlp_endpoint, otlp_auth) else {
         return (Box::new(subscriber), None);
     };
-    let log_layer = crate::otlp::OtlpLogLayer::new(&otlp_endpoint, &otlp_auth);
-    let trace_layer = crate::otlp::OtlpTraceLayer::new(&otlp_endpoint, &otlp_auth);
-    let metrics_layer = crate::otlp::metrics::OtlpMetricsLayer::new(&otlp_endpoint, &otlp_auth);
+    let metrics_layer =
+        crate::otlp::metrics::OtlpMetricsLayer::new(&otlp_endpoint, &otlp_auth, protocol);
+    // The duration and size of every flush are exported by the metrics layer
+    let log_layer = crate::otlp::OtlpLogLayer::new(&otlp_endpoint, &otlp_auth, protocol)
+        .with_flush_metrics(metrics_layer.flush_metrics());
+    let trace_layer = crate::otlp::OtlpTraceLayer::new(&otlp_endpoint, &otlp_auth, protocol)
+        .with_flush_metrics(metrics_layer.flush_metrics());
 
     let subscriber = subscriber
-        .with(SpanIdLayer::default())
+        .with(SpanIdLayer::new(trace_sample_ratio))
         .with(SpanTimeLayer::default())
         .with(log_layer.clone())
         .with(trace_layer.clone())
         .with(metrics_layer.clone());
     let otlp_layer = (log_layer, trace_layer, metrics_layer);
 
+    status.set_active(true);
     // A task that will flush every second
     let handle = tokio::spawn(async move {
         let mut interval = interval(flush_interval);
         interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
         loop {
-            tokio::select! {
-                _ = interval.tick() => {},
-                () = cancel_token.cancelled() => {
-                    otlp_layer.flush(&attributes).await;
-                    break;
-                }
+            let cancelled = tokio::select! {
+                _ = interval.tick() => false,
+                () = cancel_token.cancelled() => true,
+            };
+            if let Some(result) = otlp_layer.flush(&attributes).await {
+                status.record(result);
+            }
+            if cancelled {
+                break;
             }
-            otlp_layer.flush(&attributes).await;
         }
+        status.set_active(false);
     });
     (Box::new(subscriber), Some(handle))
 }
 
 pub trait Toilet {
-    async fn flush(&self, _attributes: &HashMap<&str, Option<String>>);
+    /// Send the recorded data to the OTLP collector
+    ///
+    /// Returns `None` when there was nothing to send, otherwise the reason the export failed.
+    async fn flush(
+        &self,
+        _attributes: &HashMap<String, Option<String>>,
+    ) -> Option<Result<(), String>>;
 }
 
 type OtlpLayer = (OtlpLogLayer, OtlpTraceLayer, OtlpMetricsLayer);
 impl Toilet for OtlpLayer {
-    async fn flush(&self, attributes: &HashMap<&str, Option<String>>) {
-        self.0.flush(attributes).await;
-        self.1.flush(attributes).await;
-        self.2.flush(attributes).await;
+    /// Fails if any of the layers failed
+    async fn flush(
+        &self,
+        attributes: &HashMap<String, Option<String>>,
+    ) -> Option<Result<(), String>> {
+        let results = [
+            self.0.flush(attributes).await,
+            self.1.flush(attributes).await,
+            self.2.flush(attributes).await,
+        ];
+        results.into_iter().flatten().reduce(Result::and)
+    }
+}
+
+/// Log the response of an export and convert it into the outcome of the flush
+async fn export_result(
+    signal: Signal,
+    protocol: Protocol,
+    result: reqwest::Result<reqwest::Response>,
+) -> Result<(), String> {
+    match result {
+        Ok(response) if !response.status().is_success() => {
+            let status = response.status();
+            tracing::info!("Failed to send {signal:?} to OTLP: {:?}", response);
+            tracing::info!("Response body: {:?}", response.text().await.unwrap());
+            Err(format!("Sending {signal:?} failed with status {status}"))
+        }
+        Ok(response) if protocol == Protocol::Grpc => {
+            let status = response.status();
+            match grpc_status(response).await? {
+                (code, _) if code == "0" => {
+                    tracing::info!("{signal:?} sent to OTLP: {status}");
+                    Ok(())
+                }
+                (code, message) => {
+                    tracing::info!("Failed to send {signal:?} to OTLP: {code} {message}");
+                    Err(format!(
+                        "Sending {signal:?} failed with grpc-status {code}: {message}"
+                    ))
+                }
+            }
+        }
+        Ok(response) => {
+            tracing::info!("{signal:?} sent to OTLP: {:?}", response);
+            Ok(())
+        }
+        Err(err) => {
+            tracing::info!("Error sending {signal:?} to OTLP: {:?}", err);
+            Err(format!("Error sending {signal:?}: {err}"))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use test_case::test_case;
     use tokio_util::sync::CancellationToken;
     use tracing::dispatcher;
     use tracing_subscriber::EnvFilter;
@@ -124,9 +385,12 @@ mod tests {
             subscriber,
             Some(url),
             Some("unittest_auth".to_string()),
-            HashMap::from([("service.name", Some("foo".to_string()))]),
+            Protocol::Http,
+            1.0,
+            HashMap::from([("service.name".to_string(), Some("foo".to_string()))]),
             Duration::from_secs(1),
             cancel_token.clone(),
+            OtlpStatus::default(),
         );
 
         let dispatch = dispatcher::Dispatch::new(subscriber);
@@ -145,4 +409,186 @@ mod tests {
             mock.assert_async().await;
         }
     }
+
+    #[tokio::test]
+    async fn otlp_layer_flush_grpc() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let mocks = [
+            "/opentelemetry.proto.collector.logs.v1.LogsService/Export",
+            "/opentelemetry.proto.collector.trace.v1.TraceService/Export",
+            "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export",
+        ]
+        .map(|path| {
+            server
+                .mock("POST", path)
+                .match_header("Authorization", "unittest_auth")
+                .match_header("Content-Type", "application/grpc")
+                .match_header("TE", "trailers")
+                .with_status(200)
+                .with_header("grpc-status", "0")
+        });
+        let mut created = Vec::new();
+        for mock in mocks {
+            created.push(mock.create_async().await);
+        }
+
+        let subscriber = tracing_subscriber::registry().with(EnvFilter::new("info"));
+        let cancel_token = CancellationToken::new();
+
+        let (subscriber, handle) = otlp(
+            subscriber,
+            Some(format!("{url}/")),
+            Some("unittest_auth".to_string()),
+            Protocol::Grpc,
+            1.0,
+            HashMap::from([("service.name".to_string(), Some("foo".to_string()))]),
+            Duration::from_secs(1),
+            cancel_token.clone(),
+            OtlpStatus::default(),
+        );
+
+        let dispatch = dispatcher::Dispatch::new(subscriber);
+        dispatcher::with_default(&dispatch, || {
+            let span = tracing::info_span!("unittest").entered();
+            tracing::info!(target: "unittest", "unittest log");
+            span.exit();
+        });
+
+        cancel_token.cancel();
+        handle.unwrap().await.unwrap();
+
+        for mock in created {
+            mock.assert_async().await;
+        }
+    }
+
+    #[test_case(200, None; "success")]
+    #[test_case(500, Some("Sending Logs failed with status 500 Internal Server Error"); "failure")]
+    #[tokio::test]
+    async fn otlp_status_flush(status: usize, error: Option<&str>) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        for path in ["/v1/logs", "/v1/traces", "/v1/metrics"] {
+            server
+                .mock("POST", path)
+                .with_status(status)
+                .create_async()
+                .await;
+        }
+
+        let subscriber = tracing_subscriber::registry().with(EnvFilter::new("info"));
+        let cancel_token = CancellationToken::new();
+        let otlp_status = OtlpStatus::default();
+
+        let (subscriber, handle) = otlp(
+            subscriber,
+            Some(url),
+            Some("unittest_auth".to_string()),
+            Protocol::Http,
+            1.0,
+            HashMap::from([("service.name".to_string(), Some("foo".to_string()))]),
+            Duration::from_secs(1),
+            cancel_token.clone(),
+            otlp_status.clone(),
+        );
+        assert_eq!(
+            otlp_status.report(),
+            OtlpReport {
+                active: true,
+                last_flush: None
+            }
+        );
+
+        let dispatch = dispatcher::Dispatch::new(subscriber);
+        dispatcher::with_default(&dispatch, || {
+            let span = tracing::info_span!("unittest").entered();
+            tracing::info!(target: "unittest", "unittest log");
+            span.exit();
+        });
+
+        cancel_token.cancel();
+        handle.unwrap().await.unwrap();
+
+        let report = otlp_status.report();
+        assert!(!report.active);
+        let last_flush = report.last_flush.unwrap();
+        assert_eq!(last_flush.success, error.is_none());
+        assert_eq!(last_flush.error.as_deref(), error);
+        assert!(!last_flush.timestamp.is_empty());
+    }
+
+    #[test]
+    fn otlp_status_inactive() {
+        let otlp_status = OtlpStatus::default();
+        let (_, handle) = otlp(
+            tracing_subscriber::registry(),
+            None,
+            None,
+            Protocol::Http,
+            1.0,
+            HashMap::new(),
+            Duration::from_secs(1),
+            CancellationToken::new(),
+            otlp_status.clone(),
+        );
+        assert!(handle.is_none());
+        assert_eq!(otlp_status.report(), OtlpReport::default());
+    }
+
+    #[test_case(&[("grpc-status", "0")], &[], None; "trailers only")]
+    #[test_case(&[], &[("grpc-status", "0")], None; "success trailer")]
+    #[test_case(
+        &[],
+        &[("grpc-status", "3"), ("grpc-message", "invalid")],
+        Some("Sending Logs failed with grpc-status 3: invalid");
+        "failure trailer"
+    )]
+    #[test_case(&[], &[], Some("Missing grpc-status in the gRPC response"); "missing")]
+    #[tokio::test]
+    async fn export_result_grpc(
+        headers: &[(&'static str, &'static str)],
+        trailers: &[(&'static str, &'static str)],
+        error: Option<&str>,
+    ) {
+        let trailers: http::HeaderMap = trailers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    http::HeaderName::from_static(name),
+                    http::HeaderValue::from_static(value),
+                )
+            })
+            .collect();
+        let body = http_body_util::Full::new(bytes::Bytes::from_static(b"\x00\x00\x00\x00\x00"))
+            .with_trailers(async move { Some(Ok(trailers)) });
+        let mut response = http::Response::builder().status(200);
+        for (name, value) in headers {
+            response = response.header(*name, *value);
+        }
+        let response = response.body(reqwest::Body::wrap(body)).unwrap();
+
+        let result = export_result(Signal::Logs, Protocol::Grpc, Ok(response.into())).await;
+        assert_eq!(result.err().as_deref(), error);
+    }
+
+    #[test]
+    fn grpc_message_prefix() {
+        assert_eq!(
+            grpc_message(b"message"),
+            b"\x00\x00\x00\x00\x07message".to_vec()
+        );
+    }
+
+    #[test_case("http", Protocol::Http; "http")]
+    #[test_case("http/protobuf", Protocol::Http; "http protobuf")]
+    #[test_case("grpc", Protocol::Grpc; "grpc")]
+    fn protocol_from_str(value: &str, expected: Protocol) {
+        assert_eq!(value.parse::<Protocol>().unwrap(), expected);
+    }
+
+    #[test]
+    fn protocol_from_str_invalid() {
+        assert!("http/json".parse::<Protocol>().is_err());
+    }
 }
@@ -1,7 +1,7 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::Instant;
 
 use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
 use opentelemetry_proto::tonic::common::v1::any_value::Value;
@@ -17,9 +17,9 @@ use tracing::Id;
 use tracing::Subscriber;
 use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
 
-use crate::otlp::Toilet;
+use crate::otlp::metrics::FlushMetrics;
+use crate::otlp::{export_result, Protocol, Signal, Toilet};
 use crate::time::time_unix_ns;
-use crate::USER_AGENT;
 
 thread_local! {
     /// Store random number generator for each thread
@@ -56,10 +56,75 @@ impl From<&TraceId> for Vec<u8> {
     }
 }
 
+/// Sampling decision of a trace, made for the root span and shared by all nested spans
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sampled(bool);
+
+/// Span of the calling system, the parent of a root span with a `traceparent` field
+#[derive(Debug, Clone, Copy)]
+struct RemoteParent(SpanId);
+
+/// Parse a W3C `traceparent` header value
+///
+/// Returns `None` when the value is invalid, a new trace is started instead.
+/// ref: <https://www.w3.org/TR/trace-context/#traceparent-header>
+fn parse_traceparent(value: &str) -> Option<(TraceId, SpanId, Sampled)> {
+    let mut parts = value.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let parent_id = parts.next()?;
+    let flags = parts.next()?;
+    // Future versions can append fields, version 00 has exactly 4
+    if version.len() != 2 || version == "ff" || (version == "00" && parts.next().is_some()) {
+        return None;
+    }
+    if trace_id.len() != 32 || parent_id.len() != 16 || flags.len() != 2 {
+        return None;
+    }
+    let is_hex = |value: &str| {
+        value
+            .bytes()
+            .all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    if ![version, trace_id, parent_id, flags]
+        .into_iter()
+        .all(is_hex)
+    {
+        return None;
+    }
+    let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+    let parent_id = u64::from_str_radix(parent_id, 16).ok()?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    if trace_id == 0 || parent_id == 0 {
+        return None;
+    }
+    Some((
+        TraceId(trace_id),
+        SpanId(parent_id),
+        Sampled(flags & 1 == 1),
+    ))
+}
+
+/// Collect the `traceparent` field of a span
+#[derive(Debug, Default)]
+struct TraceParentVisitor(Option<String>);
+
+impl Visit for TraceParentVisitor {
+    fn record_debug(&mut self, _field: &Field, _value: &dyn core::fmt::Debug) {
+        // do nothing
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "traceparent" {
+            self.0 = Some(value.to_string());
+        }
+    }
+}
+
 /// <https://opentelemetry.io/docs/specs/otlp/#otlpgrpc>
 fn build_trace_export_body(
     spans: Vec<Span>,
-    attributes: &HashMap<&str, Option<String>>,
+    attributes: &HashMap<String, Option<String>>,
 ) -> ExportTraceServiceRequest {
     let scope_spans = ScopeSpans {
         scope: None,
@@ -73,7 +138,7 @@ fn build_trace_export_body(
             continue;
         };
         attrs.push(KeyValue {
-            key: (*key).into(),
+            key: key.clone(),
             value: Some(AnyValue {
                 value: Some(Value::StringValue(value.into())),
             }),
@@ -100,17 +165,33 @@ fn build_trace_export_body(
 pub struct OtlpTraceLayer {
     otlp_endpoint: String,
     otlp_auth: String,
+    protocol: Protocol,
+    /// Shared by all flushes of the layer
+    client: reqwest::Client,
     /// Buffer of Spans
     spans: Arc<RwLock<Vec<Span>>>,
+    /// Duration and size of the flushes
+    flush_metrics: FlushMetrics,
 }
 
 // Public methods
 impl OtlpTraceLayer {
-    pub fn new(otlp_endpoint: &str, otlp_auth: &str) -> Self {
+    pub fn new(otlp_endpoint: &str, otlp_auth: &str, protocol: Protocol) -> Self {
         Self {
             otlp_endpoint: otlp_endpoint.to_string(),
             otlp_auth: otlp_auth.to_string(),
+            protocol,
+            client: protocol.client(),
             spans: Arc::new(RwLock::new(vec![])),
+            flush_metrics: FlushMetrics::default(),
+        }
+    }
+
+    /// Record the flushes in `flush_metrics`, shared with the metrics layer
+    pub(super) fn with_flush_metrics(self, flush_metrics: FlushMetrics) -> Self {
+        Self {
+            flush_metrics,
+            ..self
         }
     }
 }
@@ -119,43 +200,33 @@ impl OtlpTraceLayer {
 impl Toilet for OtlpTraceLayer {
     /// Push all recorded log messages to the OTLP collector
     /// This should be called at the end of every request, after the span is closed
-    async fn flush(&self, attributes: &HashMap<&str, Option<String>>) {
+    async fn flush(
+        &self,
+        attributes: &HashMap<String, Option<String>>,
+    ) -> Option<Result<(), String>> {
         let spans: Vec<Span> = self.spans.write().unwrap().drain(..).collect();
         if spans.is_empty() {
             tracing::debug!("No spans to send");
-            return;
+            return None;
         }
         tracing::info!("Sending {} spans to OTLP", spans.len());
-        let client = reqwest::Client::builder()
-            .user_agent(USER_AGENT)
-            .timeout(Duration::from_secs(10))
-            .build()
-            .unwrap();
-
+        let spans_count = spans.len();
         let body = build_trace_export_body(spans, attributes).encode_to_vec();
-        let mut url = url::Url::parse(&self.otlp_endpoint).unwrap();
-        url.path_segments_mut().unwrap().extend(&["v1", "traces"]);
         // send to OTLP Collector
-        match client
-            .post(url)
-            .header("Content-Type", "application/x-protobuf")
-            .header("Authorization", &self.otlp_auth)
-            .body(body)
-            .send()
-            .await
-        {
-            Ok(response) => {
-                if response.status().is_success() {
-                    tracing::info!("Traces sent to OTLP: {:?}", response);
-                } else {
-                    tracing::info!("Failed to send traces to OTLP: {:?}", response);
-                    tracing::info!("Response body: {:?}", response.text().await.unwrap());
-                }
-            }
-            Err(err) => {
-                tracing::info!("Error sending traces to OTLP: {:?}", err);
-            }
-        }
+        let start = Instant::now();
+        let result = self
+            .protocol
+            .export(
+                &self.client,
+                &self.otlp_endpoint,
+                &self.otlp_auth,
+                Signal::Traces,
+                body,
+            )
+            .await;
+        self.flush_metrics
+            .record(Signal::Traces, start.elapsed(), spans_count);
+        Some(export_result(Signal::Traces, self.protocol, result).await)
     }
 }
 
@@ -164,6 +235,8 @@ where
     S: Subscriber + for<'a> LookupSpan<'a>,
 {
     /// Insert a new Span in the spans Extensions
+    ///
+    /// Spans of traces that are not sampled are skipped.
     fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
         let Some(span) = ctx.span(id) else {
             tracing::info!("Span {id:?} does not exist");
@@ -171,6 +244,12 @@ where
         };
         let otel_span = {
             let extensions = span.extensions();
+            if extensions
+                .get::<Sampled>()
+                .is_some_and(|sampled| !sampled.0)
+            {
+                return;
+            }
             let Some(trace_id) = extensions.get::<TraceId>() else {
                 tracing::info!("Could not find Trace ID for Span {id:?}");
                 return;
@@ -181,11 +260,17 @@ where
                 return;
             };
 
-            let parent_span_id = span
-                .parent()
-                .map(|p_span| p_span.extensions().get::<SpanId>().map(Vec::<u8>::from))
-                .unwrap_or_default()
-                .unwrap_or_default();
+            let parent_span_id = match span.parent() {
+                Some(parent) => parent
+                    .extensions()
+                    .get::<SpanId>()
+                    .map(Vec::<u8>::from)
+                    .unwrap_or_default(),
+                None => extensions
+                    .get::<RemoteParent>()
+                    .map(|parent| Vec::<u8>::from(&parent.0))
+                    .unwrap_or_default(),
+            };
             let mut visitor = OtelVisitor::default();
             attrs.record(&mut visitor);
 
@@ -211,6 +296,12 @@ where
         };
         let (start_time, end_time) = {
             let extensions = span.extensions();
+            if extensions
+                .get::<Sampled>()
+                .is_some_and(|sampled| !sampled.0)
+            {
+                return;
+            }
             let Some(start_time) = extensions.get::<SpanEnter>() else {
                 tracing::info!("SpanEnter not defined for Span {id:?}");
                 return;
@@ -320,15 +411,34 @@ where
     }
 }
 
-#[derive(Debug, Default)]
-pub struct SpanIdLayer {}
+#[derive(Debug)]
+pub struct SpanIdLayer {
+    /// Fraction of the root spans that are sampled, between 0.0 and 1.0
+    sample_ratio: f64,
+}
 
-/// Insert [`SpanId`] and [`TraceId`] into the span extensions
+impl SpanIdLayer {
+    pub fn new(sample_ratio: f64) -> Self {
+        Self { sample_ratio }
+    }
+}
+
+impl Default for SpanIdLayer {
+    /// Sample all traces
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+/// Insert [`SpanId`], [`TraceId`] and [`Sampled`] into the span extensions
+///
+/// A root span with a valid W3C `traceparent` field continues that trace,
+/// including its sampling decision.
 impl<S> Layer<S> for SpanIdLayer
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
 {
-    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
         let Some(span) = ctx.span(id) else {
             tracing::info!("Span {id:?} does not exist");
             return;
@@ -337,17 +447,39 @@ where
         // Add the SpanId to the extensions of this span
         extensions.insert(SpanId::new());
 
-        // Add the TraceId to the extensions of this span
+        // Add the TraceId and sampling decision to the extensions of this span
         match span.parent() {
-            // This is the root span, generate a new TraceId
-            None => extensions.insert(TraceId::new()),
-            // This is a leaf span, add the parent TraceId as the TraceId for this span
-            Some(parent) => extensions.insert(
-                *parent
-                    .extensions()
-                    .get::<TraceId>()
-                    .expect("TraceId not set, this is a bug"),
-            ),
+            // This is the root span, generate a new TraceId and decide if the trace is sampled
+            None => {
+                let mut visitor = TraceParentVisitor::default();
+                attrs.record(&mut visitor);
+                if let Some((trace_id, parent_id, sampled)) =
+                    visitor.0.as_deref().and_then(parse_traceparent)
+                {
+                    extensions.insert(trace_id);
+                    extensions.insert(RemoteParent(parent_id));
+                    extensions.insert(sampled);
+                    return;
+                }
+                extensions.insert(TraceId::new());
+                let sampled =
+                    CURRENT_RNG.with(|rng| rng.borrow_mut().random::<f64>()) < self.sample_ratio;
+                extensions.insert(Sampled(sampled));
+            }
+            // This is a leaf span, use the TraceId and sampling decision of the parent
+            Some(parent) => {
+                let parent_extensions = parent.extensions();
+                extensions.insert(
+                    *parent_extensions
+                        .get::<TraceId>()
+                        .expect("TraceId not set, this is a bug"),
+                );
+                extensions.insert(
+                    *parent_extensions
+                        .get::<Sampled>()
+                        .expect("Sampled not set, this is a bug"),
+                );
+            }
         }
     }
 }
@@ -356,6 +488,7 @@ where
 mod tests {
 
     use super::*;
+    use test_case::test_case;
     use tracing::dispatcher;
     use tracing_core::LevelFilter;
     use tracing_subscriber::prelude::*;
@@ -374,7 +507,7 @@ mod tests {
             .await;
 
         // init tracing with the otlp layer
-        let otlp_layer = OtlpTraceLayer::new(&url, "unittest_auth");
+        let otlp_layer = OtlpTraceLayer::new(&url, "unittest_auth", Protocol::Http);
         let otlp_clone = otlp_layer.clone();
         let subscriber = tracing_subscriber::registry()
             .with(SpanIdLayer::default())
@@ -404,11 +537,151 @@ mod tests {
             assert_eq!(&spans[0].parent_span_id, &spans[1].span_id);
         }
         otlp_clone
-            .flush(&HashMap::from([("unittest", Some("test1".into()))]))
+            .flush(&HashMap::from([(
+                "unittest".to_string(),
+                Some("test1".into()),
+            )]))
             .await;
         mock.assert_async().await;
     }
 
+    #[test]
+    fn build_trace_export_body_resource() {
+        let body = build_trace_export_body(
+            vec![],
+            &HashMap::from([
+                ("cloud.region".to_string(), Some("westeurope".to_string())),
+                ("k8s.pod.name".to_string(), None),
+            ]),
+        );
+
+        let resource = body.resource_spans[0].resource.as_ref().unwrap();
+        assert_eq!(
+            resource.attributes,
+            vec![KeyValue {
+                key: "cloud.region".to_string(),
+                value: Some(AnyValue {
+                    value: Some(Value::StringValue("westeurope".to_string())),
+                }),
+                ..KeyValue::default()
+            }]
+        );
+    }
+
+    #[test_case(0.0, 0; "none")]
+    #[test_case(1.0, 6; "all")]
+    fn otlp_trace_layer_sampling(sample_ratio: f64, expected: usize) {
+        let otlp_layer = OtlpTraceLayer::new("http://localhost", "", Protocol::Http);
+        let otlp_clone = otlp_layer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanIdLayer::new(sample_ratio))
+            .with(SpanTimeLayer::default())
+            .with(otlp_layer.with_filter(LevelFilter::INFO));
+        let dispatch = dispatcher::Dispatch::new(subscriber);
+        dispatcher::with_default(&dispatch, || {
+            for _ in 0..3 {
+                let span = tracing::info_span!("unittest").entered();
+                tracing::info_span!("subspan").entered().exit();
+                span.exit();
+            }
+        });
+
+        assert_eq!(otlp_clone.spans.read().unwrap().len(), expected);
+    }
+
+    /// Nested spans follow the sampling decision of the root span
+    #[test]
+    fn otlp_trace_layer_sampling_consistent() {
+        let otlp_layer = OtlpTraceLayer::new("http://localhost", "", Protocol::Http);
+        let otlp_clone = otlp_layer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanIdLayer::new(0.5))
+            .with(SpanTimeLayer::default())
+            .with(otlp_layer.with_filter(LevelFilter::INFO));
+        let dispatch = dispatcher::Dispatch::new(subscriber);
+        dispatcher::with_default(&dispatch, || {
+            for _ in 0..100 {
+                let span = tracing::info_span!("unittest").entered();
+                tracing::info_span!("subspan").entered().exit();
+                span.exit();
+            }
+        });
+
+        let spans = otlp_clone.spans.read().unwrap();
+        // Spans are stored on close, every sampled root is preceded by its subspan
+        assert_eq!(spans.len() % 2, 0);
+        for pair in spans.chunks(2) {
+            assert_eq!(pair[0].name, "subspan");
+            assert_eq!(pair[1].name, "unittest");
+            assert_eq!(pair[0].trace_id, pair[1].trace_id);
+        }
+    }
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn otlp_trace_layer_traceparent() {
+        let otlp_layer = OtlpTraceLayer::new("http://localhost", "", Protocol::Http);
+        let otlp_clone = otlp_layer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanIdLayer::new(0.0))
+            .with(SpanTimeLayer::default())
+            .with(otlp_layer.with_filter(LevelFilter::INFO));
+        let dispatch = dispatcher::Dispatch::new(subscriber);
+        dispatcher::with_default(&dispatch, || {
+            let span = tracing::info_span!("fetch", traceparent = TRACEPARENT).entered();
+            tracing::info_span!("subspan").entered().exit();
+            span.exit();
+        });
+
+        // Sampled by the caller, even though the sample ratio is 0
+        let spans = otlp_clone.spans.read().unwrap();
+        assert_eq!(spans.len(), 2);
+        let trace_id = 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736_u128.to_be_bytes();
+        assert_eq!(spans[1].name, "fetch");
+        assert_eq!(spans[1].trace_id, trace_id);
+        assert_eq!(
+            spans[1].parent_span_id,
+            0x00f0_67aa_0ba9_02b7_u64.to_be_bytes()
+        );
+        assert_eq!(spans[0].trace_id, trace_id);
+        assert_eq!(spans[0].parent_span_id, spans[1].span_id);
+    }
+
+    #[test_case("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"; "not sampled")]
+    #[test_case("00-00000000000000000000000000000000-00f067aa0ba902b7-01"; "zero trace id")]
+    #[test_case("00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01"; "zero parent id")]
+    #[test_case("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"; "uppercase")]
+    #[test_case("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"; "invalid version")]
+    #[test_case("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra"; "extra field")]
+    #[test_case("00-4bf92f3577b34da6-00f067aa0ba902b7-01"; "short trace id")]
+    fn otlp_trace_layer_traceparent_ignored(traceparent: &str) {
+        let otlp_layer = OtlpTraceLayer::new("http://localhost", "", Protocol::Http);
+        let otlp_clone = otlp_layer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(SpanIdLayer::new(1.0))
+            .with(SpanTimeLayer::default())
+            .with(otlp_layer.with_filter(LevelFilter::INFO));
+        let dispatch = dispatcher::Dispatch::new(subscriber);
+        dispatcher::with_default(&dispatch, || {
+            tracing::info_span!("fetch", traceparent).entered().exit();
+        });
+
+        let spans = otlp_clone.spans.read().unwrap();
+        if traceparent.ends_with("-00") {
+            // The caller decided not to sample the trace
+            assert!(spans.is_empty());
+            return;
+        }
+        // A new trace is started
+        assert_eq!(spans.len(), 1);
+        assert_ne!(
+            spans[0].trace_id,
+            0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736_u128.to_be_bytes()
+        );
+        assert!(spans[0].parent_span_id.is_empty());
+    }
+
     #[tokio::test]
     async fn otlp_trace_layer_no_records() {
         let mut server = mockito::Server::new_async().await;
@@ -421,7 +694,7 @@ mod tests {
             .await;
 
         // init tracing with the otlp layer
-        let otlp_layer = OtlpTraceLayer::new(&url, "");
+        let otlp_layer = OtlpTraceLayer::new(&url, "", Protocol::Http);
         let otlp_clone = otlp_layer.clone();
         let subscriber = tracing_subscriber::registry()
             .with(SpanIdLayer::default())
@@ -433,7 +706,9 @@ mod tests {
         });
 
         assert_eq!(otlp_clone.spans.read().unwrap().len(), 0);
-        otlp_clone.flush(&HashMap::from([("unittest", None)])).await;
+        otlp_clone
+            .flush(&HashMap::from([("unittest".to_string(), None)]))
+            .await;
 
         mock.assert_async().await;
     }
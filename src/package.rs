@@ -1,4 +1,8 @@
-use std::{collections::HashMap, marker::PhantomData, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    path::Path,
+};
 
 use anyhow::{bail, Result};
 use http::StatusCode;
@@ -23,27 +27,73 @@ pub struct Package<'a, T: FileState> {
     version: Option<String>,
     arch: Option<String>,
     sha256: Option<String>,
+    sha512: Option<String>,
     project_urls: Option<String>,
+    /// JSON list of the `Requires-Dist` entries of the package file
+    requires_dist: Option<String>,
+    /// Digest of the `ImageManifest` this package file is pinned to
+    digest: Option<String>,
+    /// Annotations of the `ImageManifest` descriptor, excluding the `PyOCI` internal ones
+    annotations: BTreeMap<String, String>,
+    /// Size in bytes of the package file
+    size: Option<u64>,
+    deprecation: Deprecation,
     _phantom: PhantomData<T>,
 }
 
+/// Deprecation of a package file, from the `com.pyoci.deprecated` and `com.pyoci.sunset` annotations
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Deprecation {
+    /// `true` or the RFC 3339 timestamp the package file was deprecated at
+    pub deprecated: Option<String>,
+    /// RFC 3339 timestamp after which the package file is expected to be removed
+    pub sunset: Option<String>,
+}
+
+impl Deprecation {
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Self {
+        Self {
+            deprecated: annotations.get("com.pyoci.deprecated").cloned(),
+            sunset: annotations.get("com.pyoci.sunset").cloned(),
+        }
+    }
+}
+
 impl<'a, T: FileState> Package<'a, T> {
+    /// Relative uri for the package, without a trailing '/'
+    pub fn package_uri(&self) -> String {
+        // We assume https on all endpoints if the scheme is not provided
+        // This prevents url encoding the scheme in the default case
+        // It also makes the default work when running behind proxies that
+        // decode the URL before hitting the server, like azure.
+        // https://learn.microsoft.com/en-us/answers/questions/1160320/azure-is-decoding-characters-in-the-url-before-rea
+        let registry = self
+            .registry
+            .strip_prefix("https://")
+            .unwrap_or(self.registry);
+        let registry = urlencoding::encode(registry);
+        format!("/{}/{}/{}", registry, self.namespace, self.name)
+    }
+
     /// Add/replace the version and architecture of the package for OCI provided values
     ///
-    /// Replaces '-' by '+' to get back to the python definition of the version
-    ///
-    /// <reference> as a tag MUST be at most 128 characters in length and MUST match the following regular expression:
-    /// [a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}
-    /// <https://github.com/opencontainers/distribution-spec/blob/main/spec.md#pulling-manifests>
+    /// Decodes the tag to get back to the python definition of the version,
+    /// see [`oci_tag_to_version`]
     pub fn with_oci_file(&self, tag: &str, arch: &str) -> Package<'a, WithFileName> {
         Package {
             registry: self.registry,
             namespace: self.namespace,
             name: self.name,
-            version: Some(tag.replace('-', "+")),
+            version: Some(oci_tag_to_version(tag)),
             arch: Some(arch.to_string()),
             sha256: None,
+            sha512: None,
             project_urls: None,
+            requires_dist: None,
+            digest: None,
+            annotations: BTreeMap::new(),
+            size: None,
+            deprecation: Deprecation::default(),
             _phantom: PhantomData,
         }
     }
@@ -86,6 +136,58 @@ fn registry_url(registry: &str) -> Result<url::Url> {
     Ok(url)
 }
 
+/// Check if `tag` is a valid OCI tag
+///
+/// ref: <https://github.com/opencontainers/distribution-spec/blob/main/spec.md#pulling-manifests>
+pub fn is_valid_tag(tag: &str) -> bool {
+    let mut chars = tag.chars();
+    tag.len() <= 128
+        && chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Encode a python version as OCI tag
+///
+/// OCI tags are not allowed to contain the "+" of a local version or the "!" of an epoch,
+/// normalized python versions can't contain a "-" or "_" character.
+/// Replace the "+" with a "-" and the "!" with a "_", see [`oci_tag_to_version`] for the reverse.
+///
+/// Returns an error when the result is not a valid OCI tag,
+/// for example when it is longer than 128 characters.
+pub fn version_to_oci_tag(version: &str) -> Result<String, PyOciError> {
+    let tag = version.replace('+', "-").replace('!', "_");
+    if is_valid_tag(&tag) {
+        Ok(tag)
+    } else {
+        Err(PyOciError::from((
+            StatusCode::BAD_REQUEST,
+            format!("Version '{version}' can not be stored as OCI tag"),
+        )))
+    }
+}
+
+/// Decode an OCI tag created by [`version_to_oci_tag`] back to the python version
+pub fn oci_tag_to_version(tag: &str) -> String {
+    tag.replace('-', "+").replace('_', "!")
+}
+
+/// Parse a digest reference
+///
+/// Only `sha256:<hex>` digests are supported.
+fn parse_digest(digest: &str) -> Result<String, PyOciError> {
+    match digest.strip_prefix("sha256:") {
+        Some(hex) if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(digest.to_string())
+        }
+        _ => Err(PyOciError::from((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid digest reference '{digest}'"),
+        ))),
+    }
+}
+
 impl Package<'_, WithoutFileName> {
     /// Create a Package without version or file information.
     pub fn new<'a>(
@@ -100,7 +202,13 @@ impl Package<'_, WithoutFileName> {
             version: None,
             arch: None,
             sha256: None,
+            sha512: None,
             project_urls: None,
+            requires_dist: None,
+            digest: None,
+            annotations: BTreeMap::new(),
+            size: None,
+            deprecation: Deprecation::default(),
             _phantom: PhantomData,
         }
     }
@@ -112,6 +220,11 @@ impl Package<'_, WithFileName> {
     /// The filename is expected to be normalized, specifically there should be no '-' in any of
     /// it's components.
     /// ref: <https://packaging.python.org/en/latest/specifications/binary-distribution-format/#escaping-and-unicode>
+    ///
+    /// Source distributions predating the normalization can still contain a '-' in the name,
+    /// the version is split off at the last '-' as a normalized version never contains one.
+    ///
+    /// The filename can be pinned to a specific `ImageManifest` by appending `@sha256:<digest>`.
     pub fn from_filename<'a>(
         registry: &'a str,
         namespace: &'a str,
@@ -121,10 +234,14 @@ impl Package<'_, WithFileName> {
         if filename.is_empty() {
             bail!("Empty filename")
         }
+        let (filename, digest) = match filename.split_once('@') {
+            Some((filename, digest)) => (filename, Some(parse_digest(digest)?)),
+            None => (filename, None),
+        };
         let (version, arch) = match filename.strip_suffix(".tar.gz") {
-            Some(rest) => match rest.splitn(2, '-').collect::<Vec<_>>()[..] {
-                [_name, version] => (version, ".tar.gz"),
-                _ => Err(PyOciError::from((
+            Some(rest) => match rest.rsplit_once('-') {
+                Some((_name, version)) => (version, ".tar.gz"),
+                None => Err(PyOciError::from((
                     StatusCode::BAD_REQUEST,
                     format!("Invalid source distribution filename '{filename}'"),
                 )))?,
@@ -141,6 +258,16 @@ impl Package<'_, WithFileName> {
                             format!("Invalid binary distribution filename '{filename}'"),
                         )))?,
                     }
+                } else if Path::new(filename).extension().is_some_and(|ext| {
+                    ["egg", "exe", "msi"]
+                        .iter()
+                        .any(|legacy| ext.eq_ignore_ascii_case(legacy))
+                }) {
+                    // Legacy distributions that used to be accepted by PyPI
+                    Err(PyOciError::from((
+                        StatusCode::BAD_REQUEST,
+                        format!("Unsupported legacy distribution '{filename}', PyOCI only supports wheels (.whl) and source distributions (.tar.gz)"),
+                    )))?
                 } else {
                     Err(PyOciError::from((
                         StatusCode::BAD_REQUEST,
@@ -156,7 +283,13 @@ impl Package<'_, WithFileName> {
             version: Some(version.to_string()),
             arch: Some(arch.to_string()),
             sha256: None,
+            sha512: None,
             project_urls: None,
+            requires_dist: None,
+            digest,
+            annotations: BTreeMap::new(),
+            size: None,
+            deprecation: Deprecation::default(),
             _phantom: PhantomData,
         })
     }
@@ -165,6 +298,18 @@ impl Package<'_, WithFileName> {
         Self { sha256, ..self }
     }
 
+    pub fn with_sha512(self, sha512: Option<String>) -> Self {
+        Self { sha512, ..self }
+    }
+
+    /// Hex encoded digests of the file, keyed by hash algorithm
+    pub fn digests(&self) -> BTreeMap<&'static str, String> {
+        [("sha256", &self.sha256), ("sha512", &self.sha512)]
+            .into_iter()
+            .filter_map(|(name, digest)| Some((name, digest.clone()?)))
+            .collect()
+    }
+
     pub fn with_project_urls(self, project_urls: Option<String>) -> Self {
         Self {
             project_urls,
@@ -180,12 +325,57 @@ impl Package<'_, WithFileName> {
         }
     }
 
-    /// Tag of the package as used for the OCI registry
-    pub fn oci_tag(&self) -> String {
-        // OCI tags are not allowed to contain a "+" character
-        // python versions can't contain a "-" character
-        // Replace the "+" from the python version with a "-" in the OCI version
-        self.version.as_ref().unwrap().replace('+', "-")
+    pub fn with_requires_dist(self, requires_dist: Option<String>) -> Self {
+        Self {
+            requires_dist,
+            ..self
+        }
+    }
+
+    /// `Requires-Dist` entries of the package file, `None` when it has no dependencies
+    pub fn requires_dist(&self) -> Option<Vec<String>> {
+        self.requires_dist
+            .as_deref()
+            .and_then(|requires_dist| serde_json::from_str::<Vec<String>>(requires_dist).ok())
+            .filter(|requires_dist| !requires_dist.is_empty())
+    }
+
+    pub fn with_annotations(self, annotations: BTreeMap<String, String>) -> Self {
+        Self {
+            annotations,
+            ..self
+        }
+    }
+
+    pub fn annotations(&self) -> &BTreeMap<String, String> {
+        &self.annotations
+    }
+
+    pub fn with_deprecation(self, deprecation: Deprecation) -> Self {
+        Self {
+            deprecation,
+            ..self
+        }
+    }
+
+    pub fn deprecation(&self) -> &Deprecation {
+        &self.deprecation
+    }
+
+    pub fn with_size(self, size: Option<u64>) -> Self {
+        Self { size, ..self }
+    }
+
+    /// Digest of the `ImageManifest` this package file is pinned to, if any
+    pub fn digest(&self) -> Option<&str> {
+        self.digest.as_deref()
+    }
+
+    /// Tag of the package as used for the OCI registry, see [`version_to_oci_tag`]
+    ///
+    /// Returns an error when the version can not be stored as OCI tag
+    pub fn oci_tag(&self) -> Result<String, PyOciError> {
+        version_to_oci_tag(self.version.as_ref().unwrap())
     }
 
     /// Architecture of the package as used for the OCI registry
@@ -193,26 +383,29 @@ impl Package<'_, WithFileName> {
         self.arch.as_ref().unwrap()
     }
 
-    /// Relative uri for this package
+    /// Check if `content` looks like the distribution type of this package
+    ///
+    /// Wheels are zip archives, source distributions are gzipped tarballs.
+    pub fn verify_content(&self, content: &[u8]) -> Result<(), PyOciError> {
+        let arch = self.oci_architecture();
+        let (kind, magic): (&str, &[u8]) = if arch == ".tar.gz" {
+            ("gzip", b"\x1f\x8b")
+        } else {
+            ("zip", b"PK\x03\x04")
+        };
+        if content.starts_with(magic) {
+            Ok(())
+        } else {
+            Err(PyOciError::from((
+                StatusCode::BAD_REQUEST,
+                format!("Content of '{}' is not a {kind} archive", self.filename()),
+            )))
+        }
+    }
+
+    /// Relative uri for this package file
     pub fn py_uri(&self) -> String {
-        // We assume https on all endpoints if the scheme is not provided
-        // This prevents url encoding the scheme in the default case
-        // It also makes the default work when running behind proxies that
-        // decode the URL before hitting the server, like azure.
-        // https://learn.microsoft.com/en-us/answers/questions/1160320/azure-is-decoding-characters-in-the-url-before-rea
-        let registry = self
-            .registry
-            .strip_prefix("https://")
-            .unwrap_or(self.registry);
-        let registry = urlencoding::encode(registry);
-        let uri = format!(
-            "/{}/{}/{}/{}",
-            registry,
-            self.namespace,
-            self.name,
-            self.filename()
-        );
-        uri
+        format!("{}/{}", self.package_uri(), self.filename())
     }
 
     /// Return the filename of this package
@@ -238,10 +431,13 @@ impl Serialize for Package<'_, WithFileName> {
     where
         S: Serializer,
     {
-        let mut map = serializer.serialize_map(Some(3))?;
+        let mut map = serializer.serialize_map(None)?;
         map.serialize_entry("py_uri", &self.py_uri())?;
         map.serialize_entry("filename", &self.filename())?;
         map.serialize_entry("sha256", &self.sha256)?;
+        if let Some(size) = self.size {
+            map.serialize_entry("size", &size)?;
+        }
         map.end()
     }
 }
@@ -275,6 +471,7 @@ mod tests {
             registry_url("http%3A%2F%2Flocalhost%3A5000").unwrap(),
             url::Url::parse("http://localhost:5000").unwrap()
         );
+        assert_eq!(registry_url("foo.io%3A4000").unwrap().port(), Some(4000));
     }
 
     #[test]
@@ -291,17 +488,49 @@ mod tests {
     #[test_case("bar-1.0.0.dev4+g1664eb2.d20231017.tar.gz", "1.0.0.dev4-g1664eb2.d20231017"; "full version")]
     fn test_info_oci_tag(filename: &str, expected: &str) {
         let info = Package::from_filename("https://foo.example", "foo", "bar", filename).unwrap();
-        assert_eq!(info.oci_tag(), expected.to_string());
+        assert_eq!(info.oci_tag().unwrap(), expected.to_string());
     }
 
-    #[test]
-    /// Test if `Info.py_uri()` url-encodes the registry
-    fn test_info_py_uri() {
-        let info = Package::from_filename("https://foo.example:4000", "bar", "baz", "baz-1.tar.gz")
-            .unwrap();
+    #[test_case("1.0.0+cuda118", "1.0.0-cuda118"; "local version")]
+    #[test_case("1!2.0", "1_2.0"; "epoch")]
+    #[test_case("1!2.0.dev4+g1664eb2.d20231017", "1_2.0.dev4-g1664eb2.d20231017"; "full version")]
+    #[test_case(&format!("1.{}", "0".repeat(126)), &format!("1.{}", "0".repeat(126)); "maximum length")]
+    /// Test if versions round-trip through OCI tags
+    fn test_version_oci_tag(version: &str, tag: &str) {
+        assert_eq!(version_to_oci_tag(version).unwrap(), tag);
+        assert!(is_valid_tag(tag));
+        assert_eq!(oci_tag_to_version(tag), version);
+    }
+
+    #[test_case(&format!("1.{}", "0".repeat(127)); "too long")]
+    #[test_case(".1"; "leading dot")]
+    #[test_case("-1"; "leading dash")]
+    #[test_case("+1"; "leading plus")]
+    #[test_case("1.0/1"; "slash")]
+    #[test_case(""; "empty")]
+    /// Test if versions that can't be stored as OCI tag are rejected
+    fn test_version_oci_tag_invalid(version: &str) {
+        let err = version_to_oci_tag(version).unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
         assert_eq!(
-            info.py_uri(),
-            "/foo.example%3A4000/bar/baz/baz-1.tar.gz".to_string()
+            err.message,
+            format!("Version '{version}' can not be stored as OCI tag")
+        );
+    }
+
+    #[test_case("https://foo.example:4000", "/foo.example%3A4000/bar/baz/baz-1.tar.gz"; "https with port")]
+    #[test_case("foo.example:4000", "/foo.example%3A4000/bar/baz/baz-1.tar.gz"; "no scheme with port")]
+    #[test_case("http://foo.example:4000", "/http%3A%2F%2Ffoo.example%3A4000/bar/baz/baz-1.tar.gz"; "http with port")]
+    #[test_case("https://foo.example", "/foo.example/bar/baz/baz-1.tar.gz"; "https without port")]
+    /// Test if `Info.py_uri()` url-encodes the registry, keeping the port
+    fn test_info_py_uri(registry: &str, expected: &str) {
+        let info = Package::from_filename(registry, "bar", "baz", "baz-1.tar.gz").unwrap();
+        assert_eq!(info.py_uri(), expected);
+        // The link points to the same registry
+        let link_registry = expected.split('/').nth(1).unwrap();
+        assert_eq!(
+            registry_url(link_registry).unwrap(),
+            info.registry().unwrap()
         );
     }
 
@@ -322,4 +551,75 @@ mod tests {
         let obj = Package::from_filename("foo", "bar", "baz", input).unwrap();
         assert_eq!(obj.filename(), input);
     }
+
+    #[test_case("zope.interface", "zope.interface-5.0.tar.gz", "5.0", ".tar.gz"; "sdist dotted name")]
+    #[test_case("zope.interface", "zope-interface-5.0.tar.gz", "5.0", ".tar.gz"; "sdist dashed name")]
+    #[test_case("backports.zoneinfo", "backports.zoneinfo-0.2.1-cp38-cp38-manylinux1_x86_64.whl", "0.2.1", "cp38-cp38-manylinux1_x86_64.whl"; "wheel dotted name")]
+    /// Test if the version is split off at the right '-'
+    fn test_info_from_filename_name(name: &str, filename: &str, version: &str, arch: &str) {
+        let info = Package::from_filename("foo", "bar", name, filename).unwrap();
+        assert_eq!(info.version.as_deref(), Some(version));
+        assert_eq!(info.arch.as_deref(), Some(arch));
+    }
+
+    #[test]
+    /// Test if a digest reference is split off the filename
+    fn test_info_from_filename_digest() {
+        let info = Package::from_filename(
+            "foo",
+            "bar",
+            "baz",
+            "baz-1.tar.gz@sha256:b7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0",
+        )
+        .unwrap();
+        assert_eq!(info.filename(), "baz-1.tar.gz");
+        assert_eq!(info.oci_tag().unwrap(), "1");
+        assert_eq!(
+            info.digest(),
+            Some("sha256:b7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0")
+        );
+    }
+
+    #[test_case("baz-1.tar.gz@sha256:1234"; "short digest")]
+    #[test_case("baz-1.tar.gz@md5:b7513fb69106a855b69153582dec4766"; "unsupported algorithm")]
+    #[test_case("baz-1.tar.gz@"; "empty digest")]
+    fn test_info_from_filename_invalid_digest(filename: &str) {
+        let Err(err) = Package::from_filename("foo", "bar", "baz", filename) else {
+            panic!("Expected an Err");
+        };
+        let err = err.downcast::<PyOciError>().unwrap();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test_case("baz-1.0-py3.8.egg"; "egg")]
+    #[test_case("baz-1.0.win-amd64.exe"; "windows installer")]
+    #[test_case("baz-1.0.win-amd64.MSI"; "msi")]
+    fn test_info_from_filename_legacy(filename: &str) {
+        let Err(err) = Package::from_filename("foo", "bar", "baz", filename) else {
+            panic!("Expected an Err");
+        };
+        let err = err.downcast::<PyOciError>().unwrap();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            err.message,
+            format!("Unsupported legacy distribution '{filename}', PyOCI only supports wheels (.whl) and source distributions (.tar.gz)")
+        );
+    }
+
+    #[test_case("foo-1.tar.gz", b"\x1f\x8b\x08\x00"; "sdist")]
+    #[test_case("foo-1-py3-none-any.whl", b"PK\x03\x04\x14\x00"; "wheel")]
+    fn verify_content(filename: &str, content: &[u8]) {
+        let package = Package::from_filename("ghcr.io", "allexveldman", "foo", filename).unwrap();
+        assert!(package.verify_content(content).is_ok());
+    }
+
+    #[test_case("foo-1.tar.gz", b"PK\x03\x04\x14\x00", "Content of 'foo-1.tar.gz' is not a gzip archive"; "sdist as zip")]
+    #[test_case("foo-1-py3-none-any.whl", b"garbage", "Content of 'foo-1-py3-none-any.whl' is not a zip archive"; "wheel garbage")]
+    #[test_case("foo-1-py3-none-any.whl", b"", "Content of 'foo-1-py3-none-any.whl' is not a zip archive"; "wheel empty")]
+    fn verify_content_mismatch(filename: &str, content: &[u8], message: &str) {
+        let package = Package::from_filename("ghcr.io", "allexveldman", "foo", filename).unwrap();
+        let err = package.verify_content(content).unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(err.message, message);
+    }
 }
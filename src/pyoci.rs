@@ -1,58 +1,285 @@
-use anyhow::{bail, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 use futures::stream::FuturesOrdered;
 use futures::stream::StreamExt;
 use http::StatusCode;
 use oci_spec::image::{
-    ImageIndex, ImageIndexBuilder, ImageManifestBuilder, MediaType, SCHEMA_VERSION,
+    Arch, Descriptor, Digest as OciDigest, DigestAlgorithm, ImageIndex, ImageIndexBuilder,
+    ImageManifest, ImageManifestBuilder, MediaType, Platform, SCHEMA_VERSION,
 };
 use reqwest::Response;
 use serde_json::to_string_pretty;
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use time::format_description::well_known::Rfc3339;
+use time::UtcDateTime;
 use url::Url;
 
 use crate::error::PyOciError;
+use crate::oci::implied_os;
+use crate::oci::platform;
 use crate::oci::Blob;
 use crate::oci::Manifest;
 use crate::oci::Oci;
 use crate::oci::PlatformManifest;
+use crate::oci::Precondition;
 use crate::service::AuthHeader;
+use crate::spool::SpooledContent;
 use crate::time::now_utc;
+use crate::transport::HttpClient;
+use crate::version::Version;
 
-use crate::package::{Package, WithFileName, WithoutFileName};
+use crate::package::{
+    is_valid_tag, oci_tag_to_version, version_to_oci_tag, Deprecation, Package, WithFileName,
+    WithoutFileName,
+};
 use crate::ARTIFACT_TYPE;
+use crate::{CONFIG_CONTENT, CONFIG_MEDIA_TYPE};
+
+/// Number of times the index is updated when it changes concurrently while publishing
+const PUBLISH_INDEX_ATTEMPTS: usize = 3;
 
 /// Client to communicate with the OCI v2 registry
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct PyOci {
     oci: Oci,
+    /// Tags to probe when the registry returns an empty tag list
+    tag_fallback: Vec<String>,
+    /// Prefixes of the annotations exposed by `package_info_for_ref`
+    annotation_prefixes: Vec<String>,
+    /// Refuse an `ImageIndex` without the `PyOCI` artifact type
+    strict_artifact_type: bool,
+    /// Fetch the size of each file in `package_info_for_ref`
+    show_sizes: bool,
+    /// Verify the repository can be published to before pushing anything
+    verify_repository: bool,
+    /// Project URL labels, and the annotation they are published as
+    project_url_annotations: Vec<(String, String)>,
+    /// Media type of the config of a published `ImageManifest`
+    config_media_type: String,
+    /// Content of the config of a published `ImageManifest`
+    config_content: String,
+    /// Reject publishing an existing file with 412 instead of 409
+    create_only: bool,
 }
 
 impl PyOci {
     /// Create a new Client
-    pub fn new(registry: Url, auth: Option<AuthHeader>) -> PyOci {
+    pub fn new(registry: Url, auth: Option<AuthHeader>, client: HttpClient) -> PyOci {
         PyOci {
-            oci: Oci::new(registry, auth),
+            oci: Oci::new(registry, auth, client),
+            tag_fallback: Vec::new(),
+            annotation_prefixes: Vec::new(),
+            strict_artifact_type: true,
+            show_sizes: false,
+            verify_repository: false,
+            project_url_annotations: Vec::new(),
+            config_media_type: CONFIG_MEDIA_TYPE.to_string(),
+            config_content: CONFIG_CONTENT.to_string(),
+            create_only: false,
+        }
+    }
+
+    /// Probe these tags when the registry returns an empty tag list
+    pub fn with_tag_fallback(self, tag_fallback: Vec<String>) -> Self {
+        Self {
+            tag_fallback,
+            ..self
+        }
+    }
+
+    /// Only expose the annotations starting with one of these prefixes
+    ///
+    /// Annotations used internally by `PyOCI` (`com.pyoci.*`) are never exposed.
+    pub fn with_annotation_prefixes(self, annotation_prefixes: Vec<String>) -> Self {
+        Self {
+            annotation_prefixes,
+            ..self
+        }
+    }
+
+    /// Accept an `ImageIndex` with a missing or different artifact type when reading,
+    /// as long as it is structured like a `PyOCI` index
+    pub fn with_strict_artifact_type(self, strict_artifact_type: bool) -> Self {
+        Self {
+            strict_artifact_type,
+            ..self
+        }
+    }
+
+    /// Add the size of each file when listing a package
+    ///
+    /// This pulls the `ImageManifest` of every file, one extra request per file.
+    pub fn with_show_sizes(self, show_sizes: bool) -> Self {
+        Self { show_sizes, ..self }
+    }
+
+    /// Verify the repository can be published to before pushing anything,
+    /// see [`Oci::verify_repository`]
+    pub fn with_verify_repository(self, verify_repository: bool) -> Self {
+        Self {
+            verify_repository,
+            ..self
+        }
+    }
+
+    /// Publish the project URLs with these labels as annotations, next to `com.pyoci.project_urls`
+    ///
+    /// The labels are matched case-insensitive, for example
+    /// `("Repository", "org.opencontainers.image.source")`.
+    pub fn with_project_url_annotations(
+        self,
+        project_url_annotations: Vec<(String, String)>,
+    ) -> Self {
+        Self {
+            project_url_annotations,
+            ..self
+        }
+    }
+
+    /// Reject publishing a file that already exists with `412 Precondition Failed`
+    /// instead of `409 Conflict`, for clients requesting create-only semantics
+    ///
+    /// The existing file is detected before any blob is pushed, a file created concurrently
+    /// is detected by the conditional push of the index.
+    pub fn with_create_only(self, create_only: bool) -> Self {
+        Self {
+            create_only,
+            ..self
+        }
+    }
+
+    /// Publish the config of the `ImageManifest` with this media type
+    ///
+    /// For registries rejecting the default `application/vnd.oci.empty.v1+json`,
+    /// for example `application/vnd.oci.image.config.v1+json`.
+    /// The default media type only allows the `{}` content, see `with_config_content`.
+    pub fn with_config_media_type(self, config_media_type: String) -> Self {
+        Self {
+            config_media_type,
+            ..self
+        }
+    }
+
+    /// Publish this content as the config of the `ImageManifest`, instead of `{}`
+    ///
+    /// For registries rejecting a tiny config or requiring a specific config schema.
+    pub fn with_config_content(self, config_content: String) -> Self {
+        Self {
+            config_content,
+            ..self
+        }
+    }
+
+    /// Check the artifact type of an existing `PyOCI` index
+    ///
+    /// When not strict, an index with a missing or different artifact type is accepted if all
+    /// manifests are `ImageManifest`s with a platform, the same as `PyOCI` creates them.
+    fn check_artifact_type(&self, index: &ImageIndex) -> Result<()> {
+        let err = match index.artifact_type() {
+            Some(MediaType::Other(value)) if value == ARTIFACT_TYPE => return Ok(()),
+            Some(value) => anyhow!("Unknown artifact type: {value}"),
+            None => anyhow!("No artifact type set"),
+        };
+        if self.strict_artifact_type {
+            return Err(err);
+        }
+        let structured = index.manifests().iter().all(|manifest| {
+            *manifest.media_type() == MediaType::ImageManifest
+                && matches!(
+                    manifest.platform().as_ref().map(Platform::architecture),
+                    Some(Arch::Other(_))
+                )
+        });
+        if !structured {
+            return Err(err);
         }
+        tracing::warn!("{err}, continuing as the ImageIndex is structured like a PyOCI package");
+        Ok(())
+    }
+
+    /// Check if the annotation can be exposed when listing a package
+    fn is_exposed_annotation(&self, key: &str) -> bool {
+        !key.starts_with("com.pyoci.")
+            && self
+                .annotation_prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_str()))
     }
 }
 
+/// Window of the versions of a package
+#[derive(Debug, PartialEq)]
+pub struct PackageVersions {
+    /// Versions within the requested window, oldest first following PEP 440
+    pub versions: Vec<String>,
+    /// Number of versions of the package
+    pub total: usize,
+    /// Newest version of the package, also when it is outside of the window
+    pub latest: Option<String>,
+}
+
 /// Create/List/Download/Delete Packages
 impl PyOci {
+    /// List the versions of a package
+    ///
+    /// The versions are ordered oldest first following PEP 440, tags that are not a version
+    /// are listed before them.
+    /// Skips the first `offset` versions and returns at most `limit` versions,
+    /// `None` returns all remaining versions.
     pub async fn list_package_versions<'a>(
         &mut self,
         package: &'a Package<'a, WithoutFileName>,
-    ) -> Result<BTreeSet<String>> {
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<PackageVersions> {
         let name = package.oci_name();
-        let result = self.oci.list_tags(&name).await?;
-        tracing::debug!("{:?}", result);
-        Ok(result)
+        let mut tags = self.list_tags(&name).await?;
+        self.remove_aliases(&name, &mut tags).await;
+        let mut tags: Vec<(Option<Version>, String)> = tags
+            .into_iter()
+            .map(|tag| (Version::from_oci_tag(&tag).ok(), tag))
+            .collect();
+        tags.sort_unstable();
+        let tags: Vec<String> = tags.into_iter().map(|(_, tag)| tag).collect();
+        tracing::debug!("{:?}", tags);
+        Ok(PackageVersions {
+            total: tags.len(),
+            latest: tags.last().cloned(),
+            versions: tags
+                .into_iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .collect(),
+        })
+    }
+
+    /// List the tags of `name`
+    ///
+    /// Some registries don't populate the tag list, if it is empty the `tag_fallback`
+    /// tags that exist are returned instead.
+    async fn list_tags(&mut self, name: &str) -> Result<BTreeSet<String>> {
+        let tags = self.oci.list_tags(name).await?;
+        if !tags.is_empty() || self.tag_fallback.is_empty() {
+            return Ok(tags);
+        }
+        tracing::info!("Empty tag list, probing {:?}", self.tag_fallback);
+        let mut tags = BTreeSet::new();
+        for tag in self.tag_fallback.clone() {
+            if self.oci.pull_manifest(name, &tag).await?.is_some() {
+                tags.insert(tag);
+            }
+        }
+        Ok(tags)
     }
 
     /// List all files for the given package
     ///
-    /// Limits the number of files to `n`
+    /// Limits the number of files to `n`, newest versions first following PEP 440,
+    /// tags that are not a version are listed after them.
+    /// Redundant descriptors for the same version and architecture are listed once.
     /// ref: <https://github.com/opencontainers/distribution-spec/blob/main/spec.md#listing-tags>
     pub async fn list_package_files<'a>(
         &mut self,
@@ -60,7 +287,8 @@ impl PyOci {
         n: usize,
     ) -> Result<Vec<Package<'a, WithFileName>>> {
         let mut n = n;
-        let tags = self.oci.list_tags(&package.oci_name()).await?;
+        let mut tags = self.list_tags(&package.oci_name()).await?;
+        self.remove_aliases(&package.oci_name(), &mut tags).await;
         let mut files: Vec<Package<WithFileName>> = Vec::new();
         let mut futures = FuturesOrdered::new();
 
@@ -80,24 +308,166 @@ impl PyOci {
         // We fetch a list of all tags from the OCI registry.
         // For each tag there can be multiple files.
         // We fetch the last `n` tags and for each tag we fetch the file names.
-        for tag in tags.iter().rev().take(n) {
+        // Tags that are not a version, like the `tag_fallback` tags, are the oldest.
+        let mut versions: Vec<(Option<Version>, String)> = tags
+            .into_iter()
+            .map(|tag| (Version::from_oci_tag(&tag).ok(), tag))
+            .collect();
+        versions.sort_unstable_by(|left, right| right.cmp(left));
+        for (_, tag) in versions.into_iter().take(n) {
             let pyoci = self.clone();
-            futures.push_back(pyoci.package_info_for_ref(package, tag));
+            futures.push_back(async move { pyoci.package_info_for_ref(package, &tag).await });
         }
+        let mut seen = HashSet::new();
         for result in futures
             .collect::<Vec<Result<Vec<Package<WithFileName>>, Error>>>()
             .await
         {
-            files.append(&mut result?);
+            for file in result? {
+                if seen.insert((file.oci_tag()?, file.oci_architecture().to_string())) {
+                    files.push(file);
+                }
+            }
         }
         Ok(files)
     }
 
+    /// Find the file of the newest version matching `arch`
+    ///
+    /// Versions are ordered following PEP 440, tags that are not a valid version are ignored.
+    /// Only the newest `n` versions are searched, 0 searches all versions.
+    pub async fn latest_package_file<'a>(
+        &mut self,
+        package: &'a Package<'a, WithoutFileName>,
+        arch: &str,
+        n: usize,
+    ) -> Result<Option<Package<'a, WithFileName>>> {
+        let tags = self.list_tags(&package.oci_name()).await?;
+        let mut versions: Vec<(Version, String)> = tags
+            .into_iter()
+            .filter_map(|tag| Some((Version::from_oci_tag(&tag).ok()?, tag)))
+            .collect();
+        versions.sort_unstable_by(|(left, _), (right, _)| right.cmp(left));
+        if n > 0 {
+            versions.truncate(n);
+        }
+
+        for (_, tag) in versions {
+            // Only the architecture is needed to find the file
+            let files = self.clone().files_for_ref(package, &tag, false).await?;
+            if let Some(file) = files
+                .into_iter()
+                .find(|file| file.oci_architecture().ends_with(arch))
+            {
+                return Ok(Some(file));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove the aliases created by `tag_package_version` from `tags`
+    ///
+    /// `PyOCI` only pushes its own `ImageIndex` to version tags and aliases, so a tag that is
+    /// not a version but points at a `PyOCI` index is an alias.
+    /// Tags that can't be read and the `tag_fallback` tags are kept.
+    async fn remove_aliases(&self, name: &str, tags: &mut BTreeSet<String>) {
+        let mut futures = FuturesOrdered::new();
+        for tag in tags
+            .iter()
+            .filter(|tag| Version::from_oci_tag(tag).is_err() && !self.tag_fallback.contains(tag))
+        {
+            let mut oci = self.oci.clone();
+            let tag = tag.clone();
+            futures.push_back(async move {
+                let manifest = oci.pull_manifest_with_digest(name, &tag).await;
+                (tag, manifest)
+            });
+        }
+        while let Some((tag, manifest)) = futures.next().await {
+            match manifest {
+                Ok(Some((Manifest::Index(index), _))) => {
+                    if matches!(
+                        index.artifact_type(),
+                        Some(MediaType::Other(value)) if value == ARTIFACT_TYPE
+                    ) {
+                        tags.remove(&tag);
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("Failed to pull '{name}:{tag}', keeping the tag: {err}"),
+            }
+        }
+    }
+
+    /// Point the `alias` tag at the `ImageIndex` of `version`
+    ///
+    /// The alias can't be a version itself, to not clash with the version tags.
+    pub async fn tag_package_version(
+        &mut self,
+        package: &Package<'_, WithoutFileName>,
+        version: &str,
+        alias: &str,
+    ) -> Result<()> {
+        if !is_valid_tag(alias) || Version::from_oci_tag(alias).is_ok() {
+            return Err(PyOciError::from((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid alias '{alias}'"),
+            ))
+            .into());
+        }
+        let name = package.oci_name();
+        let tag = version_to_oci_tag(version)?;
+        let index = self.version_index(package, version).await?;
+        self.check_artifact_type(&index)?;
+        self.oci
+            .tag_manifest(&name, &tag, alias)
+            .await
+            .with_context(|| format!("Failed to push alias '{name}:{alias}'"))
+    }
+
+    /// Pull the `ImageIndex` of a version, as stored in the registry
+    ///
+    /// The artifact type is not checked, so indexes not created by `PyOCI` can be inspected.
+    pub async fn version_index(
+        &mut self,
+        package: &Package<'_, WithoutFileName>,
+        version: &str,
+    ) -> Result<ImageIndex> {
+        let tag = version_to_oci_tag(version)?;
+        match self.oci.pull_manifest(&package.oci_name(), &tag).await? {
+            Some(Manifest::Index(index)) => Ok(*index),
+            Some(Manifest::Manifest(_)) => {
+                bail!("Expected ImageIndex, got ImageManifest");
+            }
+            None => Err(PyOciError::from((
+                StatusCode::NOT_FOUND,
+                format!("Version '{version}' does not exist"),
+            ))
+            .into()),
+        }
+    }
+
     /// Fetch all files for a single version of a package
+    ///
+    /// Files without a sha256 annotation, published by older versions of `PyOCI`,
+    /// get the digest of their layer, which pulls the `ImageManifest` of the file.
     pub async fn package_info_for_ref<'a>(
+        self,
+        package: &'a Package<'a, WithoutFileName>,
+        reference: &str,
+    ) -> Result<Vec<Package<'a, WithFileName>>> {
+        self.files_for_ref(package, reference, true).await
+    }
+
+    /// Fetch all files for a single version of a package
+    ///
+    /// With `details`, the `ImageManifest` of a file is pulled when the sha256 annotation is
+    /// missing or the size is requested.
+    async fn files_for_ref<'a>(
         mut self,
         package: &'a Package<'a, WithoutFileName>,
         reference: &str,
+        details: bool,
     ) -> Result<Vec<Package<'a, WithFileName>>> {
         let manifest = self
             .oci
@@ -117,34 +487,62 @@ impl PyOci {
             }
         };
 
-        let artifact_type = index.artifact_type();
-        match artifact_type {
-            // Artifact type is as expected, do nothing
-            Some(MediaType::Other(value)) if value == ARTIFACT_TYPE => {}
-            // Artifact type has unexpected value, err
-            Some(value) => bail!("Unknown artifact type: {value}"),
-            // Artifact type is not set, err
-            None => bail!("No artifact type set"),
-        }
+        self.check_artifact_type(&index)?;
         let mut files: Vec<Package<WithFileName>> = Vec::new();
         for manifest in index.manifests() {
             match manifest.platform().as_ref().unwrap().architecture() {
                 oci_spec::image::Arch::Other(arch) => {
                     let mut sha256_digest = None;
+                    let mut sha512_digest = None;
                     let mut project_urls = None;
+                    let mut requires_dist = None;
+                    let mut labels = BTreeMap::new();
+                    let mut deprecation = Deprecation::default();
                     if let Some(annotations) = manifest.annotations() {
+                        deprecation = Deprecation::from_annotations(annotations);
                         sha256_digest = annotations
                             .get("com.pyoci.sha256_digest")
                             .map(ToString::to_string);
+                        sha512_digest = annotations
+                            .get("com.pyoci.sha512_digest")
+                            .map(ToString::to_string);
                         project_urls = annotations
                             .get("com.pyoci.project_urls")
                             .map(ToString::to_string);
+                        requires_dist = annotations
+                            .get("com.pyoci.requires_dist")
+                            .map(ToString::to_string);
+                        labels = annotations
+                            .iter()
+                            .filter(|(key, _)| self.is_exposed_annotation(key))
+                            .map(|(key, value)| (key.clone(), value.clone()))
+                            .collect();
                     }
-                    let file = package
+                    let mut file = package
                         .with_oci_file(reference, arch)
-                        .with_sha256(sha256_digest)
-                        .with_project_urls(project_urls);
-                    files.push(file);
+                        .with_sha512(sha512_digest)
+                        .with_project_urls(project_urls)
+                        .with_requires_dist(requires_dist)
+                        .with_annotations(labels)
+                        .with_deprecation(deprecation);
+                    // Files published by older versions of PyOCI lack the sha256 annotation,
+                    // the digest of the layer is the sha256 of the file.
+                    if details && (self.show_sizes || sha256_digest.is_none()) {
+                        let image_manifest = self
+                            .pull_image_manifest(&file, manifest.digest().as_ref())
+                            .await?;
+                        let layer = image_manifest.layers().first();
+                        if sha256_digest.is_none() {
+                            sha256_digest = layer
+                                .map(Descriptor::digest)
+                                .filter(|digest| digest.algorithm() == &DigestAlgorithm::Sha256)
+                                .map(|digest| digest.digest().to_string());
+                        }
+                        if self.show_sizes {
+                            file = file.with_size(layer.map(Descriptor::size));
+                        }
+                    }
+                    files.push(file.with_sha256(sha256_digest));
                 }
                 arch => bail!("Unsupported architecture '{arch}'"),
             }
@@ -153,14 +551,72 @@ impl PyOci {
     }
 
     /// Download a single file of a package
+    ///
+    /// If the package is pinned to a digest, the `ImageManifest` is pulled directly
+    /// without resolving the version.
+    ///
+    /// Returns the deprecation of the file next to the response, read from the annotations
+    /// of the `ImageManifest`.
     pub async fn download_package_file(
         &mut self,
         package: &Package<'_, WithFileName>,
-    ) -> Result<Response> {
+    ) -> Result<(Response, Deprecation)> {
+        let manifest = match package.digest() {
+            Some(digest) => self.pull_image_manifest(package, digest).await?,
+            None => self.platform_manifest(package).await?,
+        };
+        // pull blob in first layer of manifest
+        let [blob_descriptor] = &manifest.layers()[..] else {
+            bail!("Image Manifest defines unexpected number of layers, was this package published by pyoci?");
+        };
+        let deprecation = manifest
+            .annotations()
+            .as_ref()
+            .map(Deprecation::from_annotations)
+            .unwrap_or_default();
+        let response = self
+            .oci
+            .pull_blob(package.oci_name(), blob_descriptor.to_owned())
+            .await?;
+        Ok((response, deprecation))
+    }
+
+    /// Check if a file of a package exists
+    ///
+    /// Only the `ImageIndex` of the version is pulled, the file exists when the index holds a
+    /// manifest for its platform, the same check that refuses publishing a file twice.
+    pub async fn package_file_exists(
+        &mut self,
+        package: &Package<'_, WithFileName>,
+    ) -> Result<bool> {
+        let index = match self
+            .oci
+            .pull_manifest(&package.oci_name(), &package.oci_tag()?)
+            .await?
+        {
+            Some(Manifest::Index(index)) => index,
+            Some(Manifest::Manifest(_)) => {
+                bail!("Expected ImageIndex, got ImageManifest");
+            }
+            None => return Ok(false),
+        };
+        self.check_artifact_type(&index)?;
+        let expected_platform = platform(package);
+        Ok(index
+            .manifests()
+            .iter()
+            .any(|manifest| manifest.platform().as_ref() == Some(&expected_platform)))
+    }
+
+    /// Resolve the `ImageManifest` for the package version and architecture
+    async fn platform_manifest(
+        &mut self,
+        package: &Package<'_, WithFileName>,
+    ) -> Result<ImageManifest> {
         // Pull index
         let index = match self
             .oci
-            .pull_manifest(&package.oci_name(), &package.oci_tag())
+            .pull_manifest(&package.oci_name(), &package.oci_tag()?)
             .await?
         {
             Some(Manifest::Index(index)) => index,
@@ -173,63 +629,62 @@ impl PyOci {
                 )
             }
         };
-        // Check artifact type
-        match index.artifact_type() {
-            // Artifact type is as expected, do nothing
-            Some(MediaType::Other(value)) if value == ARTIFACT_TYPE => {}
-            // Artifact type has unexpected value, err
-            Some(value) => bail!("Unknown artifact type: {value}"),
-            // Artifact type is not set, err
-            None => bail!("No artifact type set"),
-        }
+        self.check_artifact_type(&index)?;
         // Find manifest descriptor for platform
-        let mut platform_manifest: Option<&oci_spec::image::Descriptor> = None;
-        for manifest in index.manifests() {
-            if let Some(platform) = manifest.platform() {
-                match platform.architecture() {
-                    oci_spec::image::Arch::Other(arch) if *arch == package.oci_architecture() => {
-                        platform_manifest = Some(manifest);
-                        break;
-                    }
-                    _ => {}
-                }
-            }
-        }
-        let Some(manifest_descriptor) = platform_manifest else {
+        // Compare the full platform as set when publishing, not only the architecture.
+        // Files not published by PyOCI can have a real OS, which has to match the OS
+        // the wheel is built for.
+        let expected_platform = platform(package);
+        let implied_os = implied_os(package);
+        let manifests = index.manifests();
+        let Some(manifest_descriptor) = manifests
+            .iter()
+            .find(|manifest| manifest.platform().as_ref() == Some(&expected_platform))
+            .or_else(|| {
+                manifests.iter().find(|manifest| {
+                    manifest.platform().as_ref().is_some_and(|platform| {
+                        platform.architecture() == expected_platform.architecture()
+                            && Some(platform.os()) == implied_os.as_ref()
+                    })
+                })
+            })
+        else {
+            let available = index
+                .manifests()
+                .iter()
+                .filter_map(|manifest| manifest.platform().as_ref())
+                .map(|platform| format!("'{}'", platform.architecture()))
+                .collect::<Vec<_>>()
+                .join(", ");
             return Err(PyOciError::from((
                 StatusCode::NOT_FOUND,
                 format!(
-                    "Requested architecture '{}' not available",
+                    "Requested architecture '{}' not available, available architectures: [{available}]",
                     package.oci_architecture()
                 ),
             ))
             .into());
         };
 
-        let manifest = match self
-            .oci
-            .pull_manifest(&package.oci_name(), manifest_descriptor.digest().as_ref())
-            .await?
-        {
-            Some(Manifest::Manifest(manifest)) => *manifest,
+        self.pull_image_manifest(package, manifest_descriptor.digest().as_ref())
+            .await
+    }
+
+    /// Pull an `ImageManifest` by digest
+    async fn pull_image_manifest(
+        &mut self,
+        package: &Package<'_, WithFileName>,
+        digest: &str,
+    ) -> Result<ImageManifest> {
+        match self.oci.pull_manifest(&package.oci_name(), digest).await? {
+            Some(Manifest::Manifest(manifest)) => Ok(*manifest),
             Some(Manifest::Index(_)) => {
                 bail!("Expected ImageManifest, got ImageIndex");
             }
-            None => {
-                return Err(PyOciError::from((
-                    StatusCode::NOT_FOUND,
-                    "ImageManifest does not exist",
-                ))
-                .into())
-            }
-        };
-        // pull blob in first layer of manifest
-        let [blob_descriptor] = &manifest.layers()[..] else {
-            bail!("Image Manifest defines unexpected number of layers, was this package published by pyoci?");
-        };
-        self.oci
-            .pull_blob(package.oci_name(), blob_descriptor.to_owned())
-            .await
+            None => Err(
+                PyOciError::from((StatusCode::NOT_FOUND, "ImageManifest does not exist")).into(),
+            ),
+        }
     }
 
     /// Publish a package file
@@ -238,32 +693,67 @@ impl PyOci {
     ///
     /// The `sha256_digest`, if provided, will be verified against the sha256 of the actual content.
     ///
+    /// The file is pushed as a layer with `layer_media_type`, the artifact type of the manifests
+    /// is always [`ARTIFACT_TYPE`].
+    ///
     /// The `annotations` will be added to the `ImageManifest`, mimicking the default docker CLI
     /// behaviour.
+    ///
+    /// `requires_dist` is stored as a JSON list in the `com.pyoci.requires_dist` annotation
+    /// when the package has any dependencies.
+    ///
+    /// `project_urls` is stored as a JSON object in the `com.pyoci.project_urls` annotation,
+    /// the URLs configured with `with_project_url_annotations` are also added to `annotations`.
+    ///
+    /// `created` overrides the creation time of the package, defaults to now.
+    ///
+    /// When enabled with `with_verify_repository`, the repository is checked before anything
+    /// is pushed, so a publish without access fails without leaving blobs behind.
+    #[allow(clippy::too_many_arguments, clippy::too_many_lines)]
     pub async fn publish_package_file(
         &mut self,
         package: &Package<'_, WithFileName>,
-        file: Vec<u8>,
+        file: SpooledContent,
+        layer_media_type: &str,
         mut annotations: HashMap<String, String>,
         sha256_digest: Option<String>,
         project_urls: HashMap<String, String>,
+        requires_dist: Vec<String>,
+        created: Option<UtcDateTime>,
     ) -> Result<()> {
         let name = package.oci_name();
-        let tag = package.oci_tag();
+        let tag = package.oci_tag()?;
+        if self.verify_repository {
+            self.oci.verify_repository(&name).await?;
+        }
 
-        let layer = Blob::new(file, ARTIFACT_TYPE);
+        let sha512_digest = file.sha512().to_string();
+        let layer = Blob::from_content(file, layer_media_type);
 
         let package_digest = verify_digest(&layer, sha256_digest)?;
 
+        for (label, annotation) in &self.project_url_annotations {
+            if let Some((_, url)) = project_urls
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(label))
+            {
+                // Labels set explicitly take precedence
+                annotations
+                    .entry(annotation.clone())
+                    .or_insert_with(|| url.clone());
+            }
+        }
+
         // Annotations added to the manifest descriptor in the ImageIndex
-        // We're adding the digest here so we don't need to pull the ImageManifest when listing
-        // packages to get the package (blob) digest
-        let mut index_manifest_annotations =
-            HashMap::from([("com.pyoci.sha256_digest".to_string(), package_digest)]);
+        // We're adding the digests and labels here so we don't need to pull the ImageManifest
+        // when listing packages to get the package (blob) digest
+        let mut index_manifest_annotations = annotations.clone();
+        index_manifest_annotations.insert("com.pyoci.sha256_digest".to_string(), package_digest);
+        index_manifest_annotations.insert("com.pyoci.sha512_digest".to_string(), sha512_digest);
 
         let creation_annotation = HashMap::from([(
             "org.opencontainers.image.created".to_string(),
-            now_utc().format(&Rfc3339)?,
+            created.unwrap_or_else(now_utc).format(&Rfc3339)?,
         )]);
 
         annotations.extend(creation_annotation.clone());
@@ -272,47 +762,115 @@ impl PyOci {
             "com.pyoci.project_urls".to_string(),
             serde_json::to_string(&project_urls)?,
         );
+        if !requires_dist.is_empty() {
+            index_manifest_annotations.insert(
+                "com.pyoci.requires_dist".to_string(),
+                serde_json::to_string(&requires_dist)?,
+            );
+        }
 
         // Build the Manifest
-        let manifest = image_manifest(package, &layer, annotations);
-        let index = self
+        let config = config_blob(&self.config_media_type, &self.config_content);
+        let manifest = image_manifest(
+            package,
+            &config,
+            &layer,
+            annotations,
+            self.oci.manifest_content_type(),
+        );
+        let (mut index, mut index_digest) = self
             .image_index(
                 package,
                 &manifest,
-                creation_annotation,
-                index_manifest_annotations,
+                creation_annotation.clone(),
+                index_manifest_annotations.clone(),
             )
             .await?;
         tracing::debug!("{}", to_string_pretty(&index).unwrap());
         tracing::debug!("{}", to_string_pretty(&manifest.manifest).unwrap());
 
-        self.oci.push_blob(&name, layer).await?;
-        self.oci.push_blob(&name, empty_config()).await?;
+        let layer_digest = layer.descriptor().digest().to_string();
         self.oci
-            .push_manifest(&name, Manifest::Manifest(Box::new(manifest.manifest)), None)
-            .await?;
+            .push_blob(&name, layer)
+            .await
+            .with_context(|| format!("Failed to push layer '{name}:{layer_digest}'"))?;
+        let config_digest = config.descriptor().digest().to_string();
+        self.oci
+            .push_blob(&name, config)
+            .await
+            .with_context(|| format!("Failed to push config '{name}:{config_digest}'"))?;
+        let (manifest_digest, _) = manifest.digest();
         self.oci
-            .push_manifest(&name, Manifest::Index(Box::new(index)), Some(&tag))
+            .push_manifest(
+                &name,
+                Manifest::Manifest(Box::new(manifest.manifest.clone())),
+                None,
+                Precondition::None,
+            )
             .await
+            .with_context(|| format!("Failed to push manifest '{name}:{manifest_digest}'"))?;
+
+        // Only replace the index we based our update on, or only create it when there was none.
+        // If it was changed by a concurrent publish, redo the update on top of the new index.
+        let mut attempt = 1;
+        loop {
+            let precondition = match &index_digest {
+                Some(digest) => Precondition::Match(digest),
+                None => Precondition::NoneMatch,
+            };
+            let result = self
+                .oci
+                .push_manifest(
+                    &name,
+                    Manifest::Index(Box::new(index)),
+                    Some(&tag),
+                    precondition,
+                )
+                .await;
+            match result {
+                Err(err) if attempt < PUBLISH_INDEX_ATTEMPTS && is_precondition_failed(&err) => {
+                    tracing::warn!("Index '{name}:{tag}' changed while publishing, retrying");
+                    attempt += 1;
+                    (index, index_digest) = self
+                        .image_index(
+                            package,
+                            &manifest,
+                            creation_annotation.clone(),
+                            index_manifest_annotations.clone(),
+                        )
+                        .await?;
+                }
+                result => {
+                    return result.with_context(|| format!("Failed to push index '{name}:{tag}'"))
+                }
+            }
+        }
     }
 
     /// Create or Update the definition of a new `ImageIndex`
+    ///
+    /// Also returns the digest of the existing index, if any.
     async fn image_index(
         &mut self,
         package: &Package<'_, WithFileName>,
         manifest: &PlatformManifest,
         index_annotations: HashMap<String, String>,
         index_manifest_annotations: HashMap<String, String>,
-    ) -> Result<ImageIndex> {
+    ) -> Result<(ImageIndex, Option<OciDigest>)> {
         let name = package.oci_name();
-        let tag = package.oci_tag();
+        let tag = package.oci_tag()?;
         // Pull an existing index
-        let index = match self.oci.pull_manifest(&name, &tag).await? {
-            Some(Manifest::Manifest(_)) => {
+        let (index, digest) = match self
+            .oci
+            .pull_manifest_with_digest(&name, &tag)
+            .await
+            .with_context(|| format!("Failed to pull index '{name}:{tag}'"))?
+        {
+            Some((Manifest::Manifest(_), _)) => {
                 bail!("Expected ImageIndex, got ImageManifest");
             }
-            Some(Manifest::Index(index)) => Some(index),
-            None => None,
+            Some((Manifest::Index(index), digest)) => (Some(index), Some(digest)),
+            None => (None, None),
         };
 
         let index = match index {
@@ -336,15 +894,20 @@ impl PyOci {
                 for existing in index.manifests() {
                     match existing.platform() {
                         Some(platform) if *platform == manifest.platform => {
+                            let status = if self.create_only {
+                                StatusCode::PRECONDITION_FAILED
+                            } else {
+                                StatusCode::CONFLICT
+                            };
                             return Err(PyOciError::from((
-                                StatusCode::CONFLICT,
+                                status,
                                 format!(
                                     "Platform '{}' already exists for version '{}'",
                                     package.oci_architecture(),
                                     tag
                                 ),
                             ))
-                            .into())
+                            .into());
                         }
                         _ => {}
                     }
@@ -355,19 +918,26 @@ impl PyOci {
                 *index
             }
         };
-        Ok(index)
+        Ok((index, digest))
     }
 
     /// Delete a package version
+    ///
+    /// If `collect_garbage` is set, the blobs of the deleted manifests are only deleted when they
+    /// are no longer referenced by any of the remaining versions, including the config blob.
+    ///
+    /// If the registry does not support deleting manifests by digest, only the tag is deleted.
     pub async fn delete_package_version(
         &mut self,
         package: &Package<'_, WithFileName>,
+        collect_garbage: bool,
     ) -> Result<()> {
         let name = package.oci_name();
-        let tag = package.oci_tag();
-        let index = match self.oci.pull_manifest(&name, &tag).await? {
-            Some(Manifest::Index(index)) => index,
-            Some(Manifest::Manifest(_)) => {
+        let tag = package.oci_tag()?;
+        // Not following nested indexes, deleting the tag has to delete what it points to
+        let index = match self.oci.pull_manifest_with_digest(&name, &tag).await? {
+            Some((Manifest::Index(index), _)) => index,
+            Some((Manifest::Manifest(_), _)) => {
                 bail!("Expected ImageIndex, got ImageManifest");
             }
             None => {
@@ -376,15 +946,10 @@ impl PyOci {
                 )
             }
         };
-        // Check artifact type
-        match index.artifact_type() {
-            // Artifact type is as expected, do nothing
-            Some(MediaType::Other(value)) if value == ARTIFACT_TYPE => {}
-            // Artifact type has unexpected value, err
-            Some(value) => bail!("Unknown artifact type: {value}"),
-            // Artifact type is not set, err
-            None => bail!("No artifact type set"),
-        }
+        self.check_artifact_type(&index)?;
+        // Blobs to delete once the manifests are gone
+        let mut blobs = BTreeSet::new();
+        let mut delete_by_digest = true;
         // Delete the manifests included in the index
         for manifest in index.manifests() {
             let digest = manifest.digest().to_string();
@@ -403,13 +968,101 @@ impl PyOci {
                 bail!("Image Manifest defines unexpected number of layers, was this package published by pyoci?");
             };
             let blob_digest = blob_descriptor.digest().to_string();
-            self.oci.delete_blob(&name, &blob_digest).await?;
+            if collect_garbage {
+                blobs.insert(blob_digest);
+                blobs.insert(manifest.config().digest().to_string());
+            } else {
+                self.oci.delete_blob(&name, &blob_digest).await?;
+            }
 
+            if !delete_by_digest {
+                continue;
+            }
             tracing::debug!("Deleting {name}:{digest}");
-            self.oci.delete_manifest(&name, &digest).await?;
+            match self.oci.delete_manifest(&name, &digest).await {
+                Ok(()) => {}
+                // Some registries only allow deleting by tag, the ImageManifests
+                // are left to the registry to clean up once the tag is removed.
+                Err(err)
+                    if err
+                        .downcast_ref::<PyOciError>()
+                        .is_some_and(|err| err.status == StatusCode::METHOD_NOT_ALLOWED) =>
+                {
+                    tracing::warn!("Registry does not support deleting by digest, deleting by tag");
+                    delete_by_digest = false;
+                }
+                Err(err) => return Err(err),
+            }
         }
         // Delete the tag/index itself
         self.oci.delete_manifest(&name, &tag).await?;
+        if collect_garbage {
+            self.collect_garbage(&name, blobs).await?;
+        }
+        Ok(())
+    }
+
+    /// Delete the pre-releases and developmental releases of a package
+    ///
+    /// Final releases and their post-releases are kept, see [`Version::is_prerelease`].
+    /// Returns the deleted versions.
+    pub async fn delete_prereleases(
+        &mut self,
+        package: &Package<'_, WithoutFileName>,
+        collect_garbage: bool,
+    ) -> Result<Vec<String>> {
+        let tags = self.list_tags(&package.oci_name()).await?;
+        let mut deleted = Vec::new();
+        for tag in tags {
+            if !Version::from_oci_tag(&tag).is_ok_and(|version| version.is_prerelease()) {
+                continue;
+            }
+            self.delete_package_version(&package.with_oci_file(&tag, ""), collect_garbage)
+                .await?;
+            deleted.push(oci_tag_to_version(&tag));
+        }
+        Ok(deleted)
+    }
+
+    /// Delete the `blobs` that are not referenced by any of the remaining package versions
+    async fn collect_garbage(&mut self, name: &str, mut blobs: BTreeSet<String>) -> Result<()> {
+        let tags = match self.oci.list_tags(name).await {
+            Ok(tags) => tags,
+            // The last tag was deleted, the repository no longer exists
+            Err(err)
+                if err
+                    .downcast_ref::<PyOciError>()
+                    .is_some_and(|err| err.status == StatusCode::NOT_FOUND) =>
+            {
+                BTreeSet::new()
+            }
+            Err(err) => return Err(err),
+        };
+        for tag in tags {
+            if blobs.is_empty() {
+                break;
+            }
+            let Some(Manifest::Index(index)) = self.oci.pull_manifest(name, &tag).await? else {
+                continue;
+            };
+            for descriptor in index.manifests() {
+                let Some(Manifest::Manifest(manifest)) = self
+                    .oci
+                    .pull_manifest(name, descriptor.digest().as_ref())
+                    .await?
+                else {
+                    continue;
+                };
+                blobs.remove(&manifest.config().digest().to_string());
+                for layer in manifest.layers() {
+                    blobs.remove(&layer.digest().to_string());
+                }
+            }
+        }
+        for digest in blobs {
+            tracing::debug!("Deleting unreferenced blob {name}:{digest}");
+            self.oci.delete_blob(name, &digest).await?;
+        }
         Ok(())
     }
 }
@@ -417,13 +1070,14 @@ impl PyOci {
 /// Get the definition of a new `ImageManifest`
 fn image_manifest(
     package: &Package<'_, WithFileName>,
+    config: &Blob,
     layer: &Blob,
     annotations: HashMap<String, String>,
+    media_type: &str,
 ) -> PlatformManifest {
-    let config = empty_config();
     let manifest = ImageManifestBuilder::default()
         .schema_version(SCHEMA_VERSION)
-        .media_type("application/vnd.oci.image.manifest.v1+json")
+        .media_type(media_type)
         .artifact_type(ARTIFACT_TYPE)
         .config(config.descriptor().clone())
         .layers(vec![layer.descriptor().clone()])
@@ -452,18 +1106,27 @@ fn verify_digest(layer: &Blob, expected_digest: Option<String>) -> Result<String
     Ok(package_digest.to_string())
 }
 
-/// static `EmptyConfig` Descriptor
-fn empty_config() -> Blob {
-    Blob::new("{}".into(), "application/vnd.oci.empty.v1+json")
+/// Config Blob of an `ImageManifest`, `EmptyConfig` with the default media type and content
+fn config_blob(media_type: &str, content: &str) -> Blob {
+    Blob::new(content.into(), media_type)
+}
+
+/// Check if the registry rejected a request because the `If-Match` or `If-None-Match`
+/// precondition failed
+fn is_precondition_failed(err: &Error) -> bool {
+    err.downcast_ref::<PyOciError>()
+        .is_some_and(|err| err.status == StatusCode::PRECONDITION_FAILED)
 }
 
 #[cfg(test)]
 mod tests {
-    use oci_spec::image::ImageManifest;
     use pretty_assertions::assert_eq;
     use serde_json::from_str;
+    use test_case::test_case;
 
     use super::*;
+    use crate::transport::MANIFEST_CONTENT_TYPE;
+    use crate::LAYER_MEDIA_TYPE;
 
     #[test]
     // Check if the digest is returned when no expected digest is provided
@@ -527,10 +1190,25 @@ mod tests {
             .with_body(index)
             .create_async()
             .await;
+        // The index lacks the sha256 annotation, the layer digest is used instead
+        let manifest_mock = server
+            .mock(
+                "GET",
+                "/v2/mockserver/bar/manifests/sha256:0d749abe1377573493e0df74df8d1282e46967754a1ebc7cc6323923a788ad5c",
+            )
+            .with_status(200)
+            .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+            .with_body(gc_manifest(
+                "sha256:b7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0",
+            ))
+            .create_async()
+            .await;
 
-        let pyoci = PyOci {
-            oci: Oci::new(Url::parse(&url).expect("valid url"), None),
-        };
+        let pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
 
         let package = Package::new("ghcr.io", "mockserver", "bar");
 
@@ -541,29 +1219,106 @@ mod tests {
 
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].py_uri(), "/ghcr.io/mockserver/bar/bar-1.tar.gz");
+        assert_eq!(
+            result[0].digests().get("sha256"),
+            Some(&"b7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0".to_string())
+        );
+        manifest_mock.assert_async().await;
     }
 
+    #[test_case(true, r#""artifactType": "application/vnd.other.package","#, r#", "platform": {"architecture": ".tar.gz", "os": "any"}"#, Some("Unknown artifact type: application/vnd.other.package"); "strict other type")]
+    #[test_case(true, "", r#", "platform": {"architecture": ".tar.gz", "os": "any"}"#, Some("No artifact type set"); "strict missing type")]
+    #[test_case(false, r#""artifactType": "application/vnd.other.package","#, r#", "platform": {"architecture": ".tar.gz", "os": "any"}"#, None; "lenient other type")]
+    #[test_case(false, "", r#", "platform": {"architecture": ".tar.gz", "os": "any"}"#, None; "lenient missing type")]
+    #[test_case(false, "", "", Some("No artifact type set"); "lenient missing platform")]
     #[tokio::test]
-    async fn package_info_for_ref_sha256_digest() {
+    async fn package_info_for_ref_artifact_type(
+        strict: bool,
+        artifact_type: &str,
+        platform: &str,
+        expected_err: Option<&str>,
+    ) {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
 
-        // Existing ImageIndex
-        let index = r#"{
+        let index = format!(
+            r#"{{
           "schemaVersion": 2,
           "mediaType": "application/vnd.oci.image.index.v1+json",
-          "artifactType": "application/pyoci.package.v1",
+          {artifact_type}
           "manifests": [
-            {
+            {{
               "mediaType": "application/vnd.oci.image.manifest.v1+json",
               "digest": "sha256:0d749abe1377573493e0df74df8d1282e46967754a1ebc7cc6323923a788ad5c",
-              "size": 6,
-              "platform": {
-                "architecture": ".tar.gz",
-                "os": "any"
-              },
-              "annotations":{
-                "com.pyoci.sha256_digest": "12345"
+              "size": 6{platform}
+            }}
+          ]
+        }}"#
+        );
+        server
+            .mock("GET", "/v2/mockserver/bar/manifests/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+            .with_body(index)
+            .create_async()
+            .await;
+        server
+            .mock(
+                "GET",
+                "/v2/mockserver/bar/manifests/sha256:0d749abe1377573493e0df74df8d1282e46967754a1ebc7cc6323923a788ad5c",
+            )
+            .with_status(200)
+            .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+            .with_body(gc_manifest(
+                "sha256:b7513fb69106a855b69153582dec476677b3c79f4a13cfee6fb7a356cfa754c0",
+            ))
+            .create_async()
+            .await;
+
+        let pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        )
+        .with_strict_artifact_type(strict);
+        let package = Package::new("ghcr.io", "mockserver", "bar");
+
+        let result = pyoci.package_info_for_ref(&package, "1").await;
+        match expected_err {
+            Some(expected) => {
+                let Err(err) = result else {
+                    panic!("Expected an error")
+                };
+                assert_eq!(err.to_string(), expected);
+            }
+            None => assert_eq!(
+                result.unwrap()[0].py_uri(),
+                "/ghcr.io/mockserver/bar/bar-1.tar.gz"
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn package_info_for_ref_sha256_digest() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        // Existing ImageIndex
+        let index = r#"{
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.index.v1+json",
+          "artifactType": "application/pyoci.package.v1",
+          "manifests": [
+            {
+              "mediaType": "application/vnd.oci.image.manifest.v1+json",
+              "digest": "sha256:0d749abe1377573493e0df74df8d1282e46967754a1ebc7cc6323923a788ad5c",
+              "size": 6,
+              "platform": {
+                "architecture": ".tar.gz",
+                "os": "any"
+              },
+              "annotations":{
+                "com.pyoci.sha256_digest": "12345"
               }
             }
           ],
@@ -579,9 +1334,11 @@ mod tests {
             .create_async()
             .await;
 
-        let pyoci = PyOci {
-            oci: Oci::new(Url::parse(&url).expect("valid url"), None),
-        };
+        let pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
 
         let package = Package::new("ghcr.io", "mockserver", "bar");
 
@@ -597,6 +1354,73 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    // Test if only annotations matching the prefixes are exposed
+    async fn package_info_for_ref_annotations() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let index = r#"{
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.index.v1+json",
+          "artifactType": "application/pyoci.package.v1",
+          "manifests": [
+            {
+              "mediaType": "application/vnd.oci.image.manifest.v1+json",
+              "digest": "sha256:0d749abe1377573493e0df74df8d1282e46967754a1ebc7cc6323923a788ad5c",
+              "size": 6,
+              "platform": {
+                "architecture": ".tar.gz",
+                "os": "any"
+              },
+              "annotations":{
+                "com.pyoci.sha256_digest": "12345",
+                "org.opencontainers.image.url": "https://github.com/allexveldman/pyoci",
+                "com.example.internal": "secret"
+              }
+            }
+          ]
+        }"#;
+        server
+            .mock("GET", "/v2/mockserver/bar/manifests/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+            .with_body(index)
+            .create_async()
+            .await;
+
+        let pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        )
+        // Internal annotations are excluded, even when they match a prefix
+        .with_annotation_prefixes(vec![
+            "org.opencontainers.".to_string(),
+            "com.pyoci.".to_string(),
+        ]);
+
+        let package = Package::new("ghcr.io", "mockserver", "bar");
+
+        let result = pyoci
+            .package_info_for_ref(&package, "1")
+            .await
+            .expect("Valid response");
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            result[0].digests().get("sha256"),
+            Some(&"12345".to_string())
+        );
+        assert_eq!(
+            result[0].annotations(),
+            &BTreeMap::from([(
+                "org.opencontainers.image.url".to_string(),
+                "https://github.com/allexveldman/pyoci".to_string()
+            )])
+        );
+    }
+
     #[test]
     fn image_manifest() {
         let package = Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz")
@@ -607,7 +1431,13 @@ mod tests {
             "test-annotation-value".to_string(),
         )]);
 
-        let result = super::image_manifest(&package, &layer, annotations.clone());
+        let result = super::image_manifest(
+            &package,
+            &config_blob(CONFIG_MEDIA_TYPE, CONFIG_CONTENT),
+            &layer,
+            annotations.clone(),
+            MANIFEST_CONTENT_TYPE,
+        );
         assert_eq!(
             result.manifest,
             from_str::<ImageManifest>(r#"{
@@ -646,9 +1476,11 @@ mod tests {
             .create_async()
             .await;
 
-        let mut pyoci = PyOci {
-            oci: Oci::new(Url::parse(&url).expect("valid url"), None),
-        };
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
 
         // Setup the objects we're publishing
         let package =
@@ -658,7 +1490,11 @@ mod tests {
             .schema_version(SCHEMA_VERSION)
             .media_type("application/vnd.oci.image.manifest.v1+json")
             .artifact_type(ARTIFACT_TYPE)
-            .config(empty_config().descriptor().to_owned())
+            .config(
+                config_blob(CONFIG_MEDIA_TYPE, CONFIG_CONTENT)
+                    .descriptor()
+                    .to_owned(),
+            )
             .layers(vec![layer.descriptor().to_owned()])
             .build()
             .expect("valid ImageManifest");
@@ -670,7 +1506,7 @@ mod tests {
         let index_manifest_annotations =
             HashMap::from([("idx-mani-key".to_string(), "idx-mani-val".to_string())]);
 
-        let result = pyoci
+        let (result, digest) = pyoci
             .image_index(
                 &package,
                 &manifest,
@@ -705,9 +1541,11 @@ mod tests {
               }
             }"#).unwrap()
         );
+        assert_eq!(digest, None);
     }
 
     #[tokio::test]
+    #[allow(clippy::too_many_lines)]
     // Test if we can update an existing ImageIndex
     async fn image_index_existing() {
         // PyOci.image_index() will reach out to see if there is an existing index
@@ -744,9 +1582,11 @@ mod tests {
             .create_async()
             .await;
 
-        let mut pyoci = PyOci {
-            oci: Oci::new(Url::parse(&url).expect("valid url"), None),
-        };
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
 
         // Setup the objects we're publishing
         let package =
@@ -756,7 +1596,11 @@ mod tests {
             .schema_version(SCHEMA_VERSION)
             .media_type("application/vnd.oci.image.manifest.v1+json")
             .artifact_type(ARTIFACT_TYPE)
-            .config(empty_config().descriptor().clone())
+            .config(
+                config_blob(CONFIG_MEDIA_TYPE, CONFIG_CONTENT)
+                    .descriptor()
+                    .clone(),
+            )
             .layers(vec![layer.descriptor().clone()])
             .build()
             .expect("valid ImageManifest");
@@ -769,7 +1613,7 @@ mod tests {
         let index_manifest_annotations =
             HashMap::from([("idx-mani-key".to_string(), "idx-mani-val".to_string())]);
 
-        let result = pyoci
+        let (result, digest) = pyoci
             .image_index(
                 &package,
                 &manifest,
@@ -813,6 +1657,7 @@ mod tests {
               }
             }"#).unwrap()
         );
+        assert_eq!(digest, Some(crate::oci::digest(index)));
     }
 
     #[tokio::test]
@@ -855,9 +1700,11 @@ mod tests {
             .create_async()
             .await;
 
-        let mut pyoci = PyOci {
-            oci: Oci::new(Url::parse(&url).expect("valid url"), None),
-        };
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
 
         // Setup the objects we're publishing
         let package =
@@ -867,7 +1714,11 @@ mod tests {
             .schema_version(SCHEMA_VERSION)
             .media_type("application/vnd.oci.image.manifest.v1+json")
             .artifact_type(ARTIFACT_TYPE)
-            .config(empty_config().descriptor().clone())
+            .config(
+                config_blob(CONFIG_MEDIA_TYPE, CONFIG_CONTENT)
+                    .descriptor()
+                    .clone(),
+            )
             .layers(vec![layer.descriptor().clone()])
             .build()
             .expect("valid ImageManifest");
@@ -886,4 +1737,1243 @@ mod tests {
             "Platform '.tar.gz' already exists for version '1'"
         );
     }
+
+    #[tokio::test]
+    // Test if the fallback tags are probed when the tag list is empty
+    async fn list_package_versions_tag_fallback() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mani1 = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/bar", "tags": []}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/latest")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(gc_index(mani1))
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/stable")
+                .with_status(404)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        )
+        .with_tag_fallback(vec!["latest".to_string(), "stable".to_string()]);
+        let package = Package::new("ghcr.io", "mockserver", "bar");
+        let result = pyoci
+            .list_package_versions(&package, 0, None)
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(result.versions, vec!["latest".to_string()]);
+    }
+
+    #[test_case(0, None, &["0.1.0", "1.0.0", "1.2.3"]; "all")]
+    #[test_case(0, Some(2), &["0.1.0", "1.0.0"]; "limit")]
+    #[test_case(1, Some(1), &["1.0.0"]; "offset and limit")]
+    #[test_case(2, None, &["1.2.3"]; "offset")]
+    #[test_case(5, Some(2), &[]; "past the end")]
+    #[tokio::test]
+    async fn list_package_versions_window(offset: usize, limit: Option<usize>, expected: &[&str]) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("GET", "/v2/mockserver/bar/tags/list")
+            .with_status(200)
+            .with_body(r#"{"name": "mockserver/bar", "tags": ["1.2.3", "0.1.0", "1.0.0"]}"#)
+            .create_async()
+            .await;
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package = Package::new("ghcr.io", "mockserver", "bar");
+        let result = pyoci
+            .list_package_versions(&package, offset, limit)
+            .await
+            .expect("Valid response");
+
+        mock.assert_async().await;
+        assert_eq!(
+            result,
+            PackageVersions {
+                versions: expected.iter().map(ToString::to_string).collect(),
+                total: 3,
+                latest: Some("1.2.3".to_string()),
+            }
+        );
+    }
+
+    #[test_case(0, None, &["1.2", "1.9", "1.10"]; "all")]
+    #[test_case(1, Some(1), &["1.9"]; "window")]
+    #[tokio::test]
+    // Test if the versions are ordered following PEP 440, not lexicographic
+    async fn list_package_versions_pep440(offset: usize, limit: Option<usize>, expected: &[&str]) {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("GET", "/v2/mockserver/bar/tags/list")
+            .with_status(200)
+            .with_body(r#"{"name": "mockserver/bar", "tags": ["1.10", "1.2", "1.9"]}"#)
+            .create_async()
+            .await;
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package = Package::new("ghcr.io", "mockserver", "bar");
+        let result = pyoci
+            .list_package_versions(&package, offset, limit)
+            .await
+            .expect("Valid response");
+
+        mock.assert_async().await;
+        assert_eq!(
+            result,
+            PackageVersions {
+                versions: expected.iter().map(ToString::to_string).collect(),
+                total: 3,
+                latest: Some("1.10".to_string()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    // Test if only aliases are skipped, other tags that are not a version are listed
+    async fn list_package_versions_alias() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list")
+                .with_status(200)
+                .with_body(
+                    r#"{"name": "mockserver/bar", "tags": ["1.0", "broken", "latest", "other"]}"#,
+                )
+                .create_async()
+                .await,
+            // Tags that can't be read are kept
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/broken")
+                .with_status(500)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/latest")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(platforms_index(&[".tar.gz"]))
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/other")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(
+                    r#"{
+                      "schemaVersion": 2,
+                      "mediaType": "application/vnd.oci.image.index.v1+json",
+                      "manifests": []
+                    }"#,
+                )
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package = Package::new("ghcr.io", "mockserver", "bar");
+        let result = pyoci
+            .list_package_versions(&package, 0, None)
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            result,
+            PackageVersions {
+                versions: vec!["broken".to_string(), "other".to_string(), "1.0".to_string()],
+                total: 3,
+                latest: Some("1.0".to_string()),
+            }
+        );
+    }
+
+    /// `ImageIndex` containing a manifest for each of the `architectures`
+    fn platforms_index(architectures: &[&str]) -> String {
+        let manifests = architectures
+            .iter()
+            .map(|architecture| {
+                format!(
+                    r#"{{
+                      "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                      "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+                      "size": 6,
+                      "platform": {{
+                        "architecture": "{architecture}",
+                        "os": "any"
+                      }}
+                    }}"#
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            r#"{{
+              "schemaVersion": 2,
+              "mediaType": "application/vnd.oci.image.index.v1+json",
+              "artifactType": "application/pyoci.package.v1",
+              "manifests": [{manifests}]
+            }}"#
+        )
+    }
+
+    #[tokio::test]
+    // Test if the index update is redone when the index changed while publishing
+    async fn publish_package_file_index_changed() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let index_before = platforms_index(&["-py3-none-any.whl"]);
+        let index_changed = platforms_index(&["-py3-none-any.whl", "-py2-none-any.whl"]);
+        let if_match = |index: &str| format!("\"{}\"", crate::oci::digest(index));
+
+        let mocks = vec![
+            // Index as it was when we started publishing
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(&index_before)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(200)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/manifests/sha256:.+".to_string()),
+                )
+                .with_status(201)
+                .create_async()
+                .await,
+            // Another publish changed the index in the meantime
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .match_header("If-Match", if_match(&index_before).as_str())
+                .with_status(412)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(&index_changed)
+                .create_async()
+                .await,
+            // Updated index keeps the concurrently published file
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .match_header("If-Match", if_match(&index_changed).as_str())
+                .match_body(mockito::Matcher::AllOf(vec![
+                    mockito::Matcher::Regex(r#""architecture":"-py2-none-any.whl""#.to_string()),
+                    mockito::Matcher::Regex(r#""architecture":".tar.gz""#.to_string()),
+                ]))
+                .with_status(201)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package =
+            Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz").unwrap();
+        pyoci
+            .publish_package_file(
+                &package,
+                b"\x1f\x8bcontent".to_vec().into(),
+                LAYER_MEDIA_TYPE,
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                vec![],
+                None,
+            )
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    // Test if the index is only created when no concurrent publish created it first
+    async fn publish_package_file_index_created() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let index_created = platforms_index(&["-py3-none-any.whl"]);
+        let if_match = format!("\"{}\"", crate::oci::digest(&index_created));
+
+        let mocks = vec![
+            // No index exists when we started publishing
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(200)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/manifests/sha256:.+".to_string()),
+                )
+                .with_status(201)
+                .create_async()
+                .await,
+            // Another publish created the index in the meantime
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .match_header("If-None-Match", "*")
+                .with_status(412)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(&index_created)
+                .create_async()
+                .await,
+            // Updated index keeps the concurrently published file
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .match_header("If-Match", if_match.as_str())
+                .match_header("If-None-Match", mockito::Matcher::Missing)
+                .match_body(mockito::Matcher::AllOf(vec![
+                    mockito::Matcher::Regex(r#""architecture":"-py3-none-any.whl""#.to_string()),
+                    mockito::Matcher::Regex(r#""architecture":".tar.gz""#.to_string()),
+                ]))
+                .with_status(201)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package =
+            Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz").unwrap();
+        pyoci
+            .publish_package_file(
+                &package,
+                b"\x1f\x8bcontent".to_vec().into(),
+                LAYER_MEDIA_TYPE,
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                vec![],
+                None,
+            )
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    // Test if a create-only publish fails when a concurrent publish created the same file
+    async fn publish_package_file_create_only_concurrent() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(200)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/manifests/sha256:.+".to_string()),
+                )
+                .with_status(201)
+                .create_async()
+                .await,
+            // Another publish created the same file in the meantime
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .match_header("If-None-Match", "*")
+                .with_status(412)
+                .expect(1)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(platforms_index(&[".tar.gz"]))
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        )
+        .with_create_only(true);
+        let package =
+            Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz").unwrap();
+        let err = pyoci
+            .publish_package_file(
+                &package,
+                b"\x1f\x8bcontent".to_vec().into(),
+                LAYER_MEDIA_TYPE,
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                vec![],
+                None,
+            )
+            .await
+            .expect_err("Existing file");
+        let err = err.downcast::<PyOciError>().expect("PyOciError");
+        assert_eq!(err.status, StatusCode::PRECONDITION_FAILED);
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn publish_package_file_project_url_annotations() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let source = r#""org.opencontainers.image.source":"https://github.com/allexveldman/pyoci""#;
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(200)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/manifests/sha256:.+".to_string()),
+                )
+                .match_body(mockito::Matcher::Regex(source.to_string()))
+                .with_status(201)
+                .create_async()
+                .await,
+            // The JSON blob is still present, unmapped URLs are not added as annotation
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .match_body(mockito::Matcher::AllOf(vec![
+                    mockito::Matcher::Regex(source.to_string()),
+                    mockito::Matcher::Regex(r#""com.pyoci.project_urls":"\{"#.to_string()),
+                    mockito::Matcher::Regex(r#"Documentation\\":\\"https://docs"#.to_string()),
+                ]))
+                .with_status(201)
+                .create_async()
+                .await,
+            server
+                .mock("PUT", mockito::Matcher::Any)
+                .match_body(mockito::Matcher::Regex(
+                    "org.opencontainers.image.url".to_string(),
+                ))
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        )
+        .with_project_url_annotations(vec![
+            (
+                "repository".to_string(),
+                "org.opencontainers.image.source".to_string(),
+            ),
+            (
+                "Homepage".to_string(),
+                "org.opencontainers.image.url".to_string(),
+            ),
+        ]);
+        let package =
+            Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz").unwrap();
+        pyoci
+            .publish_package_file(
+                &package,
+                b"\x1f\x8bcontent".to_vec().into(),
+                LAYER_MEDIA_TYPE,
+                HashMap::new(),
+                None,
+                HashMap::from([
+                    (
+                        "Repository".to_string(),
+                        "https://github.com/allexveldman/pyoci".to_string(),
+                    ),
+                    (
+                        "Documentation".to_string(),
+                        "https://docs.example".to_string(),
+                    ),
+                ]),
+                vec![],
+                None,
+            )
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn publish_package_file_config_media_type() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let config = r#""config":\{"mediaType":"application/vnd.oci.image.config.v1\+json","digest":"sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a","size":2\}"#;
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/blobs/.+".to_string()),
+                )
+                .expect(2)
+                .with_status(200)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/manifests/sha256:.+".to_string()),
+                )
+                .match_body(mockito::Matcher::Regex(config.to_string()))
+                .with_status(201)
+                .create_async()
+                .await,
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .with_status(201)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        )
+        .with_config_media_type("application/vnd.oci.image.config.v1+json".to_string());
+        let package =
+            Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz").unwrap();
+        pyoci
+            .publish_package_file(
+                &package,
+                b"\x1f\x8bcontent".to_vec().into(),
+                LAYER_MEDIA_TYPE,
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                vec![],
+                None,
+            )
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    // Test if the configured config content is pushed and referenced by the manifest
+    async fn publish_package_file_config_content() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let content = r#"{"architecture":"any","os":"any"}"#;
+        let config_digest = crate::oci::digest(content).to_string();
+        let layer_digest = crate::oci::digest(b"\x1f\x8bcontent").to_string();
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    format!("/v2/mockserver/bar/blobs/{layer_digest}").as_str(),
+                )
+                .with_status(200)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "HEAD",
+                    format!("/v2/mockserver/bar/blobs/{config_digest}").as_str(),
+                )
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock("POST", "/v2/mockserver/bar/blobs/uploads/")
+                .with_status(202)
+                .with_header("Location", "/v2/mockserver/bar/blobs/uploads/1")
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    format!(
+                        "/v2/mockserver/bar/blobs/uploads/1?digest={}",
+                        urlencoding::encode(&config_digest)
+                    )
+                    .as_str(),
+                )
+                .match_body(content)
+                .with_status(201)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "PUT",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/manifests/sha256:.+".to_string()),
+                )
+                .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                    "config": {
+                        "mediaType": "application/vnd.oci.image.config.v1+json",
+                        "digest": config_digest,
+                        "size": content.len(),
+                    }
+                })))
+                .with_status(201)
+                .create_async()
+                .await,
+            server
+                .mock("PUT", "/v2/mockserver/bar/manifests/1")
+                .with_status(201)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        )
+        .with_config_media_type("application/vnd.oci.image.config.v1+json".to_string())
+        .with_config_content(content.to_string());
+        let package =
+            Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz").unwrap();
+        pyoci
+            .publish_package_file(
+                &package,
+                b"\x1f\x8bcontent".to_vec().into(),
+                LAYER_MEDIA_TYPE,
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                vec![],
+                None,
+            )
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    // Test if a package published with an image config can be downloaded
+    async fn download_package_file_config_media_type() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mani = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+        // sha256 of "foo"
+        let layer = "sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+        let manifest = gc_manifest(layer).replace(
+            "application/vnd.oci.empty.v1+json",
+            "application/vnd.oci.image.config.v1+json",
+        );
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(gc_index(mani))
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    format!("/v2/mockserver/bar/manifests/{mani}").as_str(),
+                )
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(manifest)
+                .create_async()
+                .await,
+            server
+                .mock("GET", format!("/v2/mockserver/bar/blobs/{layer}").as_str())
+                .with_status(200)
+                .with_body("foo")
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package =
+            Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz").unwrap();
+        let (response, _) = pyoci.download_package_file(&package).await.unwrap();
+
+        assert_eq!(response.bytes().await.unwrap(), "foo");
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    // Test if redundant descriptors are listed once, newest version first
+    async fn list_package_files_duplicate_descriptor() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let descriptor = serde_json::json!({
+          "mediaType": "application/vnd.oci.image.manifest.v1+json",
+          "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+          "size": 6,
+          "platform": {"architecture": ".tar.gz", "os": "any"},
+          "annotations": {"com.pyoci.sha256_digest": "1234"}
+        });
+        let index = |manifests: Vec<&serde_json::Value>| {
+            serde_json::json!({
+              "schemaVersion": 2,
+              "mediaType": "application/vnd.oci.image.index.v1+json",
+              "artifactType": "application/pyoci.package.v1",
+              "manifests": manifests
+            })
+            .to_string()
+        };
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/bar", "tags": ["1.9.0", "1.10.0"]}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1.10.0")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(index(vec![&descriptor, &descriptor]))
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1.9.0")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(index(vec![&descriptor]))
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package = Package::new("ghcr.io", "mockserver", "bar");
+        let files = pyoci.list_package_files(&package, 0).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            files.iter().map(Package::filename).collect::<Vec<_>>(),
+            ["bar-1.10.0.tar.gz", "bar-1.9.0.tar.gz"]
+        );
+    }
+
+    #[tokio::test]
+    // Test if the `tag_fallback` tags are listed when the tag list is empty
+    async fn list_package_files_tag_fallback() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mani1 = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+        let layer1 = "sha256:2222222222222222222222222222222222222222222222222222222222222222";
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name": "mockserver/bar", "tags": []}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/latest")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(gc_index(mani1))
+                .expect(2)
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    format!("/v2/mockserver/bar/manifests/{mani1}").as_str(),
+                )
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(gc_manifest(layer1))
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        )
+        .with_tag_fallback(vec!["latest".to_string()]);
+        let package = Package::new("ghcr.io", "mockserver", "bar");
+        let files = pyoci.list_package_files(&package, 0).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(
+            files.iter().map(Package::filename).collect::<Vec<_>>(),
+            ["bar-latest.tar.gz"]
+        );
+    }
+
+    /// `ImageIndex` containing a single `.tar.gz` manifest
+    fn gc_index(manifest_digest: &str) -> String {
+        format!(
+            r#"{{
+              "schemaVersion": 2,
+              "mediaType": "application/vnd.oci.image.index.v1+json",
+              "artifactType": "application/pyoci.package.v1",
+              "manifests": [
+                {{
+                  "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                  "digest": "{manifest_digest}",
+                  "size": 6,
+                  "platform": {{
+                    "architecture": ".tar.gz",
+                    "os": "any"
+                  }}
+                }}
+              ]
+            }}"#
+        )
+    }
+
+    /// `ImageManifest` with the empty config and a single layer
+    fn gc_manifest(layer_digest: &str) -> String {
+        format!(
+            r#"{{
+              "schemaVersion": 2,
+              "mediaType": "application/vnd.oci.image.manifest.v1+json",
+              "artifactType": "application/pyoci.package.v1",
+              "config": {{
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+                "size": 2
+              }},
+              "layers": [
+                {{
+                  "mediaType": "application/pyoci.package.v1",
+                  "digest": "{layer_digest}",
+                  "size": 3
+                }}
+              ]
+            }}"#
+        )
+    }
+
+    #[tokio::test]
+    // Test if blobs referenced by another version are not deleted
+    async fn delete_package_version_gc_referenced() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mani1 = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+        let mani2 = "sha256:2222222222222222222222222222222222222222222222222222222222222222";
+        let layer = "sha256:3333333333333333333333333333333333333333333333333333333333333333";
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(gc_index(mani1))
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    format!("/v2/mockserver/bar/manifests/{mani1}").as_str(),
+                )
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(gc_manifest(layer))
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "DELETE",
+                    format!("/v2/mockserver/bar/manifests/{mani1}").as_str(),
+                )
+                .with_status(202)
+                .create_async()
+                .await,
+            server
+                .mock("DELETE", "/v2/mockserver/bar/manifests/1")
+                .with_status(202)
+                .create_async()
+                .await,
+            // Version 2 remains and references the same layer
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list")
+                .with_status(200)
+                .with_body(r#"{"name":"mockserver/bar","tags":["2"]}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/2")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(gc_index(mani2))
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    format!("/v2/mockserver/bar/manifests/{mani2}").as_str(),
+                )
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(gc_manifest(layer))
+                .create_async()
+                .await,
+            // Neither the layer nor the config blob should be deleted
+            server
+                .mock(
+                    "DELETE",
+                    mockito::Matcher::Regex(r"/v2/mockserver/bar/blobs/.+".to_string()),
+                )
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package = Package::new("ghcr.io", "mockserver", "bar").with_oci_file("1", "");
+        pyoci
+            .delete_package_version(&package, true)
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    // Test if blobs are deleted when the last version is removed
+    async fn delete_package_version_gc_unreferenced() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mani1 = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+        let layer = "sha256:3333333333333333333333333333333333333333333333333333333333333333";
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(gc_index(mani1))
+                .create_async()
+                .await,
+            server
+                .mock("GET", format!("/v2/mockserver/bar/manifests/{mani1}").as_str())
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(gc_manifest(layer))
+                .create_async()
+                .await,
+            server
+                .mock("DELETE", format!("/v2/mockserver/bar/manifests/{mani1}").as_str())
+                .with_status(202)
+                .create_async()
+                .await,
+            server
+                .mock("DELETE", "/v2/mockserver/bar/manifests/1")
+                .with_status(202)
+                .create_async()
+                .await,
+            // Repository no longer exists
+            server
+                .mock("GET", "/v2/mockserver/bar/tags/list")
+                .with_status(404)
+                .create_async()
+                .await,
+            server
+                .mock("DELETE", format!("/v2/mockserver/bar/blobs/{layer}").as_str())
+                .with_status(202)
+                .create_async()
+                .await,
+            server
+                .mock("DELETE", "/v2/mockserver/bar/blobs/sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a")
+                .with_status(202)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package = Package::new("ghcr.io", "mockserver", "bar").with_oci_file("1", "");
+        pyoci
+            .delete_package_version(&package, true)
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    // Test if an index wrapping the PyOCI index is not modified by a publish or delete
+    async fn nested_index_not_modified() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let wrapper = r#"{
+          "schemaVersion": 2,
+          "mediaType": "application/vnd.oci.image.index.v1+json",
+          "manifests": [
+            {
+              "mediaType": "application/vnd.oci.image.index.v1+json",
+              "digest": "sha256:2222222222222222222222222222222222222222222222222222222222222222",
+              "size": 6
+            }
+          ]
+        }"#;
+        let mut mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(wrapper)
+                .expect(2)
+                .create_async()
+                .await,
+        ];
+        for method in ["HEAD", "POST", "PUT", "DELETE"] {
+            mocks.push(
+                server
+                    .mock(method, mockito::Matcher::Any)
+                    .expect(0)
+                    .create_async()
+                    .await,
+            );
+        }
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package =
+            Package::from_filename("ghcr.io", "mockserver", "bar", "bar-1.tar.gz").unwrap();
+        pyoci
+            .publish_package_file(
+                &package,
+                b"\x1f\x8bcontent".to_vec().into(),
+                LAYER_MEDIA_TYPE,
+                HashMap::new(),
+                None,
+                HashMap::new(),
+                vec![],
+                None,
+            )
+            .await
+            .expect_err("Expected an Err");
+        pyoci
+            .delete_package_version(&package, false)
+            .await
+            .expect_err("Expected an Err");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    // Test if a registry without digest deletes falls back to deleting the tag
+    async fn delete_package_version_by_tag() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mani1 = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+        let layer = "sha256:3333333333333333333333333333333333333333333333333333333333333333";
+
+        let mocks = vec![
+            server
+                .mock("GET", "/v2/mockserver/bar/manifests/1")
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.index.v1+json")
+                .with_body(gc_index(mani1))
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    format!("/v2/mockserver/bar/manifests/{mani1}").as_str(),
+                )
+                .with_status(200)
+                .with_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .with_body(gc_manifest(layer))
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "DELETE",
+                    format!("/v2/mockserver/bar/blobs/{layer}").as_str(),
+                )
+                .with_status(202)
+                .create_async()
+                .await,
+            // Digest deletes are not supported
+            server
+                .mock(
+                    "DELETE",
+                    format!("/v2/mockserver/bar/manifests/{mani1}").as_str(),
+                )
+                .with_status(405)
+                .create_async()
+                .await,
+            server
+                .mock("DELETE", "/v2/mockserver/bar/manifests/1")
+                .with_status(202)
+                .create_async()
+                .await,
+        ];
+
+        let mut pyoci = PyOci::new(
+            Url::parse(&url).expect("valid url"),
+            None,
+            HttpClient::default(),
+        );
+        let package = Package::new("ghcr.io", "mockserver", "bar").with_oci_file("1", "");
+        pyoci
+            .delete_package_version(&package, false)
+            .await
+            .expect("Valid response");
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
 }
@@ -0,0 +1,609 @@
+//! In-memory OCI registry
+//!
+//! Implements the subset of the OCI distribution specification used by `PyOCI`,
+//! intended for integration tests and local development.
+//! Nothing is persisted, all content is lost when the process stops.
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, RawQuery, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::any,
+    Json, Router,
+};
+use serde_json::json;
+
+use crate::oci::digest;
+
+/// Content of a single repository
+#[derive(Debug, Default)]
+struct Repository {
+    /// Blobs by digest
+    blobs: HashMap<String, Bytes>,
+    /// (media type, content) of manifests by digest
+    manifests: HashMap<String, (String, Bytes)>,
+    /// Manifest digests by tag
+    tags: BTreeMap<String, String>,
+}
+
+impl Repository {
+    /// Resolve a tag or digest to a manifest digest
+    fn resolve(&self, reference: &str) -> Option<String> {
+        if self.manifests.contains_key(reference) {
+            return Some(reference.to_string());
+        }
+        self.tags.get(reference).cloned()
+    }
+}
+
+type Repositories = Arc<RwLock<HashMap<String, Repository>>>;
+
+/// Router serving the in-memory registry
+pub fn router() -> Router {
+    Router::new()
+        .route("/v2/", any(|| async { StatusCode::OK }))
+        .route("/v2/{*path}", any(handle))
+        .with_state(Repositories::default())
+}
+
+/// Route requests based on the path suffix, the repository name can contain any number of "/"
+async fn handle(
+    State(repositories): State<Repositories>,
+    method: Method,
+    Path(path): Path<String>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut repositories = repositories.write().expect("Failed to lock repositories");
+
+    if let Some(name) = path.strip_suffix("/tags/list") {
+        tags(&repositories, &method, name)
+    } else if let Some((name, reference)) = path.rsplit_once("/manifests/") {
+        manifest(&mut repositories, &method, name, reference, &headers, body)
+    } else if let Some((name, _)) = path.rsplit_once("/blobs/uploads") {
+        upload(&mut repositories, &method, name, query.as_deref(), body)
+    } else if let Some((name, blob_digest)) = path.rsplit_once("/blobs/") {
+        blob(&mut repositories, &method, name, blob_digest)
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+/// `/v2/<name>/tags/list`
+fn tags(repositories: &HashMap<String, Repository>, method: &Method, name: &str) -> Response {
+    match (method, repositories.get(name)) {
+        (&Method::GET, Some(repository)) => {
+            Json(json!({"name": name, "tags": repository.tags.keys().collect::<Vec<_>>()}))
+                .into_response()
+        }
+        (&Method::GET, None) => StatusCode::NOT_FOUND.into_response(),
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+/// `/v2/<name>/manifests/<reference>`
+fn manifest(
+    repositories: &mut HashMap<String, Repository>,
+    method: &Method,
+    name: &str,
+    reference: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response {
+    match *method {
+        Method::GET | Method::HEAD => {
+            let Some(repository) = repositories.get(name) else {
+                return StatusCode::NOT_FOUND.into_response();
+            };
+            let Some((media_type, content)) = repository
+                .resolve(reference)
+                .and_then(|digest| repository.manifests.get(&digest))
+            else {
+                return StatusCode::NOT_FOUND.into_response();
+            };
+            (
+                [(header::CONTENT_TYPE, media_type.clone())],
+                content.clone(),
+            )
+                .into_response()
+        }
+        Method::PUT => {
+            let media_type = headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let manifest_digest = digest(&body).to_string();
+            let repository = repositories.entry(name.to_string()).or_default();
+            repository
+                .manifests
+                .insert(manifest_digest.clone(), (media_type, body));
+            if reference != manifest_digest {
+                repository
+                    .tags
+                    .insert(reference.to_string(), manifest_digest.clone());
+            }
+            (
+                StatusCode::CREATED,
+                [(
+                    header::LOCATION,
+                    format!("/v2/{name}/manifests/{manifest_digest}"),
+                )],
+            )
+                .into_response()
+        }
+        Method::DELETE => {
+            let Some(repository) = repositories.get_mut(name) else {
+                return StatusCode::NOT_FOUND.into_response();
+            };
+            if repository.tags.remove(reference).is_some()
+                || repository.manifests.remove(reference).is_some()
+            {
+                StatusCode::ACCEPTED.into_response()
+            } else {
+                StatusCode::NOT_FOUND.into_response()
+            }
+        }
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+/// `/v2/<name>/blobs/uploads/`
+///
+/// Content is only accepted in a single PUT, so upload sessions are not tracked.
+fn upload(
+    repositories: &mut HashMap<String, Repository>,
+    method: &Method,
+    name: &str,
+    query: Option<&str>,
+    body: Bytes,
+) -> Response {
+    match *method {
+        Method::POST => (
+            StatusCode::ACCEPTED,
+            [(header::LOCATION, format!("/v2/{name}/blobs/uploads/upload"))],
+        )
+            .into_response(),
+        Method::PUT => {
+            let Some(expected) = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+                .find_map(|(key, value)| (key == "digest").then_some(value))
+            else {
+                return (StatusCode::BAD_REQUEST, "Missing digest").into_response();
+            };
+            let blob_digest = digest(&body).to_string();
+            if blob_digest != expected {
+                return (StatusCode::BAD_REQUEST, "Digest does not match content").into_response();
+            }
+            repositories
+                .entry(name.to_string())
+                .or_default()
+                .blobs
+                .insert(blob_digest.clone(), body);
+            (
+                StatusCode::CREATED,
+                [(header::LOCATION, format!("/v2/{name}/blobs/{blob_digest}"))],
+            )
+                .into_response()
+        }
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+/// `/v2/<name>/blobs/<digest>`
+fn blob(
+    repositories: &mut HashMap<String, Repository>,
+    method: &Method,
+    name: &str,
+    blob_digest: &str,
+) -> Response {
+    let Some(repository) = repositories.get_mut(name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match (method, repository.blobs.get(blob_digest)) {
+        (&Method::GET | &Method::HEAD, Some(blob)) => blob.clone().into_response(),
+        (&Method::DELETE, Some(_)) => {
+            repository.blobs.remove(blob_digest);
+            StatusCode::ACCEPTED.into_response()
+        }
+        (&Method::GET | &Method::HEAD | &Method::DELETE, None) => {
+            StatusCode::NOT_FOUND.into_response()
+        }
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::pyoci_service;
+    use crate::Env;
+
+    use axum::body::{to_bytes, Body};
+    use axum::extract::Request;
+    use axum::middleware::Next;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+    use test_case::test_case;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    /// Serve the in-memory registry on a random port, returning the URL-encoded registry
+    async fn serve() -> String {
+        serve_router(router()).await
+    }
+
+    /// Serve `router` on a random port, returning the URL-encoded registry
+    async fn serve_router(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        urlencoding::encode(&url).into_owned()
+    }
+
+    async fn send(request: Request) -> (StatusCode, Bytes) {
+        let env = Env::default();
+        let response = pyoci_service(&env).oneshot(request).await.unwrap();
+        let status = response.status();
+        (
+            status,
+            to_bytes(response.into_body(), usize::MAX).await.unwrap(),
+        )
+    }
+
+    /// Request to publish hello-world `version` to `namespace`
+    fn publish_request(registry: &str, namespace: &str, version: &str) -> Request {
+        let mut form = format!(
+            "--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            hello-world\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"content\"; filename=\"hello_world-{version}.tar.gz\"\r\n\
+            \r\n"
+        )
+        .into_bytes();
+        form.extend_from_slice(b"\x1f\x8bsomeawesomepackagedata\r\n--foobar--\r\n");
+        Request::builder()
+            .method("POST")
+            .uri(format!("/{registry}/{namespace}/"))
+            .header("Content-Type", "multipart/form-data; boundary=foobar")
+            .body(Body::from(form))
+            .unwrap()
+    }
+
+    /// Publish hello-world `version` through the `PyOCI` handlers
+    async fn publish(registry: &str, version: &str) {
+        let (status, body) = send(publish_request(registry, "allexveldman", version)).await;
+        assert_eq!(status, StatusCode::OK, "{body:?}");
+    }
+
+    /// Publish, list, download and delete a package through the `PyOCI` handlers
+    #[tokio::test]
+    async fn end_to_end() {
+        let registry = serve().await;
+        publish(&registry, "1.0.0").await;
+
+        let (status, body) = send(
+            Request::builder()
+                .uri(format!("/{registry}/allexveldman/hello-world/json"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["releases"], json!({"1.0.0": []}));
+        assert_eq!(body["urls"][0]["filename"], "hello_world-1.0.0.tar.gz");
+
+        let (status, body) = send(
+            Request::builder()
+                .uri(format!(
+                    "/{registry}/allexveldman/hello-world/hello_world-1.0.0.tar.gz"
+                ))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, &b"\x1f\x8bsomeawesomepackagedata"[..]);
+
+        let (status, _) = send(
+            Request::builder()
+                .method("DELETE")
+                .uri(format!("/{registry}/allexveldman/hello-world/1.0.0"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(
+            Request::builder()
+                .uri(format!("/{registry}/allexveldman/hello-world/json"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["releases"], json!({}));
+    }
+
+    /// An alias points at the same `ImageIndex` as the version and is not listed as a version
+    #[tokio::test]
+    async fn tag_alias() {
+        let registry = serve().await;
+        publish(&registry, "1.0.0").await;
+
+        let (status, body) = send(
+            Request::builder()
+                .method("POST")
+                .uri(format!(
+                    "/{registry}/allexveldman/hello-world/1.0.0/tag/latest"
+                ))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body:?}");
+
+        let registry_url = urlencoding::decode(&registry).unwrap().into_owned();
+        let manifest = |reference: &str| {
+            reqwest::get(format!(
+                "{registry_url}/v2/allexveldman/hello_world/manifests/{reference}"
+            ))
+        };
+        let version = manifest("1.0.0").await.unwrap().bytes().await.unwrap();
+        let alias = manifest("latest").await.unwrap().bytes().await.unwrap();
+        assert_eq!(alias, version);
+
+        let (status, body) = send(
+            Request::builder()
+                .uri(format!("/{registry}/allexveldman/hello-world/json"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["releases"], json!({"1.0.0": []}));
+    }
+
+    /// Manifest fetches of concurrent listings share the global limit
+    #[tokio::test]
+    async fn global_upstream_concurrency() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let counter = {
+            let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+            axum::middleware::from_fn(move |request: Request, next: Next| {
+                let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+                async move {
+                    if request.method() != Method::GET
+                        || !request.uri().path().contains("/manifests/")
+                    {
+                        return next.run(request).await;
+                    }
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    // Keep the fetch in flight long enough to overlap with the others
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    let response = next.run(request).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    response
+                }
+            })
+        };
+        let registry = serve_router(router().layer(counter)).await;
+        for version in ["1.0.0", "1.1.0", "1.2.0"] {
+            publish(&registry, version).await;
+        }
+        max_in_flight.store(0, Ordering::SeqCst);
+
+        let env = Env {
+            upstream_concurrency: 2,
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+        let list = || {
+            service.clone().oneshot(
+                Request::builder()
+                    .uri(format!("/{registry}/allexveldman/hello-world/"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        let (first, second) = tokio::join!(list(), list());
+
+        assert_eq!(first.unwrap().status(), StatusCode::OK);
+        assert_eq!(second.unwrap().status(), StatusCode::OK);
+        // 2 listings of 3 versions, never more than 2 fetches at the same time
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    /// Each file of a multi-file publish is verified against its own `sha256_digest`
+    #[tokio::test]
+    async fn publish_multiple_files_digest() {
+        let registry = serve().await;
+
+        let mut form = b"--foobar\r\n\
+            Content-Disposition: form-data; name=\":action\"\r\n\
+            \r\n\
+            file_upload\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"protocol_version\"\r\n\
+            \r\n\
+            1\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\
+            \r\n\
+            hello-world\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"sha256_digest\"\r\n\
+            \r\n\
+            7244ca446253b2193b5eb95510df109d42b5d40da8e64c6609e66facc397a8dc\r\n\
+            --foobar\r\n\
+            Content-Disposition: form-data; name=\"sha256_digest\"\r\n\
+            \r\n\
+            1111111111111111111111111111111111111111111111111111111111111111\r\n"
+            .to_vec();
+        for version in ["1.0.0", "2.0.0"] {
+            form.extend_from_slice(
+                format!(
+                    "--foobar\r\n\
+                    Content-Disposition: form-data; name=\"content\"; filename=\"hello_world-{version}.tar.gz\"\r\n\
+                    \r\n"
+                )
+                .as_bytes(),
+            );
+            form.extend_from_slice(b"\x1f\x8bsomeawesomepackagedata\r\n");
+        }
+        form.extend_from_slice(b"--foobar--\r\n");
+        let (status, body) = send(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/{registry}/allexveldman/"))
+                .header("Content-Type", "multipart/form-data; boundary=foobar")
+                .body(Body::from(form))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body,
+            &b"Provided sha256_digest does not match the package content"[..]
+        );
+
+        // Only the file with the matching digest is published
+        let (status, body) = send(
+            Request::builder()
+                .uri(format!("/{registry}/allexveldman/hello-world/json"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["releases"], json!({"1.0.0": []}));
+    }
+
+    /// Publishes beyond the limit of a namespace are rejected, other namespaces proceed
+    #[tokio::test]
+    async fn namespace_publish_concurrency() {
+        let started = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let stalled = Arc::new(AtomicBool::new(false));
+        let stall = {
+            let (started, release, stalled) = (started.clone(), release.clone(), stalled.clone());
+            axum::middleware::from_fn(move |request: Request, next: Next| {
+                let (started, release, stalled) =
+                    (started.clone(), release.clone(), stalled.clone());
+                async move {
+                    // Keep the first publish to team-a in flight until released
+                    if request.uri().path().contains("/team-a/")
+                        && !stalled.swap(true, Ordering::SeqCst)
+                    {
+                        started.notify_one();
+                        release.notified().await;
+                    }
+                    next.run(request).await
+                }
+            })
+        };
+        let registry = serve_router(router().layer(stall)).await;
+
+        let env = Env {
+            publish_concurrency: 1,
+            ..Env::default()
+        };
+        let service = pyoci_service(&env);
+        let first = service
+            .clone()
+            .oneshot(publish_request(&registry, "team-a", "1.0.0"));
+        let others = async {
+            started.notified().await;
+            let second = service
+                .clone()
+                .oneshot(publish_request(&registry, "team-a", "1.1.0"))
+                .await
+                .unwrap();
+            let other = service
+                .clone()
+                .oneshot(publish_request(&registry, "team-b", "1.0.0"))
+                .await
+                .unwrap();
+            release.notify_one();
+            (second, other)
+        };
+        let (first, (second, other)) = tokio::join!(first, others);
+        let first = first.unwrap();
+
+        assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(other.status(), StatusCode::OK);
+        assert_eq!(first.status(), StatusCode::OK);
+        // The permit is given back once the publish completed
+        let third = service
+            .oneshot(publish_request(&registry, "team-a", "1.2.0"))
+            .await
+            .unwrap();
+        assert_eq!(third.status(), StatusCode::OK);
+    }
+
+    /// With `verify_namespace`, a missing repository is published to, a forbidden one is
+    /// rejected before anything is pushed
+    #[test_case(StatusCode::NOT_FOUND, StatusCode::OK, 2; "missing repository")]
+    #[test_case(StatusCode::FORBIDDEN, StatusCode::FORBIDDEN, 0; "forbidden")]
+    #[tokio::test]
+    async fn verify_namespace(
+        tag_list: StatusCode,
+        expected: StatusCode,
+        expected_manifests: usize,
+    ) {
+        let manifests = Arc::new(AtomicUsize::new(0));
+        let check = {
+            let manifests = manifests.clone();
+            axum::middleware::from_fn(move |request: Request, next: Next| {
+                let manifests = manifests.clone();
+                async move {
+                    let path = request.uri().path();
+                    if path.ends_with("/tags/list") {
+                        return tag_list.into_response();
+                    }
+                    if request.method() == Method::PUT && path.contains("/manifests/") {
+                        manifests.fetch_add(1, Ordering::SeqCst);
+                    }
+                    next.run(request).await
+                }
+            })
+        };
+        let registry = serve_router(router().layer(check)).await;
+
+        let env = Env {
+            verify_namespace: true,
+            ..Env::default()
+        };
+        let response = pyoci_service(&env)
+            .oneshot(publish_request(&registry, "team-a", "1.0.0"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), expected);
+        // The ImageManifest and ImageIndex are only pushed when the check passes
+        assert_eq!(manifests.load(Ordering::SeqCst), expected_manifests);
+    }
+}
@@ -7,16 +7,18 @@ use http::{HeaderValue, StatusCode};
 use pin_project::pin_project;
 use serde::Deserialize;
 use std::future::Future;
+use std::path::PathBuf;
 use std::pin::Pin;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::task::{ready, Context, Poll};
+use std::time::SystemTime;
 use tower::{Layer, Service};
 use url::Url;
 
 use crate::error::PyOciError;
 
 /// Authorization header that can be either Basic or Bearer
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AuthHeader {
     Basic(Authorization<Basic>),
     Bearer(Authorization<Bearer>),
@@ -128,6 +130,55 @@ impl AuthLayer {
             Some(auth) => Self::from(auth),
         }
     }
+
+    /// Replace the Bearer token used by the services of this layer
+    pub fn set_bearer(&self, bearer: Authorization<Bearer>) {
+        *self.bearer.write().expect("Failed to get write lock") = Some(bearer);
+    }
+}
+
+/// Modification time of a token file and the token read from it
+type CachedToken = (SystemTime, Authorization<Bearer>);
+
+/// Bearer token read from a file
+///
+/// The file is read again when it was modified, so a rotated token, like a projected
+/// Kubernetes service account token, is picked up without a restart.
+#[derive(Debug, Clone)]
+pub struct TokenFile {
+    path: PathBuf,
+    // Shared between requests, so the file is only read again when it was modified
+    cached: Arc<Mutex<Option<CachedToken>>>,
+}
+
+impl TokenFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Bearer token in the file, ignoring surrounding whitespace
+    pub async fn token(&self) -> Result<Authorization<Bearer>> {
+        let path = self.path.display();
+        let modified = tokio::fs::metadata(&self.path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Failed to read token file '{path}'"))?;
+        if let Some((cached_at, token)) = &*self.cached.lock().expect("Failed to get lock") {
+            if *cached_at == modified {
+                return Ok(token.clone());
+            }
+        }
+        let token = tokio::fs::read_to_string(&self.path)
+            .await
+            .with_context(|| format!("Failed to read token file '{path}'"))?;
+        let token = Authorization::bearer(token.trim())
+            .with_context(|| format!("Invalid Bearer token in '{path}'"))?;
+        *self.cached.lock().expect("Failed to get lock") = Some((modified, token.clone()));
+        Ok(token)
+    }
 }
 
 impl From<AuthHeader> for AuthLayer {
@@ -139,11 +190,11 @@ impl From<AuthHeader> for AuthLayer {
         match auth {
             AuthHeader::Basic(basic) => Self {
                 basic: Some(basic),
-                bearer: Arc::default(),
+                ..Self::default()
             },
             AuthHeader::Bearer(bearer) => Self {
-                basic: None,
                 bearer: Arc::new(RwLock::new(Some(bearer))),
+                ..Self::default()
             },
         }
     }
@@ -205,6 +256,13 @@ where
     }
 }
 
+/// Times a single request is authenticated
+///
+/// A token can still be rejected, for example when the registry expects another audience or scope
+/// than it advertised at first. The request is then authenticated once more, using the
+/// `WWW-Authenticate` header of the second 401.
+const MAX_AUTHENTICATIONS: u8 = 2;
+
 /// The Future returned by `AuthService`
 /// Implements the actual authentication logic
 #[pin_project]
@@ -214,6 +272,8 @@ where
 {
     // Clone of the original request to retry after authentication
     request: Option<Req>,
+    // Number of times the request was authenticated
+    authentications: u8,
     // Clone of the original service, used to do the authentication request and retry
     // the original request
     auth: AuthService<S>,
@@ -244,6 +304,7 @@ where
     fn new(request: Option<Req>, inner: AuthService<S>, future: S::Future) -> Self {
         Self {
             request,
+            authentications: 0,
             auth: inner,
             state: AuthState::Called { future },
         }
@@ -288,10 +349,11 @@ where
                         .take()
                         .is_some()
                         && basic_token.is_none()
+                        && *this.authentications == 0
                     {
                         // If we don't also have a basic token it means we either got a
                         // bearer token to begin with, or got a bearer token from an anonymous
-                        // exchange with the wrong scope. Either way there is nothing more to do.
+                        // exchange in an earlier request. Either way there is nothing more to do.
                         return Poll::Ready(Ok(response));
                     }
 
@@ -330,12 +392,17 @@ where
                 // Polling authentication request
                 AuthStateProj::Authenticating { future } => match ready!(future.poll(cx)) {
                     Ok(bearer_token) => {
-                        // Take the original request, this prevents infinitely retrying if the
-                        // server keeps returning 401
-                        let mut request = this
-                            .request
-                            .take()
-                            .ok_or_else(|| anyhow!("Tried to retry twice after authentication"))?;
+                        *this.authentications += 1;
+                        // Take the original request on the last authentication, this prevents
+                        // infinitely retrying if the server keeps returning 401
+                        let request = if *this.authentications < MAX_AUTHENTICATIONS {
+                            this.request.as_ref().and_then(reqwest::Request::try_clone)
+                        } else {
+                            this.request.take()
+                        };
+                        let mut request = request.ok_or_else(|| {
+                            anyhow!("Tried to retry too often after authentication")
+                        })?;
                         // Insert the new bearer token into the original request
                         request.headers_mut().typed_insert(bearer_token.clone());
                         // Store the bearer token for later use
@@ -415,8 +482,8 @@ where
 
     let body = response.text().await?;
     let auth = serde_json::from_str::<AuthResponse>(&body).map_err(|err| {
+        // Don't log the body, it can contain the token
         tracing::info!("Failed to parse AuthResponse");
-        tracing::debug!(body);
         PyOciError::from((
             StatusCode::BAD_GATEWAY,
             format!("Failed to parse authentication response: {err}"),
@@ -785,6 +852,133 @@ mod tests {
         assert_eq!(response.text().await.unwrap(), "Hello, world!");
     }
 
+    #[tokio::test]
+    /// Test if we authenticate again when the first token is rejected
+    async fn auth_service_insufficient_token() {
+        let mut server = Server::new_async().await;
+        let url = server.url();
+        let mocks = vec![
+            // Response to unauthenticated request
+            server
+                .mock("GET", "/foobar")
+                .with_status(401)
+                .with_header(
+                    "WWW-Authenticate",
+                    &format!(
+                        "Bearer realm=\"{url}/token\",service=\"pyoci.fakeservice\",scope=\"pull\""
+                    ),
+                )
+                .create_async()
+                .await,
+            // Token exchange with the advertised scope
+            server
+                .mock(
+                    "GET",
+                    "/token?grant_type=password&service=pyoci.fakeservice&scope=pull",
+                )
+                .match_header("Authorization", "Basic dXNlcjpwYXNz")
+                .with_status(200)
+                .with_body(r#"{"token":"mytoken"}"#)
+                .create_async()
+                .await,
+            // Token is rejected, with the scope that is actually needed
+            server
+                .mock("GET", "/foobar")
+                .match_header("Authorization", "Bearer mytoken")
+                .with_status(401)
+                .with_header(
+                    "WWW-Authenticate",
+                    &format!("Bearer realm=\"{url}/token\",service=\"pyoci.fakeservice\",scope=\"repository:foobar:pull\""),
+                )
+                .create_async()
+                .await,
+            // Token exchange with the corrected scope
+            server
+                .mock(
+                    "GET",
+                    "/token?grant_type=password&service=pyoci.fakeservice&scope=repository%3Afoobar%3Apull",
+                )
+                .match_header("Authorization", "Basic dXNlcjpwYXNz")
+                .with_status(200)
+                .with_body(r#"{"token":"mysecondtoken"}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/foobar")
+                .match_header("Authorization", "Bearer mysecondtoken")
+                .with_status(200)
+                .with_body("Hello, world!")
+                .create_async()
+                .await,
+        ];
+
+        let mut service = ServiceBuilder::new()
+            .layer(AuthLayer::new(Some(
+                Authorization::basic("user", "pass").into(),
+            )))
+            .service(Client::default());
+
+        let request = reqwest::Request::new(
+            http::Method::GET,
+            Url::parse(&format!("{url}/foobar")).unwrap(),
+        );
+        let response = service.call(request).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "Hello, world!");
+    }
+
+    #[tokio::test]
+    /// Test if the 401 is returned when the second token is also rejected
+    async fn auth_service_insufficient_token_bounded() {
+        let mut server = Server::new_async().await;
+        let url = server.url();
+        let mocks = vec![
+            server
+                .mock("GET", "/foobar")
+                .expect(3)
+                .with_status(401)
+                .with_header(
+                    "WWW-Authenticate",
+                    &format!(
+                        "Bearer realm=\"{url}/token\",service=\"pyoci.fakeservice\",scope=\"pull\""
+                    ),
+                )
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    "/token?grant_type=password&service=pyoci.fakeservice&scope=pull",
+                )
+                .expect(2)
+                .with_status(200)
+                .with_body(r#"{"token":"mytoken"}"#)
+                .create_async()
+                .await,
+        ];
+
+        let mut service = ServiceBuilder::new()
+            .layer(AuthLayer::new(Some(
+                Authorization::basic("user", "pass").into(),
+            )))
+            .service(Client::default());
+
+        let request = reqwest::Request::new(
+            http::Method::GET,
+            Url::parse(&format!("{url}/foobar")).unwrap(),
+        );
+        let response = service.call(request).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
     // Test if the original response it returned if the request can't be cloned.
     // Without a clone we can't retry after authentication.
     #[tokio::test]
@@ -997,6 +1191,39 @@ mod tests {
         assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
         assert_eq!(response.text().await.unwrap(), "Unauthorized");
     }
+
+    // Test if the token file is read again when it was modified
+    #[tokio::test]
+    async fn token_file_rotated() {
+        let path =
+            std::env::temp_dir().join(format!("pyoci-token-{:032x}", rand::random::<u128>()));
+        let write = |token: &str, modified: u64| {
+            std::fs::write(&path, token).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(modified))
+                .unwrap();
+        };
+        let token_file = TokenFile::new(path.clone());
+
+        write("firsttoken\n", 1);
+        let first = token_file.token().await.unwrap();
+        // Not modified, the cached token is used
+        write("ignored\n", 1);
+        let cached = token_file.token().await.unwrap();
+        // Token got rotated
+        write("secondtoken\n", 2);
+        let second = token_file.token().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.token(), "firsttoken");
+        assert_eq!(cached.token(), "firsttoken");
+        assert_eq!(second.token(), "secondtoken");
+        assert!(token_file.token().await.is_err());
+    }
+
     // Test if BAD_GATEWAY is returned on response of the upsteam server without a
     // WWW-Authenticate header.
     #[tokio::test]
@@ -0,0 +1,360 @@
+use anyhow::Result;
+use futures::ready;
+use http::StatusCode;
+use pin_project::pin_project;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+use crate::error::PyOciError;
+
+/// State of the circuit for a single registry
+#[derive(Debug, Default)]
+struct Circuit {
+    /// Number of consecutive failed requests
+    failures: u32,
+    /// Requests are short-circuited until this moment
+    open_until: Option<Instant>,
+}
+
+/// Per-registry circuit breaker
+///
+/// After `threshold` consecutive failures the circuit for that registry opens and requests
+/// are rejected for `cooldown`, or for the duration of the `Retry-After` header if the registry
+/// provided one. Once the cooldown passed, a single probe request is let through (half-open),
+/// if it succeeds the circuit closes, if it fails the circuit opens again.
+///
+/// A `threshold` of 0 disables the circuit breaker.
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    circuits: Arc<Mutex<HashMap<String, Circuit>>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            circuits: Arc::default(),
+        }
+    }
+
+    /// Check if a request to `registry` is allowed
+    ///
+    /// Returns the remaining time the circuit is open if the request is not allowed.
+    fn check(&self, registry: &str, now: Instant) -> Result<(), Duration> {
+        if self.threshold == 0 {
+            return Ok(());
+        }
+        let mut circuits = self.circuits.lock().expect("Failed to lock circuits");
+        let Some(circuit) = circuits.get_mut(registry) else {
+            return Ok(());
+        };
+        match circuit.open_until {
+            Some(open_until) if open_until > now => Err(open_until - now),
+            Some(_) => {
+                // Half-open, let this request through as a probe and keep rejecting others
+                // until the probe completed.
+                circuit.open_until = Some(now + self.cooldown);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record the outcome of a request to `registry`
+    fn record(&self, registry: &str, outcome: Outcome, now: Instant) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut circuits = self.circuits.lock().expect("Failed to lock circuits");
+        match outcome {
+            Outcome::Success => {
+                circuits.remove(registry);
+            }
+            Outcome::Failure(retry_after) => {
+                let circuit = circuits.entry(registry.to_string()).or_default();
+                circuit.failures += 1;
+                if circuit.failures >= self.threshold || retry_after.is_some() {
+                    let cooldown = retry_after.unwrap_or(self.cooldown);
+                    tracing::warn!(
+                        "Opening circuit for {registry} for {}s after {} failures",
+                        cooldown.as_secs(),
+                        circuit.failures
+                    );
+                    circuit.open_until = Some(now + cooldown);
+                }
+            }
+        }
+    }
+}
+
+/// Outcome of a request as seen by the circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Success,
+    /// Failed request, with the optional `Retry-After` duration the registry provided
+    Failure(Option<Duration>),
+}
+
+impl Outcome {
+    fn from_response(response: &reqwest::Response) -> Self {
+        let status = response.status();
+        if !status.is_server_error() && status != StatusCode::TOO_MANY_REQUESTS {
+            return Outcome::Success;
+        }
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        Outcome::Failure(retry_after)
+    }
+}
+
+/// Key of the circuit a request belongs to
+fn registry_key(request: &reqwest::Request) -> String {
+    request.url().origin().ascii_serialization()
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerLayer {
+    breaker: CircuitBreaker,
+}
+
+impl CircuitBreakerLayer {
+    pub fn new(breaker: CircuitBreaker) -> Self {
+        Self { breaker }
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreakerService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        CircuitBreakerService {
+            breaker: self.breaker.clone(),
+            inner: service,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerService<S> {
+    breaker: CircuitBreaker,
+    inner: S,
+}
+
+impl<S> Service<reqwest::Request> for CircuitBreakerService<S>
+where
+    S: Service<reqwest::Request, Response = reqwest::Response>,
+    S::Error: Into<anyhow::Error>,
+{
+    type Response = S::Response;
+    type Error = anyhow::Error;
+    type Future = CircuitBreakerFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: reqwest::Request) -> Self::Future {
+        let registry = registry_key(&request);
+        match self.breaker.check(&registry, Instant::now()) {
+            Ok(()) => CircuitBreakerFuture::Called {
+                future: self.inner.call(request),
+                breaker: self.breaker.clone(),
+                registry,
+            },
+            Err(remaining) => CircuitBreakerFuture::Open {
+                error: Some(PyOciError::from((
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!(
+                        "Registry {registry} is unavailable, retry after {}s",
+                        remaining.as_secs() + 1
+                    ),
+                ))),
+            },
+        }
+    }
+}
+
+#[pin_project(project = CircuitBreakerFutureProj)]
+pub enum CircuitBreakerFuture<F> {
+    // Circuit is closed, polling the request
+    Called {
+        #[pin]
+        future: F,
+        breaker: CircuitBreaker,
+        registry: String,
+    },
+    // Circuit is open, the request is rejected
+    Open {
+        error: Option<PyOciError>,
+    },
+}
+
+impl<F, E> Future for CircuitBreakerFuture<F>
+where
+    F: Future<Output = Result<reqwest::Response, E>>,
+    E: Into<anyhow::Error>,
+{
+    type Output = anyhow::Result<reqwest::Response>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            CircuitBreakerFutureProj::Called {
+                future,
+                breaker,
+                registry,
+            } => {
+                let result = ready!(future.poll(cx)).map_err(Into::into);
+                let outcome = match &result {
+                    Ok(response) => Outcome::from_response(response),
+                    Err(_) => Outcome::Failure(None),
+                };
+                breaker.record(registry, outcome, Instant::now());
+                Poll::Ready(result)
+            }
+            CircuitBreakerFutureProj::Open { error } => Poll::Ready(Err(error
+                .take()
+                .expect("CircuitBreakerFuture polled after completion")
+                .into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use tower::ServiceBuilder;
+    use url::Url;
+
+    const REGISTRY: &str = "https://registry.example";
+
+    #[test]
+    fn circuit_breaker_trips() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+
+        assert_eq!(breaker.check(REGISTRY, now), Ok(()));
+        breaker.record(REGISTRY, Outcome::Failure(None), now);
+        // Below the threshold
+        assert_eq!(breaker.check(REGISTRY, now), Ok(()));
+        breaker.record(REGISTRY, Outcome::Failure(None), now);
+        // Threshold reached, circuit is open
+        assert_eq!(
+            breaker.check(REGISTRY, now + Duration::from_secs(1)),
+            Err(Duration::from_secs(9))
+        );
+        // Other registries are not affected
+        assert_eq!(breaker.check("https://other.example", now), Ok(()));
+    }
+
+    #[test]
+    fn circuit_breaker_recovers() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(10));
+        let now = Instant::now();
+
+        breaker.record(REGISTRY, Outcome::Failure(None), now);
+        assert!(breaker.check(REGISTRY, now).is_err());
+
+        // Cooldown passed, a single probe is allowed
+        let now = now + Duration::from_secs(10);
+        assert_eq!(breaker.check(REGISTRY, now), Ok(()));
+        assert!(breaker.check(REGISTRY, now).is_err());
+
+        // Probe succeeded, circuit is closed
+        breaker.record(REGISTRY, Outcome::Success, now);
+        assert_eq!(breaker.check(REGISTRY, now), Ok(()));
+    }
+
+    #[test]
+    fn circuit_breaker_probe_fails() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(10));
+        let now = Instant::now();
+
+        breaker.record(REGISTRY, Outcome::Failure(None), now);
+        let now = now + Duration::from_secs(10);
+        assert_eq!(breaker.check(REGISTRY, now), Ok(()));
+
+        // Probe failed, circuit opens again for the full cooldown
+        breaker.record(REGISTRY, Outcome::Failure(None), now);
+        assert_eq!(breaker.check(REGISTRY, now), Err(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn circuit_breaker_retry_after() {
+        let breaker = CircuitBreaker::new(5, Duration::from_secs(10));
+        let now = Instant::now();
+
+        // Retry-After opens the circuit immediately, for the requested duration
+        breaker.record(
+            REGISTRY,
+            Outcome::Failure(Some(Duration::from_secs(90))),
+            now,
+        );
+        assert_eq!(breaker.check(REGISTRY, now), Err(Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn circuit_breaker_disabled() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(10));
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            breaker.record(REGISTRY, Outcome::Failure(None), now);
+        }
+        assert_eq!(breaker.check(REGISTRY, now), Ok(()));
+    }
+
+    // Requests are short-circuited once the registry failed too often
+    #[tokio::test]
+    async fn circuit_breaker_service() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let mock = server
+            .mock("GET", "/foobar")
+            .with_status(500)
+            // The third request never reaches the registry
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut service = ServiceBuilder::new()
+            .layer(CircuitBreakerLayer::new(CircuitBreaker::new(
+                2,
+                Duration::from_secs(90),
+            )))
+            .service(Client::new());
+
+        for _ in 0..2 {
+            let request = reqwest::Request::new(
+                http::Method::GET,
+                Url::parse(&format!("{url}/foobar")).unwrap(),
+            );
+            let response = service.call(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        let request = reqwest::Request::new(
+            http::Method::GET,
+            Url::parse(&format!("{url}/foobar")).unwrap(),
+        );
+        let err = service
+            .call(request)
+            .await
+            .unwrap_err()
+            .downcast::<PyOciError>()
+            .unwrap();
+        assert_eq!(err.status, StatusCode::SERVICE_UNAVAILABLE);
+        mock.assert_async().await;
+    }
+}
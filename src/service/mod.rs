@@ -1,5 +1,9 @@
 mod auth;
+mod circuit_breaker;
 mod log;
+mod rate_limit;
 
-pub use auth::{AuthHeader, AuthLayer, AuthService};
+pub use auth::{AuthHeader, AuthLayer, AuthService, TokenFile};
+pub use circuit_breaker::{CircuitBreaker, CircuitBreakerLayer, CircuitBreakerService};
 pub use log::{RequestLog, RequestLogLayer};
+pub use rate_limit::{RateLimitLayer, RateLimitService, RateLimiter};
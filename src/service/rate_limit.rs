@@ -0,0 +1,259 @@
+use futures::ready;
+use pin_project::pin_project;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::time::Sleep;
+use tower::{Layer, Service};
+
+/// Token bucket of a single registry
+#[derive(Debug)]
+struct Bucket {
+    /// Requests that can be sent right away, negative when requests are waiting
+    tokens: f64,
+    /// Moment the tokens were last refilled
+    updated: Instant,
+}
+
+/// Per-registry rate limit of the outbound requests
+///
+/// Each registry gets a token bucket holding up to `rate` tokens, refilled at `rate` tokens
+/// per second. A burst of up to `rate` requests is sent right away, after that the requests
+/// are delayed to stay at `rate` requests per second.
+///
+/// Buckets that refilled completely are removed, they are the same as a new bucket.
+///
+/// A `rate` of 0 disables the rate limit.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    rate: f64,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Take a token for a request to `registry`
+    ///
+    /// Returns how long to wait before sending the request. Tokens are taken ahead of time,
+    /// so concurrent requests queue up instead of all sending once a token is available.
+    pub fn reserve(&self, registry: &str, now: Instant) -> Duration {
+        if self.rate <= 0.0 {
+            return Duration::ZERO;
+        }
+        // Allow a burst of at least one request for rates below 1 per second
+        let capacity = self.rate.max(1.0);
+        let mut buckets = self
+            .buckets
+            .lock()
+            .expect("Failed to lock rate limit buckets");
+        // Idle long enough to be full again
+        buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * self.rate < capacity
+        });
+        let bucket = buckets.entry(registry.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(capacity);
+        bucket.updated = bucket.updated.max(now);
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.rate)
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.buckets
+            .lock()
+            .expect("Failed to lock rate limit buckets")
+            .len()
+    }
+}
+
+/// Key of the bucket a request belongs to
+fn registry_key(request: &reqwest::Request) -> String {
+    request.url().origin().ascii_serialization()
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    limiter: RateLimiter,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: RateLimiter) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RateLimitService {
+            limiter: self.limiter.clone(),
+            inner: service,
+        }
+    }
+}
+
+/// Delays each request to stay within the rate limit of its registry
+///
+/// Placed below the `AuthService`, so the token requests are limited as well.
+#[derive(Debug, Clone)]
+pub struct RateLimitService<S> {
+    limiter: RateLimiter,
+    inner: S,
+}
+
+impl<S> Service<reqwest::Request> for RateLimitService<S>
+where
+    S: Service<reqwest::Request> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = RateLimitFuture<S>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: reqwest::Request) -> Self::Future {
+        let registry = registry_key(&request);
+        let delay = self.limiter.reserve(&registry, Instant::now());
+        if delay.is_zero() {
+            return RateLimitFuture {
+                waiting: None,
+                future: Some(self.inner.call(request)),
+            };
+        }
+        tracing::debug!("Rate limit of {registry} reached, waiting {delay:?}");
+        RateLimitFuture {
+            // The ready service is used once the delay passed
+            waiting: Some((
+                Box::pin(tokio::time::sleep(delay)),
+                self.inner.clone(),
+                request,
+            )),
+            future: None,
+        }
+    }
+}
+
+#[pin_project]
+pub struct RateLimitFuture<S>
+where
+    S: Service<reqwest::Request>,
+{
+    // Delay before sending the request, with the service to send it with
+    waiting: Option<(Pin<Box<Sleep>>, S, reqwest::Request)>,
+    // The request being sent
+    #[pin]
+    future: Option<S::Future>,
+}
+
+impl<S> Future for RateLimitFuture<S>
+where
+    S: Service<reqwest::Request>,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if let Some((sleep, _, _)) = this.waiting.as_mut() {
+            ready!(sleep.as_mut().poll(cx));
+            let (_, mut service, request) = this.waiting.take().expect("waiting for the delay");
+            this.future.set(Some(service.call(request)));
+        }
+        this.future
+            .as_pin_mut()
+            .expect("request sent once")
+            .poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const REGISTRY: &str = "https://registry.example";
+
+    #[test]
+    fn rate_limiter_throttles_bursts() {
+        let limiter = RateLimiter::new(2.0);
+        let now = Instant::now();
+
+        let delays: Vec<_> = (0..5).map(|_| limiter.reserve(REGISTRY, now)).collect();
+        // A burst of `rate` requests, the rest is spread at `rate` requests per second
+        assert_eq!(
+            delays,
+            [0, 0, 500, 1000, 1500].map(Duration::from_millis).to_vec()
+        );
+        // Other registries are not affected
+        assert_eq!(
+            limiter.reserve("https://other.example", now),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn rate_limiter_refills() {
+        let limiter = RateLimiter::new(2.0);
+        let now = Instant::now();
+
+        for _ in 0..2 {
+            assert_eq!(limiter.reserve(REGISTRY, now), Duration::ZERO);
+        }
+        // Half a second refills a single token
+        let now = now + Duration::from_millis(500);
+        assert_eq!(limiter.reserve(REGISTRY, now), Duration::ZERO);
+        assert_eq!(limiter.reserve(REGISTRY, now), Duration::from_millis(500));
+
+        // The bucket does not fill beyond `rate` tokens
+        let now = now + Duration::from_mins(1);
+        for _ in 0..2 {
+            assert_eq!(limiter.reserve(REGISTRY, now), Duration::ZERO);
+        }
+        assert_eq!(limiter.reserve(REGISTRY, now), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn rate_limiter_evicts_idle() {
+        let limiter = RateLimiter::new(2.0);
+        let now = Instant::now();
+
+        limiter.reserve(REGISTRY, now);
+        limiter.reserve("https://other.example", now);
+        assert_eq!(limiter.len(), 2);
+
+        // Half a second refills a single token, both buckets are full again
+        let now = now + Duration::from_millis(500);
+        limiter.reserve(REGISTRY, now);
+        assert_eq!(limiter.len(), 1);
+    }
+
+    #[test]
+    fn rate_limiter_disabled() {
+        let limiter = RateLimiter::new(0.0);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert_eq!(limiter.reserve(REGISTRY, now), Duration::ZERO);
+        }
+    }
+}
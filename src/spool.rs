@@ -0,0 +1,282 @@
+//! Spooled buffer for uploaded package content
+//!
+//! Content is kept in memory up to a threshold, larger content spills to a temporary file.
+//! The digests are calculated while the content is written, so the content does not need to be
+//! read back before it is sent to the registry.
+use std::{io::SeekFrom, path::PathBuf};
+
+use anyhow::{Context, Result};
+use base16ct::lower::encode_string as hex_encode;
+use sha2::{Digest, Sha256, Sha512};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+
+/// Number of leading bytes kept in memory to inspect the content type
+const HEAD_SIZE: usize = 8;
+
+/// Writer for [`SpooledContent`]
+pub struct Spool {
+    /// Bytes to keep in memory before spilling to a file, 0 always keeps the content in memory
+    threshold: usize,
+    storage: Storage,
+    len: u64,
+    head: Vec<u8>,
+    sha256: Sha256,
+    sha512: Sha512,
+}
+
+enum Storage {
+    Memory(Vec<u8>),
+    File(File, TempFile),
+}
+
+impl Spool {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            storage: Storage::Memory(Vec::new()),
+            len: 0,
+            head: Vec::with_capacity(HEAD_SIZE),
+            sha256: Sha256::new(),
+            sha512: Sha512::new(),
+        }
+    }
+
+    /// Append a chunk of content
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.sha256.update(chunk);
+        self.sha512.update(chunk);
+        let missing = HEAD_SIZE.saturating_sub(self.head.len());
+        self.head
+            .extend_from_slice(&chunk[..missing.min(chunk.len())]);
+        self.len += chunk.len() as u64;
+
+        if let Storage::Memory(data) = &mut self.storage {
+            if self.threshold == 0 || data.len() + chunk.len() <= self.threshold {
+                data.extend_from_slice(chunk);
+                return Ok(());
+            }
+            // Exceeding the threshold, move the content to a file
+            let (mut file, temp_file) = TempFile::create().await?;
+            file.write_all(data).await?;
+            self.storage = Storage::File(file, temp_file);
+        }
+        if let Storage::File(file, _) = &mut self.storage {
+            file.write_all(chunk).await?;
+        }
+        Ok(())
+    }
+
+    /// Finish writing
+    pub async fn finish(self) -> Result<SpooledContent> {
+        let data = match self.storage {
+            Storage::Memory(data) => Data::Memory(data),
+            Storage::File(mut file, temp_file) => {
+                file.flush().await?;
+                Data::File(temp_file)
+            }
+        };
+        Ok(SpooledContent {
+            data,
+            len: self.len,
+            head: self.head,
+            sha256: hex_encode(&self.sha256.finalize()),
+            sha512: hex_encode(&self.sha512.finalize()),
+        })
+    }
+}
+
+/// Content written by a [`Spool`]
+#[derive(Debug)]
+pub struct SpooledContent {
+    data: Data,
+    len: u64,
+    head: Vec<u8>,
+    sha256: String,
+    sha512: String,
+}
+
+#[derive(Debug)]
+enum Data {
+    Memory(Vec<u8>),
+    File(TempFile),
+}
+
+impl SpooledContent {
+    /// Size of the content in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// First bytes of the content
+    pub fn head(&self) -> &[u8] {
+        &self.head
+    }
+
+    /// Hex encoded sha256 digest of the content
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    /// Hex encoded sha512 digest of the content
+    pub fn sha512(&self) -> &str {
+        &self.sha512
+    }
+
+    /// True if the content was spilled to a temporary file
+    #[cfg(test)]
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.data, Data::File(_))
+    }
+
+    /// Read up to `len` bytes starting at `offset`
+    pub async fn read_range(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let start = offset.min(self.len);
+        let end = self.len.min(start.saturating_add(len));
+        let size = usize::try_from(end - start)?;
+        match &self.data {
+            Data::Memory(data) => Ok(data[usize::try_from(start)?..][..size].to_vec()),
+            Data::File(temp_file) => {
+                let mut file = File::open(&temp_file.0)
+                    .await
+                    .context("Failed to open spooled content")?;
+                file.seek(SeekFrom::Start(start)).await?;
+                let mut buf = vec![0; size];
+                file.read_exact(&mut buf).await?;
+                Ok(buf)
+            }
+        }
+    }
+
+    /// Convert the content into a request body
+    ///
+    /// Content in a temporary file is streamed, the file is removed when the body is dropped.
+    pub async fn into_body(self) -> Result<reqwest::Body> {
+        match self.data {
+            Data::Memory(data) => Ok(data.into()),
+            Data::File(temp_file) => {
+                let file = File::open(&temp_file.0)
+                    .await
+                    .context("Failed to open spooled content")?;
+                let stream = ReaderStream::new(file);
+                Ok(reqwest::Body::wrap_stream(futures::StreamExt::map(
+                    stream,
+                    move |chunk| {
+                        // Keep the file around until the stream is done
+                        let _ = &temp_file;
+                        chunk
+                    },
+                )))
+            }
+        }
+    }
+}
+
+/// Content is equal when the size and digest match, regardless of where it is stored
+impl PartialEq for SpooledContent {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.sha512 == other.sha512
+    }
+}
+
+impl Eq for SpooledContent {}
+
+impl From<Vec<u8>> for SpooledContent {
+    fn from(data: Vec<u8>) -> Self {
+        Self {
+            len: data.len() as u64,
+            head: data[..HEAD_SIZE.min(data.len())].to_vec(),
+            sha256: hex_encode(&Sha256::digest(&data)),
+            sha512: hex_encode(&Sha512::digest(&data)),
+            data: Data::Memory(data),
+        }
+    }
+}
+
+/// Temporary file, removed on drop
+#[derive(Debug)]
+struct TempFile(PathBuf);
+
+impl TempFile {
+    async fn create() -> Result<(File, Self)> {
+        let path =
+            std::env::temp_dir().join(format!("pyoci-upload-{:032x}", rand::random::<u128>()));
+        let file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("Failed to create temporary file '{}'", path.display()))?;
+        Ok((file, Self(path)))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.0) {
+            tracing::warn!(
+                "Failed to remove temporary file '{}': {err}",
+                self.0.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    async fn spool(threshold: usize, chunks: &[&[u8]]) -> SpooledContent {
+        let mut spool = Spool::new(threshold);
+        for chunk in chunks {
+            spool.write(chunk).await.unwrap();
+        }
+        spool.finish().await.unwrap()
+    }
+
+    async fn body_bytes(content: SpooledContent) -> Vec<u8> {
+        let body = axum::body::Body::new(content.into_body().await.unwrap());
+        axum::body::to_bytes(body, usize::MAX).await.unwrap().into()
+    }
+
+    #[tokio::test]
+    async fn spool_memory() {
+        let content = spool(10, &[b"hello", b"world"]).await;
+
+        assert!(!content.is_spilled());
+        assert_eq!(content, SpooledContent::from(b"helloworld".to_vec()));
+        assert_eq!(content.head(), b"hellowor");
+        assert_eq!(content.read_range(8, 4).await.unwrap(), b"ld");
+        assert_eq!(content.read_range(12, 4).await.unwrap(), b"");
+        assert_eq!(body_bytes(content).await, b"helloworld");
+    }
+
+    #[tokio::test]
+    async fn spool_file() {
+        let content = spool(8, &[b"hello", b"world", b"!"]).await;
+
+        assert!(content.is_spilled());
+        let Data::File(TempFile(path)) = &content.data else {
+            unreachable!()
+        };
+        let path = path.clone();
+        assert_eq!(std::fs::read(&path).unwrap(), b"helloworld!");
+        // Only the head of the content is kept in memory
+        assert_eq!(content.head(), b"hellowor");
+        assert_eq!(content, SpooledContent::from(b"helloworld!".to_vec()));
+        assert_eq!(
+            content.sha256(),
+            "98d234db7e91f5ba026a25d0d6f17bc5ee0a347ea2216b0c9de06d43536d49f4"
+        );
+
+        assert_eq!(content.read_range(4, 4).await.unwrap(), b"owor");
+        assert_eq!(content.read_range(8, 4).await.unwrap(), b"ld!");
+        assert_eq!(body_bytes(content).await, b"helloworld!");
+        // Temporary file is removed once the body is consumed
+        assert!(!path.exists());
+    }
+}
@@ -1,14 +1,315 @@
-use anyhow::Result;
-use std::future::poll_fn;
+use anyhow::{Context, Result};
+use std::future::{poll_fn, Future};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
 use tower::{Service, ServiceBuilder};
+use url::Url;
 
+use crate::cache::BlobCache;
 use crate::service::AuthHeader;
 use crate::service::AuthLayer;
 use crate::service::AuthService;
+use crate::service::CircuitBreaker;
+use crate::service::CircuitBreakerLayer;
+use crate::service::CircuitBreakerService;
+use crate::service::RateLimitLayer;
+use crate::service::RateLimitService;
+use crate::service::RateLimiter;
 use crate::service::RequestLog;
 use crate::service::RequestLogLayer;
+use crate::service::TokenFile;
 use crate::USER_AGENT;
 
+/// Options for the HTTP client used to send requests to the upstream OCI registry
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Use HTTP/2 without negotiating the protocol first
+    pub http2: bool,
+    /// Number of consecutive failures before requests to a registry are short-circuited,
+    /// 0 disables the circuit breaker
+    pub circuit_breaker_threshold: u32,
+    /// How long requests to a failing registry are short-circuited
+    pub circuit_breaker_cooldown: Duration,
+    /// Maximum requests per second to a single registry, 0 disables the rate limit
+    pub rate_limit: f64,
+    /// PEM file with additional root certificates to trust
+    pub ca_bundle: Option<PathBuf>,
+    /// Skip verification of the registry TLS certificate
+    pub tls_insecure: bool,
+    /// Maximum size in bytes of manifest and tag list responses, 0 disables the limit
+    pub json_limit: usize,
+    /// Maximum number of manifests in an `ImageIndex`, 0 disables the limit
+    pub max_manifests: usize,
+    /// Size in bytes of the chunks blobs are uploaded in, 0 uploads blobs in a single request
+    pub upload_chunk_size: u64,
+    /// File to read the registry Bearer token from,
+    /// used for requests to `bearer_token_hosts` without credentials
+    pub bearer_token_file: Option<PathBuf>,
+    /// Registry hosts, `host` or `host:port`, the token of `bearer_token_file` is sent to
+    pub bearer_token_hosts: Vec<String>,
+    /// Maximum number of concurrent manifest fetches across all requests, 0 disables the limit
+    pub upstream_concurrency: usize,
+    /// Size in bytes of the in-process cache for downloaded blobs, 0 disables the cache
+    pub blob_cache_bytes: u64,
+    /// Proxy to send the requests to the registry through
+    pub proxy: Option<String>,
+    /// Hosts that bypass the proxy, a domain also matches its subdomains
+    pub no_proxy: Vec<String>,
+    /// Media types for the `Accept` header of manifest requests, in order of preference,
+    /// empty uses [`MANIFEST_ACCEPT`]
+    pub manifest_accept: Vec<String>,
+    /// `Content-Type` of pushed `ImageManifest`s, `None` uses [`MANIFEST_CONTENT_TYPE`]
+    pub manifest_content_type: Option<String>,
+    /// `Content-Type` of pushed `ImageIndex`es, `None` uses [`INDEX_CONTENT_TYPE`]
+    pub index_content_type: Option<String>,
+    /// Maximum number of redirects followed for a single request, 0 does not follow redirects
+    pub max_redirects: usize,
+    /// Maximum idle connections kept open per registry host, `None` does not limit them
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long an idle connection is kept open, `None` uses the reqwest default of 90 seconds
+    pub pool_idle_timeout: Option<Duration>,
+    /// How the pages of the tag list are requested
+    pub tag_pagination: TagPagination,
+}
+
+/// Pagination of the tag list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TagPagination {
+    /// Follow the `Link` header of each page
+    #[default]
+    Link,
+    /// Without a `Link` header, request the next page with `?last=<last tag>`
+    /// until a page is shorter than the first one
+    Last,
+}
+
+impl FromStr for TagPagination {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "link" => Ok(TagPagination::Link),
+            "last" => Ok(TagPagination::Last),
+            _ => anyhow::bail!("Unknown tag pagination '{value}', expected 'link' or 'last'"),
+        }
+    }
+}
+
+/// Default `Accept` header of manifest requests
+pub const MANIFEST_ACCEPT: &str =
+    "application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json";
+
+/// Default `Content-Type` of a pushed `ImageManifest`
+pub const MANIFEST_CONTENT_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Default `Content-Type` of a pushed `ImageIndex`
+pub const INDEX_CONTENT_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// Hosts that always bypass the proxy, a registry on the local machine is never proxied
+const LOCAL_HOSTS: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+impl ClientOptions {
+    /// Build the HTTP client
+    ///
+    /// The client is meant to be shared between requests so connections can be reused.
+    pub fn build(&self) -> Result<HttpClient> {
+        Ok(HttpClient {
+            client: self.builder()?.build()?,
+            circuit_breaker: CircuitBreaker::new(
+                self.circuit_breaker_threshold,
+                self.circuit_breaker_cooldown,
+            ),
+            rate_limiter: RateLimiter::new(self.rate_limit),
+            json_limit: self.json_limit,
+            max_manifests: self.max_manifests,
+            upload_chunk_size: self.upload_chunk_size,
+            bearer_token_file: self.bearer_token_file.clone().map(TokenFile::new),
+            bearer_token_hosts: self.bearer_token_hosts.clone(),
+            manifest_permits: (self.upstream_concurrency > 0)
+                .then(|| Arc::new(Semaphore::new(self.upstream_concurrency))),
+            blob_cache: (self.blob_cache_bytes > 0)
+                .then(|| Arc::new(BlobCache::new(self.blob_cache_bytes))),
+            manifest_accept: self.manifest_accept(),
+            manifest_content_type: self.manifest_content_type.clone(),
+            index_content_type: self.index_content_type.clone(),
+            tag_pagination: self.tag_pagination,
+        })
+    }
+
+    /// `Accept` header of manifest requests, `None` uses [`MANIFEST_ACCEPT`]
+    ///
+    /// Without configured media types, the configured `Content-Type`s are accepted as well,
+    /// the registry can return manifests with the type they were pushed with.
+    fn manifest_accept(&self) -> Option<String> {
+        if !self.manifest_accept.is_empty() {
+            return Some(self.manifest_accept.join(", "));
+        }
+        let extra: Vec<&str> = [&self.manifest_content_type, &self.index_content_type]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|content_type| {
+                ![MANIFEST_CONTENT_TYPE, INDEX_CONTENT_TYPE].contains(content_type)
+            })
+            .collect();
+        (!extra.is_empty()).then(|| {
+            [MANIFEST_ACCEPT]
+                .into_iter()
+                .chain(extra)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+    }
+
+    fn builder(&self) -> Result<reqwest::ClientBuilder> {
+        let mut builder = reqwest::Client::builder().user_agent(USER_AGENT);
+        if self.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(path) = &self.ca_bundle {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read CA bundle '{}'", path.display()))?;
+            let certs = reqwest::Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("Invalid CA bundle '{}'", path.display()))?;
+            builder = builder.tls_certs_merge(certs);
+        }
+        if self.tls_insecure {
+            tracing::warn!(
+                "TLS certificate verification is disabled, connections to the registry are NOT secure"
+            );
+            builder = builder.tls_danger_accept_invalid_certs(true);
+        }
+        // Registries often redirect blob downloads to object storage.
+        // reqwest removes the Authorization header when a redirect goes to a different host,
+        // so the registry credentials are not leaked to the storage backend.
+        builder = builder.redirect(match self.max_redirects {
+            0 => reqwest::redirect::Policy::none(),
+            max => reqwest::redirect::Policy::limited(max),
+        });
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            let no_proxy = LOCAL_HOSTS
+                .iter()
+                .copied()
+                .chain(self.no_proxy.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(",");
+            let proxy = reqwest::Proxy::all(proxy)
+                .with_context(|| format!("Invalid proxy '{proxy}'"))?
+                .no_proxy(reqwest::NoProxy::from_string(&no_proxy));
+            builder = builder.proxy(proxy);
+        }
+        Ok(builder)
+    }
+}
+
+/// HTTP client shared between requests
+///
+/// Holds the connection pool and the circuit breaker state of the upstream registries.
+#[derive(Debug, Clone, Default)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    circuit_breaker: CircuitBreaker,
+    rate_limiter: RateLimiter,
+    json_limit: usize,
+    max_manifests: usize,
+    upload_chunk_size: u64,
+    bearer_token_file: Option<TokenFile>,
+    bearer_token_hosts: Vec<String>,
+    manifest_permits: Option<Arc<Semaphore>>,
+    blob_cache: Option<Arc<BlobCache>>,
+    manifest_accept: Option<String>,
+    manifest_content_type: Option<String>,
+    index_content_type: Option<String>,
+    tag_pagination: TagPagination,
+}
+
+impl HttpClient {
+    /// Maximum size in bytes of manifest and tag list responses, 0 means no limit
+    pub fn json_limit(&self) -> usize {
+        self.json_limit
+    }
+
+    /// Maximum number of manifests in an `ImageIndex`, 0 means no limit
+    pub fn max_manifests(&self) -> usize {
+        self.max_manifests
+    }
+
+    /// Size in bytes of the chunks blobs are uploaded in, 0 means a single request
+    pub fn upload_chunk_size(&self) -> u64 {
+        self.upload_chunk_size
+    }
+
+    /// Token file to authenticate to `registry` with, if it is one of the configured hosts
+    pub fn bearer_token_file(&self, registry: &Url) -> Option<TokenFile> {
+        let host = registry.host_str()?;
+        let host_port = registry.port().map(|port| format!("{host}:{port}"));
+        self.bearer_token_hosts
+            .iter()
+            .any(|allowed| allowed == host || Some(allowed) == host_port.as_ref())
+            .then(|| self.bearer_token_file.clone())
+            .flatten()
+    }
+
+    /// Permits for fetching a manifest, shared by all requests, `None` means no limit
+    pub fn manifest_permits(&self) -> Option<Arc<Semaphore>> {
+        self.manifest_permits.clone()
+    }
+
+    /// Cache for downloaded blobs, shared by all requests, `None` means no caching
+    pub fn blob_cache(&self) -> Option<Arc<BlobCache>> {
+        self.blob_cache.clone()
+    }
+
+    /// `Accept` header of manifest requests
+    pub fn manifest_accept(&self) -> &str {
+        self.manifest_accept.as_deref().unwrap_or(MANIFEST_ACCEPT)
+    }
+
+    /// `Content-Type` header of pushed `ImageManifest`s
+    pub fn manifest_content_type(&self) -> &str {
+        self.manifest_content_type
+            .as_deref()
+            .unwrap_or(MANIFEST_CONTENT_TYPE)
+    }
+
+    /// `Content-Type` header of pushed `ImageIndex`es
+    pub fn index_content_type(&self) -> &str {
+        self.index_content_type
+            .as_deref()
+            .unwrap_or(INDEX_CONTENT_TYPE)
+    }
+
+    /// How the pages of the tag list are requested
+    pub fn tag_pagination(&self) -> TagPagination {
+        self.tag_pagination
+    }
+}
+
+tokio::task_local! {
+    /// Bytes received from the upstream registry while handling the current request
+    static UPSTREAM_BYTES: Arc<AtomicU64>;
+}
+
+/// Run `future`, counting the bytes received from the upstream registry
+///
+/// Counts the `Content-Length` of the successful GET responses sent by an [`HttpTransport`]
+/// within `future`, so the manifests and blobs that were fetched.
+pub async fn count_upstream_bytes<F: Future>(future: F) -> (F::Output, u64) {
+    let bytes = Arc::new(AtomicU64::new(0));
+    let output = UPSTREAM_BYTES.scope(bytes.clone(), future).await;
+    (output, bytes.load(Ordering::Relaxed))
+}
+
 /// HTTP Transport
 ///
 /// This struct is responsible for sending HTTP requests to the upstream OCI registry
@@ -16,7 +317,10 @@ use crate::USER_AGENT;
 #[derive(Debug, Clone)]
 pub struct HttpTransport {
     client: reqwest::Client,
-    service: AuthService<RequestLog<reqwest::Client>>,
+    service: CircuitBreakerService<AuthService<RateLimitService<RequestLog<reqwest::Client>>>>,
+    auth_layer: AuthLayer,
+    /// Refreshes the Bearer token of `auth_layer` before each request
+    token_file: Option<TokenFile>,
 }
 
 impl HttpTransport {
@@ -24,20 +328,27 @@ impl HttpTransport {
     ///
     /// auth: Basic auth string
     ///       Will be swapped for a Bearer token if needed
-    pub fn new(auth: Option<AuthHeader>) -> Self {
-        let client = reqwest::Client::builder()
-            .user_agent(USER_AGENT)
-            .build()
-            .unwrap();
+    /// client: HTTP client used to send the requests, see [`ClientOptions`]
+    pub fn new(auth: Option<AuthHeader>, client: HttpClient) -> Self {
+        let auth_layer = AuthLayer::new(auth);
         Self {
             service: ServiceBuilder::new()
-                .layer(AuthLayer::new(auth))
+                .layer(CircuitBreakerLayer::new(client.circuit_breaker))
+                .layer(auth_layer.clone())
+                .layer(RateLimitLayer::new(client.rate_limiter))
                 .layer(RequestLogLayer::new("subrequest"))
-                .service(client.clone()),
-            client,
+                .service(client.client.clone()),
+            client: client.client,
+            auth_layer,
+            token_file: None,
         }
     }
 
+    /// Authenticate with the Bearer token in `token_file`, see [`HttpClient::bearer_token_file`]
+    pub fn with_token_file(self, token_file: Option<TokenFile>) -> Self {
+        Self { token_file, ..self }
+    }
+
     /// Send a request
     ///
     /// When authentication is required, this method will automatically authenticate
@@ -45,10 +356,23 @@ impl HttpTransport {
     /// this session.
     pub async fn send(&mut self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
         let request = request.build()?;
+        if let Some(token_file) = &self.token_file {
+            match token_file.token().await {
+                Ok(bearer) => self.auth_layer.set_bearer(bearer),
+                // Keep using the previous token, the registry will reject it if it expired
+                Err(err) => tracing::error!("{err:#}"),
+            }
+        }
+        let is_get = request.method() == reqwest::Method::GET;
 
         poll_fn(|ctx| self.service.poll_ready(ctx)).await?;
         let response = self.service.call(request).await?;
 
+        if is_get && response.status().is_success() {
+            let length = response.content_length().unwrap_or_default();
+            // Not counting outside of `count_upstream_bytes`
+            let _ = UPSTREAM_BYTES.try_with(|bytes| bytes.fetch_add(length, Ordering::Relaxed));
+        }
         Ok(response)
     }
 
@@ -64,6 +388,10 @@ impl HttpTransport {
     pub fn put(&self, url: url::Url) -> reqwest::RequestBuilder {
         self.client.put(url)
     }
+    /// Create a new PATCH request
+    pub fn patch(&self, url: url::Url) -> reqwest::RequestBuilder {
+        self.client.patch(url)
+    }
     /// Create a new HEAD request
     pub fn head(&self, url: url::Url) -> reqwest::RequestBuilder {
         self.client.head(url)
@@ -79,8 +407,17 @@ mod tests {
     use super::*;
     use headers::Authorization;
     use http::StatusCode;
+    use std::time::Instant;
+    use test_case::test_case;
     use url::Url;
 
+    #[test_case("link", TagPagination::Link; "link")]
+    #[test_case("last", TagPagination::Last; "last")]
+    fn tag_pagination_from_str(value: &str, expected: TagPagination) {
+        assert_eq!(value.parse::<TagPagination>().unwrap(), expected);
+        assert!("next".parse::<TagPagination>().is_err());
+    }
+
     /// Test happy-flow, no auth needed
     #[tokio::test]
     async fn http_transport_send() {
@@ -94,7 +431,7 @@ mod tests {
                 .await,
         ];
 
-        let mut transport = HttpTransport::new(None);
+        let mut transport = HttpTransport::new(None, HttpClient::default());
         let request = transport.get(Url::parse(&format!("{}/foobar", &server.url())).unwrap());
         let response = transport.send(request).await.unwrap();
         for mock in mocks {
@@ -104,6 +441,87 @@ mod tests {
         assert_eq!(response.text().await.unwrap(), "Hello, world!");
     }
 
+    /// Requests beyond the burst are delayed to the configured rate
+    #[tokio::test]
+    async fn http_transport_send_rate_limit() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/foobar")
+            .with_status(200)
+            .expect(12)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            rate_limit: 10.0,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut transport = HttpTransport::new(None, client);
+        let start = Instant::now();
+        for _ in 0..12 {
+            let request = transport.get(Url::parse(&format!("{}/foobar", &server.url())).unwrap());
+            let response = transport.send(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        // A burst of 10 requests, the next 2 are spaced 100ms apart
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        mock.assert_async().await;
+    }
+
+    /// The token exchange and the retried request count towards the rate limit
+    #[tokio::test]
+    async fn http_transport_send_rate_limit_auth() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let mocks = vec![
+            server
+                .mock("GET", "/foobar")
+                .with_status(401)
+                .with_header(
+                    "WWW-Authenticate",
+                    &format!("Bearer realm=\"{url}/token\",service=\"pyoci.fakeservice\""),
+                )
+                .create_async()
+                .await,
+            server
+                .mock(
+                    "GET",
+                    "/token?grant_type=password&service=pyoci.fakeservice",
+                )
+                .with_status(200)
+                .with_body(r#"{"token":"mytoken"}"#)
+                .create_async()
+                .await,
+            server
+                .mock("GET", "/foobar")
+                .match_header("Authorization", "Bearer mytoken")
+                .with_status(200)
+                .create_async()
+                .await,
+        ];
+
+        let client = ClientOptions {
+            rate_limit: 2.0,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut transport =
+            HttpTransport::new(Some(Authorization::basic("user", "pass").into()), client);
+        let start = Instant::now();
+        let request = transport.get(Url::parse(&format!("{url}/foobar")).unwrap());
+        let response = transport.send(request).await.unwrap();
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(response.status(), StatusCode::OK);
+        // A burst of 2 requests, the retried request waits for the next token
+        assert!(start.elapsed() >= Duration::from_millis(500));
+    }
+
     /// Test happy-flow, with authentication
     #[tokio::test]
     async fn http_transport_send_auth() {
@@ -141,7 +559,10 @@ mod tests {
                 .await,
         ];
 
-        let mut transport = HttpTransport::new(Some(Authorization::basic("user", "pass").into()));
+        let mut transport = HttpTransport::new(
+            Some(Authorization::basic("user", "pass").into()),
+            HttpClient::default(),
+        );
         let request = transport.get(Url::parse(&format!("{url}/foobar")).unwrap());
         let response = transport.send(request).await.unwrap();
         for mock in mocks {
@@ -197,7 +618,10 @@ mod tests {
                 .await,
         ];
 
-        let mut transport = HttpTransport::new(Some(Authorization::basic("user", "pass").into()));
+        let mut transport = HttpTransport::new(
+            Some(Authorization::basic("user", "pass").into()),
+            HttpClient::default(),
+        );
         // clone the transport to check if they share the bearer token state
         let mut transport2 = transport.clone();
 
@@ -254,7 +678,7 @@ mod tests {
                 .await,
         ];
 
-        let mut transport = HttpTransport::new(None);
+        let mut transport = HttpTransport::new(None, HttpClient::default());
         let request = transport.get(Url::parse(&format!("{url}/foobar")).unwrap());
         let response = transport.send(request).await.unwrap();
         for mock in mocks {
@@ -291,7 +715,7 @@ mod tests {
                 .await,
         ];
 
-        let mut transport = HttpTransport::new(None);
+        let mut transport = HttpTransport::new(None, HttpClient::default());
         let request = transport.get(Url::parse(&format!("{url}/foobar")).unwrap());
         let response = transport.send(request).await.unwrap();
         for mock in mocks {
@@ -326,7 +750,10 @@ mod tests {
                 .await,
         ];
 
-        let mut transport = HttpTransport::new(Some(Authorization::basic("user", "pass").into()));
+        let mut transport = HttpTransport::new(
+            Some(Authorization::basic("user", "pass").into()),
+            HttpClient::default(),
+        );
         let request = transport.get(Url::parse(&format!("{url}/foobar")).unwrap());
         let response = transport.send(request).await.unwrap();
         for mock in mocks {
@@ -372,7 +799,10 @@ mod tests {
                 .await,
         ];
 
-        let mut transport = HttpTransport::new(Some(Authorization::basic("user", "pass").into()));
+        let mut transport = HttpTransport::new(
+            Some(Authorization::basic("user", "pass").into()),
+            HttpClient::default(),
+        );
         let request = transport.get(Url::parse(&format!("{url}/foobar")).unwrap());
         let response = transport.send(request).await.unwrap();
         for mock in mocks {
@@ -381,4 +811,294 @@ mod tests {
         assert_eq!(response.status(), StatusCode::FORBIDDEN);
         assert_eq!(response.text().await.unwrap(), "Forbidden");
     }
+
+    /// Test if the client speaks HTTP/2 without negotiating when enabled
+    #[tokio::test]
+    async fn http_transport_http2() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/foobar")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let client = ClientOptions {
+            http2: true,
+            ..ClientOptions::default()
+        }
+        .build()
+        .unwrap();
+        let mut transport = HttpTransport::new(None, client);
+        let request = transport.get(Url::parse(&format!("{}/foobar", &server.url())).unwrap());
+        let response = transport.send(request).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.version(), http::Version::HTTP_2);
+    }
+
+    /// Test if the client uses HTTP/1.1 against a plain HTTP registry by default
+    #[tokio::test]
+    async fn http_transport_http1_default() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/foobar")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let client = ClientOptions::default().build().unwrap();
+        let mut transport = HttpTransport::new(None, client);
+        let request = transport.get(Url::parse(&format!("{}/foobar", &server.url())).unwrap());
+        let response = transport.send(request).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(response.version(), http::Version::HTTP_11);
+    }
+
+    const CA_BUNDLE: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBiDCCAS2gAwIBAgIUIU8JPJsfxoqL+6VMwtm6hfJ/PaMwCgYIKoZIzj0EAwIw
+GDEWMBQGA1UEAwwNcHlvY2ktdGVzdC1jYTAgFw0yNjEwMTYxNTEwNDlaGA8yMTI2
+MDkyMjE1MTA0OVowGDEWMBQGA1UEAwwNcHlvY2ktdGVzdC1jYTBZMBMGByqGSM49
+AgEGCCqGSM49AwEHA0IABNJWMgBZg9SiRlRRShNSyHRx3/nYqsvaziXNunBbX/3s
+HMoB5qDngM/7QOx59KVM4qZt1qVOQ2Gsh8Q/L15KfTyjUzBRMB0GA1UdDgQWBBRF
+mEqBsWNRlptZvkKh4NMEenzmFzAfBgNVHSMEGDAWgBRFmEqBsWNRlptZvkKh4NME
+enzmFzAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0kAMEYCIQCh6dEfpXgO
+xJPSQTsYLzJBhX/C6WD9gUX9eiJ2wuIEdwIhALDOLqV1Dj8SCpjVvoVbnOAJxDQ2
+csS3F5KM2HlhSgca
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn client_options_ca_bundle() {
+        let path = std::env::temp_dir().join("pyoci-client-options-ca-bundle.pem");
+        std::fs::write(&path, CA_BUNDLE).unwrap();
+        let options = ClientOptions {
+            ca_bundle: Some(path),
+            ..ClientOptions::default()
+        };
+        assert!(options.build().is_ok());
+    }
+
+    #[test]
+    fn client_options_ca_bundle_missing() {
+        let options = ClientOptions {
+            ca_bundle: Some(PathBuf::from("/does/not/exist.pem")),
+            ..ClientOptions::default()
+        };
+        let err = options.build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to read CA bundle '/does/not/exist.pem'"
+        );
+    }
+
+    #[test]
+    fn client_options_tls_insecure() {
+        let options = ClientOptions {
+            tls_insecure: true,
+            ..ClientOptions::default()
+        };
+        let builder = format!("{:?}", options.builder().unwrap());
+        assert!(builder.contains("tls_danger_accept_invalid_certs: true"));
+
+        let builder = format!("{:?}", ClientOptions::default().builder().unwrap());
+        assert!(!builder.contains("tls_danger_accept_invalid_certs"));
+    }
+
+    #[test_case(&["registry.internal"], false; "listed host")]
+    #[test_case(&["internal"], false; "listed domain")]
+    #[test_case(&[], true; "not listed")]
+    #[tokio::test]
+    async fn client_options_no_proxy(no_proxy: &[&str], proxied: bool) {
+        let mut registry = mockito::Server::new_async().await;
+        let registry_mock = registry
+            .mock("GET", "/foobar")
+            .with_status(200)
+            .expect(usize::from(!proxied))
+            .create_async()
+            .await;
+        let mut proxy = mockito::Server::new_async().await;
+        let proxy_mock = proxy
+            .mock("GET", mockito::Matcher::Any)
+            .with_status(200)
+            .expect(usize::from(proxied))
+            .create_async()
+            .await;
+
+        let options = ClientOptions {
+            proxy: Some(proxy.url()),
+            no_proxy: no_proxy.iter().map(ToString::to_string).collect(),
+            ..ClientOptions::default()
+        };
+        // Resolve the registry host to the mock server when the proxy is bypassed
+        let client = options
+            .builder()
+            .unwrap()
+            .resolve("registry.internal", registry.socket_address())
+            .build()
+            .unwrap();
+        let url = format!(
+            "http://registry.internal:{}/foobar",
+            registry.socket_address().port()
+        );
+        let response = client.get(url).send().await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        registry_mock.assert_async().await;
+        proxy_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn client_options_no_proxy_localhost() {
+        let mut registry = mockito::Server::new_async().await;
+        let registry_mock = registry
+            .mock("GET", "/foobar")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        // The proxy is unreachable, requests to a local registry do not use it
+        let options = ClientOptions {
+            proxy: Some("http://proxy.invalid:3128".to_string()),
+            ..ClientOptions::default()
+        };
+        let mut transport = HttpTransport::new(None, options.build().unwrap());
+        let request = transport.get(Url::parse(&format!("{}/foobar", registry.url())).unwrap());
+        let response = transport.send(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        registry_mock.assert_async().await;
+    }
+
+    #[test_case("localhost", false; "cross host")]
+    #[test_case("127.0.0.1", true; "same host")]
+    #[tokio::test]
+    async fn client_options_redirect_authorization(host: &str, forwarded: bool) {
+        let mut registry = mockito::Server::new_async().await;
+        let mut storage = mockito::Server::new_async().await;
+        // The storage server shares the registry address for a same host redirect
+        let storage_url = if forwarded {
+            registry.url()
+        } else {
+            format!("http://{host}:{}", storage.socket_address().port())
+        };
+        let authorization = if forwarded {
+            mockito::Matcher::Exact("Bearer token".to_string())
+        } else {
+            mockito::Matcher::Missing
+        };
+        let target = if forwarded {
+            &mut registry
+        } else {
+            &mut storage
+        };
+        let storage_mock = target
+            .mock("GET", "/storage/blob")
+            .match_header("authorization", authorization)
+            .with_status(200)
+            .with_body("hello")
+            .create_async()
+            .await;
+        let registry_mock = registry
+            .mock("GET", "/v2/foo/blobs/sha256:1234")
+            .match_header("authorization", "Bearer token")
+            .with_status(307)
+            .with_header("Location", &format!("{storage_url}/storage/blob"))
+            .create_async()
+            .await;
+
+        let options = ClientOptions {
+            max_redirects: 10,
+            ..ClientOptions::default()
+        };
+        let mut transport = HttpTransport::new(None, options.build().unwrap());
+        let request = transport
+            .get(Url::parse(&format!("{}/v2/foo/blobs/sha256:1234", registry.url())).unwrap())
+            .bearer_auth("token");
+        let response = transport.send(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "hello");
+        registry_mock.assert_async().await;
+        storage_mock.assert_async().await;
+    }
+
+    /// Serve empty responses on a raw socket, returns the URL and the number of accepted connections
+    async fn connection_counting_server() -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buffer = [0; 1024];
+                    while stream.read(&mut buffer).await.is_ok_and(|read| read > 0) {
+                        let response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+                        if stream.write_all(response).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    #[test_case(None, 1; "connection reused")]
+    #[test_case(Some(0), 3; "no idle connections")]
+    #[tokio::test]
+    async fn client_options_pool_max_idle_per_host(max_idle: Option<usize>, expected: usize) {
+        let (url, connections) = connection_counting_server().await;
+
+        let options = ClientOptions {
+            pool_max_idle_per_host: max_idle,
+            pool_idle_timeout: Some(Duration::from_secs(30)),
+            ..ClientOptions::default()
+        };
+        let mut transport = HttpTransport::new(None, options.build().unwrap());
+        for _ in 0..3 {
+            let request = transport.get(Url::parse(&format!("{url}/foobar")).unwrap());
+            let response = transport.send(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.bytes().await.unwrap();
+        }
+
+        assert_eq!(
+            connections.load(std::sync::atomic::Ordering::SeqCst),
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn client_options_redirect_disabled() {
+        let mut registry = mockito::Server::new_async().await;
+        let mocks = vec![
+            registry
+                .mock("GET", "/v2/foo/blobs/sha256:1234")
+                .with_status(307)
+                .with_header("Location", "/storage/blob")
+                .create_async()
+                .await,
+            registry
+                .mock("GET", "/storage/blob")
+                .expect(0)
+                .create_async()
+                .await,
+        ];
+
+        let mut transport = HttpTransport::new(None, ClientOptions::default().build().unwrap());
+        let request = transport
+            .get(Url::parse(&format!("{}/v2/foo/blobs/sha256:1234", registry.url())).unwrap());
+        let response = transport.send(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
 }
@@ -0,0 +1,227 @@
+//! PEP 440 version ordering
+//!
+//! Only normalized versions are parsed, as found in distribution filenames.
+//! ref: <https://packaging.python.org/en/latest/specifications/version-specifiers/>
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::package::oci_tag_to_version;
+
+/// Sort key of a PEP 440 version
+///
+/// The field order matches the comparison order of the specification.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    epoch: u64,
+    /// Release segment without trailing zeros, "1.0" == "1"
+    release: Vec<u64>,
+    pre: Pre,
+    /// A version without post-release sorts before any post-release
+    post: Option<u64>,
+    dev: Dev,
+    /// A version without local label sorts before any local version
+    local: Option<Vec<LocalSegment>>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Pre {
+    /// Developmental release of a final release, "1.0.dev1" < "1.0a1"
+    DevRelease,
+    Alpha(u64),
+    Beta(u64),
+    ReleaseCandidate(u64),
+    Final,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Dev {
+    Dev(u64),
+    None,
+}
+
+/// Alphanumeric segments sort before numeric segments
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum LocalSegment {
+    Alphanumeric(String),
+    Numeric(u64),
+}
+
+impl Version {
+    /// Parse the version from an OCI tag, see [`oci_tag_to_version`]
+    pub fn from_oci_tag(tag: &str) -> Result<Self, Error> {
+        oci_tag_to_version(tag).parse()
+    }
+
+    /// Pre-release or developmental release, post-releases of a final release are not
+    pub fn is_prerelease(&self) -> bool {
+        self.pre != Pre::Final || self.dev != Dev::None
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (public, local) = match value.split_once('+') {
+            Some((public, local)) => (public, Some(local)),
+            None => (value, None),
+        };
+        let (epoch, public) = match public.split_once('!') {
+            Some((epoch, public)) => (epoch.parse()?, public),
+            None => (0, public),
+        };
+
+        let mut parts = public.split('.').peekable();
+        let mut release = Vec::new();
+        let mut pre = None;
+        while let Some(part) = parts.next() {
+            let digits = part.len() - part.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 0 {
+                bail!("Invalid version '{value}'");
+            }
+            release.push(part[..digits].parse()?);
+            let rest = &part[digits..];
+            if !rest.is_empty() {
+                // Pre-release is attached to the last release number, "1.0a1"
+                pre = Some(parse_pre(rest).ok_or_else(|| anyhow!("Invalid version '{value}'"))?);
+                break;
+            }
+            if parts
+                .peek()
+                .is_some_and(|next| !next.starts_with(|c: char| c.is_ascii_digit()))
+            {
+                break;
+            }
+        }
+        while release.last() == Some(&0) {
+            release.pop();
+        }
+
+        let mut post = None;
+        let mut dev = Dev::None;
+        for part in parts {
+            if let Some(number) = part.strip_prefix("post").filter(|_| post.is_none()) {
+                post = Some(number.parse()?);
+            } else if let Some(number) = part.strip_prefix("dev").filter(|_| dev == Dev::None) {
+                dev = Dev::Dev(number.parse()?);
+            } else {
+                bail!("Invalid version '{value}'");
+            }
+        }
+
+        let pre = match (pre, &post, &dev) {
+            (Some(pre), _, _) => pre,
+            (None, None, Dev::Dev(_)) => Pre::DevRelease,
+            (None, _, _) => Pre::Final,
+        };
+        let local = local.map(|local| {
+            local
+                .split('.')
+                .map(|segment| match segment.parse() {
+                    Ok(number) => LocalSegment::Numeric(number),
+                    Err(_) => LocalSegment::Alphanumeric(segment.to_string()),
+                })
+                .collect()
+        });
+
+        Ok(Version {
+            epoch,
+            release,
+            pre,
+            post,
+            dev,
+            local,
+        })
+    }
+}
+
+/// Parse a normalized pre-release segment, "a1", "b2" or "rc3"
+fn parse_pre(value: &str) -> Option<Pre> {
+    let (kind, number) = value.split_at(value.find(|c: char| c.is_ascii_digit())?);
+    let number = number.parse().ok()?;
+    match kind {
+        "a" => Some(Pre::Alpha(number)),
+        "b" => Some(Pre::Beta(number)),
+        "rc" => Some(Pre::ReleaseCandidate(number)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn version_order() {
+        let versions = [
+            "1.0.dev456",
+            "1.0a1",
+            "1.0a2.dev456",
+            "1.0a12.dev456",
+            "1.0a12",
+            "1.0b1.dev456",
+            "1.0b2",
+            "1.0b2.post345.dev456",
+            "1.0b2.post345",
+            "1.0rc1.dev456",
+            "1.0rc1",
+            "1.0",
+            "1.0+abc.5",
+            "1.0+abc.7",
+            "1.0+5",
+            "1.0.post456.dev34",
+            "1.0.post456",
+            "1.0.15",
+            "1.1.dev1",
+            "2!0.1",
+        ];
+        let parsed: Vec<Version> = versions.iter().map(|v| v.parse().unwrap()).collect();
+        for (i, pair) in parsed.windows(2).enumerate() {
+            assert!(pair[0] < pair[1], "{} < {}", versions[i], versions[i + 1]);
+        }
+    }
+
+    #[test_case("1.0", "1"; "trailing zero")]
+    #[test_case("1.0.0", "1.0"; "trailing zeros")]
+    #[test_case("0!1.0", "1.0"; "default epoch")]
+    fn version_equal(left: &str, right: &str) {
+        assert_eq!(
+            left.parse::<Version>().unwrap(),
+            right.parse::<Version>().unwrap()
+        );
+    }
+
+    #[test]
+    fn version_from_oci_tag() {
+        assert_eq!(
+            Version::from_oci_tag("1.0-abc.5").unwrap(),
+            "1.0+abc.5".parse::<Version>().unwrap()
+        );
+        assert_eq!(
+            Version::from_oci_tag("2_1.0").unwrap(),
+            "2!1.0".parse::<Version>().unwrap()
+        );
+    }
+
+    #[test_case("1.0a1", true; "alpha")]
+    #[test_case("1.0rc1", true; "release candidate")]
+    #[test_case("1.0.dev1", true; "dev")]
+    #[test_case("1.0.post1.dev1", true; "post dev")]
+    #[test_case("1.0", false; "final release")]
+    #[test_case("1.0.post1", false; "post")]
+    #[test_case("1.0+abc.5", false; "local")]
+    fn version_is_prerelease(value: &str, expected: bool) {
+        assert_eq!(value.parse::<Version>().unwrap().is_prerelease(), expected);
+    }
+
+    #[test_case(""; "empty")]
+    #[test_case("latest"; "tag")]
+    #[test_case("1.0-1"; "not normalized")]
+    #[test_case("1.0c1"; "unknown pre-release")]
+    #[test_case("1.0.post1.post2"; "double post")]
+    fn version_invalid(value: &str) {
+        assert!(value.parse::<Version>().is_err());
+    }
+}